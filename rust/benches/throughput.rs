@@ -0,0 +1,159 @@
+//! Criterion benchmarks of the state layer: single transfers against trees of
+//! growing size, whole synthetic blocks applied through `apply_transaction`,
+//! and updating leaves then committing the new root. Run them with
+//! `cargo bench --features bench`; compare against a saved baseline
+//! (`-- --save-baseline main`, then `-- --baseline main`) to catch regressions
+//! in the Merkle layer or the transaction handlers.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use num_bigint::BigUint;
+use pwr_stateful_vida::database_service::{DatabaseService, DEFAULT_TOKEN};
+use pwr_stateful_vida::handler::apply_transaction;
+use serde_json::json;
+
+const VIDA_ID: u64 = 7;
+const INITIAL_BALANCE: u64 = 1_000_000_000;
+
+fn address(account: u64) -> Vec<u8> {
+    let mut address = vec![0u8; 20];
+    address[12..].copy_from_slice(&(account + 1).to_be_bytes());
+    address
+}
+
+// A database in its own temporary directory, funded with `accounts` balances
+struct Bench {
+    db: DatabaseService,
+    dir: PathBuf,
+    accounts: u64,
+    block_number: u64,
+}
+
+impl Bench {
+    fn open(name: &str, accounts: u64) -> Self {
+        let dir = std::env::temp_dir().join(format!("pwr-bench-{}-{}-{}", process::id(), name, accounts));
+        let _ = fs::remove_dir_all(&dir);
+        let db = DatabaseService::open(&dir, "state", &[VIDA_ID]).unwrap();
+        for account in 0..accounts {
+            db.set_balance(VIDA_ID, DEFAULT_TOKEN, &address(account), &BigUint::from(INITIAL_BALANCE)).unwrap();
+        }
+        db.flush(VIDA_ID).unwrap();
+        Bench { db, dir, accounts, block_number: 0 }
+    }
+
+    fn next_block(&mut self) -> u64 {
+        self.block_number += 1;
+        self.block_number
+    }
+}
+
+impl Drop for Bench {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// One transfer between accounts spread across the tree, inside an open batch
+fn transfer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transfer");
+    for accounts in [100u64, 1_000, 10_000] {
+        let mut bench = Bench::open("transfer", accounts);
+        let amount = BigUint::from(1u32);
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::from_parameter(accounts), &accounts, |b, _| {
+            b.iter_custom(|iters| {
+                let block_number = bench.next_block();
+                bench.db.begin_block(VIDA_ID, block_number).unwrap();
+                let start = Instant::now();
+                for i in 0..iters {
+                    let sender = address(i * 7919 % bench.accounts);
+                    let receiver = address((i * 7919 + 1) % bench.accounts);
+                    bench.db.start_transaction(VIDA_ID).unwrap();
+                    bench.db.transfer(VIDA_ID, DEFAULT_TOKEN, &sender, &receiver, &amount).unwrap();
+                }
+                let elapsed = start.elapsed();
+                bench.db.abort_block(VIDA_ID).unwrap();
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
+// A block of transfer payloads applied and committed as the node does
+fn apply_block(c: &mut Criterion) {
+    let actions = vec!["transfer".to_string()];
+    let mut group = c.benchmark_group("apply_block");
+    group.measurement_time(Duration::from_secs(10));
+    for transactions in [10u64, 100, 1_000] {
+        let mut bench = Bench::open("block", 1_000);
+        let mut nonces = vec![0u64; bench.accounts as usize];
+        let mut counter = 0u64;
+        group.throughput(Throughput::Elements(transactions));
+        group.bench_with_input(BenchmarkId::from_parameter(transactions), &transactions, |b, &transactions| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let block_number = bench.next_block();
+                    let block: Vec<(String, String, Vec<u8>)> = (0..transactions)
+                        .map(|_| {
+                            counter += 1;
+                            let sender = counter % bench.accounts;
+                            let receiver = (counter * 31 + 1) % bench.accounts;
+                            let nonce = nonces[sender as usize];
+                            nonces[sender as usize] += 1;
+                            let payload = json!({
+                                "action": "transfer",
+                                "receiver": hex::encode(address(receiver)),
+                                "amount": "1",
+                                "nonce": nonce
+                            });
+                            (format!("{:064x}", counter), hex::encode(address(sender)), serde_json::to_vec(&payload).unwrap())
+                        })
+                        .collect();
+
+                    let start = Instant::now();
+                    for (hash, sender, data) in &block {
+                        apply_transaction(&bench.db, &actions, VIDA_ID, block_number, hash, sender, data).unwrap();
+                    }
+                    bench.db.commit_block(VIDA_ID, block_number).unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
+// Updating leaves of a large tree, reading the new root and committing it to disk
+fn root(c: &mut Criterion) {
+    let mut group = c.benchmark_group("root");
+    for updates in [1u64, 100, 1_000] {
+        let mut bench = Bench::open("root", 10_000);
+        let mut round = 0u64;
+        group.throughput(Throughput::Elements(updates));
+        group.bench_with_input(BenchmarkId::from_parameter(updates), &updates, |b, &updates| {
+            b.iter(|| {
+                round += 1;
+                let block_number = bench.next_block();
+                bench.db.begin_block(VIDA_ID, block_number).unwrap();
+                for i in 0..updates {
+                    let account = (round * updates + i) * 7919 % bench.accounts;
+                    bench.db.set_balance(VIDA_ID, DEFAULT_TOKEN, &address(account), &BigUint::from(round)).unwrap();
+                }
+                let root = bench.db.get_root_hash(VIDA_ID).unwrap();
+                bench.db.commit_block(VIDA_ID, block_number).unwrap();
+                root
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transfer, apply_block, root);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+// Generates the gRPC service from proto/vida.proto when built with the `grpc` feature
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/vida.proto")?;
+    Ok(())
+}
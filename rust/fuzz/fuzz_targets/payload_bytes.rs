@@ -0,0 +1,14 @@
+//! Arbitrary bytes as transaction data: invalid UTF-8, truncated or deeply
+//! nested JSON and non-object values must all be rejected without a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pwr_stateful_vida_fuzz::{address, apply};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&sender, data)) = data.split_first() else {
+        return;
+    };
+    apply(&address(sender), data);
+});
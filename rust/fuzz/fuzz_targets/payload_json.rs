@@ -0,0 +1,12 @@
+//! Well-formed JSON payloads naming built-in actions, with adversarial field
+//! values: wrong types, out-of-range numbers, huge amounts, bad addresses.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pwr_stateful_vida_fuzz::{address, apply, FuzzTransaction};
+
+fuzz_target!(|txn: FuzzTransaction| {
+    let data = serde_json::to_vec(&txn.payload()).unwrap();
+    apply(&address(txn.sender), &data);
+});
@@ -0,0 +1,144 @@
+//! Shared harness of the fuzz targets: one database for the whole fuzzing
+//! process, seeded from a genesis that funds a few accounts and makes the
+//! first one admin, with every built-in action enabled. Inputs go through
+//! `apply_transaction` exactly as transactions from the RPC do. A rejection
+//! is an expected outcome; a panic or a storage error is a bug.
+
+use std::fs;
+use std::process;
+use std::sync::{Mutex, OnceLock};
+
+use arbitrary::Arbitrary;
+use pwr_stateful_vida::database_service::DatabaseService;
+use pwr_stateful_vida::genesis::Genesis;
+use pwr_stateful_vida::handler::{apply_transaction, ApplyError};
+use serde_json::{json, Map, Number, Value};
+
+const VIDA_ID: u64 = 7;
+const ACCOUNTS: u8 = 4;
+
+/// Actions enabled for the fuzzed VIDA.
+pub const ACTIONS: [&str; 20] = [
+    "transfer", "delegate", "approve", "transferFrom", "registerPeer", "mint", "burn",
+    "escrow", "claim", "reclaim", "vest", "setFees", "propose", "vote", "enact",
+    "freeze", "unfreeze", "createMultisig", "submitMultisig", "approveMultisig",
+];
+
+// Payload fields read by the built-in handlers
+const FIELDS: [&str; 30] = [
+    "action", "address", "amount", "basisPoints", "cliffBlock", "collector", "enabled",
+    "endBlock", "escrowId", "expiryBlock", "flat", "from", "memo", "multisig", "nonce",
+    "owners", "parameter", "peer", "proposalId", "quorumBasisPoints", "receiver",
+    "reference", "spender", "startBlock", "support", "threshold", "tokenId",
+    "transactionId", "value", "votingPeriod",
+];
+
+/// Hex address of a genesis account; account 0 is the admin.
+pub fn address(account: u8) -> String {
+    format!("{:040x}", account % ACCOUNTS + 1)
+}
+
+struct Harness {
+    db: DatabaseService,
+    actions: Vec<String>,
+    block_number: u64,
+    counter: u64,
+}
+
+fn harness() -> &'static Mutex<Harness> {
+    static HARNESS: OnceLock<Mutex<Harness>> = OnceLock::new();
+    HARNESS.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("pwr-fuzz-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = DatabaseService::open(&dir, "state", &[VIDA_ID]).unwrap();
+        let allocations: Vec<Value> = (0..ACCOUNTS)
+            .map(|account| json!({ "address": format!("0x{}", address(account)), "balance": "1000000000" }))
+            .collect();
+        let genesis: Genesis = serde_json::from_value(json!({
+            "allocations": allocations,
+            "admins": [format!("0x{}", address(0))]
+        })).unwrap();
+        genesis.apply(&db, VIDA_ID).unwrap();
+        let actions = ACTIONS.iter().map(|action| action.to_string()).collect();
+        Mutex::new(Harness { db, actions, block_number: 0, counter: 0 })
+    })
+}
+
+/// Applies `data` as a transaction from `sender` in a block of its own and
+/// commits it, so state carries over between inputs like on a live node.
+pub fn apply(sender: &str, data: &[u8]) {
+    let mut harness = harness().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    harness.block_number += 1;
+    harness.counter += 1;
+    let block_number = harness.block_number;
+    let hash = format!("{:064x}", harness.counter);
+
+    match apply_transaction(&harness.db, &harness.actions, VIDA_ID, block_number, &hash, sender, data) {
+        Ok(()) | Err(ApplyError::Rejected(_)) => {}
+        Err(ApplyError::Storage(reason)) => panic!("storage error on valid database: {}", reason),
+    }
+    harness.db.commit_block(VIDA_ID, block_number).unwrap();
+}
+
+/// JSON value biased towards what handlers parse: addresses of funded
+/// accounts, numbers at the edges of their range and amount strings.
+#[derive(Arbitrary, Debug)]
+pub enum FuzzValue {
+    Null,
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Text(String),
+    Digits(Vec<u8>),
+    Address(u8),
+    PrefixedAddress(u8),
+    Action(u8),
+    Array(Vec<FuzzValue>),
+    Object(Vec<(String, FuzzValue)>),
+}
+
+impl FuzzValue {
+    pub fn to_json(&self) -> Value {
+        match self {
+            FuzzValue::Null => Value::Null,
+            FuzzValue::Bool(b) => Value::Bool(*b),
+            FuzzValue::Unsigned(n) => json!(n),
+            FuzzValue::Signed(n) => json!(n),
+            FuzzValue::Float(f) => Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+            FuzzValue::Text(text) => Value::String(text.clone()),
+            FuzzValue::Digits(digits) => Value::String(digits.iter().map(|d| char::from(b'0' + d % 10)).collect()),
+            FuzzValue::Address(account) => Value::String(address(*account)),
+            FuzzValue::PrefixedAddress(account) => Value::String(format!("0x{}", address(*account))),
+            FuzzValue::Action(index) => Value::String(ACTIONS[*index as usize % ACTIONS.len()].to_string()),
+            FuzzValue::Array(values) => Value::Array(values.iter().map(FuzzValue::to_json).collect()),
+            FuzzValue::Object(fields) => Value::Object(
+                fields.iter().map(|(name, value)| (name.clone(), value.to_json())).collect(),
+            ),
+        }
+    }
+}
+
+/// Transaction with a payload built from known field names, so inputs reach
+/// the handlers' validation instead of failing on a missing action.
+#[derive(Arbitrary, Debug)]
+pub struct FuzzTransaction {
+    pub sender: u8,
+    pub action: u8,
+    pub fields: Vec<(u8, FuzzValue)>,
+    pub extra: Vec<(String, FuzzValue)>,
+}
+
+impl FuzzTransaction {
+    pub fn payload(&self) -> Value {
+        let mut payload = Map::new();
+        payload.insert("action".to_string(), json!(ACTIONS[self.action as usize % ACTIONS.len()]));
+        for (field, value) in &self.fields {
+            payload.insert(FIELDS[*field as usize % FIELDS.len()].to_string(), value.to_json());
+        }
+        for (name, value) in &self.extra {
+            payload.insert(name.clone(), value.to_json());
+        }
+        Value::Object(payload)
+    }
+}
@@ -0,0 +1,53 @@
+use sha3::{Digest, Keccak256};
+
+/// Length of an account address in bytes.
+pub const ADDRESS_LENGTH: usize = 20;
+
+/// The all-zero address. Nobody holds its key, so it never sends or receives
+/// funds: transfers to it burn them instead.
+pub const ZERO_ADDRESS: [u8; ADDRESS_LENGTH] = [0; ADDRESS_LENGTH];
+
+/// Parses a 20-byte hex address, with or without a 0x prefix. All-lowercase
+/// and all-uppercase addresses are accepted as is; mixed-case ones must carry
+/// a valid EIP-55 checksum, so a mistyped character is caught instead of
+/// moving funds to an unowned address.
+pub fn parse_address(address: &str) -> Result<Vec<u8>, String> {
+    let address_hex = address.strip_prefix("0x").unwrap_or(address);
+    if address_hex.len() != ADDRESS_LENGTH * 2 {
+        return Err(format!("Invalid address: {} is not {} bytes", address, ADDRESS_LENGTH));
+    }
+    let decoded = hex::decode(address_hex).map_err(|_| format!("Invalid address: {}", address))?;
+
+    let has_lower = address_hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = address_hex.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper && to_checksum(&decoded)[2..] != *address_hex {
+        return Err(format!("Invalid address checksum: {}", address));
+    }
+    Ok(decoded)
+}
+
+/// Parses the address funds are credited to, like [`parse_address`] but
+/// rejecting the zero address.
+pub fn parse_recipient(address: &str) -> Result<Vec<u8>, String> {
+    let decoded = parse_address(address)?;
+    if decoded == ZERO_ADDRESS {
+        return Err(format!("Invalid receiver: {} is the zero address", address));
+    }
+    Ok(decoded)
+}
+
+/// Formats an address with its EIP-55 checksum: hex letters are uppercased
+/// where the matching nibble of the Keccak-256 hash of the lowercase hex
+/// address is 8 or more.
+pub fn to_checksum(address: &[u8]) -> String {
+    let lower = hex::encode(address);
+    let hash = Keccak256::digest(lower.as_bytes());
+    let checksummed: String = lower.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 { c.to_ascii_uppercase() } else { c }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
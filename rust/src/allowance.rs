@@ -0,0 +1,94 @@
+use num_bigint::BigUint;
+use serde_json::Value;
+use tracing::info;
+
+use crate::authorization::{self, decode_hex_address};
+use crate::registry::{ALLOWANCE_EXCEEDED, INSUFFICIENT_FUNDS, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Built-in `approve` action: the sender sets how much of a token `spender`
+/// may move from the sender's balance with `transferFrom`. A new approval
+/// replaces the previous one for that token; approving `0` revokes it.
+pub struct ApproveHandler;
+
+impl TransactionHandler for ApproveHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let spender_hex = ctx.payload.get("spender")
+            .and_then(Value::as_str)
+            .ok_or("Missing spender")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+
+        let owner = decode_hex_address(ctx.sender)?;
+        let spender = decode_hex_address(spender_hex)?;
+        if owner == spender {
+            return Err("Cannot approve self".to_string());
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &owner, ctx.sender, nonce)?;
+        ctx.db.set_allowance(ctx.vida_id, token_id, &owner, &spender, &amount)
+            .map_err(|_| "Failed to store allowance".to_string())?;
+        info!("Allowance of {} for {} set to {} of token {}", ctx.sender, spender_hex, amount, token_id);
+        Ok(())
+    }
+}
+
+/// Built-in `transferFrom` action: the sender, acting as spender, moves
+/// `amount` of a token from `from` to `receiver`, spending the allowance
+/// `from` granted for that token with `approve`. The transfer fee is paid
+/// by `from` and spent from the allowance too. The nonce is the sender's.
+pub struct TransferFromHandler;
+
+impl TransactionHandler for TransferFromHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let owner_hex = ctx.payload.get("from")
+            .and_then(Value::as_str)
+            .ok_or("Missing from")?;
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let note = transfer::parse_note(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+
+        let spender = decode_hex_address(ctx.sender)?;
+        let owner = decode_hex_address(owner_hex)?;
+        let receiver = authorization::decode_recipient(receiver_hex)?;
+
+        authorization::check_not_frozen(ctx.db, ctx.vida_id, &[&owner, &receiver])?;
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &spender, ctx.sender, nonce)?;
+
+        let allowance = ctx.db.get_allowance(ctx.vida_id, token_id, &owner, &spender)
+            .map_err(|_| "Failed to read allowance".to_string())?;
+        let fee = match ctx.db.get_fee_policy(ctx.vida_id) {
+            Ok(Some(policy)) => policy.fee(&amount).map_err(|_| "Invalid fee policy".to_string())?,
+            Ok(None) => BigUint::default(),
+            Err(_) => return Err("Failed to read fee policy".to_string()),
+        };
+        let spent = &amount + &fee;
+        if allowance < spent {
+            return Err(format!(
+                "{}: {} and a fee of {} requested from {} by {}, {} approved",
+                ALLOWANCE_EXCEEDED, amount, fee, owner_hex, ctx.sender, allowance
+            ));
+        }
+
+        match ctx.db.transfer_with_fee(ctx.vida_id, token_id, &owner, &receiver, &amount) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(format!("{}: {} from {} to {}", INSUFFICIENT_FUNDS, amount, owner_hex, receiver_hex));
+            }
+            Err(_) => return Err("Transfer operation failed".to_string()),
+        }
+
+        let remaining: BigUint = allowance - &spent;
+        ctx.db.set_allowance(ctx.vida_id, token_id, &owner, &spender, &remaining)
+            .map_err(|_| "Failed to update allowance".to_string())?;
+
+        info!("TransferFrom succeeded: {} from {} to {} by {}", amount, owner_hex, receiver_hex, ctx.sender);
+        transfer::record_transfer(ctx.db, ctx.vida_id, token_id, &owner, &receiver, &amount, &note, ctx.block_number);
+        Ok(())
+    }
+}
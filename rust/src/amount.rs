@@ -0,0 +1,56 @@
+use std::sync::OnceLock;
+use num_bigint::BigUint;
+
+/// Most decimals a token may be configured with.
+pub const MAX_DECIMALS: u32 = 36;
+
+// Decimals set by `configure`; amounts are whole base units until then
+static DECIMALS: OnceLock<u32> = OnceLock::new();
+
+/// Sets the decimals of the token, used to read amounts written with a
+/// decimal point. Only the first call has an effect.
+pub fn configure(decimals: u32) {
+    let _ = DECIMALS.set(decimals);
+}
+
+/// Returns the decimals amounts in payloads are read with.
+pub fn decimals() -> u32 {
+    DECIMALS.get().copied().unwrap_or(0)
+}
+
+/// Parses an amount in base units. An amount with a decimal point, like
+/// "1.5", is in whole tokens of `decimals` decimals and is scaled exactly;
+/// it may not have more fractional digits than the token has decimals.
+pub fn parse(text: &str, decimals: u32) -> Result<BigUint, String> {
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    let digits = match text.split_once('.') {
+        // Read as before decimals existed, so existing payloads keep their meaning
+        None => text.to_string(),
+        Some((whole, fraction)) if is_digits(whole) && is_digits(fraction) => {
+            if fraction.len() > decimals as usize {
+                return Err(format!("Invalid amount: {} has more than {} decimals", text, decimals));
+            }
+            format!("{}{}{}", whole, fraction, "0".repeat(decimals as usize - fraction.len()))
+        }
+        _ => return Err(format!("Invalid amount: {}", text)),
+    };
+    digits.parse().map_err(|_| format!("Invalid amount: {}", text))
+}
+
+/// Formats base units as whole tokens of `decimals` decimals, without
+/// trailing zeros, e.g. "1.5"; with no decimals the amount is unchanged.
+pub fn format(amount: &BigUint, decimals: u32) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
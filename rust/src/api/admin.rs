@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use serde_json::json;
+use warp::Filter;
+
+use super::{ApiError, GET};
+use crate::diagnostics;
+use crate::handler;
+use crate::reload;
+use crate::state::SharedState;
+use crate::webhooks;
+
+// Balances sent per chunk of a /admin/state/export response body
+const EXPORT_CHUNK_SIZE: usize = 500;
+
+// Body of a request adding a peer
+#[derive(Deserialize, ToSchema)]
+pub(super) struct PeerRequest {
+    peer: String,
+}
+
+// Body of a request forcing revalidation of a block
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct RevalidateRequest {
+    block_number: u64,
+    vida_id: Option<u64>,
+}
+
+// Body of a request reprocessing dead-lettered transactions
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ReprocessRequest {
+    vida_id: Option<u64>,
+    hash: Option<String>,
+}
+
+// Body of a request registering a webhook
+#[derive(Deserialize, ToSchema)]
+pub(super) struct WebhookRequest {
+    url: String,
+    addresses: Vec<String>,
+    secret: String,
+}
+
+pub struct Admin;
+
+impl Admin {
+    /// Registers the admin endpoints, separate from the public read-only routes:
+    /// GET /admin/peers lists known peers with their health, POST /admin/peers
+    /// adds `{"peer": "host:port"}` and DELETE /admin/peers/<peer> removes one.
+    /// POST /admin/sync/pause and /admin/sync/resume stop and restart syncing,
+    /// POST /admin/flush flushes committed state to disk and POST /admin/revalidate
+    /// rechecks `{"blockNumber": n, "vidaId": id}` against peers.
+    /// POST /admin/failed-transactions/reprocess applies the failed
+    /// transactions of `{"vidaId": id}` again, or only `{"hash": h}`.
+    /// POST /admin/config/reload re-reads the configuration file and applies
+    /// the settings that can change without a restart.
+    /// GET /admin/webhooks lists the registered webhooks, POST /admin/webhooks
+    /// registers `{"url": u, "addresses": [..], "secret": s}` and
+    /// DELETE /admin/webhooks/<id> removes one. GET /admin/state/export streams
+    /// every non-zero balance of a VIDA as newline-delimited JSON, optionally
+    /// only those of a `tokenId`. GET /admin/diagnostics lists the blocks with
+    /// a saved root mismatch diagnostics bundle and
+    /// GET /admin/diagnostics/<block> returns one.
+    /// Every request must carry `Authorization: Bearer <admin_token>`; the
+    /// endpoints are disabled while no token is configured.
+    pub fn run(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let list = warp::path!("admin" / "peers")
+            .and(warp::get())
+            .and(Self::authorized(state.clone()))
+            .map(|state: SharedState| {
+                let peers = state.read().unwrap().peers.statuses();
+                warp::reply::json(&json!({ "peers": peers }))
+            });
+
+        let add = warp::path!("admin" / "peers")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and(warp::body::json())
+            .and_then(|state: SharedState, request: PeerRequest| async move {
+                let added = state.write().unwrap().peers.add(&request.peer);
+                Self::reply(added, "Peer already known")
+            });
+
+        let remove = warp::path!("admin" / "peers" / String)
+            .and(warp::delete())
+            .and(Self::authorized(state.clone()))
+            .and_then(|peer: String, state: SharedState| async move {
+                let removed = state.write().unwrap().peers.remove(&peer);
+                Self::reply(removed, "Unknown peer")
+            });
+
+        let pause = warp::path!("admin" / "sync" / "pause")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and_then(|state: SharedState| async move {
+                handler::pause_sync(&state).await;
+                Ok::<_, warp::Rejection>(warp::reply::json(&json!({ "paused": true })))
+            });
+
+        let resume = warp::path!("admin" / "sync" / "resume")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and_then(|state: SharedState| async move {
+                let result = handler::resume_sync(&state).await.map(|_| json!({ "paused": false }));
+                Self::result_reply(result)
+            });
+
+        let flush = warp::path!("admin" / "flush")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and_then(|state: SharedState| async move {
+                let db = state.read().unwrap().db.clone();
+                let result = handler::flush_committed(&db).await.map(|flushed| json!({ "flushed": flushed }));
+                Self::result_reply(result)
+            });
+
+        let revalidate = warp::path!("admin" / "revalidate")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and(warp::body::json())
+            .and_then(|state: SharedState, request: RevalidateRequest| async move {
+                let vida_id = request.vida_id.unwrap_or_else(|| state.read().unwrap().config.vida_id);
+                let result = handler::revalidate_block(&state, vida_id, request.block_number).await
+                    .map(|valid| json!({ "vidaId": vida_id, "blockNumber": request.block_number, "valid": valid }));
+                Self::result_reply(result)
+            });
+
+        let reprocess = warp::path!("admin" / "failed-transactions" / "reprocess")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and(warp::body::json())
+            .and_then(|state: SharedState, request: ReprocessRequest| async move {
+                let vida_id = request.vida_id.unwrap_or_else(|| state.read().unwrap().config.vida_id);
+                let result = handler::reprocess_failed(&state, vida_id, request.hash.as_deref()).await
+                    .map(|(would_apply, failed)| json!({ "vidaId": vida_id, "wouldApply": would_apply, "failed": failed }));
+                Self::result_reply(result)
+            });
+
+        let reload = warp::path!("admin" / "config" / "reload")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and_then(|state: SharedState| async move {
+                reload::reload(&state)
+                    .map(|changed| warp::reply::json(&json!({ "reloaded": true, "changed": changed })))
+                    .map_err(|e| warp::reject::custom(ApiError::bad_request(e.to_string())))
+            });
+
+        let list_webhooks = warp::path!("admin" / "webhooks")
+            .and(warp::get())
+            .and(Self::authorized(state.clone()))
+            .and_then(|state: SharedState| async move {
+                let db = state.read().unwrap().db.clone();
+                let result = db.get_webhooks()
+                    .map(|webhooks| json!({ "webhooks": webhooks }))
+                    .map_err(|e| format!("{:?}", e));
+                Self::result_reply(result)
+            });
+
+        let add_webhook = warp::path!("admin" / "webhooks")
+            .and(warp::post())
+            .and(Self::authorized(state.clone()))
+            .and(warp::body::json())
+            .and_then(|state: SharedState, request: WebhookRequest| async move {
+                let db = state.read().unwrap().db.clone();
+                webhooks::register(&db, &request.url, &request.addresses, &request.secret)
+                    .map(|webhook| warp::reply::json(&webhook))
+                    .map_err(|e| warp::reject::custom(ApiError::bad_request(e)))
+            });
+
+        let remove_webhook = warp::path!("admin" / "webhooks" / u64)
+            .and(warp::delete())
+            .and(Self::authorized(state.clone()))
+            .and_then(|id: u64, state: SharedState| async move {
+                let db = state.read().unwrap().db.clone();
+                match webhooks::remove(&db, id) {
+                    Ok(removed) => Self::reply(removed, "Unknown webhook"),
+                    Err(e) => Err(warp::reject::custom(ApiError::internal(e))),
+                }
+            });
+
+        let export = warp::path!("admin" / "state" / "export")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::authorized(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::export_balances(params, &state).map_err(warp::reject::custom)
+            });
+
+        let list_diagnostics = warp::path!("admin" / "diagnostics")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::authorized(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                let vida_id = GET::parse_vida_id(&params, &state).map_err(warp::reject::custom)?;
+                let dir = state.read().unwrap().config.diagnostics_dir.clone();
+                let blocks = diagnostics::list(&dir, vida_id);
+                Ok::<_, warp::Rejection>(warp::reply::json(&json!({ "vidaId": vida_id, "blocks": blocks })))
+            });
+
+        let get_diagnostics = warp::path!("admin" / "diagnostics" / u64)
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::authorized(state))
+            .and_then(|block_number: u64, params: HashMap<String, String>, state: SharedState| async move {
+                let vida_id = GET::parse_vida_id(&params, &state).map_err(warp::reject::custom)?;
+                let dir = state.read().unwrap().config.diagnostics_dir.clone();
+                match diagnostics::load(&dir, vida_id, block_number) {
+                    Ok(Some(bundle)) => Ok(warp::reply::json(&bundle)),
+                    Ok(None) => Err(warp::reject::custom(ApiError::not_found(format!("No diagnostics saved for block {}", block_number)))),
+                    Err(e) => Err(warp::reject::custom(ApiError::internal(e.to_string()))),
+                }
+            });
+
+        list.or(add).unify()
+            .or(remove).unify()
+            .or(pause).unify()
+            .or(resume).unify()
+            .or(flush).unify()
+            .or(revalidate).unify()
+            .or(reprocess).unify()
+            .or(reload).unify()
+            .or(list_webhooks).unify()
+            .or(add_webhook).unify()
+            .or(remove_webhook).unify()
+            .or(list_diagnostics).unify()
+            .or(get_diagnostics).unify()
+            .or(export)
+    }
+
+    // Streams the balances as newline-delimited JSON, reading the next chunk
+    // only once the previous one was sent
+    fn export_balances(params: HashMap<String, String>, state: &SharedState) -> Result<warp::http::Response<warp::hyper::Body>, ApiError> {
+        let vida_id = GET::parse_vida_id(&params, state)?;
+        let token_id = params.get("tokenId")
+            .map(|token_id| token_id.parse::<u64>().map_err(|_| ApiError::bad_request("Invalid tokenId format")))
+            .transpose()?;
+        let db = state.read().unwrap().db.clone();
+        let mut balances = db.balances(vida_id)
+            .map_err(ApiError::database)?
+            .filter(move |entry| match (entry, token_id) {
+                (Ok(entry), Some(token_id)) => entry.token_id == token_id,
+                _ => true,
+            });
+
+        let chunks = std::iter::from_fn(move || {
+            let mut chunk = String::new();
+            for entry in balances.by_ref().take(EXPORT_CHUNK_SIZE) {
+                match entry {
+                    Ok(entry) => {
+                        let line = json!({
+                            "tokenId": entry.token_id,
+                            "address": format!("0x{}", hex::encode(&entry.address)),
+                            "balance": entry.balance.to_string()
+                        });
+                        chunk.push_str(&line.to_string());
+                        chunk.push('\n');
+                    }
+                    // The status is already sent; ending the body early marks the export incomplete
+                    Err(e) => return Some(Err(std::io::Error::other(format!("{:?}", e)))),
+                }
+            }
+            (!chunk.is_empty()).then_some(Ok(chunk))
+        });
+        warp::http::Response::builder()
+            .header("content-type", "application/x-ndjson")
+            .body(warp::hyper::Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .map_err(|e| ApiError::internal(format!("Failed to build response: {}", e)))
+    }
+
+    // Passes the state through only if the request carries the configured admin token
+    fn authorized(state: SharedState) -> impl Filter<Extract = (SharedState,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("authorization")
+            .and_then(move |header: Option<String>| {
+                let state = state.clone();
+                async move {
+                    let token = state.read().unwrap().config.admin_token.clone();
+                    let presented = header.as_deref().and_then(|value| value.strip_prefix("Bearer "));
+                    if !token.is_empty() && presented == Some(token.as_str()) {
+                        Ok(state)
+                    } else {
+                        Err(warp::reject::custom(ApiError::unauthorized()))
+                    }
+                }
+            })
+    }
+
+    // Builds the JSON reply of a peer or webhook change, rejecting with 409 if nothing changed
+    fn reply(changed: bool, error: &str) -> Result<warp::reply::Json, warp::Rejection> {
+        if changed {
+            Ok(warp::reply::json(&json!({ "ok": true })))
+        } else {
+            Err(warp::reject::custom(ApiError::conflict(error)))
+        }
+    }
+
+    // Builds the JSON reply of an operation that may fail, rejecting with 500 on failure
+    fn result_reply(result: Result<serde_json::Value, String>) -> Result<warp::reply::Json, warp::Rejection> {
+        result
+            .map(|body| warp::reply::json(&body))
+            .map_err(|e| warp::reject::custom(ApiError::internal(e)))
+    }
+}
@@ -0,0 +1,55 @@
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use warp::http::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG};
+use warp::http::{HeaderValue, StatusCode};
+use warp::hyper::{self, Body};
+use warp::reply::Response;
+use warp::Reply;
+
+/// `Cache-Control` of replies that never change, such as the roots of
+/// blocks before the last checked one.
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// `Cache-Control` of replies that may change at any block; caches must
+/// revalidate them with their `ETag` before reuse.
+pub const REVALIDATE: &str = "no-cache";
+
+/// Adds caching headers to a successful GET reply: `Cache-Control` letting
+/// shared caches keep it for `max_age_secs`, unless the endpoint already set
+/// one, and a strong `ETag` over the body. A request whose `If-None-Match`
+/// lists that tag gets an empty 304 instead.
+pub async fn finish(reply: impl Reply, if_none_match: Option<String>, max_age_secs: u64) -> Response {
+    let response = reply.into_response();
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to buffer reply for its ETag: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(&bytes)[..16]));
+    if !parts.headers.contains_key(CACHE_CONTROL) {
+        if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={}", max_age_secs)) {
+            parts.headers.insert(CACHE_CONTROL, value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(ETAG, value);
+    }
+
+    let matched = if_none_match.map_or(false, |tags| {
+        tags.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    });
+    if matched {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_TYPE);
+        parts.headers.remove(CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
@@ -0,0 +1,104 @@
+use std::convert::Infallible;
+use pwr_rs::merkle_tree::MerkleTreeError;
+use serde::Serialize;
+use tracing::error;
+use warp::http::StatusCode;
+use warp::Reply;
+
+/// Error returned by an API endpoint. It is raised as a warp rejection and
+/// rendered by `handle_rejection` as `{"code": ..., "message": ...}` with the
+/// matching HTTP status.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// JSON body of an error response
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+}
+
+impl ApiError {
+    /// 400: the request parameters are missing or malformed.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::BAD_REQUEST, code: "BAD_REQUEST", message: message.into() }
+    }
+
+    /// 401: the request lacks valid credentials.
+    pub fn unauthorized() -> Self {
+        Self { status: StatusCode::UNAUTHORIZED, code: "UNAUTHORIZED", message: "Unauthorized".to_string() }
+    }
+
+    /// 403: the caller is not allowed to perform the request.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::FORBIDDEN, code: "FORBIDDEN", message: message.into() }
+    }
+
+    /// 404: the requested data does not exist.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::NOT_FOUND, code: "NOT_FOUND", message: message.into() }
+    }
+
+    /// 409: the request conflicts with the current state.
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::CONFLICT, code: "CONFLICT", message: message.into() }
+    }
+
+    /// 500: the node failed to serve the request.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::INTERNAL_SERVER_ERROR, code: "INTERNAL_ERROR", message: message.into() }
+    }
+
+    /// 503: the data cannot be served right now but may be later.
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::SERVICE_UNAVAILABLE, code: "UNAVAILABLE", message: message.into() }
+    }
+
+    /// 500 for a failed database read; the details are logged, not returned.
+    pub fn database(e: MerkleTreeError) -> Self {
+        error!("Database error while serving request: {:?}", e);
+        Self::internal("Database error")
+    }
+}
+
+impl warp::reject::Reject for ApiError {}
+
+/// Turns every rejection, from the endpoints or from warp itself, into a JSON
+/// error response. Shared by all routes.
+pub async fn handle_rejection(rejection: warp::Rejection) -> Result<warp::reply::Response, Infallible> {
+    let error = if let Some(e) = rejection.find::<ApiError>() {
+        ApiError { status: e.status, code: e.code, message: e.message.clone() }
+    } else if rejection.is_not_found() {
+        ApiError::not_found("Not found")
+    } else if let Some(e) = rejection.find::<warp::reject::InvalidQuery>() {
+        ApiError::bad_request(e.to_string())
+    } else if let Some(e) = rejection.find::<warp::body::BodyDeserializeError>() {
+        ApiError::bad_request(e.to_string())
+    } else if let Some(e) = rejection.find::<warp::reject::MissingHeader>() {
+        ApiError::bad_request(e.to_string())
+    } else if let Some(e) = rejection.find::<warp::cors::CorsForbidden>() {
+        ApiError::forbidden(e.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ApiError {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            code: "METHOD_NOT_ALLOWED",
+            message: "Method not allowed".to_string(),
+        }
+    } else if rejection.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        ApiError {
+            status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            code: "UNSUPPORTED_MEDIA_TYPE",
+            message: "Unsupported media type".to_string(),
+        }
+    } else {
+        error!("Unhandled rejection: {:?}", rejection);
+        ApiError::internal("Internal server error")
+    };
+
+    let body = warp::reply::json(&ErrorBody { code: error.code, message: &error.message });
+    Ok(warp::reply::with_status(body, error.status).into_response())
+}
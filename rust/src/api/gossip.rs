@@ -0,0 +1,57 @@
+use serde_json::json;
+use warp::Filter;
+
+use super::ApiError;
+use crate::gossip::Attestation;
+use crate::signing;
+use crate::state::SharedState;
+
+pub struct Gossip;
+
+impl Gossip {
+    /// Registers POST /attestations, where peers push the root they computed
+    /// for a block while `root_gossip` is enabled. Only nodes in the peer list
+    /// may attest, with a valid signature if their public key is configured;
+    /// the endpoint answers 404 while gossip is disabled.
+    pub fn run(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("attestations")
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(warp::any().map(move || state.clone()))
+            .and_then(|attestation: Attestation, state: SharedState| async move {
+                Self::handle_attestation(attestation, &state)
+                    .map(|()| warp::reply::json(&json!({ "ok": true })))
+                    .map_err(warp::reject::custom)
+            })
+    }
+
+    fn handle_attestation(attestation: Attestation, state: &SharedState) -> Result<(), ApiError> {
+        let mut state = state.write().unwrap();
+        if !state.config.root_gossip {
+            return Err(ApiError::not_found("Root gossip is disabled"));
+        }
+        if state.config.vida(attestation.vida_id).is_none() {
+            return Err(ApiError::not_found(format!("VIDA {} is not synced by this node", attestation.vida_id)));
+        }
+        if !state.peers.statuses().iter().any(|peer| peer.address == attestation.peer) {
+            return Err(ApiError::forbidden(format!("{} is not a known peer", attestation.peer)));
+        }
+        let root_hex = attestation.root_hash.strip_prefix("0x").unwrap_or(&attestation.root_hash);
+        let root = hex::decode(root_hex)
+            .ok()
+            .filter(|root| !root.is_empty())
+            .ok_or_else(|| ApiError::bad_request("Invalid rootHash format"))?;
+
+        if let Some(public_key) = state.config.peer_public_keys.get(&attestation.peer) {
+            let signed = attestation.signature.as_deref().map_or(false, |signature| {
+                signing::verify_root(public_key, attestation.block_number, &root, signature)
+            });
+            if !signed {
+                return Err(ApiError::forbidden("Missing or invalid attestation signature"));
+            }
+        }
+
+        state.attestations.record(&attestation.peer, attestation.vida_id, attestation.block_number, root);
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::convert::Infallible;
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject};
+use async_graphql_warp::GraphQLResponse;
+use warp::Filter;
+
+use crate::database_service::{DatabaseService, TransactionRecord, DEFAULT_TOKEN};
+use crate::handler;
+use crate::state::SharedState;
+
+// Most history records returned by one `transactions` field
+const MAX_TRANSACTIONS: u64 = 100;
+
+/// Schema served at /graphql.
+pub type VidaSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub struct GraphQl;
+
+impl GraphQl {
+    /// Registers POST /graphql, answering queries against [`VidaSchema`], and
+    /// GET /graphql, serving a GraphiQL page to explore it. Only built with
+    /// the `graphql` feature.
+    pub fn run(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(state)
+            .finish();
+
+        let query = warp::path("graphql")
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(async_graphql_warp::graphql(schema))
+            .and_then(|(schema, request): (VidaSchema, async_graphql::Request)| async move {
+                Ok::<_, Infallible>(GraphQLResponse::from(schema.execute(request).await))
+            });
+
+        let graphiql = warp::path("graphql")
+            .and(warp::path::end())
+            .and(warp::get())
+            .map(|| warp::reply::html(GraphiQLSource::build().endpoint("/graphql").finish()));
+
+        query.or(graphiql)
+    }
+}
+
+/// Root of every query. Fields take an optional `vidaId` like the REST
+/// endpoints, defaulting to the primary VIDA.
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// A VIDA synced by this node.
+    async fn vida(&self, ctx: &Context<'_>, id: Option<u64>) -> Result<Vida> {
+        let state = ctx.data::<SharedState>()?.read().unwrap();
+        let id = id.unwrap_or(state.config.vida_id);
+        if state.config.vida(id).is_none() {
+            return Err(format!("VIDA {} is not synced by this node", id).into());
+        }
+        Ok(Vida { id, db: state.db.clone() })
+    }
+}
+
+/// State of one VIDA.
+pub struct Vida {
+    id: u64,
+    db: DatabaseService,
+}
+
+#[Object]
+impl Vida {
+    async fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Last block whose root was validated.
+    async fn last_checked_block(&self) -> Result<u64> {
+        self.db.get_last_checked_block(self.id).map_err(database_error)
+    }
+
+    /// Hex root hash after a block, the last checked one by default.
+    async fn root_hash(&self, block_number: Option<u64>) -> Result<Option<String>> {
+        let last_checked_block = self.db.get_last_checked_block(self.id).map_err(database_error)?;
+        let root = match block_number {
+            Some(block_number) if block_number > last_checked_block => None,
+            Some(block_number) if block_number < last_checked_block => {
+                self.db.get_block_root_hash(self.id, block_number).map_err(database_error)?
+            }
+            _ => self.db.get_root_hash(self.id).map_err(database_error)?,
+        };
+        Ok(root.map(hex::encode))
+    }
+
+    /// Total supply of a token, null if the database does not track it.
+    async fn total_supply(&self, token_id: Option<u64>) -> Result<Option<String>> {
+        let supply = self.db.get_total_supply(self.id, token_id.unwrap_or(DEFAULT_TOKEN)).map_err(database_error)?;
+        Ok(supply.map(|supply| supply.to_string()))
+    }
+
+    /// An account, by hex address with or without a 0x prefix.
+    async fn account(&self, address: String) -> Result<Account> {
+        let decoded = crate::address::parse_address(&address)?;
+        Ok(Account { vida_id: self.id, address: decoded, db: self.db.clone() })
+    }
+
+    /// Sync progress of the VIDA.
+    async fn status(&self, ctx: &Context<'_>) -> Result<SyncStatus> {
+        let last_checked_block = self.db.get_last_checked_block(self.id).map_err(database_error)?;
+        let state = ctx.data::<SharedState>()?.read().unwrap();
+        let latest_block = state.sync.latest_chain_block();
+        Ok(SyncStatus {
+            last_checked_block,
+            latest_block,
+            lag: latest_block.map(|latest| latest.saturating_sub(last_checked_block)),
+            blocks_per_minute: state.sync.blocks_per_minute(self.id),
+            sync_paused: handler::is_sync_paused(),
+        })
+    }
+}
+
+/// An address on a VIDA.
+pub struct Account {
+    vida_id: u64,
+    address: Vec<u8>,
+    db: DatabaseService,
+}
+
+#[Object]
+impl Account {
+    async fn address(&self) -> String {
+        format!("0x{}", hex::encode(&self.address))
+    }
+
+    /// Balance of a token, as of `blockNumber` if given.
+    async fn balance(&self, token_id: Option<u64>, block_number: Option<u64>) -> Result<Option<String>> {
+        let token_id = token_id.unwrap_or(DEFAULT_TOKEN);
+        let balance = match block_number {
+            Some(block_number) => self.db.get_balance_at(self.vida_id, token_id, &self.address, block_number)
+                .map_err(database_error)?,
+            None => Some(self.db.get_balance(self.vida_id, token_id, &self.address).map_err(database_error)?),
+        };
+        Ok(balance.map(|balance| balance.to_string()))
+    }
+
+    /// Nonce the account's next transaction must carry.
+    async fn nonce(&self) -> Result<u64> {
+        self.db.get_nonce(self.vida_id, &self.address).map_err(database_error)
+    }
+
+    /// Number of entries in the account's transaction history.
+    async fn transaction_count(&self) -> Result<u64> {
+        self.db.get_transaction_count(self.vida_id, &self.address).map_err(database_error)
+    }
+
+    /// Transaction history, oldest first; at most 100 entries per query.
+    async fn transactions(&self, offset: Option<u64>, limit: Option<u64>) -> Result<Vec<Transaction>> {
+        let limit = limit.unwrap_or(20).min(MAX_TRANSACTIONS);
+        let records = self.db.get_transactions(self.vida_id, &self.address, offset.unwrap_or(0), limit)
+            .map_err(database_error)?;
+        Ok(records.into_iter().map(Transaction::from).collect())
+    }
+}
+
+/// Entry in an account's transaction history.
+#[derive(SimpleObject)]
+pub struct Transaction {
+    block_number: u64,
+    counterparty: String,
+    amount: String,
+    /// `incoming` or `outgoing`.
+    direction: String,
+    token_id: u64,
+    memo: Option<String>,
+    reference: Option<String>,
+}
+
+impl From<TransactionRecord> for Transaction {
+    fn from(record: TransactionRecord) -> Self {
+        let direction = serde_json::to_value(record.direction)
+            .ok()
+            .and_then(|direction| direction.as_str().map(str::to_string))
+            .unwrap_or_default();
+        Transaction {
+            block_number: record.block_number,
+            counterparty: record.counterparty,
+            amount: record.amount,
+            direction,
+            token_id: record.token_id.unwrap_or(DEFAULT_TOKEN),
+            memo: record.memo,
+            reference: record.reference,
+        }
+    }
+}
+
+/// Sync progress of a VIDA, as reported by /status.
+#[derive(SimpleObject)]
+pub struct SyncStatus {
+    last_checked_block: u64,
+    latest_block: Option<u64>,
+    lag: Option<u64>,
+    blocks_per_minute: f64,
+    sync_paused: bool,
+}
+
+// Database errors are reported in the response's `errors` list
+fn database_error(e: pwr_rs::merkle_tree::MerkleTreeError) -> async_graphql::Error {
+    async_graphql::Error::new(format!("Database error: {:?}", e))
+}
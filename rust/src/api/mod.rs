@@ -1,51 +1,1039 @@
-use warp::Filter;
-use std::collections::HashMap;
-use crate::database_service::DatabaseService;
+use utoipa::OpenApi;
+use warp::{Filter, Reply};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use num_bigint::BigUint;
+use pwr_rs::merkle_tree::MerkleTreeError;
+use serde_json::{json, Value};
+use crate::address;
+use crate::amount;
+use crate::config::Config;
+use crate::database_service::{DatabaseService, Receipt, StateSnapshot, DEFAULT_TOKEN};
+use crate::handler;
+use crate::state::SharedState;
+use crate::state_sync::{self, StateChunk};
+
+mod admin;
+mod cache;
+mod error;
+mod gossip;
+mod openapi;
+mod pagination;
+mod simulate;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod ws;
+
+pub use admin::Admin;
+pub use error::ApiError;
+pub use gossip::Gossip;
+pub use openapi::ApiDoc;
+pub use pagination::{ListQuery, Order, Page};
+pub use simulate::Simulate;
+#[cfg(feature = "graphql")]
+pub use graphql::{GraphQl, VidaSchema};
+
+// Most blocks a /receipts request may span
+const MAX_RECEIPT_BLOCKS: u64 = 10_000;
+
+// Most blocks a /rootHashes request may span
+const MAX_ROOT_HASH_BLOCKS: u64 = 1_000;
+
+// Holders listed by /holders without a `limit`, and the most it may ask for
+const DEFAULT_HOLDERS_LIMIT: usize = 100;
+const MAX_HOLDERS_LIMIT: usize = 1_000;
+
+// Entries of /state-diff sent per chunk of the response body
+const STATE_DIFF_CHUNK_SIZE: usize = 500;
+
+/// Combines the public, simulation and admin endpoints under `/v1`, plus /graphql when
+/// built with the `graphql` feature, rendering every error as a JSON `{code, message}`
+/// body with the matching HTTP status. `/v1/openapi.json` describes the endpoints as
+/// OpenAPI. They are also served without the prefix, which peers and clients of
+/// earlier releases still call. With `cors_origins` configured, browsers on those
+/// origins may call the endpoints directly.
+pub fn routes(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = Infallible> + Clone {
+    let cors = {
+        let config = &state.read().unwrap().config;
+        (!config.cors_origins.is_empty()).then(|| cors(config))
+    };
+    let api = GET::run(state.clone())
+        .or(Gossip::run(state.clone()))
+        .or(Simulate::run(state.clone()))
+        .or(Admin::run(state.clone()));
+    let openapi = warp::path!("v1" / "openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&ApiDoc::openapi()));
+    let routes = openapi.or(warp::path("v1").and(api.clone())).or(api);
+    #[cfg(feature = "graphql")]
+    let routes = routes.or(GraphQl::run(state));
+    let routes = match cors {
+        Some(cors) => routes.with(cors).map(Reply::into_response).boxed(),
+        None => routes.map(Reply::into_response).boxed(),
+    };
+    routes.recover(error::handle_rejection)
+}
+
+// Lets browsers on `cors_origins` call the API with `cors_methods`; "*" allows any origin
+fn cors(config: &Config) -> warp::cors::Cors {
+    let cors = warp::cors()
+        .allow_methods(config.cors_methods.iter().map(String::as_str))
+        .allow_headers(["content-type", "if-none-match", "authorization"])
+        .expose_headers(["etag", handler::ROOT_SIGNATURE_HEADER]);
+    let cors = if config.cors_origins.iter().any(|origin| origin == "*") {
+        cors.allow_any_origin()
+    } else {
+        cors.allow_origins(config.cors_origins.iter().map(String::as_str))
+    };
+    cors.build()
+}
 
 pub struct GET;
 
 impl GET {
     /// Initializes and registers all GET endpoint handlers with the Warp framework.
     /// Currently registers the /rootHash endpoint for retrieving Merkle root hashes
-    /// for specific block numbers.
-    pub fn run() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        warp::path("rootHash")
+    /// for specific block numbers, signed in the `X-Root-Signature` header, /rootHashes
+    /// for the signed roots of the blocks from `fromBlock` to `toBlock` at once, the /balance endpoint for account balances
+    /// (optionally as of a past `blockNumber`) and
+    /// the /transactions endpoint for account history, /receipts for the receipts of the
+    /// blocks from `fromBlock` to `toBlock`, and /genesisHash
+    /// used by peers to detect genesis mismatches at startup, and /state/export
+    /// serving full state snapshots to diverged peers, /state/chunks serving
+    /// the same state in chunks with proofs for fast sync, /state-diff streaming the
+    /// keys changed between a `fromBlock` and a `toBlock` as newline-delimited
+    /// JSON for indexers, and /allowance for
+    /// amounts approved for `transferFrom`, /supply for the total supply (with
+    /// `audit=true` checking it against all balances), /holders for the accounts
+    /// with the largest balances and their share of supply, /stats for the number
+    /// of accounts, transfers and state keys, /status for sync progress,
+    /// lag behind the chain and peer health, /peers for the health and
+    /// reputation scores of known peers, /misbehavior for the peers caught
+    /// reporting roots that conflict with a quorum (optionally for one `peer`),
+    /// /failed-transactions for the
+    /// transactions that could not be applied, /changes for the journal of state
+    /// changes applied in a `blockNumber`, /escrows for pending escrows,
+    /// /vesting for the vesting schedules of an `address`, /proposals for
+    /// governance proposals and the policy deciding them, /multisig for the
+    /// owners and transactions of the multisig account at an `address`, /account-status for
+    /// whether an `address` is frozen, /receipt for the outcome of the
+    /// transaction with a `txHash`, /block for the root and transaction summaries
+    /// of the block with a `number`. /ws upgrades to a WebSocket that
+    /// pushes block, root hash and balance events. Every endpoint
+    /// accepts an optional `vidaId` parameter defaulting to the primary VIDA;
+    /// /balance, /supply and /holders also take an optional `tokenId`. The lists of
+    /// /transactions, /receipts and /peers are paged with `cursor`, `limit` and
+    /// `order`; see `ListQuery`.
+    /// Failures are rejected with an `ApiError` for `routes` to render.
+    pub fn run(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let root_hash = warp::path("rootHash")
             .and(warp::get())
             .and(warp::query::<HashMap<String, String>>())
-            .map(|params: HashMap<String, String>| {
-                match Self::handle_root_hash(params) {
-                    Ok(response) => response,
-                    Err(_) => String::new()
-                }
-            })
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_root_hash(params, &state)
+                    .map(|(root_hash, signature, finalized)| {
+                        let cache_control = if finalized { cache::IMMUTABLE } else { cache::REVALIDATE };
+                        let reply = warp::reply::with_header(root_hash, handler::ROOT_SIGNATURE_HEADER, signature);
+                        warp::reply::with_header(reply, "Cache-Control", cache_control)
+                    })
+                    .map_err(warp::reject::custom)
+            });
+
+        let root_hashes = warp::path("rootHashes")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_root_hashes(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let balance = warp::path("balance")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_balance(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let transactions = warp::path("transactions")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_transactions(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let genesis_hash = warp::path("genesisHash")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_genesis_hash(params, &state).map_err(warp::reject::custom)
+            });
+
+        let state_export = warp::path!("state" / "export")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_state_export(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let state_chunks = warp::path!("state" / "chunks")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_state_chunks(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let state_diff = warp::path("state-diff")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_state_diff(params, &state).map_err(warp::reject::custom)
+            });
+
+        let allowance = warp::path("allowance")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_allowance(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let supply = warp::path("supply")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_supply(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let holders = warp::path("holders")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_holders(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let stats = warp::path("stats")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_stats(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let status = warp::path("status")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_status(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let peers = warp::path("peers")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_peers(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let misbehavior = warp::path("misbehavior")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_misbehavior(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let failed_transactions = warp::path("failed-transactions")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_failed_transactions(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let changes = warp::path("changes")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_changes(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let escrows = warp::path("escrows")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_escrows(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let vesting = warp::path("vesting")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_vesting(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let proposals = warp::path("proposals")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_proposals(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let multisig = warp::path("multisig")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_multisig(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let account_status = warp::path("account-status")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_account_status(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let receipts = warp::path("receipts")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_receipts(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let receipt = warp::path("receipt")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_receipt(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let block = warp::path("block")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|params: HashMap<String, String>, state: SharedState| async move {
+                Self::handle_block(params, &state)
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            });
+
+        let events = warp::path("ws")
+            .and(warp::ws())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(Self::with_state(state.clone()))
+            .and_then(|socket: warp::ws::Ws, params: HashMap<String, String>, state: SharedState| async move {
+                Self::parse_vida_id(&params, &state)
+                    .map(|vida_id| socket.on_upgrade(move |socket| ws::serve(socket, vida_id)))
+                    .map_err(warp::reject::custom)
+            });
+
+        // Streamed and upgraded replies are left out of ETag computation
+        let max_age = state.read().unwrap().config.cache_max_age_secs;
+        let cached = root_hash.or(root_hashes).or(balance).or(transactions).or(genesis_hash).or(state_export).or(state_chunks).or(allowance).or(supply).or(holders).or(stats).or(status).or(peers).or(misbehavior).or(failed_transactions).or(changes).or(escrows).or(vesting).or(proposals).or(multisig).or(account_status).or(receipts).or(receipt).or(block);
+        let cached = warp::header::optional::<String>("if-none-match")
+            .and(cached)
+            .then(move |if_none_match: Option<String>, reply| cache::finish(reply, if_none_match, max_age));
+
+        cached.or(state_diff).or(events)
     }
     
-    fn handle_root_hash(params: HashMap<String, String>) -> Result<String, String> {
+    // Returns the hex root of a block, the node's signature over it and whether
+    // the root is final, i.e. of a block before the last checked one
+    fn handle_root_hash(params: HashMap<String, String>, state: &SharedState) -> Result<(String, String, bool), ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
         let block_number_str = params.get("blockNumber")
-            .ok_or("Missing blockNumber parameter")?;
+            .ok_or_else(|| ApiError::bad_request("Missing blockNumber parameter"))?;
         let block_number: u64 = block_number_str.parse()
-            .map_err(|_| "Invalid block number format")?;
+            .map_err(|_| ApiError::bad_request("Invalid block number format"))?;
         
-        let last_checked_block = DatabaseService::get_last_checked_block()
-            .map_err(|_| "Database error")?;
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
         
-        if block_number == last_checked_block {
-            let root_hash = DatabaseService::get_root_hash()
-                .map_err(|_| "Database error")?;
-            match root_hash {
-                Some(hash) => Ok(hex::encode(hash)),
-                None => Ok(String::new())
-            }
+        let root_hash = if block_number == last_checked_block {
+            db.get_root_hash(vida_id)
+                .map_err(ApiError::database)?
+                .ok_or_else(|| ApiError::not_found("No root hash recorded yet"))?
         } else if block_number < last_checked_block && block_number > 1 {
-            let block_root_hash = DatabaseService::get_block_root_hash(block_number)
-                .map_err(|_| "Database error")?;
-            
-            match block_root_hash {
-                Some(hash) => Ok(hex::encode(hash)),
-                None => Ok(format!("Block root hash not found for block number: {}", block_number))
-            }
+            db.get_block_root_hash(vida_id, block_number)
+                .map_err(ApiError::database)?
+                .ok_or_else(|| ApiError::not_found(format!("Block root hash not found for block number: {}", block_number)))?
+        } else if block_number > last_checked_block {
+            return Err(ApiError::not_found(format!("Block {} has not been processed yet", block_number)));
         } else {
-            Ok("Invalid block number".to_string())
+            return Err(ApiError::bad_request("Invalid block number"));
+        };
+        let signature = state.read().unwrap().node_key.sign_root(block_number, &root_hash);
+        Ok((hex::encode(root_hash), signature, block_number < last_checked_block))
+    }
+
+    // Returns the roots recorded for a range of blocks with the node's signature
+    // over each; blocks without a recorded root are left out
+    fn handle_root_hashes(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let parse_block = |name: &str| -> Result<u64, ApiError> {
+            params.get(name)
+                .ok_or_else(|| ApiError::bad_request(format!("Missing {} parameter", name)))?
+                .parse()
+                .map_err(|_| ApiError::bad_request(format!("Invalid {} format", name)))
+        };
+        let from_block = parse_block("fromBlock")?;
+        let to_block = parse_block("toBlock")?;
+        if to_block < from_block {
+            return Err(ApiError::bad_request("toBlock must not be before fromBlock"));
+        }
+        if to_block - from_block >= MAX_ROOT_HASH_BLOCKS {
+            return Err(ApiError::bad_request(format!("A request may span at most {} blocks", MAX_ROOT_HASH_BLOCKS)));
+        }
+
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+        let mut roots = BTreeMap::new();
+        let mut signatures = BTreeMap::new();
+        for block_number in from_block.max(2)..=to_block.min(last_checked_block) {
+            let root_hash = if block_number == last_checked_block {
+                db.get_root_hash(vida_id).map_err(ApiError::database)?
+            } else {
+                db.get_block_root_hash(vida_id, block_number).map_err(ApiError::database)?
+            };
+            if let Some(root_hash) = root_hash {
+                let signature = state.read().unwrap().node_key.sign_root(block_number, &root_hash);
+                roots.insert(block_number, hex::encode(root_hash));
+                signatures.insert(block_number, signature);
+            }
+        }
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "fromBlock": from_block,
+            "toBlock": to_block,
+            "lastCheckedBlock": last_checked_block,
+            "roots": roots,
+            "signatures": signatures
+        }))
+    }
+
+    fn handle_genesis_hash(params: HashMap<String, String>, state: &SharedState) -> Result<String, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let genesis_hash = db.get_genesis_hash(vida_id)
+            .map_err(ApiError::database)?;
+        genesis_hash.map(hex::encode)
+            .ok_or_else(|| ApiError::not_found("No genesis hash recorded"))
+    }
+
+    fn handle_state_export(params: HashMap<String, String>, state: &SharedState) -> Result<StateSnapshot, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        // Exports are refused while a block is being applied; the peer should retry
+        db.export_state(vida_id).map_err(|e| match e {
+            MerkleTreeError::IllegalState(message) => ApiError::unavailable(message),
+            e => ApiError::database(e),
+        })
+    }
+
+    fn handle_state_chunks(params: HashMap<String, String>, state: &SharedState) -> Result<StateChunk, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let parse = |name: &str| -> Result<Option<u64>, ApiError> {
+            params.get(name)
+                .map(|value| value.parse().map_err(|_| ApiError::bad_request(format!("Invalid {} format", name))))
+                .transpose()
+        };
+        let start = parse("start")?.unwrap_or(0);
+        let count = parse("count")?.unwrap_or(state_sync::MAX_CHUNK_ENTRIES);
+
+        state_sync::serve_chunk(&db, vida_id, parse("blockNumber")?, start, count).map_err(|e| match e {
+            MerkleTreeError::IllegalState(message) => ApiError::unavailable(message),
+            MerkleTreeError::InvalidArgument(message) => ApiError::conflict(message),
+            e => ApiError::database(e),
+        })
+    }
+
+    // Streams the diff as newline-delimited JSON, sent in chunks so large
+    // diffs never have to be rendered as one document
+    fn handle_state_diff(params: HashMap<String, String>, state: &SharedState) -> Result<warp::http::Response<warp::hyper::Body>, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let parse_block = |name: &str| -> Result<u64, ApiError> {
+            params.get(name)
+                .ok_or_else(|| ApiError::bad_request(format!("Missing {} parameter", name)))?
+                .parse()
+                .map_err(|_| ApiError::bad_request(format!("Invalid {} format", name)))
+        };
+        let from_block = parse_block("fromBlock")?;
+        let to_block = parse_block("toBlock")?;
+
+        let diff = db.state_diff(vida_id, from_block, to_block).map_err(|e| match e {
+            MerkleTreeError::InvalidArgument(message) => ApiError::bad_request(message),
+            e => ApiError::database(e),
+        })?;
+        let chunks: Vec<Result<String, Infallible>> = diff.chunks(STATE_DIFF_CHUNK_SIZE)
+            .map(|entries| {
+                Ok(entries.iter()
+                    .map(|entry| serde_json::to_string(entry).unwrap_or_default() + "\n")
+                    .collect())
+            })
+            .collect();
+        warp::http::Response::builder()
+            .header("content-type", "application/x-ndjson")
+            .body(warp::hyper::Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .map_err(|e| ApiError::internal(format!("Failed to build response: {}", e)))
+    }
+
+    fn handle_balance(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let address = Self::parse_address(&params)?;
+        let token_id = Self::parse_token_id(&params)?;
+        let decimals = state.read().unwrap().config.token_decimals;
+
+        // With blockNumber, answer from the balance history instead of the latest state
+        if let Some(block_number) = params.get("blockNumber") {
+            let block: u64 = block_number.parse()
+                .map_err(|_| ApiError::bad_request("Invalid block number format"))?;
+            let balance = db.get_balance_at(vida_id, token_id, &address, block)
+                .map_err(ApiError::database)?
+                .ok_or_else(|| ApiError::not_found("No balance history recorded for address"))?;
+            return Ok(json!({
+                "vidaId": vida_id,
+                "address": format!("0x{}", hex::encode(&address)),
+                "tokenId": token_id,
+                "balance": balance.to_string(),
+                "formattedBalance": amount::format(&balance, decimals),
+                "decimals": decimals,
+                "block": block
+            }));
+        }
+
+        let balance = db.get_balance(vida_id, token_id, &address)
+            .map_err(ApiError::database)?;
+        let block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "address": format!("0x{}", hex::encode(&address)),
+            "tokenId": token_id,
+            "balance": balance.to_string(),
+            "formattedBalance": amount::format(&balance, decimals),
+            "decimals": decimals,
+            "block": block
+        }))
+    }
+
+    fn handle_peers(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let query = ListQuery::parse(&params, Order::Asc)?;
+        let peers = state.read().unwrap().peers.statuses();
+        let page = query.page(peers, |peer| peer.address.clone(), |_| None)?;
+
+        let mut response = query.describe(&page);
+        response["peers"] = json!(page.items);
+        Ok(response)
+    }
+
+    fn handle_misbehavior(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let reports: Vec<_> = db.get_misbehavior_reports()
+            .map_err(ApiError::database)?
+            .into_iter()
+            .filter(|report| report.vida_id == vida_id)
+            .filter(|report| params.get("peer").map_or(true, |peer| report.peer == *peer))
+            .collect();
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "total": reports.len(),
+            "reports": reports
+        }))
+    }
+
+    fn handle_failed_transactions(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let transactions = db.get_failed_transactions(vida_id)
+            .map_err(ApiError::database)?;
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "total": transactions.len(),
+            "transactions": transactions
+        }))
+    }
+
+    fn handle_escrows(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let escrows: Vec<Value> = db.pending_escrows(vida_id)
+            .map_err(ApiError::database)?
+            .into_iter()
+            .map(|(id, escrow)| {
+                let mut entry = serde_json::to_value(escrow).unwrap_or_default();
+                entry["id"] = json!(id);
+                entry
+            })
+            .collect();
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "total": escrows.len(),
+            "escrows": escrows
+        }))
+    }
+
+    fn handle_vesting(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let address = Self::parse_address(&params)?;
+        let schedules: Vec<Value> = db.get_vesting_schedules(vida_id, &address)
+            .map_err(ApiError::database)?
+            .into_iter()
+            .map(|(id, schedule)| {
+                let mut entry = serde_json::to_value(schedule).unwrap_or_default();
+                entry["id"] = json!(id);
+                entry
+            })
+            .collect();
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "address": format!("0x{}", hex::encode(&address)),
+            "schedules": schedules
+        }))
+    }
+
+    fn handle_proposals(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let policy = db.get_governance_policy(vida_id)
+            .map_err(ApiError::database)?;
+        let proposals: Vec<Value> = db.get_proposals(vida_id)
+            .map_err(ApiError::database)?
+            .into_iter()
+            .map(|(id, proposal)| {
+                let mut entry = serde_json::to_value(proposal).unwrap_or_default();
+                entry["id"] = json!(id);
+                entry
+            })
+            .collect();
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "policy": policy,
+            "proposals": proposals
+        }))
+    }
+
+    fn handle_multisig(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let address = Self::parse_address(&params)?;
+        let multisig = db.get_multisig(vida_id, &address)
+            .map_err(ApiError::database)?
+            .ok_or_else(|| ApiError::not_found("Address is not a multisig account"))?;
+        let transactions: Vec<Value> = db.get_multisig_transactions(vida_id, &address)
+            .map_err(ApiError::database)?
+            .into_iter()
+            .map(|(id, transaction)| {
+                let mut entry = serde_json::to_value(transaction).unwrap_or_default();
+                entry["id"] = json!(id);
+                entry
+            })
+            .collect();
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "address": format!("0x{}", hex::encode(&address)),
+            "owners": multisig.owners,
+            "threshold": multisig.threshold,
+            "transactions": transactions
+        }))
+    }
+
+    fn handle_account_status(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let address = Self::parse_address(&params)?;
+        let frozen = db.is_frozen(vida_id, &address)
+            .map_err(ApiError::database)?;
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "address": format!("0x{}", hex::encode(&address)),
+            "frozen": frozen
+        }))
+    }
+
+    fn handle_receipts(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let query = ListQuery::parse(&params, Order::Desc)?;
+        let from_block = query.from_block
+            .ok_or_else(|| ApiError::bad_request("Missing fromBlock parameter"))?;
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+        let to_block = query.to_block.unwrap_or(last_checked_block).min(last_checked_block);
+        if to_block.saturating_sub(from_block) >= MAX_RECEIPT_BLOCKS {
+            return Err(ApiError::bad_request(format!("A request may span at most {} blocks", MAX_RECEIPT_BLOCKS)));
+        }
+
+        // Read block by block from the cursor's block on; the page stops reading once full
+        let cursor = query.cursor::<(u64, u32)>()?.map(|(block_number, _)| block_number);
+        let blocks: Box<dyn Iterator<Item = u64>> = match query.order {
+            Order::Asc => Box::new(cursor.unwrap_or(from_block).max(from_block)..=to_block),
+            Order::Desc => Box::new((from_block..=cursor.unwrap_or(to_block).min(to_block)).rev()),
+        };
+        let receipts = blocks.flat_map(|block_number| match Self::block_receipts(&db, vida_id, block_number) {
+            Ok(mut receipts) => {
+                if query.order == Order::Desc {
+                    receipts.reverse();
+                }
+                receipts.into_iter().map(Ok).collect()
+            }
+            Err(e) => vec![Err(e)],
+        });
+        let page = query.collect(receipts, |receipt| (receipt.block_number, receipt.index), |receipt| Some(receipt.block_number))?;
+
+        let mut response = query.describe(&page);
+        response["vidaId"] = json!(vida_id);
+        response["receipts"] = json!(page.items);
+        Ok(response)
+    }
+
+    // Returns the receipts of a block in the order its transactions were applied
+    fn block_receipts(db: &DatabaseService, vida_id: u64, block_number: u64) -> Result<Vec<Receipt>, ApiError> {
+        let mut receipts = Vec::new();
+        while let Some(receipt) = db.get_receipt_at(vida_id, block_number, receipts.len() as u32).map_err(ApiError::database)? {
+            receipts.push(receipt);
         }
+        Ok(receipts)
+    }
+
+    // Returns the root of a block with the receipts of its transactions, which
+    // name each transaction's sender, action and outcome
+    fn handle_block(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let block_number: u64 = params.get("number")
+            .ok_or_else(|| ApiError::bad_request("Missing number parameter"))?
+            .parse()
+            .map_err(|_| ApiError::bad_request("Invalid block number format"))?;
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+        if block_number > last_checked_block {
+            return Err(ApiError::not_found(format!("Block {} has not been processed yet", block_number)));
+        }
+
+        let root_hash = db.get_block_root_hash(vida_id, block_number)
+            .map_err(ApiError::database)?;
+        let transactions = Self::block_receipts(&db, vida_id, block_number)?;
+        Ok(json!({
+            "vidaId": vida_id,
+            "blockNumber": block_number,
+            "rootHash": root_hash.map(hex::encode),
+            "transactionCount": transactions.len(),
+            "transactions": transactions
+        }))
+    }
+
+    fn handle_receipt(params: HashMap<String, String>, state: &SharedState) -> Result<Receipt, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let tx_hash = params.get("txHash")
+            .ok_or_else(|| ApiError::bad_request("Missing txHash parameter"))?;
+        let hash = hex::decode(tx_hash.trim_start_matches("0x"))
+            .map_err(|_| ApiError::bad_request("Invalid txHash format"))?;
+
+        db.get_receipt(vida_id, &hash)
+            .map_err(ApiError::database)?
+            .ok_or_else(|| ApiError::not_found(format!("No receipt for transaction {}", tx_hash)))
+    }
+
+    fn handle_changes(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let block_number: u64 = params.get("blockNumber")
+            .ok_or_else(|| ApiError::bad_request("Missing blockNumber parameter"))?
+            .parse()
+            .map_err(|_| ApiError::bad_request("Invalid block number format"))?;
+
+        let changes = db.get_changes(vida_id, block_number)
+            .map_err(ApiError::database)?;
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "blockNumber": block_number,
+            "changes": changes
+        }))
+    }
+
+    fn handle_allowance(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let owner = Self::parse_hex_param(&params, "owner")?;
+        let spender = Self::parse_hex_param(&params, "spender")?;
+        let token_id = Self::parse_token_id(&params)?;
+
+        let allowance = db.get_allowance(vida_id, token_id, &owner, &spender)
+            .map_err(ApiError::database)?;
+        let block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "owner": format!("0x{}", hex::encode(&owner)),
+            "spender": format!("0x{}", hex::encode(&spender)),
+            "tokenId": token_id,
+            "allowance": allowance.to_string(),
+            "block": block
+        }))
+    }
+
+    fn handle_supply(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let token_id = Self::parse_token_id(&params)?;
+        let block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+
+        if params.get("audit").map_or(false, |audit| audit == "true") {
+            let audit = db.audit_supply(vida_id, token_id)
+                .map_err(ApiError::database)?;
+            return Ok(json!({ "vidaId": vida_id, "tokenId": token_id, "block": block, "audit": audit }));
+        }
+
+        let total_supply = db.get_total_supply(vida_id, token_id)
+            .map_err(ApiError::database)?;
+        let decimals = state.read().unwrap().config.token_decimals;
+        Ok(json!({
+            "vidaId": vida_id,
+            "tokenId": token_id,
+            "totalSupply": total_supply.as_ref().map(|supply| supply.to_string()),
+            "formattedTotalSupply": total_supply.as_ref().map(|supply| amount::format(supply, decimals)),
+            "decimals": decimals,
+            "block": block
+        }))
+    }
+
+    fn handle_holders(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let token_id = Self::parse_token_id(&params)?;
+        let limit = match params.get("limit") {
+            Some(limit) => limit.parse()
+                .ok()
+                .filter(|limit| (1..=MAX_HOLDERS_LIMIT).contains(limit))
+                .ok_or_else(|| ApiError::bad_request(format!("limit must be between 1 and {}", MAX_HOLDERS_LIMIT)))?,
+            None => DEFAULT_HOLDERS_LIMIT,
+        };
+        let block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+        let holders = db.top_holders(vida_id, token_id, limit)
+            .map_err(ApiError::database)?;
+
+        // Shares are taken of the recorded supply, or of all balances for untracked databases
+        let total_supply = db.get_total_supply(vida_id, token_id)
+            .map_err(ApiError::database)?
+            .unwrap_or(holders.balance_sum);
+        let decimals = state.read().unwrap().config.token_decimals;
+        let top: Vec<Value> = holders.top.iter()
+            .map(|(address, balance)| json!({
+                "address": format!("0x{}", hex::encode(address)),
+                "balance": balance.to_string(),
+                "formattedBalance": amount::format(balance, decimals),
+                "share": Self::share(balance, &total_supply)
+            }))
+            .collect();
+        Ok(json!({
+            "vidaId": vida_id,
+            "tokenId": token_id,
+            "block": block,
+            "totalSupply": total_supply.to_string(),
+            "formattedTotalSupply": amount::format(&total_supply, decimals),
+            "decimals": decimals,
+            "holders": holders.holders,
+            "top": top
+        }))
+    }
+
+    // Renders `part / whole` as a decimal fraction with six digits, "0.000000" for no supply
+    fn share(part: &BigUint, whole: &BigUint) -> String {
+        if *whole == BigUint::from(0u32) {
+            return "0.000000".to_string();
+        }
+        let millionths = part * BigUint::from(1_000_000u32) / whole;
+        let integer = &millionths / BigUint::from(1_000_000u32);
+        let fraction = &millionths % BigUint::from(1_000_000u32);
+        format!("{}.{:0>6}", integer, fraction.to_string())
+    }
+
+    fn handle_stats(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+        let stats = db.stats(vida_id)
+            .map_err(ApiError::database)?;
+        Ok(json!({ "vidaId": vida_id, "block": block, "stats": stats }))
+    }
+
+    fn handle_status(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(ApiError::database)?;
+
+        let state = state.read().unwrap();
+        let latest_block = state.sync.latest_chain_block();
+        let peers = state.peers.statuses();
+        let quarantined = peers.iter().filter(|peer| peer.quarantined).count();
+        Ok(json!({
+            "vidaId": vida_id,
+            "lastCheckedBlock": last_checked_block,
+            "latestBlock": latest_block,
+            "lag": latest_block.map(|latest| latest.saturating_sub(last_checked_block)),
+            "blocksPerMinute": state.sync.blocks_per_minute(vida_id),
+            "live": state.sync.is_live(vida_id),
+            "catchUp": state.sync.catch_up_progress(vida_id, last_checked_block),
+            "lastValidation": state.sync.last_validation(vida_id),
+            "syncPaused": handler::is_sync_paused(),
+            "peers": {
+                "total": peers.len(),
+                "active": peers.len() - quarantined,
+                "quarantined": quarantined
+            }
+        }))
+    }
+
+    fn handle_transactions(params: HashMap<String, String>, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = Self::parse_vida_id(&params, state)?;
+        let db = state.read().unwrap().db.clone();
+        let address = Self::parse_address(&params)?;
+        let query = ListQuery::parse(&params, Order::Desc)?;
+        let total = db.get_transaction_count(vida_id, &address)
+            .map_err(ApiError::database)?;
+
+        // History records are stored by position, oldest first; read from the cursor on
+        let cursor = query.cursor::<u64>()?;
+        let positions: Box<dyn Iterator<Item = u64>> = match query.order {
+            Order::Asc => Box::new(cursor.map_or(0, |index| index.saturating_add(1))..total),
+            Order::Desc => Box::new((0..cursor.unwrap_or(total).min(total)).rev()),
+        };
+        let records = positions.filter_map(|index| match db.get_transaction(vida_id, &address, index) {
+            Ok(Some(record)) => Some(Ok((index, record))),
+            Ok(None) => None,
+            Err(e) => Some(Err(ApiError::database(e))),
+        });
+        let page = query.collect(records, |(index, _)| *index, |(_, record)| Some(record.block_number))?;
+
+        let mut response = query.describe(&page);
+        response["vidaId"] = json!(vida_id);
+        response["address"] = json!(format!("0x{}", hex::encode(&address)));
+        response["total"] = json!(total);
+        response["transactions"] = json!(page.items.iter().map(|(_, record)| record).collect::<Vec<_>>());
+        Ok(response)
+    }
+
+    // Makes the shared application state available to a route
+    fn with_state(state: SharedState) -> impl Filter<Extract = (SharedState,), Error = Infallible> + Clone {
+        warp::any().map(move || state.clone())
+    }
+
+    // Reads the optional `vidaId` query parameter, defaulting to the primary VIDA
+    fn parse_vida_id(params: &HashMap<String, String>, state: &SharedState) -> Result<u64, ApiError> {
+        match params.get("vidaId") {
+            Some(vida_id) => {
+                let vida_id: u64 = vida_id.parse()
+                    .map_err(|_| ApiError::bad_request("Invalid vidaId format"))?;
+                if state.read().unwrap().config.vida(vida_id).is_none() {
+                    return Err(ApiError::not_found(format!("VIDA {} is not synced by this node", vida_id)));
+                }
+                Ok(vida_id)
+            }
+            None => Ok(state.read().unwrap().config.vida_id)
+        }
+    }
+
+    // Reads the optional `tokenId` query parameter, defaulting to the default token
+    fn parse_token_id(params: &HashMap<String, String>) -> Result<u64, ApiError> {
+        match params.get("tokenId") {
+            Some(token_id) => token_id.parse().map_err(|_| ApiError::bad_request("Invalid tokenId format")),
+            None => Ok(DEFAULT_TOKEN),
+        }
+    }
+
+    // Decodes the hex `address` query parameter, with or without a 0x prefix
+    fn parse_address(params: &HashMap<String, String>) -> Result<Vec<u8>, ApiError> {
+        Self::parse_hex_param(params, "address")
+    }
+
+    // Decodes a hex address query parameter, with or without a 0x prefix and
+    // checksummed or not
+    fn parse_hex_param(params: &HashMap<String, String>, name: &str) -> Result<Vec<u8>, ApiError> {
+        let address_str = params.get(name)
+            .ok_or_else(|| ApiError::bad_request(format!("Missing {} parameter", name)))?;
+        address::parse_address(address_str)
+            .map_err(|reason| ApiError::bad_request(format!("Invalid {} format: {}", name, reason)))
     }
 }
@@ -0,0 +1,506 @@
+// warp builds the routes in `GET::run` and friends; the functions here only
+// carry their OpenAPI description and are never called
+#![allow(dead_code)]
+
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use super::admin::{PeerRequest, ReprocessRequest, RevalidateRequest, WebhookRequest};
+use super::simulate::SimulateRequest;
+use crate::gossip::Attestation;
+
+/// OpenAPI description of the `/v1` HTTP API, served at `/v1/openapi.json`
+/// so clients can be generated instead of written by hand. JSON replies
+/// without a fixed shape are described as plain objects.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "PWR Stateful VIDA", description = "State, root hashes and sync status of the VIDAs synced by a node."),
+    paths(
+        root_hash, root_hashes, genesis_hash, balance, transactions, receipts, allowance, supply, holders, stats, status, peers, misbehavior,
+        failed_transactions, changes, escrows, vesting, proposals, multisig, account_status, receipt, block,
+        state_export, state_chunks, state_diff, simulate, attestations,
+        admin_peers, admin_add_peer, admin_remove_peer, admin_pause, admin_resume, admin_flush,
+        admin_revalidate, admin_reprocess, admin_reload, admin_state_export, admin_webhooks, admin_add_webhook, admin_remove_webhook,
+        admin_diagnostics, admin_diagnostic,
+    ),
+    components(schemas(
+        ErrorResponse, SimulateRequest, Attestation, PeerRequest, RevalidateRequest, ReprocessRequest, WebhookRequest,
+    )),
+    modifiers(&AdminToken),
+    tags(
+        (name = "state", description = "Balances, history and other VIDA state"),
+        (name = "sync", description = "Root hashes, snapshots and sync status used by peers and operators"),
+        (name = "admin", description = "Token-protected node management"),
+    )
+)]
+pub struct ApiDoc;
+
+// Bearer token scheme of the /admin endpoints
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "adminToken",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+// JSON body of every error response, see `error::handle_rejection`
+#[derive(ToSchema)]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+#[utoipa::path(
+    get, path = "/v1/rootHash", tag = "sync",
+    params(
+        ("blockNumber" = u64, Query, description = "Checked block whose root to return"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Hex root hash of the block", body = String, content_type = "text/plain",
+            headers(("X-Root-Signature" = String, description = "Node signature over the block number and root"))),
+        (status = 400, description = "Missing or invalid block number", body = ErrorResponse),
+        (status = 404, description = "Block not processed yet or no root recorded", body = ErrorResponse),
+    )
+)]
+fn root_hash() {}
+
+#[utoipa::path(
+    get, path = "/v1/rootHashes", tag = "sync",
+    params(
+        ("fromBlock" = u64, Query, description = "First block"),
+        ("toBlock" = u64, Query, description = "Last block, at most 999 blocks after fromBlock"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Hex roots and node signatures by block number, for the checked blocks of the range that have one", body = Object),
+        (status = 400, description = "Missing or invalid range", body = ErrorResponse),
+    )
+)]
+fn root_hashes() {}
+
+#[utoipa::path(
+    get, path = "/v1/genesisHash", tag = "sync",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses(
+        (status = 200, description = "Hex hash of the applied genesis", body = String, content_type = "text/plain"),
+        (status = 404, description = "No genesis recorded", body = ErrorResponse),
+    )
+)]
+fn genesis_hash() {}
+
+#[utoipa::path(
+    get, path = "/v1/balance", tag = "state",
+    params(
+        ("address" = String, Query, description = "Hex account address"),
+        ("blockNumber" = Option<u64>, Query, description = "Past block to read the balance at"),
+        ("tokenId" = Option<u64>, Query, description = "Token, the native one by default"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Balance of the account in base units, and formatted with the token decimals", body = Object),
+        (status = 400, description = "Invalid address or block", body = ErrorResponse),
+    )
+)]
+fn balance() {}
+
+#[utoipa::path(
+    get, path = "/v1/transactions", tag = "state",
+    params(
+        ("address" = String, Query, description = "Hex account address"),
+        ("cursor" = Option<String>, Query, description = "nextCursor of the previous page"),
+        ("limit" = Option<u32>, Query, description = "Items per page, 20 by default and at most 100"),
+        ("order" = Option<String>, Query, description = "asc or desc"),
+        ("fromBlock" = Option<u64>, Query, description = "Only records of this block or later"),
+        ("toBlock" = Option<u64>, Query, description = "Only records of this block or earlier"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "One page of the account's transaction history, newest first by default", body = Object),
+        (status = 400, description = "Invalid address, cursor or range", body = ErrorResponse),
+    )
+)]
+fn transactions() {}
+
+#[utoipa::path(
+    get, path = "/v1/receipts", tag = "state",
+    params(
+        ("fromBlock" = u64, Query, description = "First block"),
+        ("toBlock" = Option<u64>, Query, description = "Last block, the last checked one by default; at most 10000 blocks after fromBlock"),
+        ("cursor" = Option<String>, Query, description = "nextCursor of the previous page"),
+        ("limit" = Option<u32>, Query, description = "Items per page, 20 by default and at most 100"),
+        ("order" = Option<String>, Query, description = "asc or desc"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "One page of the receipts of the blocks, newest first by default", body = Object),
+        (status = 400, description = "Missing or invalid range or cursor", body = ErrorResponse),
+    )
+)]
+fn receipts() {}
+
+#[utoipa::path(
+    get, path = "/v1/allowance", tag = "state",
+    params(
+        ("owner" = String, Query, description = "Hex address of the approving account"),
+        ("spender" = String, Query, description = "Hex address allowed to spend"),
+        ("tokenId" = Option<u64>, Query, description = "Token, the native one by default"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Amount of the token approved for transferFrom", body = Object),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+    )
+)]
+fn allowance() {}
+
+#[utoipa::path(
+    get, path = "/v1/supply", tag = "state",
+    params(
+        ("audit" = Option<bool>, Query, description = "Also check the supply against all balances"),
+        ("tokenId" = Option<u64>, Query, description = "Token, the native one by default"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses((status = 200, description = "Total supply in base units and formatted, with the audit result if requested", body = Object))
+)]
+fn supply() {}
+
+#[utoipa::path(
+    get, path = "/v1/holders", tag = "state",
+    params(
+        ("limit" = Option<usize>, Query, description = "Holders to list, 100 by default and at most 1000"),
+        ("tokenId" = Option<u64>, Query, description = "Token, the native one by default"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Largest holders with their balance and share of supply", body = Object),
+        (status = 400, description = "Invalid limit", body = ErrorResponse),
+    )
+)]
+fn holders() {}
+
+#[utoipa::path(
+    get, path = "/v1/stats", tag = "state",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Account, transfer and state key counts, the last flush time and flush durations", body = Object))
+)]
+fn stats() {}
+
+#[utoipa::path(
+    get, path = "/v1/status", tag = "sync",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Sync progress, lag behind the chain with its catch-up ETA, and peer health", body = Object))
+)]
+fn status() {}
+
+#[utoipa::path(
+    get, path = "/v1/peers", tag = "sync",
+    params(
+        ("cursor" = Option<String>, Query, description = "nextCursor of the previous page"),
+        ("limit" = Option<u32>, Query, description = "Items per page, 20 by default and at most 100"),
+        ("order" = Option<String>, Query, description = "asc or desc"),
+    ),
+    responses((status = 200, description = "Health and reputation of known peers, by address", body = Object))
+)]
+fn peers() {}
+
+#[utoipa::path(
+    get, path = "/v1/misbehavior", tag = "sync",
+    params(
+        ("peer" = Option<String>, Query, description = "Only the reports about this peer"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses((status = 200, description = "Peers that reported roots conflicting with a quorum", body = Object))
+)]
+fn misbehavior() {}
+
+#[utoipa::path(
+    get, path = "/v1/failed-transactions", tag = "state",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Transactions that could not be applied", body = Object))
+)]
+fn failed_transactions() {}
+
+#[utoipa::path(
+    get, path = "/v1/changes", tag = "state",
+    params(
+        ("blockNumber" = u64, Query, description = "Block whose state changes to return"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Journal of the state changes applied in the block", body = Object),
+        (status = 400, description = "Missing or invalid block number", body = ErrorResponse),
+    )
+)]
+fn changes() {}
+
+#[utoipa::path(
+    get, path = "/v1/escrows", tag = "state",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Pending escrows", body = Object))
+)]
+fn escrows() {}
+
+#[utoipa::path(
+    get, path = "/v1/vesting", tag = "state",
+    params(
+        ("address" = String, Query, description = "Hex account address"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses((status = 200, description = "Vesting schedules of the account", body = Object))
+)]
+fn vesting() {}
+
+#[utoipa::path(
+    get, path = "/v1/proposals", tag = "state",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Governance proposals, their votes and the policy deciding them", body = Object))
+)]
+fn proposals() {}
+
+#[utoipa::path(
+    get, path = "/v1/multisig", tag = "state",
+    params(
+        ("address" = String, Query, description = "Hex address of the multisig account"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Owners, threshold and transactions of the multisig account", body = Object),
+        (status = 404, description = "No multisig account at the address", body = ErrorResponse),
+    )
+)]
+fn multisig() {}
+
+#[utoipa::path(
+    get, path = "/v1/account-status", tag = "state",
+    params(
+        ("address" = String, Query, description = "Hex account address"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses((status = 200, description = "Whether the account is frozen", body = Object))
+)]
+fn account_status() {}
+
+#[utoipa::path(
+    get, path = "/v1/receipt", tag = "state",
+    params(
+        ("txHash" = String, Query, description = "Hex transaction hash"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Outcome of the transaction", body = Object),
+        (status = 404, description = "Unknown transaction", body = ErrorResponse),
+    )
+)]
+fn receipt() {}
+
+#[utoipa::path(
+    get, path = "/v1/block", tag = "state",
+    params(
+        ("number" = u64, Query, description = "Block number"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Root hash, transaction count and the receipts of the block's transactions in order", body = Object),
+        (status = 404, description = "Block not processed yet", body = ErrorResponse),
+    )
+)]
+fn block() {}
+
+#[utoipa::path(
+    get, path = "/v1/state/export", tag = "sync",
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses(
+        (status = 200, description = "Full state snapshot at the last checked block", body = Object),
+        (status = 503, description = "A block is being applied; retry later", body = ErrorResponse),
+    )
+)]
+fn state_export() {}
+
+#[utoipa::path(
+    get, path = "/v1/state/chunks", tag = "sync",
+    params(
+        ("blockNumber" = Option<u64>, Query, description = "Block the state is pinned at, the last checked one by default"),
+        ("start" = Option<u64>, Query, description = "Index of the first entry"),
+        ("count" = Option<u64>, Query, description = "Number of entries"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Chunk of state entries with a proof against the state commitment", body = Object),
+        (status = 409, description = "The block is no longer served", body = ErrorResponse),
+        (status = 503, description = "A block is being applied; retry later", body = ErrorResponse),
+    )
+)]
+fn state_chunks() {}
+
+#[utoipa::path(
+    get, path = "/v1/state-diff", tag = "sync",
+    params(
+        ("fromBlock" = u64, Query, description = "First committed block"),
+        ("toBlock" = u64, Query, description = "Last committed block"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Every key changed between the blocks, one JSON object per line",
+            body = String, content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid block range", body = ErrorResponse),
+    )
+)]
+fn state_diff() {}
+
+#[utoipa::path(
+    post, path = "/v1/simulate", tag = "state",
+    request_body = SimulateRequest,
+    responses(
+        (status = 200, description = "Whether the action would succeed and the balances it would leave", body = Object),
+        (status = 400, description = "Invalid sender or payload", body = ErrorResponse),
+    )
+)]
+fn simulate() {}
+
+#[utoipa::path(
+    post, path = "/v1/attestations", tag = "sync",
+    request_body = Attestation,
+    responses(
+        (status = 200, description = "Attestation recorded", body = Object),
+        (status = 403, description = "Unknown peer or invalid signature", body = ErrorResponse),
+        (status = 404, description = "Root gossip is disabled", body = ErrorResponse),
+    )
+)]
+fn attestations() {}
+
+#[utoipa::path(
+    get, path = "/v1/admin/peers", tag = "admin", security(("adminToken" = [])),
+    responses((status = 200, description = "Known peers with their health", body = Object))
+)]
+fn admin_peers() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/peers", tag = "admin", security(("adminToken" = [])),
+    request_body = PeerRequest,
+    responses(
+        (status = 200, description = "Peer added", body = Object),
+        (status = 409, description = "Peer already known", body = ErrorResponse),
+    )
+)]
+fn admin_add_peer() {}
+
+#[utoipa::path(
+    delete, path = "/v1/admin/peers/{peer}", tag = "admin", security(("adminToken" = [])),
+    params(("peer" = String, Path, description = "Peer as host:port")),
+    responses(
+        (status = 200, description = "Peer removed", body = Object),
+        (status = 409, description = "Unknown peer", body = ErrorResponse),
+    )
+)]
+fn admin_remove_peer() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/sync/pause", tag = "admin", security(("adminToken" = [])),
+    responses((status = 200, description = "Syncing paused", body = Object))
+)]
+fn admin_pause() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/sync/resume", tag = "admin", security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "Syncing resumed", body = Object),
+        (status = 500, description = "The subscriptions could not be restarted", body = ErrorResponse),
+    )
+)]
+fn admin_resume() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/flush", tag = "admin", security(("adminToken" = [])),
+    responses((status = 200, description = "Committed state flushed to disk", body = Object))
+)]
+fn admin_flush() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/config/reload", tag = "admin", security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "Configuration reloaded, with the settings that changed", body = Object),
+        (status = 400, description = "The file is invalid or changes a setting that needs a restart", body = ErrorResponse),
+    )
+)]
+fn admin_reload() {}
+
+#[utoipa::path(
+    get, path = "/v1/admin/state/export", tag = "admin", security(("adminToken" = [])),
+    params(
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+        ("tokenId" = Option<u64>, Query, description = "Only balances of this token"),
+    ),
+    responses(
+        (status = 200, description = "Every non-zero balance, one {tokenId, address, balance} object per line",
+            body = String, content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid tokenId", body = ErrorResponse),
+    )
+)]
+fn admin_state_export() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/revalidate", tag = "admin", security(("adminToken" = [])),
+    request_body = RevalidateRequest,
+    responses((status = 200, description = "Whether peers agree with the block's root", body = Object))
+)]
+fn admin_revalidate() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/failed-transactions/reprocess", tag = "admin", security(("adminToken" = [])),
+    request_body = ReprocessRequest,
+    responses((status = 200, description = "Number of transactions that would now apply and that still fail; nothing is applied", body = Object))
+)]
+fn admin_reprocess() {}
+
+#[utoipa::path(
+    get, path = "/v1/admin/webhooks", tag = "admin", security(("adminToken" = [])),
+    responses((status = 200, description = "Registered webhooks", body = Object))
+)]
+fn admin_webhooks() {}
+
+#[utoipa::path(
+    post, path = "/v1/admin/webhooks", tag = "admin", security(("adminToken" = [])),
+    request_body = WebhookRequest,
+    responses(
+        (status = 200, description = "The registered webhook", body = Object),
+        (status = 400, description = "Invalid URL, address or secret", body = ErrorResponse),
+    )
+)]
+fn admin_add_webhook() {}
+
+#[utoipa::path(
+    delete, path = "/v1/admin/webhooks/{id}", tag = "admin", security(("adminToken" = [])),
+    params(("id" = u64, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "Webhook removed", body = Object),
+        (status = 409, description = "Unknown webhook", body = ErrorResponse),
+    )
+)]
+fn admin_remove_webhook() {}
+
+#[utoipa::path(
+    get, path = "/v1/admin/diagnostics", tag = "admin", security(("adminToken" = [])),
+    params(("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default")),
+    responses((status = 200, description = "Blocks with a saved root mismatch diagnostics bundle, oldest first", body = Object))
+)]
+fn admin_diagnostics() {}
+
+#[utoipa::path(
+    get, path = "/v1/admin/diagnostics/{block}", tag = "admin", security(("adminToken" = [])),
+    params(
+        ("block" = u64, Path, description = "Block whose root peers did not agree on"),
+        ("vidaId" = Option<u64>, Query, description = "VIDA, the primary one by default"),
+    ),
+    responses(
+        (status = 200, description = "Local and peer roots, receipts and state changes of the block", body = Object),
+        (status = 404, description = "No bundle saved for the block", body = ErrorResponse),
+    )
+)]
+fn admin_diagnostic() {}
@@ -0,0 +1,188 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use serde_json::{json, Value};
+
+use super::ApiError;
+
+// Items per page when the request does not set `limit`, and the most it may ask for
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+/// Direction a list endpoint returns its items in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// Key a list is sorted by, which also marks where the next page starts.
+pub trait CursorKey: Ord + Sized {
+    fn to_cursor(&self) -> String;
+    fn from_cursor(cursor: &str) -> Option<Self>;
+}
+
+impl CursorKey for u64 {
+    fn to_cursor(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_cursor(cursor: &str) -> Option<Self> {
+        cursor.parse().ok()
+    }
+}
+
+impl CursorKey for (u64, u32) {
+    fn to_cursor(&self) -> String {
+        format!("{}:{}", self.0, self.1)
+    }
+
+    fn from_cursor(cursor: &str) -> Option<Self> {
+        let (first, second) = cursor.split_once(':')?;
+        Some((first.parse().ok()?, second.parse().ok()?))
+    }
+}
+
+impl CursorKey for String {
+    fn to_cursor(&self) -> String {
+        self.clone()
+    }
+
+    fn from_cursor(cursor: &str) -> Option<Self> {
+        Some(cursor.to_string())
+    }
+}
+
+/// Paging, block range and sort order of a list request, read from the
+/// `cursor`, `limit`, `fromBlock`, `toBlock` and `order` (`asc` or `desc`)
+/// query parameters. Cursors are opaque: clients pass back the `nextCursor`
+/// of the previous page, which stays valid while new items are added.
+#[derive(Debug, Clone)]
+pub struct ListQuery {
+    cursor: Option<String>,
+    pub limit: usize,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub order: Order,
+}
+
+/// One page of a list and the cursor of the next one, if the page is full.
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl ListQuery {
+    /// Reads the list parameters, using `default_order` when `order` is not given.
+    pub fn parse(params: &HashMap<String, String>, default_order: Order) -> Result<Self, ApiError> {
+        let parse_block = |name: &str| -> Result<Option<u64>, ApiError> {
+            params.get(name)
+                .map(|value| value.parse().map_err(|_| ApiError::bad_request(format!("Invalid {} format", name))))
+                .transpose()
+        };
+        let limit = match params.get("limit") {
+            Some(limit) => limit.parse()
+                .ok()
+                .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                .ok_or_else(|| ApiError::bad_request(format!("limit must be between 1 and {}", MAX_LIMIT)))?,
+            None => DEFAULT_LIMIT,
+        };
+        let order = match params.get("order").map(String::as_str) {
+            Some("asc") => Order::Asc,
+            Some("desc") => Order::Desc,
+            Some(other) => return Err(ApiError::bad_request(format!("Invalid order {}, expected asc or desc", other))),
+            None => default_order,
+        };
+        let cursor = params.get("cursor")
+            .map(|cursor| {
+                hex::decode(cursor)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .ok_or_else(|| ApiError::bad_request("Invalid cursor"))
+            })
+            .transpose()?;
+        let query = Self { cursor, limit, from_block: parse_block("fromBlock")?, to_block: parse_block("toBlock")?, order };
+        if let (Some(from_block), Some(to_block)) = (query.from_block, query.to_block) {
+            if from_block > to_block {
+                return Err(ApiError::bad_request("fromBlock must not be after toBlock"));
+            }
+        }
+        Ok(query)
+    }
+
+    /// Returns the key the cursor points at, the last item of the previous page.
+    pub fn cursor<K: CursorKey>(&self) -> Result<Option<K>, ApiError> {
+        self.cursor.as_deref()
+            .map(|cursor| K::from_cursor(cursor).ok_or_else(|| ApiError::bad_request("Invalid cursor")))
+            .transpose()
+    }
+
+    /// Whether a block lies within the requested range.
+    pub fn contains_block(&self, block_number: u64) -> bool {
+        self.from_block.map_or(true, |from_block| block_number >= from_block)
+            && self.to_block.map_or(true, |to_block| block_number <= to_block)
+    }
+
+    /// Sorts `items` by `key` in the requested order and cuts the page after
+    /// the cursor, skipping items outside the block range. Items without a
+    /// block are never filtered out.
+    pub fn page<T, K: CursorKey>(
+        &self,
+        mut items: Vec<T>,
+        key: impl Fn(&T) -> K,
+        block_number: impl Fn(&T) -> Option<u64>,
+    ) -> Result<Page<T>, ApiError> {
+        items.sort_by(|a, b| self.compare(&key(a), &key(b)));
+        self.collect(items.into_iter().map(Ok), key, block_number)
+    }
+
+    /// Cuts the page from `items`, which must come in the requested order and
+    /// may be produced lazily; items up to the cursor are skipped, so the
+    /// source only needs to start near it. Stops reading once the page is full.
+    pub fn collect<T, K: CursorKey>(
+        &self,
+        items: impl Iterator<Item = Result<T, ApiError>>,
+        key: impl Fn(&T) -> K,
+        block_number: impl Fn(&T) -> Option<u64>,
+    ) -> Result<Page<T>, ApiError> {
+        let cursor = self.cursor::<K>()?;
+        let mut page = Vec::new();
+        for item in items {
+            let item = item?;
+            if let Some(cursor) = &cursor {
+                if self.compare(&key(&item), cursor) != Ordering::Greater {
+                    continue;
+                }
+            }
+            if !block_number(&item).map_or(true, |block_number| self.contains_block(block_number)) {
+                continue;
+            }
+            page.push(item);
+            if page.len() == self.limit {
+                break;
+            }
+        }
+        let next_cursor = if page.len() == self.limit {
+            page.last().map(|item| hex::encode(key(item).to_cursor()))
+        } else {
+            None
+        };
+        Ok(Page { items: page, next_cursor })
+    }
+
+    /// Renders the paging fields shared by every list reply.
+    pub fn describe<T>(&self, page: &Page<T>) -> Value {
+        json!({
+            "limit": self.limit,
+            "order": if self.order == Order::Asc { "asc" } else { "desc" },
+            "nextCursor": page.next_cursor
+        })
+    }
+
+    // Compares two keys as they are ordered in the reply
+    fn compare<K: Ord>(&self, a: &K, b: &K) -> Ordering {
+        match self.order {
+            Order::Asc => a.cmp(b),
+            Order::Desc => b.cmp(a),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+use serde_json::{json, Map, Value};
+use warp::Filter;
+
+use super::ApiError;
+use crate::address;
+use crate::database_service::ReceiptStatus;
+use crate::handler;
+use crate::state::SharedState;
+
+// Body of a request simulating an action
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct SimulateRequest {
+    vida_id: Option<u64>,
+    sender: String,
+    #[schema(value_type = Object)]
+    payload: Map<String, Value>,
+}
+
+pub struct Simulate;
+
+impl Simulate {
+    /// Registers POST /simulate, which runs `{"sender": address, "payload":
+    /// {...}}` through the same handler as a transaction with that payload,
+    /// without changing any state. The reply tells whether the action would
+    /// succeed, its receipt status and rejection reason, and the balances it
+    /// would leave for every account it changes. `vidaId` defaults to the
+    /// primary VIDA.
+    pub fn run(state: SharedState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("simulate")
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(warp::any().map(move || state.clone()))
+            .and_then(|request: SimulateRequest, state: SharedState| async move {
+                Self::handle_simulate(request, &state)
+                    .await
+                    .map(|response| warp::reply::json(&response))
+                    .map_err(warp::reject::custom)
+            })
+    }
+
+    async fn handle_simulate(request: SimulateRequest, state: &SharedState) -> Result<Value, ApiError> {
+        let vida_id = match request.vida_id {
+            Some(vida_id) if state.read().unwrap().config.vida(vida_id).is_none() => {
+                return Err(ApiError::not_found(format!("VIDA {} is not synced by this node", vida_id)));
+            }
+            Some(vida_id) => vida_id,
+            None => state.read().unwrap().config.vida_id,
+        };
+        let sender = address::parse_address(&request.sender)
+            .map_err(|reason| ApiError::bad_request(format!("Invalid sender format: {}", reason)))?;
+
+        let simulation = handler::simulate_transaction(state, vida_id, &hex::encode(&sender), &request.payload)
+            .await
+            .map_err(ApiError::internal)?;
+        let balances: Vec<Value> = simulation.db.pending_balances(vida_id)
+            .map_err(ApiError::database)?
+            .into_iter()
+            .map(|(token_id, address, balance)| json!({
+                "address": format!("0x{}", hex::encode(&address)),
+                "tokenId": token_id,
+                "balance": balance.to_string()
+            }))
+            .collect();
+        let (status, reason) = match simulation.rejection {
+            Some((status, reason)) => (status, Some(reason)),
+            None => (ReceiptStatus::Applied, None),
+        };
+
+        Ok(json!({
+            "vidaId": vida_id,
+            "block": simulation.block_number,
+            "success": status == ReceiptStatus::Applied,
+            "status": status,
+            "reason": reason,
+            "balances": balances
+        }))
+    }
+}
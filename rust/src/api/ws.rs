@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use warp::ws::{Message, WebSocket};
+
+use crate::address;
+use crate::events::{self, Event};
+
+// Command sent by a client to manage its balance subscriptions
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+enum Command {
+    Subscribe { address: String },
+    Unsubscribe { address: String },
+}
+
+/// Streams events of one VIDA to a WebSocket client. Block and root hash
+/// events are always sent; balance events only for addresses the client
+/// subscribed to with `{"action":"subscribe","address":"0x..."}`.
+pub async fn serve(socket: WebSocket, vida_id: u64) {
+    let (mut sink, mut stream) = socket.split();
+    let mut events = events::subscribe();
+    let mut addresses = HashSet::new();
+
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                let message = match message {
+                    Some(Ok(message)) if !message.is_close() => message,
+                    _ => break,
+                };
+                let Ok(text) = message.to_str() else { continue };
+                let reply = match apply_command(text, &mut addresses) {
+                    Ok(address) => serde_json::json!({ "address": address, "subscriptions": addresses.len() }),
+                    Err(e) => serde_json::json!({ "error": e }),
+                };
+                if sink.send(Message::text(reply.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket client lagging, {} events dropped", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !wants(&event, vida_id, &addresses) {
+                    continue;
+                }
+                let Ok(payload) = serde_json::to_string(&event) else { continue };
+                if sink.send(Message::text(payload)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("WebSocket client for VIDA {} disconnected", vida_id);
+}
+
+// Updates the subscribed addresses; returns the normalized address affected
+fn apply_command(text: &str, addresses: &mut HashSet<String>) -> Result<String, String> {
+    let command: Command = serde_json::from_str(text).map_err(|_| "Invalid command".to_string())?;
+    match command {
+        Command::Subscribe { address } => {
+            let address = normalize_address(&address)?;
+            addresses.insert(address.clone());
+            Ok(address)
+        }
+        Command::Unsubscribe { address } => {
+            let address = normalize_address(&address)?;
+            addresses.remove(&address);
+            Ok(address)
+        }
+    }
+}
+
+// Whether an event should be forwarded to a client with the given subscriptions
+fn wants(event: &Event, vida_id: u64, addresses: &HashSet<String>) -> bool {
+    if event.vida_id() != vida_id {
+        return false;
+    }
+    match event {
+        Event::BalanceChanged { address, .. } => addresses.contains(address),
+        _ => true,
+    }
+}
+
+// Lowercases an address and ensures it carries a 0x prefix
+fn normalize_address(address: &str) -> Result<String, String> {
+    address::parse_address(address).map(|decoded| format!("0x{}", hex::encode(decoded)))
+}
@@ -0,0 +1,120 @@
+use serde_json::Value;
+use tracing::info;
+
+use crate::address;
+use crate::database_service::DatabaseService;
+use crate::registry::{FROZEN, NOT_AN_ADMIN, NOT_AUTHORIZED, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Checks that `spender`, the verified sender of a VIDA transaction, may move
+/// funds owned by `owner`. Owners may always spend their own funds; anyone
+/// else needs a delegation granted by the owner through the `delegate` action.
+pub fn authorize_spend(db: &DatabaseService, vida_id: u64, spender: &[u8], owner: &[u8]) -> Result<(), String> {
+    if spender.is_empty() || owner.is_empty() {
+        return Err("Spender and owner addresses must not be empty".to_string());
+    }
+    if spender == owner {
+        return Ok(());
+    }
+    
+    match db.is_delegate(vida_id, owner, spender) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!(
+            "0x{} {} to spend from 0x{}",
+            hex::encode(spender), NOT_AUTHORIZED, hex::encode(owner)
+        )),
+        Err(_) => Err("Failed to read delegation".to_string()),
+    }
+}
+
+/// Rejects a transfer if any of the given addresses was frozen by an admin.
+pub fn check_not_frozen(db: &DatabaseService, vida_id: u64, addresses: &[&[u8]]) -> Result<(), String> {
+    for address in addresses {
+        match db.is_frozen(vida_id, address) {
+            Ok(false) => {}
+            Ok(true) => return Err(format!("Account 0x{} {}", hex::encode(address), FROZEN)),
+            Err(_) => return Err("Failed to read account status".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Built-in `delegate` action: the sender grants (`"enabled": true`) or revokes
+/// (`"enabled": false`) the right of `spender` to move the sender's funds.
+pub struct DelegateHandler;
+
+impl TransactionHandler for DelegateHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let spender_hex = ctx.payload.get("spender")
+            .and_then(Value::as_str)
+            .ok_or("Missing spender")?;
+        let enabled = ctx.payload.get("enabled")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        
+        let owner = decode_hex_address(ctx.sender)?;
+        let spender = decode_hex_address(spender_hex)?;
+        if owner == spender {
+            return Err("Cannot delegate to self".to_string());
+        }
+        
+        ctx.db.set_delegate(ctx.vida_id, &owner, &spender, enabled)
+            .map_err(|_| "Failed to store delegation".to_string())?;
+        info!("Delegation from {} to {} set to {}", ctx.sender, spender_hex, enabled);
+        Ok(())
+    }
+}
+
+/// Built-in `freeze` action: a genesis admin blocks all transfers from and to
+/// `address`, for deployments with compliance requirements. Guarded by the
+/// admin's `nonce`.
+pub struct FreezeHandler;
+
+impl TransactionHandler for FreezeHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        set_frozen(ctx, true)
+    }
+}
+
+/// Built-in `unfreeze` action: a genesis admin lifts a freeze on `address`.
+/// Guarded by the admin's `nonce`.
+pub struct UnfreezeHandler;
+
+impl TransactionHandler for UnfreezeHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        set_frozen(ctx, false)
+    }
+}
+
+// Shared by `freeze` and `unfreeze`
+fn set_frozen(ctx: &TransactionContext, frozen: bool) -> Result<(), String> {
+    let address_hex = ctx.payload.get("address")
+        .and_then(Value::as_str)
+        .ok_or("Missing address")?;
+    let nonce = transfer::parse_nonce(ctx.payload)?;
+
+    let sender = decode_hex_address(ctx.sender)?;
+    let address = decode_hex_address(address_hex)?;
+    let admins = ctx.db.get_admins(ctx.vida_id)
+        .map_err(|_| "Failed to read admins".to_string())?;
+    if !admins.contains(&sender) {
+        return Err(format!("{} {}", ctx.sender, NOT_AN_ADMIN));
+    }
+
+    transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+    ctx.db.set_frozen(ctx.vida_id, &address, frozen)
+        .map_err(|_| "Failed to store account status".to_string())?;
+    info!("Account {} {} by {}", address_hex, if frozen { "frozen" } else { "unfrozen" }, ctx.sender);
+    Ok(())
+}
+
+/// Decodes a 20-byte hex address with or without 0x prefix, checking its
+/// checksum if it is mixed-case. See [`address::parse_address`].
+pub fn decode_hex_address(address: &str) -> Result<Vec<u8>, String> {
+    address::parse_address(address)
+}
+
+/// Decodes the address a transaction credits, rejecting the zero address.
+pub fn decode_recipient(address: &str) -> Result<Vec<u8>, String> {
+    address::parse_recipient(address)
+}
@@ -0,0 +1,77 @@
+use std::collections::{BTreeMap, HashMap};
+use num_bigint::BigUint;
+
+/// Least-recently-used cache of account balances, keyed by balance key. Holds at
+/// most `capacity` entries; a capacity of 0 disables caching.
+#[derive(Debug)]
+pub struct BalanceCache {
+    capacity: usize,
+    // Balance and last use of each cached address
+    entries: HashMap<Vec<u8>, (BigUint, u64)>,
+    // Addresses by last use, oldest first
+    recency: BTreeMap<u64, Vec<u8>>,
+    tick: u64,
+}
+
+impl BalanceCache {
+    /// Creates an empty cache holding up to `capacity` balances.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Changes the capacity, evicting the least recently used entries if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the cached balance of an address and marks it as recently used.
+    pub fn get(&mut self, address: &[u8]) -> Option<BigUint> {
+        let tick = self.next_tick();
+        let (balance, last_use) = self.entries.get_mut(address)?;
+        self.recency.remove(last_use);
+        *last_use = tick;
+        self.recency.insert(tick, address.to_vec());
+        Some(balance.clone())
+    }
+
+    /// Caches the balance of an address.
+    pub fn insert(&mut self, address: &[u8], balance: BigUint) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, last_use)) = self.entries.insert(address.to_vec(), (balance, tick)) {
+            self.recency.remove(&last_use);
+        }
+        self.recency.insert(tick, address.to_vec());
+        self.evict();
+    }
+
+    /// Drops every cached balance.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            match self.recency.pop_first() {
+                Some((_, address)) => {
+                    self.entries.remove(&address);
+                }
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::OnceLock;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::database_service::{DatabaseService, StateChange, DEFAULT_TOKEN};
+
+// Events waiting for the broker before new ones are dropped
+const BROKER_QUEUE: usize = 10_000;
+
+// Queue of the publishing task, set by `start`
+static QUEUE: OnceLock<mpsc::Sender<BrokerEvent>> = OnceLock::new();
+
+/// Finalized state change published to the message broker as JSON.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BrokerEvent {
+    /// A transfer was applied in a committed block.
+    #[serde(rename_all = "camelCase")]
+    TransferApplied {
+        vida_id: u64,
+        block_number: u64,
+        tx_index: u32,
+        token_id: u64,
+        from: String,
+        to: String,
+        amount: String,
+    },
+    /// Tokens were minted in a committed block.
+    #[serde(rename_all = "camelCase")]
+    Minted { vida_id: u64, block_number: u64, tx_index: u32, token_id: u64, to: String, amount: String },
+    /// A block was committed with the root peers agreed on.
+    #[serde(rename_all = "camelCase")]
+    BlockFinalized { vida_id: u64, block_number: u64, root_hash: String },
+}
+
+impl BrokerEvent {
+    // Last part of the NATS subject the event is published under
+    fn name(&self) -> &'static str {
+        match self {
+            BrokerEvent::TransferApplied { .. } => "transfer",
+            BrokerEvent::Minted { .. } => "mint",
+            BrokerEvent::BlockFinalized { .. } => "block",
+        }
+    }
+
+    fn vida_id(&self) -> u64 {
+        match self {
+            BrokerEvent::TransferApplied { vida_id, .. }
+            | BrokerEvent::Minted { vida_id, .. }
+            | BrokerEvent::BlockFinalized { vida_id, .. } => *vida_id,
+        }
+    }
+}
+
+// Connection to the configured broker
+enum Broker {
+    #[cfg(feature = "kafka")]
+    Kafka(rdkafka::producer::FutureProducer),
+    #[cfg(feature = "nats")]
+    Nats(async_nats::Client),
+}
+
+impl Broker {
+    async fn connect(kind: &str, url: &str) -> Result<Self, String> {
+        match kind {
+            #[cfg(feature = "kafka")]
+            "kafka" => rdkafka::ClientConfig::new()
+                .set("bootstrap.servers", url)
+                .create()
+                .map(Broker::Kafka)
+                .map_err(|e| format!("Failed to create Kafka producer for {}: {}", url, e)),
+            #[cfg(feature = "nats")]
+            "nats" => async_nats::connect(url).await
+                .map(Broker::Nats)
+                .map_err(|e| format!("Failed to connect to NATS at {}: {}", url, e)),
+            other => {
+                let _ = url;
+                Err(format!("Broker {} is not supported by this build; enable its feature", other))
+            }
+        }
+    }
+
+    // Kafka events go to the topic keyed by VIDA, so each VIDA's events stay
+    // in order; NATS events go to `<topic>.<vida id>.<event>`
+    async fn publish(&self, topic: &str, event: &BrokerEvent) -> Result<(), String> {
+        let payload = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        match self {
+            #[cfg(feature = "kafka")]
+            Broker::Kafka(producer) => {
+                let key = event.vida_id().to_string();
+                let record = rdkafka::producer::FutureRecord::to(topic).key(&key).payload(&payload);
+                producer.send(record, std::time::Duration::from_secs(10)).await
+                    .map(|_| ())
+                    .map_err(|(e, _)| e.to_string())
+            }
+            #[cfg(feature = "nats")]
+            Broker::Nats(client) => {
+                let subject = format!("{}.{}.{}", topic, event.vida_id(), event.name());
+                client.publish(subject, payload.into()).await.map_err(|e| e.to_string())
+            }
+            #[cfg(not(any(feature = "kafka", feature = "nats")))]
+            _ => {
+                let _ = (topic, payload);
+                unreachable!("no broker can be connected without a broker feature")
+            }
+        }
+    }
+}
+
+/// Connects to the broker set in `broker`, if any, and starts publishing
+/// the events queued by `publish_committed` to `broker_topic`.
+pub async fn start(config: &Config) -> Result<(), String> {
+    if config.broker.is_empty() {
+        return Ok(());
+    }
+    let broker = Broker::connect(&config.broker, &config.broker_url).await?;
+    let (sender, mut receiver) = mpsc::channel(BROKER_QUEUE);
+    if QUEUE.set(sender).is_err() {
+        return Ok(());
+    }
+    info!("Publishing finalized state changes to {} at {}", config.broker, config.broker_url);
+
+    let topic = config.broker_topic.clone();
+    tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            if let Err(e) = broker.publish(&topic, &event).await {
+                warn!("Failed to publish {} event of VIDA {}: {}", event.name(), event.vida_id(), e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Queues the transfers and mints of the committed blocks `first_block` to
+/// `block_number` for the broker, then the finalization of each block with
+/// a root. Publishing is best effort: events that find the queue full are
+/// dropped with a warning rather than holding up the sync.
+pub fn publish_committed(db: &DatabaseService, vida_id: u64, first_block: u64, block_number: u64) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    for block in first_block..=block_number {
+        let changes = match db.get_changes(vida_id, block) {
+            Ok(changes) => changes,
+            Err(e) => {
+                warn!("Failed to read changes of block {} for the broker: {:?}", block, e);
+                Vec::new()
+            }
+        };
+        let mut events: Vec<BrokerEvent> = changes.into_iter()
+            .filter_map(|record| match record.change {
+                StateChange::Transfer { from, to, amount, token_id } => Some(BrokerEvent::TransferApplied {
+                    vida_id,
+                    block_number: block,
+                    tx_index: record.tx_index,
+                    token_id: token_id.unwrap_or(DEFAULT_TOKEN),
+                    from: format!("0x{}", from),
+                    to: format!("0x{}", to),
+                    amount,
+                }),
+                StateChange::Mint { to, amount, token_id } => Some(BrokerEvent::Minted {
+                    vida_id,
+                    block_number: block,
+                    tx_index: record.tx_index,
+                    token_id: token_id.unwrap_or(DEFAULT_TOKEN),
+                    to: format!("0x{}", to),
+                    amount,
+                }),
+                _ => None,
+            })
+            .collect();
+        if let Ok(Some(root_hash)) = db.get_block_root_hash(vida_id, block) {
+            events.push(BrokerEvent::BlockFinalized { vida_id, block_number: block, root_hash: hex::encode(root_hash) });
+        }
+        for event in events {
+            if queue.try_send(event).is_err() {
+                warn!("Broker queue full, dropping events of VIDA {} block {}", vida_id, block);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use futures_util::future::join_all;
+use tracing::{info, instrument};
+
+use crate::handler;
+use crate::source::VidaSource;
+use crate::state::SharedState;
+
+/// Brings a VIDA close to the chain head before it is subscribed. Ranges of
+/// `catch_up_batch_blocks` blocks are fetched from the source, up to
+/// `catch_up_parallelism` at a time, then applied in block order with a
+/// validated checkpoint at the end of each range. Stops once the VIDA is
+/// within `catch_up_threshold` blocks of the head, or with an error if a
+/// range cannot be fetched or its checkpoint is rejected; either way the live
+/// subscription resumes from the last checked block.
+#[instrument(skip(source, state))]
+pub async fn catch_up<S: VidaSource>(source: &S, state: &SharedState, vida_id: u64, start_block: u64) -> Result<(), String> {
+    let (batch_blocks, parallelism, threshold, db) = {
+        let state = state.read().unwrap();
+        let config = &state.config;
+        (config.catch_up_batch_blocks, config.catch_up_parallelism, config.catch_up_threshold, state.db.clone())
+    };
+    if batch_blocks == 0 || parallelism == 0 {
+        return Ok(());
+    }
+
+    loop {
+        let latest_block = source.latest_block().await
+            .map_err(|e| format!("Failed to get latest block: {}", e))?;
+        state.write().unwrap().sync.record_chain_block(latest_block);
+
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(|e| format!("Failed to get last checked block: {:?}", e))?;
+        let from_block = if last_checked_block > 0 { last_checked_block + 1 } else { start_block };
+        let target = latest_block.saturating_sub(threshold);
+        if from_block > target {
+            info!("VIDA {} caught up at block {}", vida_id, last_checked_block);
+            return Ok(());
+        }
+
+        let ranges = block_ranges(from_block, target, batch_blocks, parallelism);
+        info!("Fetching blocks {} to {} of VIDA {} in {} ranges", from_block, ranges[ranges.len() - 1].1, vida_id, ranges.len());
+        let fetched = join_all(ranges.iter().map(|&(start, end)| source.fetch_range(vida_id, start, end))).await;
+
+        // Ranges are applied strictly in order; a later range is useless once one fails
+        for ((start, end), transactions) in ranges.into_iter().zip(fetched) {
+            let mut transactions = transactions
+                .map_err(|e| format!("Failed to fetch blocks {} to {}: {}", start, end, e))?;
+            // Stable sort: transactions of a block keep the order the source returned them in
+            transactions.sort_by_key(|txn| txn.block_number);
+            for txn in transactions {
+                handler::process_transaction(txn);
+            }
+
+            handler::on_chain_progress(vida_id, end).await;
+            let checked = db.get_last_checked_block(vida_id)
+                .map_err(|e| format!("Failed to get last checked block: {:?}", e))?;
+            if checked != end {
+                return Err(format!("Checkpoint at block {} was not committed", end));
+            }
+        }
+    }
+}
+
+// Splits `from..=to` into at most `count` consecutive ranges of `size` blocks
+fn block_ranges(from: u64, to: u64, size: u64, count: u64) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    let mut start = from;
+    while start <= to && (ranges.len() as u64) < count {
+        let end = start.saturating_add(size - 1).min(to);
+        ranges.push((start, end));
+        start = end + 1;
+    }
+    ranges
+}
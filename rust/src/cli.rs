@@ -0,0 +1,105 @@
+use clap::{Parser, Subcommand};
+
+use crate::config::{Config, FlagOverrides};
+use crate::error::Error;
+
+/// Command line of the node binary. Without a subcommand the node syncs.
+#[derive(Debug, Parser)]
+#[command(name = "pwr-stateful-vida", version, about = "Stateful VIDA engine for the PWR chain")]
+pub struct Cli {
+    /// Configuration file, instead of VIDA_CONFIG or `config.toml`
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Port of the HTTP API
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+
+    /// Directory holding the database, instead of `database_path`
+    #[arg(long, global = true)]
+    pub db_path: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What the binary should do once the configuration is loaded.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Sync the configured VIDAs and serve the API (default)
+    Sync {
+        /// Peers to validate root hashes against, instead of the configured ones
+        peers: Vec<String>,
+    },
+    /// Write the state of a VIDA to a snapshot file
+    ExportState {
+        /// Snapshot file to write
+        file: String,
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Replace the state of a VIDA with a snapshot file
+    ImportState {
+        /// Snapshot file to read
+        file: String,
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Check the stored state against its recorded root hash and total supply
+    VerifyState {
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Replay the journal from genesis, recomputing every balance, and print
+    /// the first block whose stored balances or tree disagree with the replay
+    Audit {
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Print the root hash recorded for a block
+    ShowRoot {
+        #[arg(long)]
+        block: u64,
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Start from a trusted checkpoint instead of block 1: import a snapshot of
+    /// `--block` that hashes to `--root-hash`, once a quorum of peers confirms
+    /// that root, then sync from there
+    TrustedSync {
+        /// Snapshot file of the checkpoint block
+        file: String,
+        #[arg(long)]
+        block: u64,
+        #[arg(long)]
+        root_hash: String,
+        #[arg(long)]
+        vida_id: Option<u64>,
+        /// Peers to confirm the checkpoint with, instead of the configured ones
+        #[arg(long, value_delimiter = ',')]
+        peers: Vec<String>,
+    },
+    /// Rebuild the state of a VIDA from genesis by fetching its transactions
+    /// again, checking every block against the root recorded in the journal
+    /// instead of asking peers. The current tree is kept on disk, unused
+    Rebuild {
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+    /// Roll the state back to a block, then sync again from there
+    RebuildFromBlock {
+        block: u64,
+        #[arg(long)]
+        vida_id: Option<u64>,
+    },
+}
+
+impl Cli {
+    /// Loads the configuration named by `--config`, then applies the flags,
+    /// which take precedence over the file and environment variables.
+    pub fn load_config(&self) -> Result<Config, Error> {
+        let mut config = Config::load_from(self.config.as_deref())?;
+        config.apply_flags(FlagOverrides { port: self.port, database_path: self.db_path.clone() });
+        Ok(config)
+    }
+}
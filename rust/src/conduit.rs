@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use pwr_rs::wallet::types::Wallet;
+use serde_json::Value;
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::authorization::decode_hex_address;
+use crate::config::Config;
+use crate::database_service::{DatabaseService, Withdrawal};
+use crate::registry::{INSUFFICIENT_FUNDS, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+// Pause before submitting again after the PWR chain refused a withdrawal
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Last committed block of each VIDA, and the wake-up of the submitting task
+static COMMITTED: OnceLock<(Mutex<BTreeMap<u64, u64>>, Notify)> = OnceLock::new();
+
+/// Built-in `withdraw` action: the sender burns `amount` of its default
+/// token to be paid the same amount of PWR, in base units, at `receiver` on
+/// the PWR chain. Guarded by the sender's `nonce`. A conduit node submits
+/// the payout once the block is committed.
+pub struct WithdrawHandler;
+
+impl TransactionHandler for WithdrawHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let receiver = decode_hex_address(receiver_hex)?;
+        let amount = u64::try_from(amount).map_err(|_| "Withdrawals are limited to amounts that fit in PWR's u64".to_string())?;
+        if amount == 0 {
+            return Err("Withdrawal amount must be positive".to_string());
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        let withdrawal = Withdrawal {
+            sender: hex::encode(&sender),
+            receiver: hex::encode(&receiver),
+            amount,
+            block_number: ctx.block_number,
+        };
+        match ctx.db.request_withdrawal(ctx.vida_id, &withdrawal) {
+            Ok(Some(id)) => {
+                info!("Withdrawal {} of {} from {} to {} on the PWR chain", id, amount, ctx.sender, receiver_hex);
+                Ok(())
+            }
+            Ok(None) => Err(format!("{}: cannot withdraw {} from {}", INSUFFICIENT_FUNDS, amount, ctx.sender)),
+            Err(_) => Err("Withdrawal failed".to_string()),
+        }
+    }
+}
+
+/// Starts submitting the withdrawals of committed blocks to the PWR chain
+/// when `conduit_wallet_file` is set. Each withdrawal belongs to one of the
+/// `conduit_submitters`, picked by its id, and only that submitter sends it,
+/// so a payout is broadcast once however many conduit nodes run.
+pub fn start(config: &Config, db: DatabaseService) -> Result<(), String> {
+    if config.conduit_wallet_file.is_empty() {
+        return Ok(());
+    }
+    let wallet = Wallet::load_wallet_with_rpc_url(&config.conduit_wallet_file, &config.conduit_wallet_password, &config.rpc_url)
+        .ok_or_else(|| format!("Failed to load conduit wallet {}", config.conduit_wallet_file))?;
+    let address = normalize(&wallet.get_address());
+    let submitters: Vec<String> = config.conduit_submitters.iter().map(|submitter| normalize(submitter)).collect();
+    if !submitters.contains(&address) {
+        return Err(format!("Conduit wallet 0x{} is not listed in conduit_submitters", address));
+    }
+
+    let mut committed = BTreeMap::new();
+    for vida_id in db.vida_ids() {
+        let block = db.get_last_checked_block(vida_id).map_err(|e| format!("Failed to read last checked block: {:?}", e))?;
+        committed.insert(vida_id, block);
+    }
+    if COMMITTED.set((Mutex::new(committed), Notify::new())).is_err() {
+        return Ok(());
+    }
+    info!("Submitting withdrawals as conduit 0x{}, {} of {} submitters", address, submitters.iter().position(|s| *s == address).unwrap_or(0) + 1, submitters.len());
+
+    let fee_per_byte = config.conduit_fee_per_byte;
+    tokio::spawn(async move {
+        let (committed, wake) = COMMITTED.get().expect("set above");
+        loop {
+            let blocks = committed.lock().unwrap().clone();
+            let mut refused = false;
+            for (vida_id, committed_block) in blocks {
+                if let Err(e) = submit_pending(&db, &wallet, vida_id, committed_block, &submitters, &address, fee_per_byte).await {
+                    warn!("{}", e);
+                    refused = true;
+                }
+            }
+            if refused {
+                let _ = timeout(RETRY_DELAY, wake.notified()).await;
+            } else {
+                wake.notified().await;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Wakes the conduit up to submit the withdrawals of a VIDA's blocks up to
+/// `block_number`, just committed.
+pub fn notify(vida_id: u64, block_number: u64) {
+    if let Some((committed, wake)) = COMMITTED.get() {
+        committed.lock().unwrap().insert(vida_id, block_number);
+        wake.notify_one();
+    }
+}
+
+// Submits this node's share of the withdrawals of committed blocks, in id
+// order, stopping at the first the PWR chain refuses so it is retried first
+async fn submit_pending(
+    db: &DatabaseService,
+    wallet: &Wallet,
+    vida_id: u64,
+    committed_block: u64,
+    submitters: &[String],
+    address: &str,
+    fee_per_byte: u64,
+) -> Result<(), String> {
+    let count = db.withdrawal_count(vida_id).map_err(|e| format!("Failed to read withdrawals: {:?}", e))?;
+    let mut next = db.get_conduit_cursor(vida_id).map_err(|e| format!("Failed to read conduit cursor: {:?}", e))?;
+    while next < count {
+        let Some(withdrawal) = db.get_withdrawal(vida_id, next).map_err(|e| format!("Failed to read withdrawal {}: {:?}", next, e))? else {
+            break;
+        };
+        if withdrawal.block_number > committed_block {
+            break;
+        }
+        if submitters[(next % submitters.len() as u64) as usize] == address {
+            let response = wallet.transfer_pwr_from_vida(vida_id, format!("0x{}", withdrawal.receiver), withdrawal.amount, fee_per_byte).await;
+            if !response.success {
+                return Err(format!("PWR chain refused withdrawal {} of VIDA {}: {}", next, vida_id, response.error));
+            }
+            info!("Submitted withdrawal {} of VIDA {} to 0x{} as {}", next, vida_id, withdrawal.receiver, response.data.unwrap_or_default());
+        }
+        next += 1;
+        db.set_conduit_cursor(vida_id, next).map_err(|e| format!("Failed to save conduit cursor: {:?}", e))?;
+    }
+    Ok(())
+}
+
+// Addresses are compared as lowercase hex without the 0x prefix
+fn normalize(address: &str) -> String {
+    address.trim_start_matches("0x").to_ascii_lowercase()
+}
@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::amount;
+use crate::error::Error;
+use crate::faucet::FaucetPolicy;
+use crate::flush::FlushPolicy;
+use crate::payload::PayloadLimits;
+use crate::peers::RequestPolicy;
+use crate::registry::ExecutionLimits;
+use crate::stall::StallAlert;
+
+// Default location of the configuration file, overridable with VIDA_CONFIG
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Runtime parameters of the node, loaded from a TOML file with
+/// environment variable overrides.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub vida_id: u64,
+    pub actions: Vec<String>,
+    pub vidas: Vec<VidaConfig>,
+    pub rpc_url: String,
+    pub fallback_rpc_urls: Vec<String>,
+    pub reconnect_initial_delay_ms: u64,
+    pub reconnect_max_delay_ms: u64,
+    pub subscription_stall_secs: u64,
+    pub poll_interval_ms: u64,
+    pub max_poll_interval_ms: u64,
+    pub stall_alert_secs: u64,
+    pub stall_alert: String,
+    pub stall_alert_url: String,
+    pub stall_alert_exit_code: i32,
+    pub catch_up_batch_blocks: u64,
+    pub catch_up_parallelism: u64,
+    pub catch_up_threshold: u64,
+    pub pipeline_capacity: usize,
+    pub port: u16,
+    pub grpc_port: u16,
+    pub start_block: u64,
+    pub pinned_block: u64,
+    pub pinned_root_hash: String,
+    pub peers: Vec<String>,
+    pub peer_quarantine_after: u32,
+    pub peer_quarantine_secs: u64,
+    pub peer_connect_timeout_ms: u64,
+    pub peer_timeout_ms: u64,
+    pub peer_retries: u32,
+    pub peer_retry_backoff_ms: u64,
+    pub peer_overrides: HashMap<String, PeerOverride>,
+    pub peer_min_score: f64,
+    pub peer_min_samples: u64,
+    pub peer_registry: bool,
+    pub root_gossip: bool,
+    pub validator_mode: bool,
+    pub replica_of: String,
+    pub replica_poll_secs: u64,
+    pub public_address: String,
+    pub node_key_file: String,
+    pub tls_cert_file: String,
+    pub tls_key_file: String,
+    pub peer_ca_file: String,
+    pub peer_public_keys: HashMap<String, String>,
+    pub admin_token: String,
+    pub cors_origins: Vec<String>,
+    pub cors_methods: Vec<String>,
+    pub cache_max_age_secs: u64,
+    pub database_path: String,
+    pub database_name: String,
+    pub balance_cache_size: usize,
+    pub max_payload_bytes: usize,
+    pub max_payload_depth: usize,
+    pub max_field_length: usize,
+    pub max_amount_bits: u64,
+    pub max_state_reads: u64,
+    pub max_state_writes: u64,
+    pub slow_handler_ms: u64,
+    pub token_decimals: u32,
+    pub faucet_cap: String,
+    pub faucet_window_blocks: u64,
+    pub rollback_after_mismatches: u32,
+    pub rollback_depth: u64,
+    pub resync_after_mismatches: u32,
+    pub startup_root_mismatch: String,
+    pub supply_audit_interval: u64,
+    pub snapshot_every_blocks: u64,
+    pub snapshot_dir: String,
+    pub snapshot_retention: usize,
+    pub diagnostics_dir: String,
+    pub diagnostics_retention: usize,
+    pub postgres_url: String,
+    pub broker: String,
+    pub broker_url: String,
+    pub broker_topic: String,
+    pub conduit_wallet_file: String,
+    pub conduit_wallet_password: String,
+    pub conduit_submitters: Vec<String>,
+    pub conduit_fee_per_byte: u64,
+    pub flush_policy: String,
+    pub flush_every_blocks: u64,
+    pub flush_interval_secs: u64,
+    pub log_level: String,
+    pub log_format: String,
+    pub genesis_file: String,
+    /// File the configuration was read from, re-read by `reload`; None if there was none.
+    #[serde(skip)]
+    pub config_file: Option<String>,
+    /// Command line flags applied over the file, applied again by `reload`.
+    #[serde(skip)]
+    pub flags: FlagOverrides,
+}
+
+/// Request settings of one peer, replacing the `peer_*` defaults for it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PeerOverride {
+    pub connect_timeout_ms: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub retries: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+}
+
+/// Settings given on the command line, which take precedence over the file
+/// and environment variables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagOverrides {
+    pub port: Option<u16>,
+    pub database_path: Option<String>,
+}
+
+/// An additional VIDA synced by the node alongside the primary one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VidaConfig {
+    pub id: u64,
+    #[serde(default = "default_start_block")]
+    pub start_block: u64,
+    #[serde(default = "default_actions")]
+    pub actions: Vec<String>,
+    #[serde(default)]
+    pub pinned_block: u64,
+    #[serde(default)]
+    pub pinned_root_hash: String,
+}
+
+impl VidaConfig {
+    /// Returns the block and root hash the state of this VIDA must reproduce,
+    /// if one is pinned.
+    pub fn pinned_root(&self) -> Option<(u64, Vec<u8>)> {
+        if self.pinned_block == 0 {
+            return None;
+        }
+        let root = hex::decode(self.pinned_root_hash.trim_start_matches("0x")).ok()?;
+        Some((self.pinned_block, root))
+    }
+
+    // Checks that a pinned root is complete and can be reached from the start block
+    fn validate_pin(&self) -> Result<(), Error> {
+        if self.pinned_block == 0 {
+            if !self.pinned_root_hash.is_empty() {
+                return Err(Error::Config(format!("VIDA {} has a pinned_root_hash but no pinned_block", self.id)));
+            }
+            return Ok(());
+        }
+        if self.pinned_block < self.start_block {
+            return Err(Error::Config(format!(
+                "Pinned block {} of VIDA {} is before its start block {}",
+                self.pinned_block, self.id, self.start_block
+            )));
+        }
+        match self.pinned_root() {
+            Some((_, root)) if !root.is_empty() => Ok(()),
+            _ => Err(Error::Config(format!("Invalid pinned_root_hash of VIDA {}: {}", self.id, self.pinned_root_hash))),
+        }
+    }
+}
+
+fn default_start_block() -> u64 {
+    1
+}
+
+fn default_actions() -> Vec<String> {
+    ["transfer", "delegate", "approve", "transferFrom"]
+        .iter()
+        .map(|action| action.to_string())
+        .collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            vida_id: 73_746_238,
+            actions: default_actions(),
+            vidas: Vec::new(),
+            rpc_url: "https://pwrrpc.pwrlabs.io/".to_string(),
+            fallback_rpc_urls: Vec::new(),
+            reconnect_initial_delay_ms: 1_000,
+            reconnect_max_delay_ms: 60_000,
+            subscription_stall_secs: 120,
+            poll_interval_ms: 100,
+            max_poll_interval_ms: 5_000,
+            stall_alert_secs: 600,
+            stall_alert: "log".to_string(),
+            stall_alert_url: String::new(),
+            stall_alert_exit_code: 3,
+            catch_up_batch_blocks: 1_000,
+            catch_up_parallelism: 4,
+            catch_up_threshold: 100,
+            pipeline_capacity: 1_024,
+            port: 8080,
+            grpc_port: 50051,
+            start_block: default_start_block(),
+            pinned_block: 0,
+            pinned_root_hash: String::new(),
+            peers: vec!["localhost:8080".to_string()],
+            peer_quarantine_after: 3,
+            peer_quarantine_secs: 300,
+            peer_connect_timeout_ms: 3_000,
+            peer_timeout_ms: 10_000,
+            peer_retries: 1,
+            peer_retry_backoff_ms: 250,
+            peer_overrides: HashMap::new(),
+            peer_min_score: 0.5,
+            peer_min_samples: 20,
+            peer_registry: false,
+            root_gossip: false,
+            validator_mode: false,
+            replica_of: String::new(),
+            replica_poll_secs: 5,
+            public_address: String::new(),
+            node_key_file: "node.key".to_string(),
+            tls_cert_file: String::new(),
+            tls_key_file: String::new(),
+            peer_ca_file: String::new(),
+            peer_public_keys: HashMap::new(),
+            admin_token: String::new(),
+            cors_origins: Vec::new(),
+            cors_methods: vec!["GET".to_string()],
+            cache_max_age_secs: 5,
+            database_path: ".".to_string(),
+            database_name: "database".to_string(),
+            balance_cache_size: 10_000,
+            max_payload_bytes: 16 * 1024,
+            max_payload_depth: 8,
+            max_field_length: 1024,
+            max_amount_bits: 256,
+            max_state_reads: 10_000,
+            max_state_writes: 1_000,
+            slow_handler_ms: 1_000,
+            token_decimals: 0,
+            faucet_cap: String::new(),
+            faucet_window_blocks: 1_000,
+            rollback_after_mismatches: 3,
+            rollback_depth: 10,
+            resync_after_mismatches: 6,
+            startup_root_mismatch: "refuse".to_string(),
+            supply_audit_interval: 1_000,
+            snapshot_every_blocks: 0,
+            snapshot_dir: "snapshots".to_string(),
+            snapshot_retention: 5,
+            diagnostics_dir: "diagnostics".to_string(),
+            diagnostics_retention: 20,
+            postgres_url: String::new(),
+            broker: String::new(),
+            broker_url: String::new(),
+            broker_topic: "vida".to_string(),
+            conduit_wallet_file: String::new(),
+            conduit_wallet_password: String::new(),
+            conduit_submitters: Vec::new(),
+            conduit_fee_per_byte: 1_000,
+            flush_policy: "checkpoint".to_string(),
+            flush_every_blocks: 100,
+            flush_interval_secs: 30,
+            log_level: "info".to_string(),
+            log_format: "text".to_string(),
+            genesis_file: "genesis.json".to_string(),
+            config_file: None,
+            flags: FlagOverrides::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the file named by VIDA_CONFIG (or
+    /// `config.toml` if present), then applies environment overrides.
+    pub fn load() -> Result<Self, Error> {
+        Self::load_from(None)
+    }
+
+    /// Like `load`, but reads `path` when given. An explicitly named file
+    /// must exist.
+    pub fn load_from(path: Option<&str>) -> Result<Self, Error> {
+        if let Some(path) = path {
+            if !Path::new(path).exists() {
+                return Err(Error::Config(format!("Config file {} not found", path)));
+            }
+        }
+        let path = path.map(str::to_string)
+            .unwrap_or_else(|| env::var("VIDA_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string()));
+
+        let mut config = if Path::new(&path).exists() {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| Error::io(format!("Failed to read config file {}", path), e))?;
+            let mut config: Config = toml::from_str(&contents)
+                .map_err(|e| Error::Config(format!("Failed to parse config file {}: {}", path, e)))?;
+            // Relative database, key and certificate paths are relative to the config file,
+            // not to the working directory
+            if let Some(dir) = Path::new(&path).parent() {
+                config.database_path = dir.join(&config.database_path).to_string_lossy().into_owned();
+                for file in [
+                    &mut config.node_key_file,
+                    &mut config.tls_cert_file,
+                    &mut config.tls_key_file,
+                    &mut config.peer_ca_file,
+                ] {
+                    if !file.is_empty() {
+                        *file = dir.join(&*file).to_string_lossy().into_owned();
+                    }
+                }
+            }
+            config.config_file = Some(path);
+            config
+        } else {
+            Config::default()
+        };
+
+        config.apply_env_overrides()?;
+        FlushPolicy::from_config(&config).map_err(Error::Config)?;
+        StallAlert::from_config(&config).map_err(Error::Config)?;
+        if !matches!(config.startup_root_mismatch.as_str(), "refuse" | "rollback") {
+            return Err(Error::Config(format!("Unknown startup_root_mismatch: {}", config.startup_root_mismatch)));
+        }
+        for vida in config.vidas() {
+            vida.validate_pin()?;
+        }
+        for origin in &config.cors_origins {
+            let host = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"));
+            if origin != "*" && host.map_or(true, |host| host.is_empty() || host.contains('/')) {
+                return Err(Error::Config(format!("Invalid CORS origin {}, expected scheme://host[:port] or *", origin)));
+            }
+        }
+        for method in &config.cors_methods {
+            warp::http::Method::from_bytes(method.as_bytes())
+                .map_err(|_| Error::Config(format!("Invalid CORS method: {}", method)))?;
+        }
+        if config.max_payload_bytes == 0 || config.max_payload_depth == 0 || config.max_field_length == 0 || config.max_amount_bits == 0 {
+            return Err(Error::Config("Payload limits must be positive".to_string()));
+        }
+        if config.poll_interval_ms == 0 || config.max_poll_interval_ms < config.poll_interval_ms {
+            return Err(Error::Config("poll_interval_ms must be positive and at most max_poll_interval_ms".to_string()));
+        }
+        if config.max_state_reads == 0 || config.max_state_writes == 0 {
+            return Err(Error::Config("max_state_reads and max_state_writes must be positive".to_string()));
+        }
+        match config.broker.as_str() {
+            "" => {}
+            "kafka" | "nats" if !config.broker_url.is_empty() && !config.broker_topic.is_empty() => {}
+            "kafka" | "nats" => return Err(Error::Config(format!("Broker {} needs broker_url and broker_topic", config.broker))),
+            other => return Err(Error::Config(format!("Unknown broker: {}, expected kafka or nats", other))),
+        }
+        if !config.conduit_wallet_file.is_empty() && config.conduit_submitters.is_empty() {
+            return Err(Error::Config("conduit_submitters must list the conduit wallets, the same on every node".to_string()));
+        }
+        if !config.replica_of.is_empty() && config.replica_poll_secs == 0 {
+            return Err(Error::Config("replica_poll_secs must be positive in replica mode".to_string()));
+        }
+        if config.token_decimals > amount::MAX_DECIMALS {
+            return Err(Error::Config(format!("token_decimals must be at most {}", amount::MAX_DECIMALS)));
+        }
+        if !config.faucet_cap.is_empty() {
+            amount::parse(&config.faucet_cap, config.token_decimals)
+                .map_err(|e| Error::Config(format!("Invalid faucet_cap: {}", e)))?;
+            if config.faucet_window_blocks == 0 {
+                return Err(Error::Config("faucet_window_blocks must be positive".to_string()));
+            }
+        }
+        let (default_policy, peer_policies) = config.request_policies();
+        if default_policy.connect_timeout.is_zero() || default_policy.timeout.is_zero() {
+            return Err(Error::Config("peer_connect_timeout_ms and peer_timeout_ms must be positive".to_string()));
+        }
+        for (peer, policy) in &peer_policies {
+            if policy.connect_timeout.is_zero() || policy.timeout.is_zero() {
+                return Err(Error::Config(format!("Peer timeouts of {} must be positive", peer)));
+            }
+        }
+        if config.tls_cert_file.is_empty() != config.tls_key_file.is_empty() {
+            return Err(Error::Config("tls_cert_file and tls_key_file must be set together".to_string()));
+        }
+        Ok(config)
+    }
+
+    /// Returns the request policy for peers and the policies of the peers in
+    /// `peer_overrides`.
+    pub fn request_policies(&self) -> (RequestPolicy, HashMap<String, RequestPolicy>) {
+        let default = RequestPolicy {
+            connect_timeout: Duration::from_millis(self.peer_connect_timeout_ms),
+            timeout: Duration::from_millis(self.peer_timeout_ms),
+            retries: self.peer_retries,
+            retry_backoff: Duration::from_millis(self.peer_retry_backoff_ms),
+        };
+        let per_peer = self.peer_overrides.iter()
+            .map(|(peer, settings)| {
+                let policy = RequestPolicy {
+                    connect_timeout: settings.connect_timeout_ms.map_or(default.connect_timeout, Duration::from_millis),
+                    timeout: settings.timeout_ms.map_or(default.timeout, Duration::from_millis),
+                    retries: settings.retries.unwrap_or(default.retries),
+                    retry_backoff: settings.retry_backoff_ms.map_or(default.retry_backoff, Duration::from_millis),
+                };
+                (peer.clone(), policy)
+            })
+            .collect();
+        (default, per_peer)
+    }
+
+    /// Returns the limits transaction payloads are decoded with.
+    pub fn payload_limits(&self) -> PayloadLimits {
+        PayloadLimits {
+            max_bytes: self.max_payload_bytes,
+            max_depth: self.max_payload_depth,
+            max_field_length: self.max_field_length,
+            max_amount_bits: self.max_amount_bits,
+        }
+    }
+
+    /// Returns the policy of the `faucet` action, None if `faucet_cap` is unset.
+    pub fn faucet_policy(&self) -> Option<FaucetPolicy> {
+        if self.faucet_cap.is_empty() {
+            return None;
+        }
+        let cap = amount::parse(&self.faucet_cap, self.token_decimals).ok()?;
+        Some(FaucetPolicy { cap, window_blocks: self.faucet_window_blocks })
+    }
+
+    /// Returns the shortest and longest pause between polls of the chain.
+    pub fn poll_intervals(&self) -> (Duration, Duration) {
+        (Duration::from_millis(self.poll_interval_ms), Duration::from_millis(self.max_poll_interval_ms))
+    }
+
+    /// Returns the limits every action handler runs within.
+    pub fn execution_limits(&self) -> ExecutionLimits {
+        ExecutionLimits {
+            max_reads: self.max_state_reads,
+            max_writes: self.max_state_writes,
+            slow_handler: Duration::from_millis(self.slow_handler_ms),
+        }
+    }
+
+    /// Applies command line flags over the loaded settings and keeps them for `reload`.
+    pub fn apply_flags(&mut self, flags: FlagOverrides) {
+        if let Some(port) = flags.port {
+            self.port = port;
+        }
+        if let Some(database_path) = &flags.database_path {
+            self.database_path = database_path.clone();
+        }
+        self.flags = flags;
+    }
+
+    /// Reads the configuration again from the file it was loaded from, with
+    /// the same environment and command line overrides. Settings that only
+    /// take effect at startup must be unchanged; otherwise the reload fails
+    /// naming them.
+    pub fn reload(&self) -> Result<Config, Error> {
+        let mut reloaded = Config::load_from(self.config_file.as_deref())?;
+        reloaded.apply_flags(self.flags.clone());
+
+        let vida_setup = |config: &Config| -> Vec<(u64, u64, Vec<String>)> {
+            config.vidas().into_iter().map(|vida| (vida.id, vida.start_block, vida.actions)).collect()
+        };
+        let fixed = [
+            ("vida_id, vidas and actions", vida_setup(self) != vida_setup(&reloaded)),
+            ("rpc_url", self.rpc_url != reloaded.rpc_url || self.fallback_rpc_urls != reloaded.fallback_rpc_urls),
+            ("poll_interval_ms and max_poll_interval_ms", self.poll_intervals() != reloaded.poll_intervals()),
+            ("port", self.port != reloaded.port),
+            ("grpc_port", self.grpc_port != reloaded.grpc_port),
+            ("database_path", self.database_path != reloaded.database_path),
+            ("database_name", self.database_name != reloaded.database_name),
+            ("node_key_file", self.node_key_file != reloaded.node_key_file),
+            ("tls_cert_file and tls_key_file", self.tls_cert_file != reloaded.tls_cert_file || self.tls_key_file != reloaded.tls_key_file),
+            ("cors_origins and cors_methods", self.cors_origins != reloaded.cors_origins || self.cors_methods != reloaded.cors_methods),
+            ("cache_max_age_secs", self.cache_max_age_secs != reloaded.cache_max_age_secs),
+            ("log_format", self.log_format != reloaded.log_format),
+            ("genesis_file", self.genesis_file != reloaded.genesis_file),
+            ("payload limits", self.payload_limits() != reloaded.payload_limits()),
+            ("token_decimals", self.token_decimals != reloaded.token_decimals),
+            ("faucet_cap and faucet_window_blocks", self.faucet_policy() != reloaded.faucet_policy()),
+            ("max_state_reads and max_state_writes", (self.max_state_reads, self.max_state_writes) != (reloaded.max_state_reads, reloaded.max_state_writes)),
+            ("replica_of", self.replica_of != reloaded.replica_of),
+            ("postgres_url", self.postgres_url != reloaded.postgres_url),
+            ("conduit_wallet_file and conduit_submitters", self.conduit_wallet_file != reloaded.conduit_wallet_file || self.conduit_submitters != reloaded.conduit_submitters),
+            ("broker, broker_url and broker_topic", (&self.broker, &self.broker_url, &self.broker_topic) != (&reloaded.broker, &reloaded.broker_url, &reloaded.broker_topic)),
+        ];
+        let changed: Vec<&str> = fixed.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name).collect();
+        if !changed.is_empty() {
+            return Err(Error::Config(format!(
+                "{} cannot change while the node runs; restart it to apply them",
+                changed.join(", ")
+            )));
+        }
+        Ok(reloaded)
+    }
+
+    /// Returns every VIDA synced by the node, the primary one first.
+    pub fn vidas(&self) -> Vec<VidaConfig> {
+        let primary = VidaConfig {
+            id: self.vida_id,
+            start_block: self.start_block,
+            actions: self.actions.clone(),
+            pinned_block: self.pinned_block,
+            pinned_root_hash: self.pinned_root_hash.clone(),
+        };
+        std::iter::once(primary).chain(self.vidas.iter().cloned()).collect()
+    }
+
+    /// Returns the RPC endpoints to use, the primary one first.
+    pub fn rpc_urls(&self) -> Vec<String> {
+        std::iter::once(self.rpc_url.clone())
+            .chain(self.fallback_rpc_urls.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the configuration of the given VIDA, if it is synced by the node.
+    pub fn vida(&self, vida_id: u64) -> Option<VidaConfig> {
+        self.vidas().into_iter().find(|vida| vida.id == vida_id)
+    }
+
+    /// Returns the address this node attests roots under: `public_address`,
+    /// or `localhost:<port>` when it is not set.
+    pub fn advertised_address(&self) -> String {
+        if self.public_address.is_empty() {
+            format!("localhost:{}", self.port)
+        } else {
+            self.public_address.clone()
+        }
+    }
+
+    // Overrides individual fields from environment variables when set
+    fn apply_env_overrides(&mut self) -> Result<(), Error> {
+        if let Ok(value) = env::var("VIDA_ID") {
+            self.vida_id = value.parse().map_err(|_| Error::Config(format!("Invalid VIDA_ID: {}", value)))?;
+        }
+        if let Ok(value) = env::var("RPC_URL") {
+            self.rpc_url = value;
+        }
+        if let Ok(value) = env::var("FALLBACK_RPC_URLS") {
+            self.fallback_rpc_urls = split_list(&value);
+        }
+        if let Ok(value) = env::var("PORT") {
+            self.port = value.parse().map_err(|_| Error::Config(format!("Invalid PORT: {}", value)))?;
+        }
+        if let Ok(value) = env::var("GRPC_PORT") {
+            self.grpc_port = value.parse().map_err(|_| Error::Config(format!("Invalid GRPC_PORT: {}", value)))?;
+        }
+        if let Ok(value) = env::var("START_BLOCK") {
+            self.start_block = value.parse().map_err(|_| Error::Config(format!("Invalid START_BLOCK: {}", value)))?;
+        }
+        if let Ok(value) = env::var("PINNED_BLOCK") {
+            self.pinned_block = value.parse().map_err(|_| Error::Config(format!("Invalid PINNED_BLOCK: {}", value)))?;
+        }
+        if let Ok(value) = env::var("PINNED_ROOT_HASH") {
+            self.pinned_root_hash = value;
+        }
+        if let Ok(value) = env::var("PEERS") {
+            self.peers = split_list(&value);
+        }
+        if let Ok(value) = env::var("REPLICA_OF") {
+            self.replica_of = value;
+        }
+        if let Ok(value) = env::var("PUBLIC_ADDRESS") {
+            self.public_address = value;
+        }
+        if let Ok(value) = env::var("NODE_KEY_FILE") {
+            self.node_key_file = value;
+        }
+        if let Ok(value) = env::var("TLS_CERT_FILE") {
+            self.tls_cert_file = value;
+        }
+        if let Ok(value) = env::var("TLS_KEY_FILE") {
+            self.tls_key_file = value;
+        }
+        if let Ok(value) = env::var("PEER_CA_FILE") {
+            self.peer_ca_file = value;
+        }
+        if let Ok(value) = env::var("ADMIN_TOKEN") {
+            self.admin_token = value;
+        }
+        if let Ok(value) = env::var("BROKER_URL") {
+            self.broker_url = value;
+        }
+        if let Ok(value) = env::var("CONDUIT_WALLET_PASSWORD") {
+            self.conduit_wallet_password = value;
+        }
+        if let Ok(value) = env::var("POSTGRES_URL") {
+            self.postgres_url = value;
+        }
+        if let Ok(value) = env::var("DATABASE_PATH") {
+            self.database_path = value;
+        }
+        if let Ok(value) = env::var("DATABASE_NAME") {
+            self.database_name = value;
+        }
+        if let Ok(value) = env::var("GENESIS_FILE") {
+            self.genesis_file = value;
+        }
+        if let Ok(value) = env::var("LOG_FORMAT") {
+            self.log_format = value;
+        }
+        if let Ok(value) = env::var("FLUSH_POLICY") {
+            self.flush_policy = value;
+        }
+        Ok(())
+    }
+}
+
+// Splits a comma-separated environment value, dropping empty entries
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -1,112 +1,3244 @@
-use std::sync::{Arc, OnceLock};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use pwr_rs::merkle_tree::{MerkleTree, MerkleTreeError};
 use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
+use tracing::info;
 
-/// Singleton service for interacting with the underlying RocksDB-backed MerkleTree.
-/// Provides methods for managing account balances, transfers, block tracking, and
-/// Merkle root hash operations.
-pub struct DatabaseService;
+use crate::address::ZERO_ADDRESS;
+use crate::balance_cache::BalanceCache;
+use crate::holder_index::HolderIndex;
+use crate::peers::{MisbehaviorReport, PeerStats};
+use crate::webhooks::Webhook;
 
-// Global static instance of the MerkleTree
-static TREE: OnceLock<Arc<MerkleTree>> = OnceLock::new();
+/// Account holding the funds of pending escrows, so balances keep adding up
+/// to the total supply. No key pair can produce this address.
+pub const ESCROW_ACCOUNT: [u8; 20] = *b"escrow\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+
+/// Account holding the still locked part of vesting schedules.
+pub const VESTING_ACCOUNT: [u8; 20] = *b"vesting\0\0\0\0\0\0\0\0\0\0\0\0\0";
+
+/// Token whose balances and supply are keyed by the address alone. Other
+/// tokens of a VIDA put the token id before the address.
+pub const DEFAULT_TOKEN: u64 = 0;
+
+/// Direction of a transfer relative to the account whose history it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// Entry in an account's transaction history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionRecord {
+    pub block_number: u64,
+    pub counterparty: String,
+    pub amount: String,
+    pub direction: Direction,
+    /// Token moved; absent for the default token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<u64>,
+    /// Free-form memo supplied with the transfer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Payer-supplied reference, e.g. an invoice number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+/// Result of checking the recorded total supply against the sum of all balances.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplyAudit {
+    /// Supply recorded by genesis, mints and burns; None for untracked databases.
+    pub recorded_supply: Option<String>,
+    pub balance_sum: String,
+    pub holders: u64,
+    pub consistent: bool,
+}
+
+/// One account balance, as yielded by `DatabaseService::balances`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceEntry {
+    pub token_id: u64,
+    pub address: Vec<u8>,
+    pub balance: BigUint,
+}
+
+/// Iterator over the non-zero balances of a VIDA, walking the key index.
+pub struct Balances {
+    trees: TreeSet,
+    next: u64,
+    count: u64,
+}
+
+impl Iterator for Balances {
+    type Item = Result<BalanceEntry, MerkleTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.count {
+            let index_key = [KEY_INDEX_PREFIX, &self.next.to_be_bytes()[..]].concat();
+            self.next += 1;
+            let key = match self.trees.journal.get_data(&index_key) {
+                Ok(Some(key)) => key,
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            let Some(token_id) = DatabaseService::balance_token(&key) else { continue };
+            match self.trees.tree.get_data(&key) {
+                Ok(Some(value)) if value.iter().any(|byte| *byte != 0) => {
+                    return Some(Ok(BalanceEntry {
+                        token_id,
+                        address: key[key.len() - ADDRESS_LENGTH..].to_vec(),
+                        balance: BigUint::from_bytes_be(&value),
+                    }));
+                }
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// Outcome of replaying the change journal of a VIDA from genesis.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerAudit {
+    pub blocks_replayed: u64,
+    pub balances_checked: u64,
+    /// First balance the replay disagrees with, None if every balance matches.
+    pub divergence: Option<LedgerDivergence>,
+}
+
+/// Balance whose replayed value differs from the one the node stored.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerDivergence {
+    pub block_number: u64,
+    pub token_id: u64,
+    pub address: String,
+    pub replayed: String,
+    pub recorded: String,
+    /// Root hash the node recorded for the block, to compare with other nodes.
+    pub root_hash: Option<String>,
+}
+
+/// Largest holders of a token, largest first, as (address, balance) pairs.
+#[derive(Debug, Clone)]
+pub struct TopHolders {
+    pub top: Vec<(Vec<u8>, BigUint)>,
+    /// Number of accounts with a non-zero balance.
+    pub holders: usize,
+    pub balance_sum: BigUint,
+}
+
+/// Aggregate figures of a VIDA's state, kept up to date as blocks are applied.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStats {
+    /// Accounts holding a non-zero balance of the default token.
+    pub accounts: usize,
+    /// Transfers applied by the blocks of this database, since its creation or last state import.
+    pub transfers: u64,
+    /// Keys ever written to the state tree, including those since cleared.
+    pub state_keys: u64,
+    /// Unix time in seconds of the last flush to disk, None if there was none since startup.
+    pub last_flush: Option<u64>,
+    pub flush: FlushMetrics,
+}
+
+/// How long a VIDA's flushes to disk took since startup. A flush writes the
+/// tree's pending nodes and hashes, so large batches take longest.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlushMetrics {
+    pub flushes: u64,
+    pub last_ms: u64,
+    pub max_ms: u64,
+    pub total_ms: u64,
+    /// How long the flush under way has been running, None if there is none.
+    pub running_ms: Option<u64>,
+}
+
+/// Funds locked by the `escrow` action until the receiver claims them or
+/// they are refunded after `expiry_block`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Escrow {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: String,
+    pub token_id: u64,
+    pub expiry_block: u64,
+}
+
+/// Tokens burned by the `withdraw` action, to be paid out in PWR to
+/// `receiver` on the base layer by a conduit node once `block_number` is
+/// committed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawal {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub block_number: u64,
+}
+
+/// Funds locked by the `vest` action for `beneficiary`. Nothing is released
+/// before `cliff_block`; from then on the amount vests linearly from
+/// `start_block` to `end_block`, and `released` tracks what was paid out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VestingSchedule {
+    pub sender: String,
+    pub beneficiary: String,
+    pub amount: String,
+    pub released: String,
+    pub token_id: u64,
+    pub start_block: u64,
+    pub cliff_block: u64,
+    pub end_block: u64,
+}
+
+/// Fee charged on every transfer and `transferFrom`, on top of the amount
+/// moved and in the same token: `flat` plus `basis_points` / 10000 of the
+/// amount, credited to `collector`. Changed only through the `setFees`
+/// admin action or a governance proposal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeePolicy {
+    pub flat: String,
+    #[serde(alias = "basis_points")]
+    pub basis_points: u32,
+    pub collector: String,
+}
+
+impl FeePolicy {
+    /// Returns the fee due on a transfer of `amount`, or an error if `flat`
+    /// is not a decimal amount.
+    pub fn fee(&self, amount: &BigUint) -> Result<BigUint, MerkleTreeError> {
+        let flat: BigUint = self.flat.parse()
+            .map_err(|_| MerkleTreeError::InvalidArgument(format!("Invalid flat fee: {}", self.flat)))?;
+        Ok(flat + amount * BigUint::from(self.basis_points) / BigUint::from(10_000u32))
+    }
+}
+
+/// How governance proposals are decided: votes stay open for
+/// `voting_period` blocks, and a proposal passes if more of the default
+/// token's supply backs it than opposes it, with at least
+/// `quorum_basis_points` / 10000 of the total supply in favour.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GovernancePolicy {
+    pub quorum_basis_points: u32,
+    pub voting_period: u64,
+}
+
+impl Default for GovernancePolicy {
+    fn default() -> Self {
+        GovernancePolicy { quorum_basis_points: 2_000, voting_period: 1_000 }
+    }
+}
+
+/// Outcome of a governance proposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProposalStatus {
+    Open,
+    Enacted,
+    Rejected,
+}
+
+/// Parameter change submitted with the `propose` action. Votes map hex
+/// voter addresses to their support and are weighted by the voters'
+/// balances when the proposal is enacted, so moving tokens between
+/// addresses cannot count them twice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Proposal {
+    pub proposer: String,
+    pub parameter: String,
+    pub value: serde_json::Value,
+    pub end_block: u64,
+    pub votes: BTreeMap<String, bool>,
+    pub status: ProposalStatus,
+}
+
+/// Account created by the `createMultisig` action. It holds funds like any
+/// address, but only spends them through transactions submitted by one of
+/// its `owners` and approved by `threshold` of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Multisig {
+    pub owners: Vec<String>,
+    pub threshold: u32,
+    pub transaction_count: u64,
+}
+
+/// Spend submitted from a multisig account. `approvals` holds the hex
+/// addresses of the owners that approved it; it is executed once they reach
+/// the account's threshold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultisigTransaction {
+    pub receiver: String,
+    pub amount: String,
+    pub token_id: u64,
+    pub approvals: BTreeSet<String>,
+    pub executed: bool,
+}
+
+/// A state mutation applied by a VIDA transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StateChange {
+    Transfer {
+        from: String,
+        to: String,
+        amount: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<u64>,
+    },
+    Mint {
+        to: String,
+        amount: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<u64>,
+    },
+    Burn {
+        from: String,
+        amount: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<u64>,
+    },
+    Nonce { address: String, nonce: u64 },
+    Delegate { owner: String, spender: String, enabled: bool },
+    Freeze { address: String, frozen: bool },
+    Allowance {
+        owner: String,
+        spender: String,
+        amount: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<u64>,
+    },
+    Fee {
+        from: String,
+        collector: String,
+        amount: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<u64>,
+    },
+    FeePolicy { flat: String, basis_points: u32, collector: String },
+}
+
+/// Entry of the change journal: a mutation and the transaction that applied it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeRecord {
+    pub block_number: u64,
+    /// Position of the transaction among those applied in its block.
+    pub tx_index: u32,
+    #[serde(flatten)]
+    pub change: StateChange,
+}
+
+/// A tree key whose value differs between two blocks, with both values in
+/// hex; an empty value means the key held nothing at that block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiffEntry {
+    pub key: String,
+    pub from_value: String,
+    pub to_value: String,
+    /// Position of the key in the key index, given for keys that held
+    /// nothing at the first block. The root depends on the order keys were
+    /// added in, so a node applying the diff adds new keys in this order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<u64>,
+}
+
+/// Outcome of a processed transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    Applied,
+    InsufficientFunds,
+    InvalidPayload,
+    Unauthorized,
+    /// Refused for any other reason, such as a stale nonce.
+    Rejected,
+}
+
+/// Receipt of a processed transaction, kept in the journal so clients can
+/// confirm its outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub tx_hash: String,
+    pub block_number: u64,
+    /// Position of the transaction among those processed in its block.
+    pub index: u32,
+    /// Hex sender; absent from receipts stored by earlier releases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    /// Lowercase action of the payload, absent if it had none or could not be decoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    pub status: ReceiptStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Transaction that could not be applied, kept in the dead-letter queue of
+/// its VIDA until it is reprocessed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedTransaction {
+    pub hash: String,
+    pub block_number: u64,
+    pub sender: String,
+    /// Raw transaction data, hex encoded.
+    pub data: String,
+    pub reason: String,
+}
+
+/// Full key/value contents of a VIDA tree at a checkpoint, in leaf insertion
+/// order so that importing it reproduces the same Merkle root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateSnapshot {
+    pub vida_id: u64,
+    pub block_number: u64,
+    pub root_hash: String,
+    pub entries: Vec<(String, String)>,
+}
+
+/// Service for interacting with the underlying RocksDB-backed MerkleTrees.
+/// Every synced VIDA has its own tree, so its keys and root hash are isolated from
+/// the other VIDAs served by the node. Node-local metadata (the last checked block
+/// and validated block roots) lives in a separate store outside the Merkle tree, so
+/// nodes with identical VIDA state always have identical roots. Provides methods for
+/// managing account balances, transfers, block tracking, and Merkle root hash operations.
+///
+/// A `DatabaseService` is a cheap-to-clone handle: clones share the same stores.
+/// Open one with `open` and pass it where it is needed; `initialize` and `global`
+/// keep the former process-wide singleton working for existing callers.
+#[derive(Clone)]
+pub struct DatabaseService {
+    stores: Arc<HashMap<u64, VidaStore>>,
+    // Node-wide metadata not tied to a VIDA, such as peer statistics
+    node: Tree,
+}
+
+// Storage belonging to a single VIDA
+struct VidaStore {
+    tree_name: String,
+    // Records which generation of trees is active; replaced wholesale on state import
+    meta: Tree,
+    trees: RwLock<TreeSet>,
+    // Previous values of keys modified since the last committed block, None
+    // for keys that did not exist
+    undo_log: Mutex<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    // Write batch collecting the changes of the blocks since the last commit
+    batch: Mutex<Option<WriteBatch>>,
+    // Addresses whose balance changed in the open batch, reported after commit
+    changed_balances: Mutex<BTreeSet<Vec<u8>>>,
+    // Write-through cache of hot balances; locked around tree access to balance keys
+    balance_cache: Mutex<BalanceCache>,
+    // Balances ordered by amount for the rich list, built on first use
+    holders: Mutex<HolderIndex>,
+    // Unix time in seconds of the last flush to disk
+    last_flush: Mutex<Option<u64>>,
+    // Durations of past flushes, and the start of the one under way
+    flush_metrics: Mutex<(FlushMetrics, Option<Instant>)>,
+}
+
+// Bookkeeping for an open write batch
+#[derive(Clone, Copy)]
+struct WriteBatch {
+    first_block: u64,
+    // Block of the transaction being applied, used to date balance history
+    current_block: u64,
+    // Key index size and last checked block when the batch was opened, restored if it is lost
+    key_count: u64,
+    last_checked_block: u64,
+    // Block and index of the transaction being applied, used to key journaled changes
+    tx_block: u64,
+    tx_index: u32,
+}
+
+// Active tree generation of a VIDA
+#[derive(Clone)]
+struct TreeSet {
+    tree: Tree,
+    // Side store for node-local metadata, per-block undo records and the key index;
+    // its root hash is never used
+    journal: Tree,
+}
+
+// Stored for the empty value of a cleared key, as pwr-rs refuses empty data. No
+// record encodes to it, so it reads back as empty
+const TOMBSTONE: &[u8] = &[0, 0];
+
+// A Merkle tree, or a copy-on-write view of one that keeps its writes in memory
+#[derive(Clone)]
+struct Tree {
+    base: Arc<MerkleTree>,
+    // Values written through a view, shadowing those of the base tree
+    overlay: Option<Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>>,
+    // Whether accesses count against the meter of a running handler; only
+    // the state tree's do, the journal holds node-local data
+    metered: bool,
+}
+
+impl Tree {
+    fn new(name: String) -> Result<Tree, MerkleTreeError> {
+        Ok(Tree { base: MerkleTree::new(name)?, overlay: None, metered: false })
+    }
+
+    // Starts an empty view over the current contents of this tree
+    fn view(&self) -> Tree {
+        Tree { base: self.base.clone(), overlay: Some(Arc::default()), metered: self.metered }
+    }
+
+    fn get_data(&self, key: &[u8]) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        if self.metered {
+            Meter::count(Access::Read)?;
+        }
+        self.read(key)
+    }
+
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        if let Some(value) = Meter::buffered(self, key) {
+            return Ok(Some(value));
+        }
+        if let Some(overlay) = &self.overlay {
+            if let Some(value) = overlay.lock().unwrap().get(key) {
+                return Ok(Some(value.clone()));
+            }
+        }
+        Ok(self.base.get_data(key)?.map(|value| if value == TOMBSTONE { Vec::new() } else { value }))
+    }
+
+    fn add_or_update_data(&self, key: &[u8], data: &[u8]) -> Result<(), MerkleTreeError> {
+        if self.metered {
+            Meter::count(Access::Write)?;
+        }
+        if Meter::buffer(self, key, data) {
+            return Ok(());
+        }
+        match &self.overlay {
+            Some(overlay) => {
+                overlay.lock().unwrap().insert(key.to_vec(), data.to_vec());
+                Ok(())
+            }
+            None => self.base.add_or_update_data(key, if data.is_empty() { TOMBSTONE } else { data }),
+        }
+    }
+
+    // Tells apart the trees and views a meter buffers writes for
+    fn id(&self) -> usize {
+        match &self.overlay {
+            Some(overlay) => Arc::as_ptr(overlay) as *const () as usize,
+            None => Arc::as_ptr(&self.base) as *const () as usize,
+        }
+    }
+
+    // Clears a key. A leaf cannot leave the tree, so the key stays with an empty value
+    fn remove(&self, key: &[u8]) -> Result<(), MerkleTreeError> {
+        self.add_or_update_data(key, &[])
+    }
+
+    // A view never hashes its writes, so it has no root of its own
+    fn get_root_hash(&self) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        match &self.overlay {
+            Some(_) => Err(MerkleTreeError::IllegalState("Simulated state has no root hash".to_string())),
+            None => self.base.get_root_hash(),
+        }
+    }
+
+    fn flush_to_disk(&self) -> Result<(), MerkleTreeError> {
+        match &self.overlay {
+            Some(_) => Ok(()),
+            None => self.base.flush_to_disk(),
+        }
+    }
+
+    fn revert_unsaved_changes(&self) -> Result<(), MerkleTreeError> {
+        match &self.overlay {
+            Some(overlay) => {
+                overlay.lock().unwrap().clear();
+                Ok(())
+            }
+            None => self.base.revert_unsaved_changes(),
+        }
+    }
+}
+
+thread_local! {
+    // Meter of the transaction handler running on this thread, if any
+    static METER: RefCell<Option<Meter>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy)]
+enum Access {
+    Read,
+    Write,
+}
+
+// State accesses of a running handler, and the writes it made, held back
+// until it is known whether it stayed within its limits
+struct Meter {
+    max_reads: u64,
+    max_writes: u64,
+    reads: u64,
+    writes: u64,
+    exceeded: Option<String>,
+    // Tree, key and value of every write, oldest first, as the tree must
+    // receive them for its root to be the same as without a meter
+    written: Vec<(Tree, Vec<u8>, Vec<u8>)>,
+    // Latest value written to each key, by tree id, for the handler's reads
+    overlay: HashMap<(usize, Vec<u8>), Vec<u8>>,
+}
+
+impl Meter {
+    // Counts an access of the state tree, failing it and every later one
+    // once a limit is exceeded
+    fn count(access: Access) -> Result<(), MerkleTreeError> {
+        METER.with(|meter| {
+            let mut meter = meter.borrow_mut();
+            let Some(meter) = meter.as_mut() else {
+                return Ok(());
+            };
+            if meter.exceeded.is_none() {
+                match access {
+                    Access::Read => meter.reads += 1,
+                    Access::Write => meter.writes += 1,
+                }
+                if meter.reads > meter.max_reads {
+                    meter.exceeded = Some(format!("Exceeded the limit of {} state reads", meter.max_reads));
+                } else if meter.writes > meter.max_writes {
+                    meter.exceeded = Some(format!("Exceeded the limit of {} state writes", meter.max_writes));
+                }
+            }
+            match &meter.exceeded {
+                Some(reason) => Err(MerkleTreeError::IllegalState(reason.clone())),
+                None => Ok(()),
+            }
+        })
+    }
+
+    // Holds back a write while a handler runs; false if none is running
+    fn buffer(tree: &Tree, key: &[u8], data: &[u8]) -> bool {
+        METER.with(|meter| {
+            let mut meter = meter.borrow_mut();
+            let Some(meter) = meter.as_mut() else {
+                return false;
+            };
+            meter.overlay.insert((tree.id(), key.to_vec()), data.to_vec());
+            meter.written.push((tree.clone(), key.to_vec(), data.to_vec()));
+            true
+        })
+    }
+
+    // Returns the value the running handler last wrote to a key, if any
+    fn buffered(tree: &Tree, key: &[u8]) -> Option<Vec<u8>> {
+        METER.with(|meter| {
+            meter.borrow().as_ref().and_then(|meter| meter.overlay.get(&(tree.id(), key.to_vec())).cloned())
+        })
+    }
+}
+
+/// Counts a read the running transaction handler makes outside its VIDA's
+/// tree, such as of another VIDA, against its read limit.
+pub(crate) fn meter_read() -> Result<(), MerkleTreeError> {
+    Meter::count(Access::Read)
+}
+
+impl VidaStore {
+    fn tree(&self) -> Tree {
+        self.trees.read().unwrap().tree.clone()
+    }
+
+    fn journal(&self) -> Tree {
+        self.trees.read().unwrap().journal.clone()
+    }
+
+    fn start_flush(&self) {
+        self.flush_metrics.lock().unwrap().1 = Some(Instant::now());
+    }
+
+    // Records the end of the flush begun with `start_flush`, successful or not
+    fn end_flush(&self, succeeded: bool) {
+        let mut metrics = self.flush_metrics.lock().unwrap();
+        let Some(started) = metrics.1.take() else {
+            return;
+        };
+        let elapsed = started.elapsed().as_millis() as u64;
+        let metrics = &mut metrics.0;
+        metrics.flushes += 1;
+        metrics.last_ms = elapsed;
+        metrics.max_ms = metrics.max_ms.max(elapsed);
+        metrics.total_ms += elapsed;
+        if succeeded {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+            *self.last_flush.lock().unwrap() = Some(now);
+        }
+    }
+
+    fn flush_metrics(&self) -> FlushMetrics {
+        let (metrics, started) = &*self.flush_metrics.lock().unwrap();
+        FlushMetrics { running_ms: started.map(|started| started.elapsed().as_millis() as u64), ..metrics.clone() }
+    }
+}
+
+// Instance installed by `initialize` for callers without a handle
+static INSTANCE: OnceLock<DatabaseService> = OnceLock::new();
+// Directory, under the working directory, in which pwr-rs opens every tree
+const TREE_ROOT: &str = "merkleTree";
 
 // Constants
+// State tree keys begin with the type of record they hold, so account bytes can
+// never be taken for another record: `BALANCE_NAMESPACE` then the balance key for
+// balances, `RECORD_NAMESPACE` (the \x02 below) then the name for other records.
+// Node-local metadata lives in the journal, apart from the state.
+const BALANCE_NAMESPACE: u8 = 0x01;
+const RECORD_NAMESPACE: u8 = 0x02;
+const NONCE_PREFIX: &[u8] = b"\x02nonce_";
+const HISTORY_PREFIX: &[u8] = b"\x02history_";
+const HISTORY_COUNT_PREFIX: &[u8] = b"\x02historyCount_";
+const DELEGATE_PREFIX: &[u8] = b"\x02delegate_";
+const ALLOWANCE_PREFIX: &[u8] = b"\x02allowance_";
+const GENESIS_HASH_KEY: &[u8] = b"\x02genesisHash";
+const ADMINS_KEY: &[u8] = b"\x02admins";
+const TOTAL_SUPPLY_KEY: &[u8] = b"\x02totalSupply";
+const REGISTERED_PEERS_KEY: &[u8] = b"\x02registeredPeers";
+const ESCROW_PREFIX: &[u8] = b"\x02escrow_";
+const ESCROW_COUNT_KEY: &[u8] = b"\x02escrowCount";
+const ESCROW_PENDING_KEY: &[u8] = b"\x02escrowPending";
+// Left under the key of a claimed or refunded escrow; escrows are stored as JSON
+const ESCROW_SETTLED: &[u8] = &[0];
+const WITHDRAWAL_PREFIX: &[u8] = b"\x02withdrawal_";
+const WITHDRAWAL_COUNT_KEY: &[u8] = b"\x02withdrawalCount";
+const FEE_POLICY_KEY: &[u8] = b"\x02feePolicy";
+const FROZEN_PREFIX: &[u8] = b"\x02frozen_";
+const FAUCET_PREFIX: &[u8] = b"\x02faucet_";
+const GOVERNANCE_POLICY_KEY: &[u8] = b"\x02governancePolicy";
+const PROPOSAL_PREFIX: &[u8] = b"\x02proposal_";
+const PROPOSAL_COUNT_KEY: &[u8] = b"\x02proposalCount";
+const VESTING_PREFIX: &[u8] = b"\x02vesting_";
+const VESTING_COUNT_KEY: &[u8] = b"\x02vestingCount";
+const VESTING_ACTIVE_KEY: &[u8] = b"\x02vestingActive";
+const VESTING_BENEFICIARY_PREFIX: &[u8] = b"\x02vestingOf_";
+const MULTISIG_PREFIX: &[u8] = b"\x02multisig_";
+const MULTISIG_COUNT_KEY: &[u8] = b"\x02multisigCount";
+const MULTISIG_TX_PREFIX: &[u8] = b"\x02multisigTx_";
+// Journal, meta and node tree keys
 const LAST_CHECKED_BLOCK_KEY: &[u8] = b"lastCheckedBlock";
 const BLOCK_ROOT_PREFIX: &str = "blockRootHash_";
+const UNDO_HEAD_KEY: &[u8] = b"undoHead";
+const UNDO_PREFIX: &str = "undo_";
+const KEY_COUNT_KEY: &[u8] = b"keyCount";
+const KEY_INDEX_PREFIX: &[u8] = b"key_";
+const PENDING_COMMIT_KEY: &[u8] = b"pendingCommit";
+const PROCESSED_PREFIX: &str = "processed_";
+const PROCESSED_BLOCKS_KEY: &[u8] = b"processedBlocks";
+const BALANCE_HISTORY_PREFIX: &[u8] = b"balanceHistory_";
+const BALANCE_AT_PREFIX: &[u8] = b"balanceAt_";
+const CHANGE_PREFIX: &[u8] = b"change_";
+const CHANGE_COUNT_PREFIX: &[u8] = b"changeCount_";
+const CHANGE_BLOCKS_KEY: &[u8] = b"changeBlocks";
+const PEER_STATS_KEY: &[u8] = b"peerStats";
+const FAILED_PREFIX: &[u8] = b"failed_";
+const RECEIPT_PREFIX: &[u8] = b"receipt_";
+const RECEIPT_AT_PREFIX: &[u8] = b"receiptAt_";
+const WEBHOOKS_KEY: &[u8] = b"webhooks";
+const MISBEHAVIOR_KEY: &[u8] = b"misbehavior";
+const FINALIZING_PREFIX: &[u8] = b"finalizing_";
+const CONDUIT_CURSOR_PREFIX: &[u8] = b"conduitCursor_";
+const TRANSFER_COUNT_KEY: &[u8] = b"transferCount";
+const EXPECTED_ROOT_PREFIX: &[u8] = b"expectedRoot_";
+// Failed transactions kept per VIDA; the oldest are dropped beyond this
+const MAX_FAILED_TRANSACTIONS: usize = 1_000;
+// Misbehavior reports kept; the oldest are dropped beyond this
+const MAX_MISBEHAVIOR_REPORTS: usize = 1_000;
+const ACTIVE_GENERATION_KEY: &[u8] = b"activeGeneration";
+const NEXT_GENERATION_KEY: &[u8] = b"nextGeneration";
+const SCHEMA_VERSION_KEY: &[u8] = b"schemaVersion";
+// After the namespace, default token balances are keyed by the bare account
+// address, others by the token id followed by the address
+const ADDRESS_LENGTH: usize = 20;
+const TOKEN_BALANCE_KEY_LENGTH: usize = 8 + ADDRESS_LENGTH;
+// Length marking the value of an undo entry whose key did not exist before
+const ABSENT: u32 = u32::MAX;
+// Balances cached per VIDA unless changed with `set_balance_cache_capacity`
+const DEFAULT_BALANCE_CACHE_SIZE: usize = 10_000;
+
+/// Version of the on-disk layout this build reads and writes. Stores of an
+/// older version are migrated when opened, newer ones are refused.
+pub const SCHEMA_VERSION: u64 = 1;
+
+// Upgrades of a store's layout, the one at index i taking version i to i + 1.
+// Each must cope with an empty store, and record the version it reaches with
+// `record_schema_version` in the meta flush that makes its changes live, so
+// an interrupted migration runs again from the start.
+type Migration = fn(&VidaStore, u64) -> Result<(), MerkleTreeError>;
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    // 1: state keys namespaced by record type
+    DatabaseService::migrate_key_layout,
+];
+
+impl DatabaseService {
+    /// Opens the database as the process-wide instance returned by `global`.
+    /// Can only be called once.
+    pub fn initialize(path: &Path, name: &str, vida_ids: &[u64]) -> Result<DatabaseService, MerkleTreeError> {
+        let service = Self::open(path, name, vida_ids)?;
+        INSTANCE.set(service.clone()).map_err(|_| {
+            MerkleTreeError::IllegalState("DatabaseService already initialized".to_string())
+        })?;
+        Ok(service)
+    }
+
+    /// Returns the instance installed by `initialize`.
+    pub fn global() -> Result<DatabaseService, MerkleTreeError> {
+        INSTANCE.get().cloned().ok_or_else(|| {
+            MerkleTreeError::IllegalState("DatabaseService not initialized. Call initialize() first.".to_string())
+        })
+    }
+
+    /// Opens a database with one tree per VIDA under the `path` directory,
+    /// created if missing. The first VIDA keeps the configured tree name so
+    /// existing databases stay readable; the others use `<name>_<vida_id>`.
+    /// pwr-rs only opens trees below `merkleTree` in the working directory,
+    /// which is created empty and left unused.
+    pub fn open(path: &Path, name: &str, vida_ids: &[u64]) -> Result<DatabaseService, MerkleTreeError> {
+        fs::create_dir_all(path).map_err(|e| {
+            MerkleTreeError::InvalidArgument(format!("Cannot create database directory {}: {}", path.display(), e))
+        })?;
+        let directory = Self::tree_directory(path)?;
+
+        let mut stores = HashMap::new();
+        for (index, vida_id) in vida_ids.iter().enumerate() {
+            let file_name = if index == 0 { name.to_string() } else { format!("{}_{}", name, vida_id) };
+            let tree_name = directory.join(file_name).to_string_lossy().into_owned();
+            let meta = Tree::new(format!("{}Meta", tree_name))?;
+            let generation = Self::decode_u64(&meta.get_data(ACTIVE_GENERATION_KEY)?.unwrap_or_default())?;
+            let store = VidaStore {
+                trees: RwLock::new(Self::open_generation(&tree_name, generation)?),
+                tree_name,
+                meta,
+                undo_log: Mutex::new(BTreeMap::new()),
+                batch: Mutex::new(None),
+                changed_balances: Mutex::new(BTreeSet::new()),
+                balance_cache: Mutex::new(BalanceCache::new(DEFAULT_BALANCE_CACHE_SIZE)),
+                holders: Mutex::new(HolderIndex::default()),
+                last_flush: Mutex::new(None),
+                flush_metrics: Mutex::default(),
+            };
+            Self::recover_interrupted_commit(&store)?;
+            Self::migrate(&store)?;
+            stores.insert(*vida_id, store);
+        }
+
+        let node = Tree::new(directory.join(format!("{}Node", name)).to_string_lossy().into_owned())?;
+        Ok(DatabaseService { stores: Arc::new(stores), node })
+    }
+
+    // Names the database directory relative to `TREE_ROOT`, so that the tree
+    // path pwr-rs builds from a tree name resolves into it
+    fn tree_directory(path: &Path) -> Result<PathBuf, MerkleTreeError> {
+        let resolve = |path: &Path| path.canonicalize().map_err(|e| {
+            MerkleTreeError::InvalidArgument(format!("Cannot resolve directory {}: {}", path.display(), e))
+        });
+        let directory = resolve(path)?;
+        fs::create_dir_all(TREE_ROOT).map_err(|e| {
+            MerkleTreeError::InvalidArgument(format!("Cannot create tree directory {}: {}", TREE_ROOT, e))
+        })?;
+        let root = resolve(Path::new(TREE_ROOT))?;
+
+        let common = root.components().zip(directory.components()).take_while(|(a, b)| a == b).count();
+        let mut relative: PathBuf = (common..root.components().count()).map(|_| "..").collect();
+        relative.extend(directory.components().skip(common));
+        Ok(relative)
+    }
+
+    /// Returns the peer statistics saved by `set_peer_stats`, keyed by peer address.
+    pub fn get_peer_stats(&self) -> Result<HashMap<String, PeerStats>, MerkleTreeError> {
+        match self.node.get_data(PEER_STATS_KEY)? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt peer statistics: {}", e))),
+            _ => Ok(HashMap::new()),
+        }
+    }
+
+    /// Saves the statistics of every peer and flushes them to disk.
+    pub fn set_peer_stats(&self, stats: &HashMap<String, PeerStats>) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(stats)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode peer statistics: {}", e)))?;
+        self.node.add_or_update_data(PEER_STATS_KEY, &data)?;
+        self.node.flush_to_disk()
+    }
+
+    /// Returns the registered webhooks.
+    pub fn get_webhooks(&self) -> Result<Vec<Webhook>, MerkleTreeError> {
+        match self.node.get_data(WEBHOOKS_KEY)? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt webhooks: {}", e))),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Replaces the registered webhooks and flushes them to disk.
+    pub fn set_webhooks(&self, webhooks: &[Webhook]) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(webhooks)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode webhooks: {}", e)))?;
+        self.node.add_or_update_data(WEBHOOKS_KEY, &data)?;
+        self.node.flush_to_disk()
+    }
+
+    /// Records a misbehavior report unless the peer was already reported for
+    /// the same block, and flushes it to disk. Returns whether it was new.
+    pub fn add_misbehavior_report(&self, report: &MisbehaviorReport) -> Result<bool, MerkleTreeError> {
+        let mut reports = self.get_misbehavior_reports()?;
+        if reports.iter().any(|known| {
+            known.peer == report.peer && known.vida_id == report.vida_id && known.block_number == report.block_number
+        }) {
+            return Ok(false);
+        }
+        reports.push(report.clone());
+        let excess = reports.len().saturating_sub(MAX_MISBEHAVIOR_REPORTS);
+        reports.drain(..excess);
+        let data = serde_json::to_vec(&reports)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode misbehavior reports: {}", e)))?;
+        self.node.add_or_update_data(MISBEHAVIOR_KEY, &data)?;
+        self.node.flush_to_disk()?;
+        Ok(true)
+    }
+
+    /// Returns the recorded misbehavior reports, oldest first.
+    pub fn get_misbehavior_reports(&self) -> Result<Vec<MisbehaviorReport>, MerkleTreeError> {
+        match self.node.get_data(MISBEHAVIOR_KEY)? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt misbehavior reports: {}", e))),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Adds a transaction to the dead-letter queue of a VIDA, or updates its
+    /// reason if it is already queued.
+    pub fn add_failed_transaction(&self, vida_id: u64, failed: &FailedTransaction) -> Result<(), MerkleTreeError> {
+        let mut queue = self.get_failed_transactions(vida_id)?;
+        match queue.iter_mut().find(|queued| queued.hash == failed.hash) {
+            Some(queued) => queued.reason = failed.reason.clone(),
+            None => queue.push(failed.clone()),
+        }
+        let excess = queue.len().saturating_sub(MAX_FAILED_TRANSACTIONS);
+        queue.drain(..excess);
+        self.set_failed_transactions(vida_id, &queue)
+    }
+
+    /// Returns the dead-letter queue of a VIDA, oldest first.
+    pub fn get_failed_transactions(&self, vida_id: u64) -> Result<Vec<FailedTransaction>, MerkleTreeError> {
+        self.get_store(vida_id)?;
+        match self.node.get_data(&Self::failed_key(vida_id))? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt failed transactions: {}", e))),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Removes a transaction from the dead-letter queue of a VIDA.
+    pub fn remove_failed_transaction(&self, vida_id: u64, hash: &str) -> Result<(), MerkleTreeError> {
+        let mut queue = self.get_failed_transactions(vida_id)?;
+        queue.retain(|queued| queued.hash != hash);
+        self.set_failed_transactions(vida_id, &queue)
+    }
+
+    // Stores the dead-letter queue of a VIDA; kept outside the VIDA's batches so aborts keep it
+    fn set_failed_transactions(&self, vida_id: u64, queue: &[FailedTransaction]) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(queue)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode failed transactions: {}", e)))?;
+        self.node.add_or_update_data(&Self::failed_key(vida_id), &data)?;
+        self.node.flush_to_disk()
+    }
+
+    // Builds the node store key holding the dead-letter queue of a VIDA
+    fn failed_key(vida_id: u64) -> Vec<u8> {
+        [FAILED_PREFIX, &vida_id.to_be_bytes()[..]].concat()
+    }
+
+    /// Persists the block of a VIDA being finalized, or clears it with None.
+    /// Kept in the node store and flushed at once, so a block whose
+    /// finalization was interrupted is known after a restart.
+    pub fn set_finalizing_block(&self, vida_id: u64, block_number: Option<u64>) -> Result<(), MerkleTreeError> {
+        let key = [FINALIZING_PREFIX, &vida_id.to_be_bytes()[..]].concat();
+        match block_number {
+            Some(block) => self.node.add_or_update_data(&key, &block.to_be_bytes())?,
+            None => self.node.remove(&key)?,
+        }
+        self.node.flush_to_disk()
+    }
+
+    /// Returns the block of a VIDA whose finalization has not completed.
+    pub fn get_finalizing_block(&self, vida_id: u64) -> Result<Option<u64>, MerkleTreeError> {
+        match self.node.get_data(&[FINALIZING_PREFIX, &vida_id.to_be_bytes()[..]].concat())? {
+            Some(data) if !data.is_empty() => Ok(Some(Self::decode_u64(&data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets how many balances are cached per VIDA; 0 disables the cache.
+    pub fn set_balance_cache_capacity(&self, capacity: usize) {
+        for store in self.stores.values() {
+            store.balance_cache.lock().unwrap().set_capacity(capacity);
+        }
+    }
+    
+    /// Get the store of the given VIDA
+    fn get_store(&self, vida_id: u64) -> Result<&VidaStore, MerkleTreeError> {
+        self.stores.get(&vida_id).ok_or_else(|| {
+            MerkleTreeError::InvalidArgument(format!("VIDA {} is not synced by this node", vida_id))
+        })
+    }
+    
+    /// Get the tree instance of the given VIDA
+    fn get_tree(&self, vida_id: u64) -> Result<Tree, MerkleTreeError> {
+        Ok(self.get_store(vida_id)?.tree())
+    }
+
+    // Opens the tree and journal of a generation; generation 0 uses the original names
+    fn open_generation(tree_name: &str, generation: u64) -> Result<TreeSet, MerkleTreeError> {
+        let name = if generation == 0 { tree_name.to_string() } else { format!("{}_g{}", tree_name, generation) };
+        Ok(TreeSet {
+            tree: Tree { metered: true, ..Tree::new(name.clone())? },
+            journal: Tree::new(format!("{}Journal", name))?,
+        })
+    }
+
+    /// Returns a handle on a copy-on-write view of a VIDA's current state,
+    /// including the changes of its open write batch. Everything written
+    /// through the handle stays in memory and is dropped with it, so actions
+    /// can be tried out without touching the real state. The view has no
+    /// root hash and serves only the given VIDA.
+    pub fn simulation(&self, vida_id: u64) -> Result<DatabaseService, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let batch = *store.batch.lock().unwrap();
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        let view = VidaStore {
+            tree_name: store.tree_name.clone(),
+            meta: store.meta.view(),
+            trees: RwLock::new(TreeSet { tree: tree.view(), journal: journal.view() }),
+            undo_log: Mutex::new(BTreeMap::new()),
+            batch: Mutex::new(batch),
+            changed_balances: Mutex::new(BTreeSet::new()),
+            // Balances are read through the view rather than the shared cache
+            balance_cache: Mutex::new(BalanceCache::new(0)),
+            holders: Mutex::new(HolderIndex::default()),
+            last_flush: Mutex::new(None),
+            flush_metrics: Mutex::default(),
+        };
+        Ok(DatabaseService {
+            stores: Arc::new(HashMap::from([(vida_id, view)])),
+            node: self.node.view(),
+        })
+    }
+
+    /// Returns the balances written since the last commit as (token, address,
+    /// balance), e.g. the balances an action changed in a `simulation`.
+    pub fn pending_balances(&self, vida_id: u64) -> Result<Vec<(u64, Vec<u8>, BigUint)>, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let keys: Vec<Vec<u8>> = store.undo_log.lock().unwrap().keys().cloned().collect();
+        let mut balances = Vec::new();
+        for key in keys {
+            if let Some(token_id) = Self::balance_token(&key) {
+                let address = key[key.len() - ADDRESS_LENGTH..].to_vec();
+                let balance = self.get_balance(vida_id, token_id, &address)?;
+                balances.push((token_id, address, balance));
+            }
+        }
+        Ok(balances)
+    }
+
+    /// Returns the ids of all VIDAs with an initialized store
+    pub fn vida_ids(&self) -> Vec<u64> {
+        self.stores.keys().copied().collect()
+    }
+
+    /// Writes a key to the tree, remembering its previous value for rollback
+    /// and indexing new keys in insertion order for state export
+    fn put(&self, vida_id: u64, key: &[u8], value: &[u8]) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        let previous = tree.get_data(key)?;
+        
+        let mut undo_log = store.undo_log.lock().unwrap();
+        if !undo_log.contains_key(key) {
+            undo_log.insert(key.to_vec(), previous.clone());
+        }
+        if previous.is_none() {
+            Self::index_key(&journal, key)?;
+        }
+        tree.add_or_update_data(key, value)
+    }
+
+    // Appends a key to the insertion-ordered key index kept in the journal
+    fn index_key(journal: &Tree, key: &[u8]) -> Result<(), MerkleTreeError> {
+        let count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        journal.add_or_update_data(&[KEY_INDEX_PREFIX, &count.to_be_bytes()[..]].concat(), key)?;
+        journal.add_or_update_data(KEY_COUNT_KEY, &(count + 1).to_be_bytes())
+    }
+
+    /// Runs `f`, a transaction handler, allowing it `max_reads` reads and
+    /// `max_writes` writes of state keys. Its writes are held in memory
+    /// until it returns. Past either limit every access fails, and whatever
+    /// `f` returns its writes are dropped and the transaction is rejected.
+    /// Only deterministic counts decide this, so every node rejects the
+    /// same transactions.
+    pub fn metered(
+        &self,
+        vida_id: u64,
+        max_reads: u64,
+        max_writes: u64,
+        f: impl FnOnce() -> Result<(), String>,
+    ) -> Result<(), String> {
+        let store = self.get_store(vida_id).map_err(|e| format!("{:?}", e))?;
+        // Keys first changed in the block by `f` are told apart by starting
+        // it with an empty undo log
+        let undo_log = std::mem::take(&mut *store.undo_log.lock().unwrap());
+        METER.with(|meter| {
+            *meter.borrow_mut() = Some(Meter {
+                max_reads,
+                max_writes,
+                reads: 0,
+                writes: 0,
+                exceeded: None,
+                written: Vec::new(),
+                overlay: HashMap::new(),
+            })
+        });
+        let result = f();
+        let meter = METER.with(|meter| meter.borrow_mut().take());
+        let changed = std::mem::replace(&mut *store.undo_log.lock().unwrap(), undo_log);
+        let Some(meter) = meter else {
+            return result;
+        };
+        if let Some(reason) = meter.exceeded {
+            // Balances cached as written are no longer those of the state
+            store.balance_cache.lock().unwrap().clear();
+            store.holders.lock().unwrap().clear();
+            return Err(reason);
+        }
+
+        let mut undo_log = store.undo_log.lock().unwrap();
+        for (key, previous) in changed {
+            undo_log.entry(key).or_insert(previous);
+        }
+        drop(undo_log);
+        for (tree, key, data) in meter.written {
+            tree.add_or_update_data(&key, &data)
+                .map_err(|e| format!("Failed to write the changes of the transaction: {:?}", e))?;
+        }
+        result
+    }
+
+    /// Get current Merkle root hash
+    pub fn get_root_hash(&self, vida_id: u64) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        tree.get_root_hash()
+    }
+    
+    /// Flush pending writes to disk
+    pub fn flush(&self, vida_id: u64) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        store.start_flush();
+        let flushed = store.tree().flush_to_disk().and_then(|()| store.journal().flush_to_disk());
+        store.end_flush(flushed.is_ok());
+        flushed
+    }
+    
+    /// Reverts all unsaved changes to the Merkle tree
+    pub fn revert_unsaved_changes(&self, vida_id: u64) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let mut balance_cache = store.balance_cache.lock().unwrap();
+        balance_cache.clear();
+        store.holders.lock().unwrap().clear();
+        store.tree().revert_unsaved_changes()?;
+        store.journal().revert_unsaved_changes()?;
+        store.undo_log.lock().unwrap().clear();
+        *store.batch.lock().unwrap() = None;
+        store.changed_balances.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Opens a write batch for the changes of `block_number` and the blocks
+    /// after it, unless one is already open. Nothing written while the batch
+    /// is open reaches disk until `commit_block`; `abort_block` discards it.
+    pub fn begin_block(&self, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let mut batch = store.batch.lock().unwrap();
+        match batch.as_mut() {
+            Some(open) => open.current_block = open.current_block.max(block_number),
+            None => {
+                let key_count = Self::decode_u64(&store.journal().get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+                *batch = Some(WriteBatch {
+                    first_block: block_number,
+                    current_block: block_number,
+                    key_count,
+                    last_checked_block: self.get_last_checked_block(vida_id)?,
+                    tx_block: 0,
+                    tx_index: 0,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the next transaction of the open batch's current block, so the
+    /// changes it applies are journaled under their own transaction index.
+    /// Returns that index.
+    pub fn start_transaction(&self, vida_id: u64) -> Result<u32, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let mut batch = store.batch.lock().unwrap();
+        let batch = batch.as_mut().ok_or_else(|| MerkleTreeError::IllegalState("No write batch open".to_string()))?;
+        if batch.tx_block == batch.current_block {
+            batch.tx_index += 1;
+        } else {
+            batch.tx_block = batch.current_block;
+            batch.tx_index = 0;
+        }
+        Ok(batch.tx_index)
+    }
+
+    /// Atomically persists the open write batch as the state after `block_number`.
+    /// The journal is flushed first with a pending-commit marker, so a crash
+    /// before the tree is flushed is undone by `initialize` on the next start.
+    /// If the commit fails, the batch is reverted in full.
+    pub fn commit_block(&self, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let batch = match *store.batch.lock().unwrap() {
+            Some(batch) if batch.first_block <= block_number => batch,
+            Some(batch) => {
+                return Err(MerkleTreeError::InvalidArgument(format!(
+                    "Block {} precedes the open batch starting at block {}", block_number, batch.first_block
+                )));
+            }
+            None => return Err(MerkleTreeError::IllegalState("No write batch open".to_string())),
+        };
+
+        store.start_flush();
+        let written = self.write_batch(store, vida_id, block_number, batch);
+        store.end_flush(written.is_ok());
+        if let Err(e) = written {
+            self.abort_block(vida_id)?;
+            Self::recover_interrupted_commit(store)?;
+            return Err(e);
+        }
+        *store.batch.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Returns the addresses whose balance changed since the last call,
+    /// used to notify subscribers once a batch is committed.
+    pub fn take_changed_balances(&self, vida_id: u64) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let changed = std::mem::take(&mut *store.changed_balances.lock().unwrap());
+        Ok(changed.into_iter().collect())
+    }
+
+    /// Returns the first block of the open write batch, if one is open
+    pub fn batch_first_block(&self, vida_id: u64) -> Result<Option<u64>, MerkleTreeError> {
+        Ok(self.get_store(vida_id)?.batch.lock().unwrap().map(|batch| batch.first_block))
+    }
+
+    /// Returns whether a write batch with uncommitted changes is open
+    pub fn has_open_batch(&self, vida_id: u64) -> Result<bool, MerkleTreeError> {
+        Ok(self.get_store(vida_id)?.batch.lock().unwrap().is_some())
+    }
+
+    /// Discards every change made since the open write batch began.
+    pub fn abort_block(&self, vida_id: u64) -> Result<(), MerkleTreeError> {
+        self.revert_unsaved_changes(vida_id)
+    }
+
+    // Flushes an open batch: undo record and marker first, then the tree, then clears the marker
+    fn write_batch(&self, store: &VidaStore, vida_id: u64, block_number: u64, batch: WriteBatch) -> Result<(), MerkleTreeError> {
+        self.commit_undo_log(vida_id, block_number)?;
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+
+        // Blocks before the previous checkpoint are never redelivered
+        Self::retain_processed_blocks(&journal, |block| block >= batch.last_checked_block)?;
+
+        // Marker layout: block, key count and last checked block before the batch, then the new root
+        let root = tree.get_root_hash()?.unwrap_or_default();
+        let marker = [
+            &block_number.to_be_bytes()[..],
+            &batch.key_count.to_be_bytes(),
+            &batch.last_checked_block.to_be_bytes(),
+            &root,
+        ].concat();
+        journal.add_or_update_data(PENDING_COMMIT_KEY, &marker)?;
+        journal.flush_to_disk()?;
+        tree.flush_to_disk()?;
+        journal.remove(PENDING_COMMIT_KEY)?;
+        journal.flush_to_disk()
+    }
+
+    // Completes or undoes a commit interrupted between the journal and tree flushes.
+    // If the tree never reached the marked root, the block's undo record, root hash,
+    // indexed keys and checkpoint are dropped so the journal matches the tree again.
+    fn recover_interrupted_commit(store: &VidaStore) -> Result<(), MerkleTreeError> {
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        // A cleared marker reads back empty
+        let marker = journal.get_data(PENDING_COMMIT_KEY)?.unwrap_or_default();
+        if marker.is_empty() {
+            return Ok(());
+        }
+        if marker.len() < 24 {
+            return Err(MerkleTreeError::IllegalState("Corrupt pending commit marker".to_string()));
+        }
+        let block_number = Self::decode_u64(&marker[..8])?;
+        let last_checked_block = Self::decode_u64(&marker[16..24])?;
+
+        if tree.get_root_hash()?.unwrap_or_default() != marker[24..] {
+            let undo_key = format!("{}{}", UNDO_PREFIX, block_number);
+            if let Some(record) = journal.get_data(undo_key.as_bytes())? {
+                if record.len() >= 8 {
+                    journal.add_or_update_data(UNDO_HEAD_KEY, &record[..8])?;
+                    for (key, _) in Self::decode_undo_entries(&record[8..])? {
+                        if Self::is_balance_key(&key) {
+                            Self::truncate_balance_history(&journal, &key, last_checked_block)?;
+                        }
+                    }
+                }
+                journal.remove(undo_key.as_bytes())?;
+            }
+            journal.remove(format!("{}{}", BLOCK_ROOT_PREFIX, block_number).as_bytes())?;
+            journal.add_or_update_data(KEY_COUNT_KEY, &marker[8..16])?;
+            journal.add_or_update_data(LAST_CHECKED_BLOCK_KEY, &marker[16..24])?;
+            Self::retain_processed_blocks(&journal, |block| block <= last_checked_block)?;
+            Self::retain_change_blocks(&journal, |block| block <= last_checked_block)?;
+        }
+        journal.remove(PENDING_COMMIT_KEY)?;
+        journal.flush_to_disk()
+    }
+
+    /// Persists the undo record for all changes made since the previous commit
+    /// under the given block number, so the block can later be rolled back.
+    pub fn commit_undo_log(&self, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let journal = store.journal();
+        let entries = std::mem::take(&mut *store.undo_log.lock().unwrap());
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let previous_head = journal.get_data(UNDO_HEAD_KEY)?.unwrap_or_default();
+        let record = Self::encode_undo_record(Self::decode_u64(&previous_head)?, entries);
+        let key = format!("{}{}", UNDO_PREFIX, block_number);
+        journal.add_or_update_data(key.as_bytes(), &record)?;
+        journal.add_or_update_data(UNDO_HEAD_KEY, &block_number.to_be_bytes())
+    }
+
+    /// Restores the tree to the state it had after block `block_number` was
+    /// committed by undoing every later block in reverse order, then flushes.
+    /// Keys created after the block keep a cleared leaf, as a tree cannot drop
+    /// one, so if the root then differs from the one recorded for the block the
+    /// state is rebuilt without them in a fresh generation. Fails if even that
+    /// does not reproduce the recorded root.
+    pub fn rollback_to_block(&self, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
+        self.revert_unsaved_changes(vida_id)?;
+        let store = self.get_store(vida_id)?;
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+
+        let mut head = Self::decode_u64(&journal.get_data(UNDO_HEAD_KEY)?.unwrap_or_default())?;
+        let mut restored_balances = BTreeSet::new();
+        // Whether each undone key was absent before the oldest undone block
+        let mut absent = BTreeMap::new();
+        while head > block_number {
+            let key = format!("{}{}", UNDO_PREFIX, head);
+            let record = journal.get_data(key.as_bytes())?.ok_or_else(|| {
+                MerkleTreeError::IllegalState(format!("Missing undo record for block {}", head))
+            })?;
+            if record.len() < 8 {
+                return Err(MerkleTreeError::IllegalState(format!("Corrupt undo record for block {}", head)));
+            }
+
+            for (key, value) in Self::decode_undo_entries(&record[8..])? {
+                match &value {
+                    Some(value) => tree.add_or_update_data(&key, value)?,
+                    None => tree.remove(&key)?,
+                }
+                if Self::is_balance_key(&key) {
+                    restored_balances.insert(key.clone());
+                }
+                absent.insert(key, value.is_none());
+            }
+            journal.remove(key.as_bytes())?;
+            journal.remove(format!("{}{}", BLOCK_ROOT_PREFIX, head).as_bytes())?;
+            head = Self::decode_u64(&record[..8])?;
+        }
+
+        // Restored balances were written straight to the tree, bypassing the cache
+        store.balance_cache.lock().unwrap().clear();
+        store.holders.lock().unwrap().clear();
+        journal.add_or_update_data(UNDO_HEAD_KEY, &head.to_be_bytes())?;
+        journal.add_or_update_data(LAST_CHECKED_BLOCK_KEY, &head.to_be_bytes())?;
+        Self::retain_processed_blocks(&journal, |block| block <= head)?;
+        Self::retain_change_blocks(&journal, |block| block <= head)?;
+        for address in &restored_balances {
+            Self::truncate_balance_history(&journal, address, head)?;
+        }
+        self.flush(vida_id)?;
+
+        let recorded = journal.get_data(format!("{}{}", BLOCK_ROOT_PREFIX, head).as_bytes())?.filter(|root| !root.is_empty());
+        match recorded {
+            Some(root) if tree.get_root_hash()?.as_ref() != Some(&root) => {
+                let created: BTreeSet<Vec<u8>> = absent.into_iter().filter(|(_, absent)| *absent).map(|(key, _)| key).collect();
+                Self::compact_generation(store, &created, head, &root)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Rebuilds the state and journal of a rollback to `block_number` in a fresh
+    // generation, leaving out the `created` keys, and makes it active if it
+    // hashes to the block's recorded `root`
+    fn compact_generation(store: &VidaStore, created: &BTreeSet<Vec<u8>>, block_number: u64, root: &[u8]) -> Result<(), MerkleTreeError> {
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        let key_count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+
+        let generation = Self::decode_u64(&store.meta.get_data(NEXT_GENERATION_KEY)?.unwrap_or_default())?.max(1);
+        store.meta.add_or_update_data(NEXT_GENERATION_KEY, &(generation + 1).to_be_bytes())?;
+        store.meta.flush_to_disk()?;
+        let trees = Self::open_generation(&store.tree_name, generation)?;
+        Self::migrate_generation(&tree, &journal, &trees, key_count, |key| {
+            Some(key.to_vec()).filter(|key| !created.contains(key))
+        })?;
+        if trees.tree.get_root_hash()?.unwrap_or_default() != root {
+            return Err(MerkleTreeError::IllegalState(format!(
+                "State of {} does not reproduce the root of block {} after rollback; rebuild it", store.tree_name, block_number
+            )));
+        }
+        trees.tree.flush_to_disk()?;
+        trees.journal.flush_to_disk()?;
+        Self::activate_generation(store, generation, trees)
+    }
+
+    /// Returns every tree key whose value changed from the state after
+    /// `from_block` to the state after `to_block`, in key order. Built from the
+    /// undo records of the commits in between, so both blocks must be
+    /// committed; when a flush policy commits several blocks at once, a block
+    /// inside such a commit reads as the commit before it.
+    pub fn state_diff(&self, vida_id: u64, from_block: u64, to_block: u64) -> Result<Vec<StateDiffEntry>, MerkleTreeError> {
+        if from_block >= to_block {
+            return Err(MerkleTreeError::InvalidArgument(format!("Block {} is not before block {}", from_block, to_block)));
+        }
+        let store = self.get_store(vida_id)?;
+        let committed = match *store.batch.lock().unwrap() {
+            Some(batch) => batch.last_checked_block,
+            None => self.get_last_checked_block(vida_id)?,
+        };
+        if to_block > committed {
+            return Err(MerkleTreeError::InvalidArgument(format!("Block {} is not committed yet", to_block)));
+        }
+        // Values the open batch replaced are those of the last commit
+        let uncommitted = store.undo_log.lock().unwrap().clone();
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+
+        // Walking the undo records newest first leaves each key with its value
+        // before the oldest commit after the block
+        let mut from_values = BTreeMap::new();
+        let mut to_values = BTreeMap::new();
+        let mut head = Self::decode_u64(&journal.get_data(UNDO_HEAD_KEY)?.unwrap_or_default())?;
+        while head > from_block {
+            let record = journal.get_data(format!("{}{}", UNDO_PREFIX, head).as_bytes())?.unwrap_or_default();
+            if record.len() < 8 {
+                return Err(MerkleTreeError::IllegalState(format!("Missing undo record for block {}", head)));
+            }
+            for (key, value) in Self::decode_undo_entries(&record[8..])? {
+                if head > to_block {
+                    to_values.insert(key.clone(), value.clone());
+                }
+                from_values.insert(key, value);
+            }
+            head = Self::decode_u64(&record[..8])?;
+        }
+
+        let mut diff = Vec::new();
+        for (key, from_value) in from_values {
+            let from_value = from_value.unwrap_or_default();
+            let to_value = match to_values.get(&key).cloned().or_else(|| uncommitted.get(&key).cloned()) {
+                Some(value) => value.unwrap_or_default(),
+                None => tree.get_data(&key)?.unwrap_or_default(),
+            };
+            if from_value != to_value {
+                diff.push(StateDiffEntry {
+                    key: hex::encode(&key),
+                    from_value: hex::encode(&from_value),
+                    to_value: hex::encode(&to_value),
+                    position: None,
+                });
+            }
+        }
+        Self::locate_new_keys(&journal, &mut diff)?;
+        Ok(diff)
+    }
+
+    /// Returns every tree key the open write batch changed, with its value
+    /// at the last commit and its current value, in key order.
+    pub fn uncommitted_diff(&self, vida_id: u64) -> Result<Vec<StateDiffEntry>, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let undo_log = store.undo_log.lock().unwrap().clone();
+        let tree = store.tree();
+        let mut diff = Vec::new();
+        for (key, from_value) in undo_log {
+            let from_value = from_value.unwrap_or_default();
+            let to_value = tree.get_data(&key)?.unwrap_or_default();
+            if from_value != to_value {
+                diff.push(StateDiffEntry {
+                    key: hex::encode(&key),
+                    from_value: hex::encode(&from_value),
+                    to_value: hex::encode(&to_value),
+                    position: None,
+                });
+            }
+        }
+        Ok(diff)
+    }
+
+    // Sets the key index position of the diff entries whose key held nothing
+    // at the first block, scanning the index from its newest key
+    fn locate_new_keys(journal: &Tree, diff: &mut [StateDiffEntry]) -> Result<(), MerkleTreeError> {
+        let mut unlocated: HashMap<String, usize> = diff.iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.from_value.is_empty())
+            .map(|(i, entry)| (entry.key.clone(), i))
+            .collect();
+        let mut position = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        while position > 0 && !unlocated.is_empty() {
+            position -= 1;
+            let key = journal.get_data(&[KEY_INDEX_PREFIX, &position.to_be_bytes()[..]].concat())?.unwrap_or_default();
+            if let Some(i) = unlocated.remove(&hex::encode(&key)) {
+                diff[i].position = Some(position);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a state diff served by another node up to `block_number`, as
+    /// a replica following that node does. Keys this node already has are
+    /// written first, then new keys in the order of their `position`, so the
+    /// root matches the serving node's. The changes are left in a batch
+    /// opened for `block_number`, for the caller to commit once the root is
+    /// verified or to abort. Balance history is recorded at `block_number`
+    /// only. Returns the resulting root.
+    pub fn apply_state_diff(&self, vida_id: u64, block_number: u64, diff: &[StateDiffEntry]) -> Result<Vec<u8>, MerkleTreeError> {
+        if self.has_open_batch(vida_id)? {
+            return Err(MerkleTreeError::IllegalState("A write batch is already open".to_string()));
+        }
+        let tree = self.get_tree(vida_id)?;
+        let mut existing = Vec::new();
+        let mut added = Vec::new();
+        for entry in diff {
+            let decode = |value: &str| hex::decode(value)
+                .map_err(|_| MerkleTreeError::InvalidArgument(format!("Invalid hex in state diff entry {}", entry.key)));
+            let change = (decode(&entry.key)?, decode(&entry.to_value)?);
+            if tree.get_data(&change.0)?.is_some() {
+                existing.push(change);
+            } else {
+                let position = entry.position.ok_or_else(|| {
+                    MerkleTreeError::InvalidArgument(format!("State diff entry {} adds a key without a position", entry.key))
+                })?;
+                added.push((position, change));
+            }
+        }
+        added.sort_by_key(|(position, _)| *position);
+
+        self.begin_block(vida_id, block_number)?;
+        let changes = existing.into_iter().chain(added.into_iter().map(|(_, change)| change));
+        if let Err(e) = self.write_diff_changes(vida_id, changes) {
+            self.abort_block(vida_id)?;
+            return Err(e);
+        }
+        Ok(self.get_root_hash(vida_id)?.unwrap_or_default())
+    }
+
+    // Writes the key/value pairs of a state diff, balances through `set_balance`
+    // so the balance cache, holder index and history follow
+    fn write_diff_changes(&self, vida_id: u64, changes: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<(), MerkleTreeError> {
+        let mut cleared_balances = false;
+        for (key, value) in changes {
+            match Self::balance_token(&key) {
+                Some(token_id) if !value.is_empty() => {
+                    let address = &key[key.len() - ADDRESS_LENGTH..];
+                    self.set_balance(vida_id, token_id, address, &BigUint::from_bytes_be(&value))?;
+                }
+                token_id => {
+                    cleared_balances |= token_id.is_some();
+                    self.put(vida_id, &key, &value)?;
+                }
+            }
+        }
+        // Balances emptied by a rollback bypass the cache, as in `rollback_to_block`
+        if cleared_balances {
+            let store = self.get_store(vida_id)?;
+            store.balance_cache.lock().unwrap().clear();
+            store.holders.lock().unwrap().clear();
+        }
+        Ok(())
+    }
+
+    /// Returns the newest block before `block_number` that `rollback_to_block`
+    /// can restore exactly: the previous block that committed changes, or 0
+    /// for the state before the first one.
+    pub fn previous_checkpoint(&self, vida_id: u64, block_number: u64) -> Result<u64, MerkleTreeError> {
+        let journal = self.get_store(vida_id)?.journal();
+        let mut head = Self::decode_u64(&journal.get_data(UNDO_HEAD_KEY)?.unwrap_or_default())?;
+        while head >= block_number && head > 0 {
+            let record = journal.get_data(format!("{}{}", UNDO_PREFIX, head).as_bytes())?.unwrap_or_default();
+            if record.len() < 8 {
+                return Err(MerkleTreeError::IllegalState(format!("Missing undo record for block {}", head)));
+            }
+            head = Self::decode_u64(&record[..8])?;
+        }
+        Ok(head)
+    }
+
+    /// Records a transaction hash as applied in its block. Returns false if it
+    /// was already recorded, meaning the transaction is a redelivery to skip.
+    /// Part of the open write batch, so an aborted batch forgets its hashes.
+    pub fn mark_transaction_processed(&self, vida_id: u64, block_number: u64, hash: &[u8]) -> Result<bool, MerkleTreeError> {
+        if hash.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Transaction hash must not be empty".to_string()));
+        }
+        
+        let journal = self.get_store(vida_id)?.journal();
+        let key = format!("{}{}", PROCESSED_PREFIX, block_number);
+        let mut hashes = journal.get_data(key.as_bytes())?.unwrap_or_default();
+        let mut cursor: &[u8] = &hashes;
+        while !cursor.is_empty() {
+            if Self::read_length_prefixed(&mut cursor)? == hash {
+                return Ok(false);
+            }
+        }
+        
+        if hashes.is_empty() {
+            let mut blocks = journal.get_data(PROCESSED_BLOCKS_KEY)?.unwrap_or_default();
+            blocks.extend_from_slice(&block_number.to_be_bytes());
+            journal.add_or_update_data(PROCESSED_BLOCKS_KEY, &blocks)?;
+        }
+        hashes.extend_from_slice(&(hash.len() as u32).to_be_bytes());
+        hashes.extend_from_slice(hash);
+        journal.add_or_update_data(key.as_bytes(), &hashes)?;
+        Ok(true)
+    }
+
+    // Forgets the processed transaction hashes of every block not matching `keep`
+    fn retain_processed_blocks(journal: &Tree, keep: impl Fn(u64) -> bool) -> Result<(), MerkleTreeError> {
+        let blocks = journal.get_data(PROCESSED_BLOCKS_KEY)?.unwrap_or_default();
+        let mut retained = Vec::new();
+        for chunk in blocks.chunks(8) {
+            let block = Self::decode_u64(chunk)?;
+            if keep(block) {
+                retained.extend_from_slice(chunk);
+            } else {
+                journal.remove(format!("{}{}", PROCESSED_PREFIX, block).as_bytes())?;
+            }
+        }
+        if retained.len() != blocks.len() {
+            journal.add_or_update_data(PROCESSED_BLOCKS_KEY, &retained)?;
+        }
+        Ok(())
+    }
+
+    // Appends a change to the journal of the transaction being applied. Changes made
+    // outside a transaction, such as genesis allocations, are not journaled.
+    fn log_change(&self, vida_id: u64, change: StateChange) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let (block_number, tx_index) = match *store.batch.lock().unwrap() {
+            Some(batch) if batch.tx_block == batch.current_block => (batch.tx_block, batch.tx_index),
+            _ => return Ok(()),
+        };
+
+        let journal = store.journal();
+        let count_key = [CHANGE_COUNT_PREFIX, &block_number.to_be_bytes()[..]].concat();
+        let count = Self::decode_u64(&journal.get_data(&count_key)?.unwrap_or_default())?;
+        if count == 0 {
+            let mut blocks = journal.get_data(CHANGE_BLOCKS_KEY)?.unwrap_or_default();
+            blocks.extend_from_slice(&block_number.to_be_bytes());
+            journal.add_or_update_data(CHANGE_BLOCKS_KEY, &blocks)?;
+        }
+        if matches!(change, StateChange::Transfer { .. }) {
+            let transfers = Self::decode_u64(&journal.get_data(TRANSFER_COUNT_KEY)?.unwrap_or_default())?;
+            journal.add_or_update_data(TRANSFER_COUNT_KEY, &(transfers + 1).to_be_bytes())?;
+        }
+        let record = ChangeRecord { block_number, tx_index, change };
+        let data = serde_json::to_vec(&record)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode change record: {}", e)))?;
+        journal.add_or_update_data(&Self::change_key(block_number, count), &data)?;
+        journal.add_or_update_data(&count_key, &(count + 1).to_be_bytes())
+    }
+
+    /// Stores the receipt of a transaction, replacing the one from an earlier
+    /// attempt to apply it.
+    pub fn put_receipt(&self, vida_id: u64, hash: &[u8], receipt: &Receipt) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(receipt)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode receipt: {}", e)))?;
+        let journal = self.get_store(vida_id)?.journal();
+        journal.add_or_update_data(&Self::receipt_at_key(receipt.block_number, receipt.index), hash)?;
+        journal.add_or_update_data(&[RECEIPT_PREFIX, hash].concat(), &data)
+    }
+
+    /// Returns the receipt of the transaction applied at `index` in `block_number`.
+    pub fn get_receipt_at(&self, vida_id: u64, block_number: u64, index: u32) -> Result<Option<Receipt>, MerkleTreeError> {
+        match self.get_store(vida_id)?.journal().get_data(&Self::receipt_at_key(block_number, index))? {
+            Some(hash) if !hash.is_empty() => self.get_receipt(vida_id, &hash),
+            _ => Ok(None),
+        }
+    }
+
+    // Builds the journal key naming the transaction at a position in a block
+    fn receipt_at_key(block_number: u64, index: u32) -> Vec<u8> {
+        [RECEIPT_AT_PREFIX, &block_number.to_be_bytes()[..], &index.to_be_bytes()[..]].concat()
+    }
+
+    /// Returns the receipt of a transaction, or None if it was not processed.
+    pub fn get_receipt(&self, vida_id: u64, hash: &[u8]) -> Result<Option<Receipt>, MerkleTreeError> {
+        match self.get_store(vida_id)?.journal().get_data(&[RECEIPT_PREFIX, hash].concat())? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt receipt: {}", e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the changes applied in `block_number`, in the order they were
+    /// applied. Blocks past the last checkpoint are rejected.
+    pub fn get_changes(&self, vida_id: u64, block_number: u64) -> Result<Vec<ChangeRecord>, MerkleTreeError> {
+        if block_number > self.get_last_checked_block(vida_id)? {
+            return Err(MerkleTreeError::InvalidArgument(format!("Block {} has not been processed yet", block_number)));
+        }
+
+        let journal = self.get_store(vida_id)?.journal();
+        let count_key = [CHANGE_COUNT_PREFIX, &block_number.to_be_bytes()[..]].concat();
+        let count = Self::decode_u64(&journal.get_data(&count_key)?.unwrap_or_default())?;
+        let mut records = Vec::new();
+        for index in 0..count {
+            let data = journal.get_data(&Self::change_key(block_number, index))?.ok_or_else(|| {
+                MerkleTreeError::IllegalState(format!("Missing change {} of block {}", index, block_number))
+            })?;
+            let record = serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt change record: {}", e)))?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    // Forgets the journaled changes of every block not matching `keep`, and their transfers
+    fn retain_change_blocks(journal: &Tree, keep: impl Fn(u64) -> bool) -> Result<(), MerkleTreeError> {
+        let blocks = journal.get_data(CHANGE_BLOCKS_KEY)?.unwrap_or_default();
+        let mut retained = Vec::new();
+        let mut dropped_transfers = 0;
+        for chunk in blocks.chunks(8) {
+            let block = Self::decode_u64(chunk)?;
+            if keep(block) {
+                retained.extend_from_slice(chunk);
+                continue;
+            }
+            let count_key = [CHANGE_COUNT_PREFIX, chunk].concat();
+            let count = Self::decode_u64(&journal.get_data(&count_key)?.unwrap_or_default())?;
+            for index in 0..count {
+                let change_key = Self::change_key(block, index);
+                let is_transfer = journal.get_data(&change_key)?
+                    .and_then(|data| serde_json::from_slice::<ChangeRecord>(&data).ok())
+                    .map_or(false, |record| matches!(record.change, StateChange::Transfer { .. }));
+                if is_transfer {
+                    dropped_transfers += 1;
+                }
+                journal.remove(&change_key)?;
+            }
+            journal.add_or_update_data(&count_key, &0u64.to_be_bytes())?;
+        }
+        if retained.len() != blocks.len() {
+            journal.add_or_update_data(CHANGE_BLOCKS_KEY, &retained)?;
+        }
+        // Transfers of dropped blocks no longer count as applied
+        if dropped_transfers > 0 {
+            let transfers = Self::decode_u64(&journal.get_data(TRANSFER_COUNT_KEY)?.unwrap_or_default())?;
+            journal.add_or_update_data(TRANSFER_COUNT_KEY, &transfers.saturating_sub(dropped_transfers).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Builds the journal key of the change at the given index of a block
+    fn change_key(block_number: u64, index: u64) -> Vec<u8> {
+        [CHANGE_PREFIX, &block_number.to_be_bytes()[..], &index.to_be_bytes()[..]].concat()
+    }
+
+    /// Exports every key/value pair of the VIDA tree in insertion order. Only
+    /// possible at a checkpoint boundary, when no changes are pending.
+    pub fn export_state(&self, vida_id: u64) -> Result<StateSnapshot, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let undo_log = store.undo_log.lock().unwrap();
+        if !undo_log.is_empty() {
+            return Err(MerkleTreeError::IllegalState("Changes pending, retry after the next checkpoint".to_string()));
+        }
+        
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        let count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        let mut entries = Vec::new();
+        for index in 0..count {
+            let index_key = [KEY_INDEX_PREFIX, &index.to_be_bytes()[..]].concat();
+            let key = journal.get_data(&index_key)?.ok_or_else(|| {
+                MerkleTreeError::IllegalState(format!("Missing key index entry {}", index))
+            })?;
+            if let Some(value) = tree.get_data(&key)? {
+                entries.push((hex::encode(&key), hex::encode(&value)));
+            }
+        }
+        
+        Ok(StateSnapshot {
+            vida_id,
+            block_number: self.get_last_checked_block(vida_id)?,
+            root_hash: hex::encode(tree.get_root_hash()?.unwrap_or_default()),
+            entries,
+        })
+    }
+
+    /// Builds a fresh tree generation from a snapshot and makes it active.
+    /// The snapshot is only accepted if the rebuilt tree hashes to both
+    /// `block_root` and the snapshot's declared root.
+    pub fn import_state(&self, vida_id: u64, snapshot: &StateSnapshot, block_root: &[u8]) -> Result<(), MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let invalid = |message: &str| MerkleTreeError::InvalidArgument(message.to_string());
+        
+        // A new generation per attempt keeps a failed import from leaving stale data behind
+        let generation = Self::decode_u64(&store.meta.get_data(NEXT_GENERATION_KEY)?.unwrap_or_default())?.max(1);
+        store.meta.add_or_update_data(NEXT_GENERATION_KEY, &(generation + 1).to_be_bytes())?;
+        store.meta.flush_to_disk()?;
+        let trees = Self::open_generation(&store.tree_name, generation)?;
+        
+        for (key_hex, value_hex) in &snapshot.entries {
+            let key = hex::decode(key_hex).map_err(|_| invalid("Invalid snapshot key"))?;
+            let value = hex::decode(value_hex).map_err(|_| invalid("Invalid snapshot value"))?;
+            if !matches!(key.first(), Some(&(BALANCE_NAMESPACE | RECORD_NAMESPACE))) {
+                return Err(invalid("Snapshot predates namespaced state keys"));
+            }
+            trees.tree.add_or_update_data(&key, &value)?;
+            Self::index_key(&trees.journal, &key)?;
+        }
+        
+        let root = trees.tree.get_root_hash()?.unwrap_or_default();
+        if root != block_root {
+            return Err(invalid("Snapshot does not match the agreed root hash"));
+        }
+        if hex::encode(&root) != snapshot.root_hash {
+            return Err(invalid("Snapshot does not match its declared root hash"));
+        }
+        trees.journal.add_or_update_data(LAST_CHECKED_BLOCK_KEY, &snapshot.block_number.to_be_bytes())?;
+        trees.journal.add_or_update_data(format!("{}{}", BLOCK_ROOT_PREFIX, snapshot.block_number).as_bytes(), &root)?;
+        trees.tree.flush_to_disk()?;
+        trees.journal.flush_to_disk()?;
+        Self::activate_generation(store, generation, trees)
+    }
+
+    /// Switches a VIDA to a fresh, empty tree generation, so its state can be
+    /// rebuilt from genesis by applying every block again. The roots recorded
+    /// for the blocks already synced are carried over as expected roots, which
+    /// the rebuilt state must reproduce; see `get_expected_root`. The previous
+    /// generation stays on disk untouched. Returns the number of roots carried over.
+    pub fn begin_rebuild(&self, vida_id: u64) -> Result<u64, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let journal = store.journal();
+        let last_checked_block = self.get_last_checked_block(vida_id)?;
+        
+        let generation = Self::decode_u64(&store.meta.get_data(NEXT_GENERATION_KEY)?.unwrap_or_default())?.max(1);
+        store.meta.add_or_update_data(NEXT_GENERATION_KEY, &(generation + 1).to_be_bytes())?;
+        store.meta.flush_to_disk()?;
+        let trees = Self::open_generation(&store.tree_name, generation)?;
+        
+        let mut carried = 0;
+        for block_number in 1..=last_checked_block {
+            let key = format!("{}{}", BLOCK_ROOT_PREFIX, block_number);
+            if let Some(root) = journal.get_data(key.as_bytes())?.filter(|root| !root.is_empty()) {
+                trees.journal.add_or_update_data(&Self::expected_root_key(block_number), &root)?;
+                carried += 1;
+            }
+        }
+        trees.journal.flush_to_disk()?;
+        Self::activate_generation(store, generation, trees)?;
+        Ok(carried)
+    }
+    
+    // Brings a store to `SCHEMA_VERSION` by running, in order, every migration
+    // past the version it records, or refuses it if a newer build wrote it
+    fn migrate(store: &VidaStore) -> Result<(), MerkleTreeError> {
+        let version = match store.meta.get_data(SCHEMA_VERSION_KEY)? {
+            Some(data) if !data.is_empty() => Self::decode_u64(&data)?,
+            _ => 0,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(MerkleTreeError::IllegalState(format!(
+                "{} has schema version {}, newer than version {} of this build; upgrade the node",
+                store.tree_name, version, SCHEMA_VERSION
+            )));
+        }
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let to = from as u64 + 1;
+            info!("Migrating {} from schema version {} to {}", store.tree_name, from, to);
+            migration(store, to)?;
+        }
+        Ok(())
+    }
+
+    // Writes the schema version a migration reaches, for its final meta flush
+    fn record_schema_version(store: &VidaStore, version: u64) -> Result<(), MerkleTreeError> {
+        store.meta.add_or_update_data(SCHEMA_VERSION_KEY, &version.to_be_bytes())
+    }
+
+    // Moves a store written before state keys were namespaced to the current
+    // layout. The state is rebuilt in a fresh generation in key index order,
+    // which gives every node migrating the same state the same new root, and
+    // the journal follows with its keys rewritten. The old generation is only
+    // read, so an interrupted migration starts over on the next open.
+    fn migrate_key_layout(store: &VidaStore, version: u64) -> Result<(), MerkleTreeError> {
+        let TreeSet { tree, journal } = store.trees.read().unwrap().clone();
+        if tree.get_root_hash()?.is_none() {
+            Self::record_schema_version(store, version)?;
+            return store.meta.flush_to_disk();
+        }
+        let key_count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        if key_count == 0 {
+            return Err(MerkleTreeError::IllegalState(format!(
+                "State of {} predates the key index and cannot be migrated; restore it from a snapshot", store.tree_name
+            )));
+        }
+
+        let generation = Self::decode_u64(&store.meta.get_data(NEXT_GENERATION_KEY)?.unwrap_or_default())?.max(1);
+        store.meta.add_or_update_data(NEXT_GENERATION_KEY, &(generation + 1).to_be_bytes())?;
+        store.meta.flush_to_disk()?;
+        let trees = Self::open_generation(&store.tree_name, generation)?;
+        Self::migrate_generation(&tree, &journal, &trees, key_count, Self::namespaced_key)?;
+        trees.tree.flush_to_disk()?;
+        trees.journal.flush_to_disk()?;
+        Self::record_schema_version(store, version)?;
+        Self::activate_generation(store, generation, trees)
+    }
+
+    // Copies the state and journal of a generation into `trees`, storing each
+    // state key as `rename` maps it or dropping it where that gives None.
+    // Expected roots of an unfinished rebuild are left behind
+    fn migrate_generation(
+        tree: &Tree,
+        journal: &Tree,
+        trees: &TreeSet,
+        key_count: u64,
+        rename: impl Fn(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<(), MerkleTreeError> {
+        let copy_as = |from: &[u8], to: &[u8]| -> Result<(), MerkleTreeError> {
+            match journal.get_data(from)? {
+                Some(value) => trees.journal.add_or_update_data(to, &value),
+                None => Ok(()),
+            }
+        };
+        let copy = |key: &[u8]| copy_as(key, key);
+
+        for index in 0..key_count {
+            let key = journal.get_data(&[KEY_INDEX_PREFIX, &index.to_be_bytes()[..]].concat())?.ok_or_else(|| {
+                MerkleTreeError::IllegalState(format!("Missing key index entry {}", index))
+            })?;
+            let Some(renamed) = rename(&key) else { continue };
+            if let Some(value) = tree.get_data(&key)? {
+                trees.tree.add_or_update_data(&renamed, &value)?;
+                Self::index_key(&trees.journal, &renamed)?;
+            }
+            if Self::is_balance_key(&renamed) {
+                for block in Self::balance_history_blocks(journal, &key)? {
+                    copy_as(&Self::balance_at_key(&key, block), &Self::balance_at_key(&renamed, block))?;
+                }
+                copy_as(&[BALANCE_HISTORY_PREFIX, &key].concat(), &[BALANCE_HISTORY_PREFIX, &renamed].concat())?;
+            }
+        }
+
+        // The oldest databases kept the checkpoint and block roots in the state tree
+        let metadata = |key: &[u8]| -> Result<Option<Vec<u8>>, MerkleTreeError> {
+            let data = match journal.get_data(key)? {
+                Some(value) => Some(value),
+                None => tree.get_data(key)?,
+            };
+            Ok(data.filter(|value| !value.is_empty()))
+        };
+        let last_checked_block = match metadata(LAST_CHECKED_BLOCK_KEY)? {
+            Some(data) => Self::decode_u64(data.get(..8).unwrap_or_default())?,
+            None => 0,
+        };
+        trees.journal.add_or_update_data(LAST_CHECKED_BLOCK_KEY, &last_checked_block.to_be_bytes())?;
+        for block_number in 0..=last_checked_block {
+            let root_key = format!("{}{}", BLOCK_ROOT_PREFIX, block_number);
+            if let Some(root) = metadata(root_key.as_bytes())? {
+                trees.journal.add_or_update_data(root_key.as_bytes(), &root)?;
+            }
+            for index in 0u32.. {
+                let at_key = Self::receipt_at_key(block_number, index);
+                let Some(hash) = journal.get_data(&at_key)?.filter(|hash| !hash.is_empty()) else { break };
+                trees.journal.add_or_update_data(&at_key, &hash)?;
+                copy(&[RECEIPT_PREFIX, &hash].concat())?;
+            }
+        }
+        // Older roots stay as agreed at the time; the current state is the one peers compare
+        let root = trees.tree.get_root_hash()?.unwrap_or_default();
+        trees.journal.add_or_update_data(format!("{}{}", BLOCK_ROOT_PREFIX, last_checked_block).as_bytes(), &root)?;
+
+        copy(PROCESSED_BLOCKS_KEY)?;
+        for chunk in journal.get_data(PROCESSED_BLOCKS_KEY)?.unwrap_or_default().chunks(8) {
+            copy(format!("{}{}", PROCESSED_PREFIX, Self::decode_u64(chunk)?).as_bytes())?;
+        }
+        copy(TRANSFER_COUNT_KEY)?;
+        copy(CHANGE_BLOCKS_KEY)?;
+        for chunk in journal.get_data(CHANGE_BLOCKS_KEY)?.unwrap_or_default().chunks(8) {
+            let block_number = Self::decode_u64(chunk)?;
+            let count_key = [CHANGE_COUNT_PREFIX, chunk].concat();
+            copy(&count_key)?;
+            for index in 0..Self::decode_u64(&journal.get_data(&count_key)?.unwrap_or_default())? {
+                copy(&Self::change_key(block_number, index))?;
+            }
+        }
+
+        copy(UNDO_HEAD_KEY)?;
+        let mut head = Self::decode_u64(&journal.get_data(UNDO_HEAD_KEY)?.unwrap_or_default())?;
+        while head > 0 {
+            let undo_key = format!("{}{}", UNDO_PREFIX, head);
+            let Some(record) = journal.get_data(undo_key.as_bytes())?.filter(|record| record.len() >= 8) else { break };
+            let previous = Self::decode_u64(&record[..8])?;
+            let entries = Self::decode_undo_entries(&record[8..])?.into_iter()
+                .filter_map(|(key, value)| Some((rename(&key)?, value)));
+            trees.journal.add_or_update_data(undo_key.as_bytes(), &Self::encode_undo_record(previous, entries))?;
+            head = previous;
+        }
+        Ok(())
+    }
+
+    // Maps a state key of the legacy layout, where balances were told apart by
+    // their length, to its namespaced form; None for the metadata the oldest
+    // databases kept among the state
+    fn namespaced_key(key: &[u8]) -> Option<Vec<u8>> {
+        if key == LAST_CHECKED_BLOCK_KEY || key.starts_with(BLOCK_ROOT_PREFIX.as_bytes()) {
+            return None;
+        }
+        let namespace = match key.len() {
+            ADDRESS_LENGTH | TOKEN_BALANCE_KEY_LENGTH => BALANCE_NAMESPACE,
+            _ => RECORD_NAMESPACE,
+        };
+        Some([&[namespace][..], key].concat())
+    }
+
+    /// Returns the root a block must reproduce while the state is rebuilt by
+    /// `begin_rebuild`, or None for blocks the previous state never reached.
+    pub fn get_expected_root(&self, vida_id: u64, block_number: u64) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        let journal = self.get_store(vida_id)?.journal();
+        Ok(journal.get_data(&Self::expected_root_key(block_number))?.filter(|root| !root.is_empty()))
+    }
+    
+    fn expected_root_key(block_number: u64) -> Vec<u8> {
+        [EXPECTED_ROOT_PREFIX, &block_number.to_be_bytes()[..]].concat()
+    }
+    
+    // Makes a flushed tree generation the active one, dropping everything cached from the previous one
+    fn activate_generation(store: &VidaStore, generation: u64, trees: TreeSet) -> Result<(), MerkleTreeError> {
+        store.meta.add_or_update_data(ACTIVE_GENERATION_KEY, &generation.to_be_bytes())?;
+        store.meta.flush_to_disk()?;
+        *store.trees.write().unwrap() = trees;
+        store.balance_cache.lock().unwrap().clear();
+        store.holders.lock().unwrap().clear();
+        store.undo_log.lock().unwrap().clear();
+        *store.batch.lock().unwrap() = None;
+        Ok(())
+    }
+
+    // Decodes an 8-byte big-endian integer, treating empty data as 0
+    fn decode_u64(bytes: &[u8]) -> Result<u64, MerkleTreeError> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        let value_bytes: [u8; 8] = bytes.try_into()
+            .map_err(|_| MerkleTreeError::InvalidArgument("Invalid u64 value format".to_string()))?;
+        Ok(u64::from_be_bytes(value_bytes))
+    }
+
+    // Record layout: previous undo block (8 bytes), then length-prefixed key/value
+    // pairs. A key that did not exist before has `ABSENT` in place of its value's length
+    fn encode_undo_record<K: AsRef<[u8]>, V: AsRef<[u8]>>(previous: u64, entries: impl IntoIterator<Item = (K, Option<V>)>) -> Vec<u8> {
+        let mut record = previous.to_be_bytes().to_vec();
+        for (key, value) in entries {
+            let key = key.as_ref();
+            record.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            record.extend_from_slice(key);
+            match value {
+                Some(value) => {
+                    record.extend_from_slice(&(value.as_ref().len() as u32).to_be_bytes());
+                    record.extend_from_slice(value.as_ref());
+                }
+                None => record.extend_from_slice(&ABSENT.to_be_bytes()),
+            }
+        }
+        record
+    }
+
+    // Splits the body of an undo record into its keys and their previous values
+    fn decode_undo_entries(mut bytes: &[u8]) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>, MerkleTreeError> {
+        let mut entries = Vec::new();
+        while !bytes.is_empty() {
+            let key = Self::read_length_prefixed(&mut bytes)?;
+            let value = match bytes.get(..4) {
+                Some(length) if length == ABSENT.to_be_bytes() => {
+                    bytes = &bytes[4..];
+                    None
+                }
+                _ => Some(Self::read_length_prefixed(&mut bytes)?),
+            };
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+
+    // Reads one u32 length-prefixed chunk and advances the cursor past it
+    fn read_length_prefixed(bytes: &mut &[u8]) -> Result<Vec<u8>, MerkleTreeError> {
+        let corrupt = || MerkleTreeError::IllegalState("Corrupt length-prefixed data".to_string());
+        let remaining: &[u8] = *bytes;
+        let len_bytes: [u8; 4] = remaining.get(..4).ok_or_else(corrupt)?.try_into().map_err(|_| corrupt())?;
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        let chunk = remaining.get(4..4 + len).ok_or_else(corrupt)?.to_vec();
+        *bytes = &remaining[4 + len..];
+        Ok(chunk)
+    }
+    
+    /// Retrieves the balance of a token held by the given address
+    pub fn get_balance(&self, vida_id: u64, token_id: u64, address: &[u8]) -> Result<BigUint, MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
+        
+        let key = Self::balance_key(token_id, address);
+        let store = self.get_store(vida_id)?;
+        let mut balance_cache = store.balance_cache.lock().unwrap();
+        if let Some(balance) = balance_cache.get(&key) {
+            // Counted as the read it saves, so limits don't depend on the cache
+            Meter::count(Access::Read)?;
+            return Ok(balance);
+        }
+        let data = store.tree().get_data(&key)?;
+        
+        let balance = match data {
+            Some(bytes) if !bytes.is_empty() => BigUint::from_bytes_be(&bytes),
+            _ => BigUint::from(0u32)
+        };
+        balance_cache.insert(&key, balance.clone());
+        Ok(balance)
+    }
+    
+    /// Sets the balance of a token held by the given address
+    pub fn set_balance(&self, vida_id: u64, token_id: u64, address: &[u8], balance: &BigUint) -> Result<(), MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
+        
+        let key = Self::balance_key(token_id, address);
+        let balance_bytes = balance.to_bytes_be();
+        let store = self.get_store(vida_id)?;
+        {
+            let mut balance_cache = store.balance_cache.lock().unwrap();
+            self.put(vida_id, &key, &balance_bytes)?;
+            balance_cache.insert(&key, balance.clone());
+        }
+        let mut holders = store.holders.lock().unwrap();
+        if holders.is_built() {
+            holders.update(token_id, address, balance);
+        }
+        drop(holders);
+        // Balance events report default token holdings only
+        if token_id == DEFAULT_TOKEN {
+            store.changed_balances.lock().unwrap().insert(address.to_vec());
+        }
+        
+        // Date the change by the transaction's block, or the last checkpoint outside a batch
+        let block_number = match *store.batch.lock().unwrap() {
+            Some(batch) => batch.current_block,
+            None => self.get_last_checked_block(vida_id)?,
+        };
+        Self::record_balance_history(&store.journal(), &key, block_number, &balance_bytes)
+    }
+
+    // Builds the tree key holding the balance of a token for an address
+    fn balance_key(token_id: u64, address: &[u8]) -> Vec<u8> {
+        if token_id == DEFAULT_TOKEN {
+            [&[BALANCE_NAMESPACE][..], address].concat()
+        } else {
+            [&[BALANCE_NAMESPACE][..], &token_id.to_be_bytes(), address].concat()
+        }
+    }
+    
+    /// Returns the balance of an address as of the end of `block_number`, or
+    /// None if no history is recorded for it, e.g. in databases created before
+    /// balance history was kept. Blocks past the last checkpoint are rejected.
+    pub fn get_balance_at(&self, vida_id: u64, token_id: u64, address: &[u8], block_number: u64) -> Result<Option<BigUint>, MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
+        if block_number > self.get_last_checked_block(vida_id)? {
+            return Err(MerkleTreeError::InvalidArgument(format!("Block {} has not been processed yet", block_number)));
+        }
+        
+        let key = Self::balance_key(token_id, address);
+        let journal = self.get_store(vida_id)?.journal();
+        let blocks = Self::balance_history_blocks(&journal, &key)?;
+        if blocks.is_empty() {
+            return Ok(None);
+        }
+        match blocks.iter().rev().find(|block| **block <= block_number) {
+            Some(block) => {
+                let value = journal.get_data(&Self::balance_at_key(&key, *block))?.unwrap_or_default();
+                Ok(Some(BigUint::from_bytes_be(&value)))
+            }
+            None => Ok(Some(BigUint::from(0u32))),
+        }
+    }
+    
+    // Stores the balance of an address at a block, keeping its list of changed blocks sorted
+    fn record_balance_history(journal: &Tree, address: &[u8], block_number: u64, balance: &[u8]) -> Result<(), MerkleTreeError> {
+        Self::truncate_balance_history(journal, address, block_number)?;
+        let mut blocks = Self::balance_history_blocks(journal, address)?;
+        if blocks.last() != Some(&block_number) {
+            blocks.push(block_number);
+            let encoded: Vec<u8> = blocks.iter().flat_map(|block| block.to_be_bytes()).collect();
+            journal.add_or_update_data(&[BALANCE_HISTORY_PREFIX, address].concat(), &encoded)?;
+        }
+        journal.add_or_update_data(&Self::balance_at_key(address, block_number), balance)
+    }
+    
+    // Drops the history entries of an address recorded after `block_number`
+    fn truncate_balance_history(journal: &Tree, address: &[u8], block_number: u64) -> Result<(), MerkleTreeError> {
+        let blocks = Self::balance_history_blocks(journal, address)?;
+        let kept = blocks.iter().take_while(|block| **block <= block_number).count();
+        if kept == blocks.len() {
+            return Ok(());
+        }
+        for block in &blocks[kept..] {
+            journal.remove(&Self::balance_at_key(address, *block))?;
+        }
+        let encoded: Vec<u8> = blocks[..kept].iter().flat_map(|block| block.to_be_bytes()).collect();
+        journal.add_or_update_data(&[BALANCE_HISTORY_PREFIX, address].concat(), &encoded)
+    }
+    
+    // Reads the ascending list of blocks at which the balance of an address changed
+    fn balance_history_blocks(journal: &Tree, address: &[u8]) -> Result<Vec<u64>, MerkleTreeError> {
+        let data = journal.get_data(&[BALANCE_HISTORY_PREFIX, address].concat())?.unwrap_or_default();
+        data.chunks(8).map(Self::decode_u64).collect()
+    }
+    
+    // Builds the journal key holding the balance of an address at a block
+    fn balance_at_key(address: &[u8], block_number: u64) -> Vec<u8> {
+        [BALANCE_AT_PREFIX, address, &block_number.to_be_bytes()[..]].concat()
+    }
+    
+    /// Transfers amount of a token from sender to receiver. The zero address
+    /// can take part in neither side; burn tokens with `burn` instead.
+    pub fn transfer(&self, vida_id: u64, token_id: u64, sender: &[u8], receiver: &[u8], amount: &BigUint) -> Result<bool, MerkleTreeError> {
+        if sender.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Sender address must not be empty".to_string()));
+        }
+        if receiver.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Receiver address must not be empty".to_string()));
+        }
+        if sender == ZERO_ADDRESS || receiver == ZERO_ADDRESS {
+            return Err(MerkleTreeError::InvalidArgument("The zero address cannot send or receive transfers".to_string()));
+        }
+        
+        let sender_balance = self.get_balance(vida_id, token_id, sender)?;
+        
+        if sender_balance < *amount {
+            return Ok(false);
+        }
+        
+        // The receiver is read after the sender is debited, so a transfer to
+        // oneself leaves the balance as it was
+        self.set_balance(vida_id, token_id, sender, &(&sender_balance - amount))?;
+        let receiver_balance = self.get_balance(vida_id, token_id, receiver)?;
+        self.set_balance(vida_id, token_id, receiver, &(&receiver_balance + amount))?;
+        self.log_change(vida_id, StateChange::Transfer {
+            from: hex::encode(sender),
+            to: hex::encode(receiver),
+            amount: amount.to_string(),
+            token_id: Self::non_default(token_id),
+        })?;
+        
+        Ok(true)
+    }
+    
+    /// Transfers `amount` like `transfer` and charges the fee of the VIDA's
+    /// fee policy to the sender on top of it. Returns false, changing
+    /// nothing, if the sender cannot cover both.
+    pub fn transfer_with_fee(&self, vida_id: u64, token_id: u64, sender: &[u8], receiver: &[u8], amount: &BigUint) -> Result<bool, MerkleTreeError> {
+        let Some(policy) = self.get_fee_policy(vida_id)? else {
+            return self.transfer(vida_id, token_id, sender, receiver, amount);
+        };
+        let fee = policy.fee(amount)?;
+        if self.get_balance(vida_id, token_id, sender)? < amount + &fee {
+            return Ok(false);
+        }
+        if !self.transfer(vida_id, token_id, sender, receiver, amount)? {
+            return Ok(false);
+        }
+        if fee == BigUint::default() {
+            return Ok(true);
+        }
 
-impl DatabaseService {
-    /// Initialize the DatabaseService. Must be called once before using any other methods.
-    pub fn initialize() -> Result<(), MerkleTreeError> {
-        let tree = MerkleTree::new("database".to_string())?;
-        TREE.set(tree).map_err(|_| {
-            MerkleTreeError::IllegalState("DatabaseService already initialized".to_string())
+        let collector = Self::decode_address(&policy.collector)?;
+        let balance = self.get_balance(vida_id, token_id, sender)?;
+        self.set_balance(vida_id, token_id, sender, &(balance - &fee))?;
+        let collector_balance = self.get_balance(vida_id, token_id, &collector)?;
+        self.set_balance(vida_id, token_id, &collector, &(collector_balance + &fee))?;
+        self.log_change(vida_id, StateChange::Fee {
+            from: hex::encode(sender),
+            collector: hex::encode(&collector),
+            amount: fee.to_string(),
+            token_id: Self::non_default(token_id),
         })?;
-        Ok(())
+        Ok(true)
+    }
+
+    /// Returns the fee policy of a VIDA, or None if transfers are free.
+    pub fn get_fee_policy(&self, vida_id: u64) -> Result<Option<FeePolicy>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(FEE_POLICY_KEY)? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt fee policy: {}", e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Replaces the fee policy of a VIDA, refusing one whose fee cannot be computed.
+    pub fn set_fee_policy(&self, vida_id: u64, policy: &FeePolicy) -> Result<(), MerkleTreeError> {
+        policy.fee(&BigUint::default())?;
+        let data = serde_json::to_vec(policy)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode fee policy: {}", e)))?;
+        self.put(vida_id, FEE_POLICY_KEY, &data)?;
+        self.log_change(vida_id, StateChange::FeePolicy {
+            flat: policy.flat.clone(),
+            basis_points: policy.basis_points,
+            collector: policy.collector.clone(),
+        })
+    }
+
+    /// Returns the governance policy of a VIDA, the default one until a
+    /// proposal changes it.
+    pub fn get_governance_policy(&self, vida_id: u64) -> Result<GovernancePolicy, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(GOVERNANCE_POLICY_KEY)? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt governance policy: {}", e))),
+            _ => Ok(GovernancePolicy::default()),
+        }
+    }
+
+    /// Replaces the governance policy of a VIDA.
+    pub fn set_governance_policy(&self, vida_id: u64, policy: &GovernancePolicy) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(policy)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode governance policy: {}", e)))?;
+        self.put(vida_id, GOVERNANCE_POLICY_KEY, &data)
+    }
+
+    /// Stores a new proposal and returns its id.
+    pub fn add_proposal(&self, vida_id: u64, proposal: &Proposal) -> Result<u64, MerkleTreeError> {
+        let id = Self::decode_u64(&self.get_tree(vida_id)?.get_data(PROPOSAL_COUNT_KEY)?.unwrap_or_default())?;
+        self.set_proposal(vida_id, id, proposal)?;
+        self.put(vida_id, PROPOSAL_COUNT_KEY, &(id + 1).to_be_bytes())?;
+        Ok(id)
+    }
+
+    /// Returns a proposal by id.
+    pub fn get_proposal(&self, vida_id: u64, id: u64) -> Result<Option<Proposal>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&[PROPOSAL_PREFIX, &id.to_be_bytes()[..]].concat())? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt proposal {}: {}", id, e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Replaces a stored proposal, e.g. to record a vote or its outcome.
+    pub fn set_proposal(&self, vida_id: u64, id: u64, proposal: &Proposal) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(proposal)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode proposal: {}", e)))?;
+        self.put(vida_id, &[PROPOSAL_PREFIX, &id.to_be_bytes()[..]].concat(), &data)
+    }
+
+    /// Returns every proposal with its id, oldest first.
+    pub fn get_proposals(&self, vida_id: u64) -> Result<Vec<(u64, Proposal)>, MerkleTreeError> {
+        let count = Self::decode_u64(&self.get_tree(vida_id)?.get_data(PROPOSAL_COUNT_KEY)?.unwrap_or_default())?;
+        let mut proposals = Vec::new();
+        for id in 0..count {
+            if let Some(proposal) = self.get_proposal(vida_id, id)? {
+                proposals.push((id, proposal));
+            }
+        }
+        Ok(proposals)
+    }
+
+    /// Creates `amount` new tokens in the balance of `address`
+    pub fn mint(&self, vida_id: u64, token_id: u64, address: &[u8], amount: &BigUint) -> Result<(), MerkleTreeError> {
+        let balance = self.get_balance(vida_id, token_id, address)?;
+        let supply = self.get_total_supply(vida_id, token_id)?.unwrap_or_default();
+        self.set_balance(vida_id, token_id, address, &(balance + amount))?;
+        self.set_total_supply(vida_id, token_id, &(supply + amount))?;
+        self.log_change(vida_id, StateChange::Mint {
+            to: hex::encode(address),
+            amount: amount.to_string(),
+            token_id: Self::non_default(token_id),
+        })
     }
     
-    /// Get the global tree instance
-    fn get_tree() -> Result<&'static Arc<MerkleTree>, MerkleTreeError> {
-        TREE.get().ok_or_else(|| {
-            MerkleTreeError::IllegalState("DatabaseService not initialized. Call initialize() first.".to_string())
+    /// Destroys `amount` tokens from the balance of `address`; returns false if
+    /// the balance is insufficient
+    pub fn burn(&self, vida_id: u64, token_id: u64, address: &[u8], amount: &BigUint) -> Result<bool, MerkleTreeError> {
+        let balance = self.get_balance(vida_id, token_id, address)?;
+        if balance < *amount {
+            return Ok(false);
+        }
+        
+        let supply = self.get_total_supply(vida_id, token_id)?.unwrap_or_default();
+        if supply < *amount {
+            return Err(MerkleTreeError::IllegalState("Burn exceeds recorded total supply".to_string()));
+        }
+        self.set_balance(vida_id, token_id, address, &(balance - amount))?;
+        self.set_total_supply(vida_id, token_id, &(supply - amount))?;
+        self.log_change(vida_id, StateChange::Burn {
+            from: hex::encode(address),
+            amount: amount.to_string(),
+            token_id: Self::non_default(token_id),
+        })?;
+        Ok(true)
+    }
+    
+    /// Returns the recorded total supply of a token, or None if the database
+    /// predates supply tracking or the token was never minted
+    pub fn get_total_supply(&self, vida_id: u64, token_id: u64) -> Result<Option<BigUint>, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        Ok(tree.get_data(&Self::total_supply_key(token_id))?.map(|bytes| BigUint::from_bytes_be(&bytes)))
+    }
+    
+    /// Records the total supply of a token
+    pub fn set_total_supply(&self, vida_id: u64, token_id: u64, supply: &BigUint) -> Result<(), MerkleTreeError> {
+        self.put(vida_id, &Self::total_supply_key(token_id), &supply.to_bytes_be())
+    }
+    
+    // Builds the tree key holding the total supply of a token
+    fn total_supply_key(token_id: u64) -> Vec<u8> {
+        if token_id == DEFAULT_TOKEN {
+            TOTAL_SUPPLY_KEY.to_vec()
+        } else {
+            [TOTAL_SUPPLY_KEY, &token_id.to_be_bytes()[..]].concat()
+        }
+    }
+    
+    // Records the token of a change only if it is not the default token
+    fn non_default(token_id: u64) -> Option<u64> {
+        Some(token_id).filter(|token| *token != DEFAULT_TOKEN)
+    }
+    
+    /// Sums every balance of a token and compares it with its recorded supply.
+    /// Walks the whole key index, so it is meant for audits rather than hot paths.
+    pub fn audit_supply(&self, vida_id: u64, token_id: u64) -> Result<SupplyAudit, MerkleTreeError> {
+        let TreeSet { tree, journal } = self.get_store(vida_id)?.trees.read().unwrap().clone();
+        let count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        let mut balance_sum = BigUint::from(0u32);
+        let mut holders = 0;
+        for index in 0..count {
+            let index_key = [KEY_INDEX_PREFIX, &index.to_be_bytes()[..]].concat();
+            let key = match journal.get_data(&index_key)? {
+                Some(key) if Self::balance_token(&key) == Some(token_id) => key,
+                _ => continue,
+            };
+            if let Some(value) = tree.get_data(&key)? {
+                if value.iter().any(|byte| *byte != 0) {
+                    balance_sum += BigUint::from_bytes_be(&value);
+                    holders += 1;
+                }
+            }
+        }
+        
+        let recorded = self.get_total_supply(vida_id, token_id)?;
+        Ok(SupplyAudit {
+            consistent: recorded.as_ref().map_or(true, |supply| *supply == balance_sum),
+            recorded_supply: recorded.map(|supply| supply.to_string()),
+            balance_sum: balance_sum.to_string(),
+            holders,
         })
     }
     
-    /// Get current Merkle root hash
-    pub fn get_root_hash() -> Result<Option<Vec<u8>>, MerkleTreeError> {
-        let tree = Self::get_tree()?;
-        tree.get_root_hash()
+    /// Recomputes every balance by replaying the journaled transfers, mints,
+    /// burns and fees of each block on top of the genesis balances, checking
+    /// the balances the node recorded after each block and finally the tree
+    /// itself. Stops at the first disagreement. State imported from a
+    /// snapshot has no journal before its block and cannot be replayed.
+    pub fn audit_ledger(&self, vida_id: u64) -> Result<LedgerAudit, MerkleTreeError> {
+        let TreeSet { tree, journal } = self.get_store(vida_id)?.trees.read().unwrap().clone();
+        let count = Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        let mut keys = Vec::new();
+        for index in 0..count {
+            let index_key = [KEY_INDEX_PREFIX, &index.to_be_bytes()[..]].concat();
+            if let Some(key) = journal.get_data(&index_key)?.filter(|key| Self::is_balance_key(key)) {
+                keys.push(key);
+            }
+        }
+
+        // Genesis balances are recorded at block 0, before any batch
+        let mut balances = BTreeMap::new();
+        for key in &keys {
+            balances.insert(key.clone(), Self::recorded_balance(&journal, key, 0)?);
+        }
+
+        let diverged = |block_number: u64, key: &[u8], replayed: &BigUint, recorded: &BigUint| -> Result<Option<LedgerDivergence>, MerkleTreeError> {
+            let root_hash = journal.get_data(format!("{}{}", BLOCK_ROOT_PREFIX, block_number).as_bytes())?
+                .filter(|root| !root.is_empty())
+                .map(hex::encode);
+            Ok(Some(LedgerDivergence {
+                block_number,
+                token_id: Self::balance_token(key).unwrap_or(DEFAULT_TOKEN),
+                address: format!("0x{}", hex::encode(&key[key.len() - ADDRESS_LENGTH..])),
+                replayed: replayed.to_string(),
+                recorded: recorded.to_string(),
+                root_hash,
+            }))
+        };
+
+        let mut blocks: Vec<u64> = journal.get_data(CHANGE_BLOCKS_KEY)?.unwrap_or_default()
+            .chunks(8)
+            .map(Self::decode_u64)
+            .collect::<Result<_, _>>()?;
+        blocks.sort_unstable();
+        let mut checked = 0;
+        for (replayed_blocks, block_number) in blocks.iter().copied().enumerate() {
+            let mut touched = BTreeSet::new();
+            for record in self.get_changes(vida_id, block_number)? {
+                // Debits and credits as (token, address, amount, is_credit)
+                let entries = match record.change {
+                    StateChange::Transfer { from, to, amount, token_id } => vec![(token_id, from, amount.clone(), false), (token_id, to, amount, true)],
+                    StateChange::Fee { from, collector, amount, token_id } => vec![(token_id, from, amount.clone(), false), (token_id, collector, amount, true)],
+                    StateChange::Mint { to, amount, token_id } => vec![(token_id, to, amount, true)],
+                    StateChange::Burn { from, amount, token_id } => vec![(token_id, from, amount, false)],
+                    _ => continue,
+                };
+                for (token_id, address, amount, is_credit) in entries {
+                    let key = Self::balance_key(token_id.unwrap_or(DEFAULT_TOKEN), &Self::decode_address(&address)?);
+                    let amount = Self::decode_escrow_amount(&amount)?;
+                    let balance = balances.entry(key.clone()).or_insert_with(|| BigUint::from(0u32));
+                    if is_credit {
+                        *balance += amount;
+                    } else if *balance >= amount {
+                        *balance -= amount;
+                    } else {
+                        let replayed = balance.clone();
+                        let recorded = Self::recorded_balance(&journal, &key, block_number)?;
+                        return Ok(LedgerAudit {
+                            blocks_replayed: replayed_blocks as u64,
+                            balances_checked: checked,
+                            divergence: diverged(block_number, &key, &replayed, &recorded)?,
+                        });
+                    }
+                    touched.insert(key);
+                }
+            }
+
+            for key in &touched {
+                let recorded = Self::recorded_balance(&journal, key, block_number)?;
+                checked += 1;
+                if balances[key] != recorded {
+                    return Ok(LedgerAudit {
+                        blocks_replayed: replayed_blocks as u64 + 1,
+                        balances_checked: checked,
+                        divergence: diverged(block_number, key, &balances[key], &recorded)?,
+                    });
+                }
+            }
+        }
+
+        // Whatever the journal does not explain was written to the tree some other way
+        let last_checked_block = self.get_last_checked_block(vida_id)?;
+        for (key, replayed) in &balances {
+            let stored = tree.get_data(key)?.map_or_else(|| BigUint::from(0u32), |value| BigUint::from_bytes_be(&value));
+            checked += 1;
+            if stored != *replayed {
+                return Ok(LedgerAudit {
+                    blocks_replayed: blocks.len() as u64,
+                    balances_checked: checked,
+                    divergence: diverged(last_checked_block, key, replayed, &stored)?,
+                });
+            }
+        }
+        Ok(LedgerAudit { blocks_replayed: blocks.len() as u64, balances_checked: checked, divergence: None })
     }
     
-    /// Flush pending writes to disk
-    pub fn flush() -> Result<(), MerkleTreeError> {
-        let tree = Self::get_tree()?;
-        tree.flush_to_disk()
+    // Reads the balance history of a balance key as of a block, 0 before its first entry
+    fn recorded_balance(journal: &Tree, key: &[u8], block_number: u64) -> Result<BigUint, MerkleTreeError> {
+        let blocks = Self::balance_history_blocks(journal, key)?;
+        match blocks.iter().rev().find(|block| **block <= block_number) {
+            Some(block) => Ok(BigUint::from_bytes_be(&journal.get_data(&Self::balance_at_key(key, *block))?.unwrap_or_default())),
+            None => Ok(BigUint::from(0u32)),
+        }
     }
     
-    /// Reverts all unsaved changes to the Merkle tree
-    pub fn revert_unsaved_changes() -> Result<(), MerkleTreeError> {
-        let tree = Self::get_tree()?;
-        tree.revert_unsaved_changes()
+    /// Returns up to `limit` holders of a token with the largest balances,
+    /// with the number of holders and their combined balance. The first call
+    /// after startup or a revert walks the key index to build the ordering.
+    pub fn top_holders(&self, vida_id: u64, token_id: u64, limit: usize) -> Result<TopHolders, MerkleTreeError> {
+        let holders = Self::holder_index(self.get_store(vida_id)?)?;
+        let (count, balance_sum) = holders.totals(token_id);
+        Ok(TopHolders { top: holders.top(token_id, limit), holders: count, balance_sum })
+    }
+    
+    /// Returns the account, transfer and key counts of a VIDA and when it was
+    /// last flushed. Accounts come from the holder index, so the first call may
+    /// walk the key index like `top_holders`; later calls only read counters.
+    pub fn stats(&self, vida_id: u64) -> Result<StateStats, MerkleTreeError> {
+        let store = self.get_store(vida_id)?;
+        let journal = store.journal();
+        Ok(StateStats {
+            accounts: Self::holder_index(store)?.totals(DEFAULT_TOKEN).0,
+            transfers: Self::decode_u64(&journal.get_data(TRANSFER_COUNT_KEY)?.unwrap_or_default())?,
+            state_keys: Self::decode_u64(&journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?,
+            last_flush: *store.last_flush.lock().unwrap(),
+            flush: store.flush_metrics(),
+        })
+    }
+    
+    /// Returns an iterator over every non-zero balance of a VIDA, of all
+    /// tokens, in the order the accounts were first written. Balances are read
+    /// as the iterator advances, so blocks applied meanwhile may show in them.
+    pub fn balances(&self, vida_id: u64) -> Result<Balances, MerkleTreeError> {
+        Self::store_balances(self.get_store(vida_id)?)
+    }
+    
+    // Iterates the balances of a store from its current tree generation
+    fn store_balances(store: &VidaStore) -> Result<Balances, MerkleTreeError> {
+        let trees = store.trees.read().unwrap().clone();
+        let count = Self::decode_u64(&trees.journal.get_data(KEY_COUNT_KEY)?.unwrap_or_default())?;
+        Ok(Balances { trees, next: 0, count })
+    }
+    
+    // Locks the holder index of a VIDA, building it from the key index if needed
+    fn holder_index(store: &VidaStore) -> Result<MutexGuard<'_, HolderIndex>, MerkleTreeError> {
+        let mut holders = store.holders.lock().unwrap();
+        if !holders.is_built() {
+            for entry in Self::store_balances(store)? {
+                let entry = entry?;
+                holders.update(entry.token_id, &entry.address, &entry.balance);
+            }
+            holders.mark_built();
+        }
+        Ok(holders)
+    }
+    
+    // Whether a tree key holds an account balance rather than prefixed state
+    fn is_balance_key(key: &[u8]) -> bool {
+        Self::balance_token(key).is_some()
+    }
+    
+    // Returns the token of a tree key holding an account balance, or None for other state
+    fn balance_token(key: &[u8]) -> Option<u64> {
+        match key.split_first() {
+            Some((&BALANCE_NAMESPACE, body)) if body.len() == ADDRESS_LENGTH => Some(DEFAULT_TOKEN),
+            Some((&BALANCE_NAMESPACE, body)) if body.len() == TOKEN_BALANCE_KEY_LENGTH => Self::decode_u64(&body[..8]).ok(),
+            _ => None,
+        }
     }
     
-    /// Retrieves the balance stored at the given address
-    pub fn get_balance(address: &[u8]) -> Result<BigUint, MerkleTreeError> {
+    /// Returns the next nonce expected from the given address
+    pub fn get_nonce(&self, vida_id: u64, address: &[u8]) -> Result<u64, MerkleTreeError> {
         if address.is_empty() {
             return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
         }
         
-        let tree = Self::get_tree()?;
-        let data = tree.get_data(address)?;
+        let tree = self.get_tree(vida_id)?;
+        let data = tree.get_data(&Self::nonce_key(address))?;
+        Self::decode_u64(&data.unwrap_or_default())
+    }
+    
+    /// Sets the next nonce expected from the given address
+    pub fn set_nonce(&self, vida_id: u64, address: &[u8], nonce: u64) -> Result<(), MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
         
-        match data {
-            Some(bytes) if !bytes.is_empty() => {
-                Ok(BigUint::from_bytes_be(&bytes))
-            }
-            _ => Ok(BigUint::from(0u32))
+        self.put(vida_id, &Self::nonce_key(address), &nonce.to_be_bytes())?;
+        self.log_change(vida_id, StateChange::Nonce { address: hex::encode(address), nonce })
+    }
+    
+    // Builds the tree key holding an account's nonce
+    fn nonce_key(address: &[u8]) -> Vec<u8> {
+        [NONCE_PREFIX, address].concat()
+    }
+    
+    /// Returns whether `owner` has authorized `spender` to move its funds
+    pub fn is_delegate(&self, vida_id: u64, owner: &[u8], spender: &[u8]) -> Result<bool, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        let data = tree.get_data(&[DELEGATE_PREFIX, owner, spender].concat())?;
+        Ok(matches!(data.as_deref(), Some([1])))
+    }
+    
+    /// Grants or revokes the right of `spender` to move funds of `owner`
+    pub fn set_delegate(&self, vida_id: u64, owner: &[u8], spender: &[u8], enabled: bool) -> Result<(), MerkleTreeError> {
+        if owner.is_empty() || spender.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
         }
+        
+        self.put(vida_id, &[DELEGATE_PREFIX, owner, spender].concat(), &[enabled as u8])?;
+        self.log_change(vida_id, StateChange::Delegate {
+            owner: hex::encode(owner),
+            spender: hex::encode(spender),
+            enabled,
+        })
     }
     
-    /// Sets the balance for the given address
-    pub fn set_balance(address: &[u8], balance: &BigUint) -> Result<(), MerkleTreeError> {
+    /// Returns whether transfers from and to `address` are blocked
+    pub fn is_frozen(&self, vida_id: u64, address: &[u8]) -> Result<bool, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        let data = tree.get_data(&[FROZEN_PREFIX, address].concat())?;
+        Ok(matches!(data.as_deref(), Some([1])))
+    }
+    
+    /// Blocks or unblocks transfers from and to `address`
+    pub fn set_frozen(&self, vida_id: u64, address: &[u8], frozen: bool) -> Result<(), MerkleTreeError> {
         if address.is_empty() {
             return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
         }
         
-        let tree = Self::get_tree()?;
-        let balance_bytes = balance.to_bytes_be();
-        tree.add_or_update_data(address, &balance_bytes)
+        self.put(vida_id, &[FROZEN_PREFIX, address].concat(), &[frozen as u8])?;
+        self.log_change(vida_id, StateChange::Freeze {
+            address: hex::encode(address),
+            frozen,
+        })
     }
     
-    /// Transfers amount from sender to receiver
-    pub fn transfer(sender: &[u8], receiver: &[u8], amount: &BigUint) -> Result<bool, MerkleTreeError> {
-        if sender.is_empty() {
-            return Err(MerkleTreeError::InvalidArgument("Sender address must not be empty".to_string()));
-        }
-        if receiver.is_empty() {
-            return Err(MerkleTreeError::InvalidArgument("Receiver address must not be empty".to_string()));
+    /// Returns how much of a token `spender` may still move from `owner` via `transferFrom`
+    pub fn get_allowance(&self, vida_id: u64, token_id: u64, owner: &[u8], spender: &[u8]) -> Result<BigUint, MerkleTreeError> {
+        if owner.is_empty() || spender.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
         }
         
-        let sender_balance = Self::get_balance(sender)?;
+        let tree = self.get_tree(vida_id)?;
+        let data = tree.get_data(&Self::allowance_key(token_id, owner, spender))?;
+        Ok(BigUint::from_bytes_be(&data.unwrap_or_default()))
+    }
+    
+    /// Sets how much of a token `spender` may move from `owner` via `transferFrom`
+    pub fn set_allowance(&self, vida_id: u64, token_id: u64, owner: &[u8], spender: &[u8], amount: &BigUint) -> Result<(), MerkleTreeError> {
+        if owner.is_empty() || spender.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
         
-        if sender_balance < *amount {
-            return Ok(false);
+        self.put(vida_id, &Self::allowance_key(token_id, owner, spender), &amount.to_bytes_be())?;
+        self.log_change(vida_id, StateChange::Allowance {
+            owner: hex::encode(owner),
+            spender: hex::encode(spender),
+            amount: amount.to_string(),
+            token_id: Self::non_default(token_id),
+        })
+    }
+    
+    /// Returns the faucet window an address last drew in and how much it got
+    /// during that window, (0, 0) if it never used the faucet.
+    pub fn get_faucet_usage(&self, vida_id: u64, address: &[u8]) -> Result<(u64, BigUint), MerkleTreeError> {
+        let data = self.get_tree(vida_id)?.get_data(&[FAUCET_PREFIX, address].concat())?.unwrap_or_default();
+        if data.len() < 8 {
+            return Ok((0, BigUint::from(0u32)));
+        }
+        Ok((Self::decode_u64(&data[..8])?, BigUint::from_bytes_be(&data[8..])))
+    }
+
+    /// Records how much an address drew from the faucet in a window.
+    pub fn set_faucet_usage(&self, vida_id: u64, address: &[u8], window: u64, drawn: &BigUint) -> Result<(), MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
+        }
+        self.put(vida_id, &[FAUCET_PREFIX, address].concat(), &[&window.to_be_bytes()[..], &drawn.to_bytes_be()].concat())
+    }
+
+    // Builds the tree key holding the allowance of a (owner, spender) pair for
+    // a token; allowances of the default token keep their unprefixed keys
+    fn allowance_key(token_id: u64, owner: &[u8], spender: &[u8]) -> Vec<u8> {
+        if token_id == DEFAULT_TOKEN {
+            [ALLOWANCE_PREFIX, owner, spender].concat()
+        } else {
+            [ALLOWANCE_PREFIX, &token_id.to_be_bytes(), owner, spender].concat()
+        }
+    }
+
+    /// Moves the escrow's amount from its sender to `ESCROW_ACCOUNT` and
+    /// stores the escrow under a new id. Returns None, changing nothing, if
+    /// the sender's balance is insufficient.
+    pub fn lock_escrow(&self, vida_id: u64, escrow: &Escrow) -> Result<Option<u64>, MerkleTreeError> {
+        let sender = Self::decode_address(&escrow.sender)?;
+        let amount = Self::decode_escrow_amount(&escrow.amount)?;
+        if !self.transfer(vida_id, escrow.token_id, &sender, &ESCROW_ACCOUNT, &amount)? {
+            return Ok(None);
+        }
+
+        let tree = self.get_tree(vida_id)?;
+        let id = Self::decode_u64(&tree.get_data(ESCROW_COUNT_KEY)?.unwrap_or_default())?;
+        let data = serde_json::to_vec(escrow)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode escrow: {}", e)))?;
+        self.put(vida_id, &Self::escrow_key(id), &data)?;
+        self.put(vida_id, ESCROW_COUNT_KEY, &(id + 1).to_be_bytes())?;
+
+        let mut pending = self.get_pending_escrow_ids(vida_id)?;
+        pending.insert((escrow.expiry_block, id));
+        self.set_pending_escrow_ids(vida_id, &pending)?;
+        Ok(Some(id))
+    }
+
+    /// Returns a pending escrow, or None once it was claimed or refunded.
+    pub fn get_escrow(&self, vida_id: u64, id: u64) -> Result<Option<Escrow>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&Self::escrow_key(id))? {
+            Some(data) if !data.is_empty() && data != ESCROW_SETTLED => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt escrow {}: {}", id, e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Pays a pending escrow out of `ESCROW_ACCOUNT` to `recipient`, its
+    /// receiver on a claim or its sender on a refund, and removes it.
+    pub fn release_escrow(&self, vida_id: u64, id: u64, recipient: &[u8]) -> Result<Escrow, MerkleTreeError> {
+        let escrow = self.get_escrow(vida_id, id)?
+            .ok_or_else(|| MerkleTreeError::InvalidArgument(format!("Escrow {} is not pending", id)))?;
+        let amount = Self::decode_escrow_amount(&escrow.amount)?;
+        if !self.transfer(vida_id, escrow.token_id, &ESCROW_ACCOUNT, recipient, &amount)? {
+            return Err(MerkleTreeError::IllegalState(format!("Escrow account cannot cover escrow {}", id)));
+        }
+
+        self.put(vida_id, &Self::escrow_key(id), ESCROW_SETTLED)?;
+        let mut pending = self.get_pending_escrow_ids(vida_id)?;
+        pending.remove(&(escrow.expiry_block, id));
+        self.set_pending_escrow_ids(vida_id, &pending)?;
+        Ok(escrow)
+    }
+
+    /// Returns the ids of pending escrows whose expiry block is before `block_number`.
+    pub fn expired_escrows(&self, vida_id: u64, block_number: u64) -> Result<Vec<u64>, MerkleTreeError> {
+        Ok(self.get_pending_escrow_ids(vida_id)?
+            .into_iter()
+            .take_while(|(expiry_block, _)| *expiry_block < block_number)
+            .map(|(_, id)| id)
+            .collect())
+    }
+
+    /// Returns every pending escrow with its id, soonest expiry first.
+    pub fn pending_escrows(&self, vida_id: u64) -> Result<Vec<(u64, Escrow)>, MerkleTreeError> {
+        let mut escrows = Vec::new();
+        for (_, id) in self.get_pending_escrow_ids(vida_id)? {
+            if let Some(escrow) = self.get_escrow(vida_id, id)? {
+                escrows.push((id, escrow));
+            }
+        }
+        Ok(escrows)
+    }
+
+    // Pending escrows as (expiry block, id), ordered so expired ones come first
+    fn get_pending_escrow_ids(&self, vida_id: u64) -> Result<BTreeSet<(u64, u64)>, MerkleTreeError> {
+        let data = self.get_tree(vida_id)?.get_data(ESCROW_PENDING_KEY)?.unwrap_or_default();
+        Ok(data.chunks_exact(16)
+            .map(|entry| (u64::from_be_bytes(entry[..8].try_into().unwrap()), u64::from_be_bytes(entry[8..].try_into().unwrap())))
+            .collect())
+    }
+
+    // Stores the pending escrow index as 16-byte (expiry block, id) entries
+    fn set_pending_escrow_ids(&self, vida_id: u64, pending: &BTreeSet<(u64, u64)>) -> Result<(), MerkleTreeError> {
+        let data: Vec<u8> = pending.iter()
+            .flat_map(|(expiry_block, id)| [expiry_block.to_be_bytes(), id.to_be_bytes()].concat())
+            .collect();
+        self.put(vida_id, ESCROW_PENDING_KEY, &data)
+    }
+
+    // Builds the tree key holding an escrow
+    fn escrow_key(id: u64) -> Vec<u8> {
+        [ESCROW_PREFIX, &id.to_be_bytes()[..]].concat()
+    }
+
+    // Stored escrows hold hex addresses and decimal amounts, validated when they were locked
+    fn decode_address(address: &str) -> Result<Vec<u8>, MerkleTreeError> {
+        hex::decode(address.trim_start_matches("0x"))
+            .map_err(|_| MerkleTreeError::InvalidArgument(format!("Invalid address: {}", address)))
+    }
+
+    fn decode_escrow_amount(amount: &str) -> Result<BigUint, MerkleTreeError> {
+        amount.parse()
+            .map_err(|_| MerkleTreeError::InvalidArgument(format!("Invalid escrow amount: {}", amount)))
+    }
+
+    /// Burns the withdrawn amount of the default token from its sender and
+    /// stores the withdrawal under the next id. Returns None, changing
+    /// nothing, if the sender's balance is insufficient.
+    pub fn request_withdrawal(&self, vida_id: u64, withdrawal: &Withdrawal) -> Result<Option<u64>, MerkleTreeError> {
+        let sender = Self::decode_address(&withdrawal.sender)?;
+        if !self.burn(vida_id, DEFAULT_TOKEN, &sender, &BigUint::from(withdrawal.amount))? {
+            return Ok(None);
+        }
+        let id = self.withdrawal_count(vida_id)?;
+        let data = serde_json::to_vec(withdrawal)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode withdrawal: {}", e)))?;
+        self.put(vida_id, &[WITHDRAWAL_PREFIX, &id.to_be_bytes()[..]].concat(), &data)?;
+        self.put(vida_id, WITHDRAWAL_COUNT_KEY, &(id + 1).to_be_bytes())?;
+        Ok(Some(id))
+    }
+
+    /// Returns a withdrawal by id.
+    pub fn get_withdrawal(&self, vida_id: u64, id: u64) -> Result<Option<Withdrawal>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&[WITHDRAWAL_PREFIX, &id.to_be_bytes()[..]].concat())? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt withdrawal {}: {}", id, e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the number of withdrawals requested, which is the next id.
+    pub fn withdrawal_count(&self, vida_id: u64) -> Result<u64, MerkleTreeError> {
+        Self::decode_u64(&self.get_tree(vida_id)?.get_data(WITHDRAWAL_COUNT_KEY)?.unwrap_or_default())
+    }
+
+    /// Returns the id of the first withdrawal this node has not yet handled
+    /// as a conduit. Node-local, kept outside the VIDA's state.
+    pub fn get_conduit_cursor(&self, vida_id: u64) -> Result<u64, MerkleTreeError> {
+        Self::decode_u64(&self.node.get_data(&Self::conduit_cursor_key(vida_id))?.unwrap_or_default())
+    }
+
+    /// Records that withdrawals before `next_id` were handled and flushes it to disk.
+    pub fn set_conduit_cursor(&self, vida_id: u64, next_id: u64) -> Result<(), MerkleTreeError> {
+        self.node.add_or_update_data(&Self::conduit_cursor_key(vida_id), &next_id.to_be_bytes())?;
+        self.node.flush_to_disk()
+    }
+
+    fn conduit_cursor_key(vida_id: u64) -> Vec<u8> {
+        [CONDUIT_CURSOR_PREFIX, &vida_id.to_be_bytes()[..]].concat()
+    }
+
+    /// Moves the schedule's amount from its sender to `VESTING_ACCOUNT` and
+    /// stores the schedule under a new id. Returns None, changing nothing, if
+    /// the sender's balance is insufficient.
+    pub fn lock_vesting(&self, vida_id: u64, schedule: &VestingSchedule) -> Result<Option<u64>, MerkleTreeError> {
+        let sender = Self::decode_address(&schedule.sender)?;
+        let beneficiary = Self::decode_address(&schedule.beneficiary)?;
+        let amount = Self::decode_escrow_amount(&schedule.amount)?;
+        if !self.transfer(vida_id, schedule.token_id, &sender, &VESTING_ACCOUNT, &amount)? {
+            return Ok(None);
+        }
+
+        let tree = self.get_tree(vida_id)?;
+        let id = Self::decode_u64(&tree.get_data(VESTING_COUNT_KEY)?.unwrap_or_default())?;
+        self.set_vesting(vida_id, id, schedule)?;
+        self.put(vida_id, VESTING_COUNT_KEY, &(id + 1).to_be_bytes())?;
+
+        let mut active = self.get_id_list(vida_id, VESTING_ACTIVE_KEY)?;
+        active.push(id);
+        self.put_id_list(vida_id, VESTING_ACTIVE_KEY, &active)?;
+        let beneficiary_key = [VESTING_BENEFICIARY_PREFIX, &beneficiary].concat();
+        let mut schedules = self.get_id_list(vida_id, &beneficiary_key)?;
+        schedules.push(id);
+        self.put_id_list(vida_id, &beneficiary_key, &schedules)?;
+        Ok(Some(id))
+    }
+
+    /// Returns a vesting schedule, including fully released ones.
+    pub fn get_vesting(&self, vida_id: u64, id: u64) -> Result<Option<VestingSchedule>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&[VESTING_PREFIX, &id.to_be_bytes()[..]].concat())? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt vesting schedule {}: {}", id, e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the vesting schedules of a beneficiary with their ids, oldest first.
+    pub fn get_vesting_schedules(&self, vida_id: u64, beneficiary: &[u8]) -> Result<Vec<(u64, VestingSchedule)>, MerkleTreeError> {
+        let mut schedules = Vec::new();
+        for id in self.get_id_list(vida_id, &[VESTING_BENEFICIARY_PREFIX, beneficiary].concat())? {
+            if let Some(schedule) = self.get_vesting(vida_id, id)? {
+                schedules.push((id, schedule));
+            }
+        }
+        Ok(schedules)
+    }
+
+    /// Pays every active schedule's tranches matured by `block_number` out of
+    /// `VESTING_ACCOUNT` to their beneficiaries, and retires schedules that
+    /// are fully released. Returns the ids and amounts paid, in id order.
+    pub fn release_vested(&self, vida_id: u64, block_number: u64) -> Result<Vec<(u64, BigUint)>, MerkleTreeError> {
+        let active = self.get_id_list(vida_id, VESTING_ACTIVE_KEY)?;
+        let mut still_active = Vec::with_capacity(active.len());
+        let mut released = Vec::new();
+        for id in active {
+            let Some(mut schedule) = self.get_vesting(vida_id, id)? else {
+                continue;
+            };
+            let amount = Self::decode_escrow_amount(&schedule.amount)?;
+            let already_released = Self::decode_escrow_amount(&schedule.released)?;
+            let vested = Self::vested_amount(&schedule, &amount, block_number);
+            if vested > already_released {
+                let tranche = &vested - &already_released;
+                let beneficiary = Self::decode_address(&schedule.beneficiary)?;
+                if !self.transfer(vida_id, schedule.token_id, &VESTING_ACCOUNT, &beneficiary, &tranche)? {
+                    return Err(MerkleTreeError::IllegalState(format!("Vesting account cannot cover schedule {}", id)));
+                }
+                schedule.released = vested.to_string();
+                self.set_vesting(vida_id, id, &schedule)?;
+                released.push((id, tranche));
+            }
+            if vested < amount {
+                still_active.push(id);
+            }
+        }
+        self.put_id_list(vida_id, VESTING_ACTIVE_KEY, &still_active)?;
+        Ok(released)
+    }
+
+    // Part of a schedule vested by a block: nothing before the cliff, then
+    // linear between the start and end blocks, with integer rounding down
+    fn vested_amount(schedule: &VestingSchedule, amount: &BigUint, block_number: u64) -> BigUint {
+        if block_number < schedule.cliff_block {
+            return BigUint::default();
+        }
+        if block_number >= schedule.end_block {
+            return amount.clone();
+        }
+        let elapsed = block_number.saturating_sub(schedule.start_block);
+        amount * BigUint::from(elapsed) / BigUint::from(schedule.end_block - schedule.start_block)
+    }
+
+    fn set_vesting(&self, vida_id: u64, id: u64, schedule: &VestingSchedule) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(schedule)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode vesting schedule: {}", e)))?;
+        self.put(vida_id, &[VESTING_PREFIX, &id.to_be_bytes()[..]].concat(), &data)
+    }
+
+    // Reads a list of ids stored as consecutive 8-byte big-endian entries
+    fn get_id_list(&self, vida_id: u64, key: &[u8]) -> Result<Vec<u64>, MerkleTreeError> {
+        let data = self.get_tree(vida_id)?.get_data(key)?.unwrap_or_default();
+        Ok(data.chunks_exact(8)
+            .map(|entry| u64::from_be_bytes(entry.try_into().unwrap()))
+            .collect())
+    }
+
+    fn put_id_list(&self, vida_id: u64, key: &[u8], ids: &[u64]) -> Result<(), MerkleTreeError> {
+        let data: Vec<u8> = ids.iter().flat_map(|id| id.to_be_bytes()).collect();
+        self.put(vida_id, key, &data)
+    }
+
+    /// Returns the address of the multisig account with the given id: the
+    /// bytes of "multisig" followed by the id. No key pair can produce it, so
+    /// only approved multisig transactions spend from it.
+    pub fn multisig_address(id: u64) -> [u8; ADDRESS_LENGTH] {
+        let mut address = [0; ADDRESS_LENGTH];
+        address[..8].copy_from_slice(b"multisig");
+        address[ADDRESS_LENGTH - 8..].copy_from_slice(&id.to_be_bytes());
+        address
+    }
+
+    /// Registers a new multisig account and returns its address.
+    pub fn create_multisig(&self, vida_id: u64, multisig: &Multisig) -> Result<[u8; ADDRESS_LENGTH], MerkleTreeError> {
+        let id = Self::decode_u64(&self.get_tree(vida_id)?.get_data(MULTISIG_COUNT_KEY)?.unwrap_or_default())?;
+        let address = Self::multisig_address(id);
+        self.set_multisig(vida_id, &address, multisig)?;
+        self.put(vida_id, MULTISIG_COUNT_KEY, &(id + 1).to_be_bytes())?;
+        Ok(address)
+    }
+
+    /// Returns the multisig account at `address`, or None for other addresses.
+    pub fn get_multisig(&self, vida_id: u64, address: &[u8]) -> Result<Option<Multisig>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&[MULTISIG_PREFIX, address].concat())? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt multisig {}: {}", hex::encode(address), e))),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_multisig(&self, vida_id: u64, address: &[u8], multisig: &Multisig) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(multisig)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode multisig: {}", e)))?;
+        self.put(vida_id, &[MULTISIG_PREFIX, address].concat(), &data)
+    }
+
+    /// Stores a transaction submitted from the multisig account at `address`
+    /// and returns its id, numbered per account.
+    pub fn add_multisig_transaction(&self, vida_id: u64, address: &[u8], transaction: &MultisigTransaction) -> Result<u64, MerkleTreeError> {
+        let mut multisig = self.get_multisig(vida_id, address)?
+            .ok_or_else(|| MerkleTreeError::InvalidArgument(format!("{} is not a multisig account", hex::encode(address))))?;
+        let id = multisig.transaction_count;
+        self.set_multisig_transaction(vida_id, address, id, transaction)?;
+        multisig.transaction_count += 1;
+        self.set_multisig(vida_id, address, &multisig)?;
+        Ok(id)
+    }
+
+    /// Returns a transaction submitted from a multisig account by id.
+    pub fn get_multisig_transaction(&self, vida_id: u64, address: &[u8], id: u64) -> Result<Option<MultisigTransaction>, MerkleTreeError> {
+        match self.get_tree(vida_id)?.get_data(&Self::multisig_transaction_key(address, id))? {
+            Some(data) if !data.is_empty() => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt multisig transaction {}: {}", id, e))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Replaces a multisig transaction, e.g. to record an approval or its execution.
+    pub fn set_multisig_transaction(&self, vida_id: u64, address: &[u8], id: u64, transaction: &MultisigTransaction) -> Result<(), MerkleTreeError> {
+        let data = serde_json::to_vec(transaction)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode multisig transaction: {}", e)))?;
+        self.put(vida_id, &Self::multisig_transaction_key(address, id), &data)
+    }
+
+    /// Returns every transaction submitted from a multisig account with its id, oldest first.
+    pub fn get_multisig_transactions(&self, vida_id: u64, address: &[u8]) -> Result<Vec<(u64, MultisigTransaction)>, MerkleTreeError> {
+        let count = self.get_multisig(vida_id, address)?.map_or(0, |multisig| multisig.transaction_count);
+        let mut transactions = Vec::new();
+        for id in 0..count {
+            if let Some(transaction) = self.get_multisig_transaction(vida_id, address, id)? {
+                transactions.push((id, transaction));
+            }
+        }
+        Ok(transactions)
+    }
+
+    // Builds the tree key holding a transaction of a multisig account
+    fn multisig_transaction_key(address: &[u8], id: u64) -> Vec<u8> {
+        [MULTISIG_TX_PREFIX, address, &id.to_be_bytes()[..]].concat()
+    }
+    
+    /// Appends a record to the transaction history of the given address
+    pub fn add_transaction_record(&self, vida_id: u64, address: &[u8], record: &TransactionRecord) -> Result<(), MerkleTreeError> {
+        if address.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Address must not be empty".to_string()));
         }
         
-        let new_sender_balance = &sender_balance - amount;
-        let receiver_balance = Self::get_balance(receiver)?;
-        let new_receiver_balance = &receiver_balance + amount;
+        let count = self.get_transaction_count(vida_id, address)?;
+        let data = serde_json::to_vec(record)
+            .map_err(|e| MerkleTreeError::InvalidArgument(format!("Failed to encode transaction record: {}", e)))?;
+        self.put(vida_id, &Self::history_key(address, count), &data)?;
+        self.put(vida_id, &[HISTORY_COUNT_PREFIX, address].concat(), &(count + 1).to_be_bytes())
+    }
+    
+    /// Returns the number of history records stored for the given address
+    pub fn get_transaction_count(&self, vida_id: u64, address: &[u8]) -> Result<u64, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        let data = tree.get_data(&[HISTORY_COUNT_PREFIX, address].concat())?;
+        Self::decode_u64(&data.unwrap_or_default())
+    }
+    
+    /// Returns up to `limit` history records for the given address, newest first,
+    /// skipping the `offset` most recent ones
+    pub fn get_transactions(&self, vida_id: u64, address: &[u8], offset: u64, limit: u64) -> Result<Vec<TransactionRecord>, MerkleTreeError> {
+        let count = self.get_transaction_count(vida_id, address)?;
+        let mut records = Vec::new();
         
-        Self::set_balance(sender, &new_sender_balance)?;
-        Self::set_balance(receiver, &new_receiver_balance)?;
+        let newest = count.saturating_sub(offset);
+        let oldest = newest.saturating_sub(limit);
+        for index in (oldest..newest).rev() {
+            if let Some(record) = self.get_transaction(vida_id, address, index)? {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+    
+    /// Returns the history record of the given address at `index`, counting
+    /// from its oldest record
+    pub fn get_transaction(&self, vida_id: u64, address: &[u8], index: u64) -> Result<Option<TransactionRecord>, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        match tree.get_data(&Self::history_key(address, index))? {
+            Some(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MerkleTreeError::IllegalState(format!("Corrupt transaction record: {}", e))),
+            None => Ok(None),
+        }
+    }
+    
+    // Builds the tree key of the history record at the given index
+    fn history_key(address: &[u8], index: u64) -> Vec<u8> {
+        [HISTORY_PREFIX, address, &index.to_be_bytes()[..]].concat()
+    }
+    
+    /// Returns the hash of the genesis the tree was created from, if recorded
+    pub fn get_genesis_hash(&self, vida_id: u64) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        Ok(tree.get_data(GENESIS_HASH_KEY)?.filter(|hash| !hash.is_empty()))
+    }
+    
+    /// Records the hash of the genesis the tree was created from
+    pub fn set_genesis_hash(&self, vida_id: u64, hash: &[u8]) -> Result<(), MerkleTreeError> {
+        if hash.is_empty() {
+            return Err(MerkleTreeError::InvalidArgument("Genesis hash must not be empty".to_string()));
+        }
         
-        Ok(true)
+        self.put(vida_id, GENESIS_HASH_KEY, hash)
+    }
+    
+    /// Returns the admin addresses declared at genesis
+    pub fn get_admins(&self, vida_id: u64) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+        self.get_list(vida_id, ADMINS_KEY)
+    }
+    
+    /// Stores the admin addresses declared at genesis
+    pub fn set_admins(&self, vida_id: u64, admins: &[Vec<u8>]) -> Result<(), MerkleTreeError> {
+        self.set_list(vida_id, ADMINS_KEY, admins)
+    }
+    
+    /// Returns the peer addresses registered on-chain through `registerPeer`
+    pub fn get_registered_peers(&self, vida_id: u64) -> Result<Vec<String>, MerkleTreeError> {
+        Ok(self.get_list(vida_id, REGISTERED_PEERS_KEY)?
+            .into_iter()
+            .map(|peer| String::from_utf8_lossy(&peer).into_owned())
+            .collect())
+    }
+    
+    /// Stores the peer addresses registered on-chain
+    pub fn set_registered_peers(&self, vida_id: u64, peers: &[String]) -> Result<(), MerkleTreeError> {
+        let peers: Vec<Vec<u8>> = peers.iter().map(|peer| peer.as_bytes().to_vec()).collect();
+        self.set_list(vida_id, REGISTERED_PEERS_KEY, &peers)
+    }
+    
+    // Reads a list stored as u32 length-prefixed items
+    fn get_list(&self, vida_id: u64, key: &[u8]) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+        let tree = self.get_tree(vida_id)?;
+        let raw = tree.get_data(key)?.unwrap_or_default();
+        let mut data: &[u8] = &raw;
+        let mut items = Vec::new();
+        while !data.is_empty() {
+            items.push(Self::read_length_prefixed(&mut data)?);
+        }
+        Ok(items)
+    }
+    
+    // Stores a list as u32 length-prefixed items
+    fn set_list(&self, vida_id: u64, key: &[u8], items: &[Vec<u8>]) -> Result<(), MerkleTreeError> {
+        let mut data = Vec::new();
+        for item in items {
+            data.extend_from_slice(&(item.len() as u32).to_be_bytes());
+            data.extend_from_slice(item);
+        }
+        self.put(vida_id, key, &data)
     }
     
     /// Get the last checked block number
-    pub fn get_last_checked_block() -> Result<u64, MerkleTreeError> {
-        let tree = Self::get_tree()?;
-        let data = tree.get_data(LAST_CHECKED_BLOCK_KEY)?;
+    pub fn get_last_checked_block(&self, vida_id: u64) -> Result<u64, MerkleTreeError> {
+        let data = self.get_metadata(vida_id, LAST_CHECKED_BLOCK_KEY)?;
         
         match data {
             Some(bytes) if bytes.len() >= 8 => {
@@ -118,28 +3250,32 @@ impl DatabaseService {
         }
     }
     
-    /// Updates the last checked block number
-    pub fn set_last_checked_block(block_number: u64) -> Result<(), MerkleTreeError> {
-        let tree = Self::get_tree()?;
+    /// Updates the last checked block number. Written to the open batch, so it
+    /// reaches disk only with the commit of the block's state.
+    pub fn set_last_checked_block(&self, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
         let block_bytes = block_number.to_be_bytes();
-        tree.add_or_update_data(LAST_CHECKED_BLOCK_KEY, &block_bytes)
+        self.get_store(vida_id)?.journal().add_or_update_data(LAST_CHECKED_BLOCK_KEY, &block_bytes)
     }
     
     /// Records the Merkle root hash for a specific block
-    pub fn set_block_root_hash(block_number: u64, root_hash: &[u8]) -> Result<(), MerkleTreeError> {
+    pub fn set_block_root_hash(&self, vida_id: u64, block_number: u64, root_hash: &[u8]) -> Result<(), MerkleTreeError> {
         if root_hash.is_empty() {
             return Err(MerkleTreeError::InvalidArgument("Root hash must not be empty".to_string()));
         }
         
-        let tree = Self::get_tree()?;
         let key = format!("{}{}", BLOCK_ROOT_PREFIX, block_number);
-        tree.add_or_update_data(key.as_bytes(), root_hash)
+        self.get_store(vida_id)?.journal().add_or_update_data(key.as_bytes(), root_hash)
     }
     
     /// Retrieves the Merkle root hash for a specific block
-    pub fn get_block_root_hash(block_number: u64) -> Result<Option<Vec<u8>>, MerkleTreeError> {
-        let tree = Self::get_tree()?;
+    pub fn get_block_root_hash(&self, vida_id: u64, block_number: u64) -> Result<Option<Vec<u8>>, MerkleTreeError> {
         let key = format!("{}{}", BLOCK_ROOT_PREFIX, block_number);
-        tree.get_data(key.as_bytes())
+        self.get_metadata(vida_id, key.as_bytes())
+    }
+    
+    // Reads node-local metadata
+    fn get_metadata(&self, vida_id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, MerkleTreeError> {
+        let data = self.get_store(vida_id)?.journal().get_data(key)?;
+        Ok(data.filter(|value| !value.is_empty()))
     }
 }
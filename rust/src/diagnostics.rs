@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::database_service::{DatabaseService, Receipt, StateDiffEntry};
+use crate::error::Error;
+
+/// What a node knew about a block when its peers did not agree on the root:
+/// the roots on both sides, the block's transactions as their receipts and
+/// the state changes they made. Captured before the block is discarded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MismatchBundle {
+    pub vida_id: u64,
+    pub block_number: u64,
+    /// Unix time of the capture, in seconds.
+    pub captured_at: u64,
+    pub local_root: String,
+    /// Root each asked peer reported, null for peers that gave none.
+    pub peer_roots: BTreeMap<String, Option<String>>,
+    pub receipts: Vec<Receipt>,
+    /// Changes since the last commit, which include blocks whose commit the
+    /// flush policy deferred.
+    pub state_diff: Vec<StateDiffEntry>,
+}
+
+impl MismatchBundle {
+    /// Collects the bundle of a block from the open write batch holding its changes.
+    pub fn capture(
+        db: &DatabaseService,
+        vida_id: u64,
+        block_number: u64,
+        local_root: &[u8],
+        peer_roots: &[(String, Option<Vec<u8>>)],
+    ) -> Result<Self, Error> {
+        let mut receipts = Vec::new();
+        while let Some(receipt) = db.get_receipt_at(vida_id, block_number, receipts.len() as u32)
+            .map_err(|e| Error::storage("Failed to read receipts", e))?
+        {
+            receipts.push(receipt);
+        }
+        let state_diff = db.uncommitted_diff(vida_id)
+            .map_err(|e| Error::storage("Failed to read uncommitted changes", e))?;
+        Ok(Self {
+            vida_id,
+            block_number,
+            captured_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()),
+            local_root: hex::encode(local_root),
+            peer_roots: peer_roots.iter()
+                .map(|(peer, root)| (peer.clone(), root.as_ref().map(hex::encode)))
+                .collect(),
+            receipts,
+            state_diff,
+        })
+    }
+}
+
+/// Writes a bundle to `<dir>/vida-<id>-<block>.json`, replacing an earlier
+/// capture of the same block, then deletes all but the `retention` newest
+/// bundles of the VIDA (0 keeps them all).
+pub fn save(dir: &str, bundle: &MismatchBundle, retention: usize) -> Result<PathBuf, Error> {
+    let data = serde_json::to_vec_pretty(bundle)
+        .map_err(|e| Error::Validation(format!("Failed to encode diagnostics bundle: {}", e)))?;
+    fs::create_dir_all(dir).map_err(|e| Error::io(format!("Failed to create diagnostics directory {}", dir), e))?;
+
+    let path = bundle_path(dir, bundle.vida_id, bundle.block_number);
+    let partial = path.with_extension("json.tmp");
+    fs::write(&partial, &data).map_err(|e| Error::io(format!("Failed to write diagnostics bundle {}", partial.display()), e))?;
+    fs::rename(&partial, &path).map_err(|e| Error::io(format!("Failed to move diagnostics bundle to {}", path.display()), e))?;
+    info!("Saved root mismatch diagnostics of VIDA {} block {} to {}", bundle.vida_id, bundle.block_number, path.display());
+
+    if retention > 0 {
+        for block_number in list(dir, bundle.vida_id).into_iter().rev().skip(retention) {
+            let old = bundle_path(dir, bundle.vida_id, block_number);
+            if let Err(e) = fs::remove_file(&old) {
+                warn!("Failed to delete old diagnostics bundle {}: {}", old.display(), e);
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Returns the blocks of a VIDA with a saved bundle, oldest first.
+pub fn list(dir: &str, vida_id: u64) -> Vec<u64> {
+    let prefix = format!("vida-{}-", vida_id);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut blocks: Vec<u64> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix(&prefix)?.strip_suffix(".json")?.parse().ok()
+        })
+        .collect();
+    blocks.sort_unstable();
+    blocks
+}
+
+/// Reads the saved bundle of a block, if there is one.
+pub fn load(dir: &str, vida_id: u64, block_number: u64) -> Result<Option<MismatchBundle>, Error> {
+    let path = bundle_path(dir, vida_id, block_number);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read(&path).map_err(|e| Error::io(format!("Failed to read diagnostics bundle {}", path.display()), e))?;
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| Error::Validation(format!("Invalid diagnostics bundle {}: {}", path.display(), e)))
+}
+
+fn bundle_path(dir: &str, vida_id: u64, block_number: u64) -> PathBuf {
+    Path::new(dir).join(format!("vida-{}-{}.json", vida_id, block_number))
+}
@@ -0,0 +1,47 @@
+use pwr_rs::merkle_tree::MerkleTreeError;
+use thiserror::Error;
+
+/// Failure of a node operation, by category, so embedders can tell a broken
+/// configuration from an unreachable RPC or a storage fault. Messages are the
+/// same ones the node logs.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A database operation failed.
+    #[error("{context}: {cause:?}")]
+    Storage { context: String, cause: MerkleTreeError },
+    /// No PWR RPC endpoint could be used.
+    #[error("{0}")]
+    Rpc(String),
+    /// A request to a peer failed, or no HTTP client could be created.
+    #[error("{0}")]
+    Http(String),
+    /// The configuration, genesis file or command line is invalid.
+    #[error("{0}")]
+    Config(String),
+    /// Stored or supplied state failed a consistency check, such as a root
+    /// hash or genesis hash mismatch.
+    #[error("{0}")]
+    Validation(String),
+    /// Reading or writing a file or signal failed.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Result of a fallible node operation.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wraps a database error with what the node was doing.
+    pub fn storage(context: impl Into<String>, cause: MerkleTreeError) -> Self {
+        Error::Storage { context: context.into(), cause }
+    }
+
+    /// Wraps an I/O error with what the node was doing.
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Error::Io { context: context.into(), source }
+    }
+}
@@ -0,0 +1,147 @@
+use serde_json::{Map, Value};
+use tracing::{error, info};
+
+use crate::authorization::{self, decode_hex_address};
+use crate::database_service::{DatabaseService, Escrow};
+use crate::registry::{INSUFFICIENT_FUNDS, NOT_THE_RECEIVER, NOT_THE_SENDER, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Built-in `escrow` action: the sender locks `amount` for `receiver` until
+/// `expiryBlock`. The receiver can `claim` the funds up to and including
+/// that block; afterwards they go back to the sender, either through
+/// `reclaim` or automatically at the next checkpoint. An optional `tokenId`
+/// locks a token other than the default one; the escrow's id is listed by
+/// `/escrows`.
+pub struct EscrowHandler;
+
+impl TransactionHandler for EscrowHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+        let expiry_block = ctx.payload.get("expiryBlock")
+            .and_then(Value::as_u64)
+            .ok_or("Invalid or missing expiryBlock")?;
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let receiver = authorization::decode_recipient(receiver_hex)?;
+        if sender == receiver {
+            return Err("Cannot escrow to self".to_string());
+        }
+        if expiry_block <= ctx.block_number {
+            return Err(format!("Expiry block {} is not after block {}", expiry_block, ctx.block_number));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        let escrow = Escrow {
+            sender: hex::encode(&sender),
+            receiver: hex::encode(&receiver),
+            amount: amount.to_string(),
+            token_id,
+            expiry_block,
+        };
+        match ctx.db.lock_escrow(ctx.vida_id, &escrow) {
+            Ok(Some(id)) => {
+                info!("Escrow {} locks {} from {} for {} until block {}", id, amount, ctx.sender, receiver_hex, expiry_block);
+                Ok(())
+            }
+            Ok(None) => Err(format!("{}: cannot escrow {} from {}", INSUFFICIENT_FUNDS, amount, ctx.sender)),
+            Err(_) => Err("Escrow operation failed".to_string()),
+        }
+    }
+}
+
+/// Built-in `claim` action: the receiver of escrow `escrowId` takes its funds
+/// before it expires. Guarded by the receiver's `nonce`.
+pub struct ClaimHandler;
+
+impl TransactionHandler for ClaimHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let (id, escrow, sender) = pending_escrow(ctx)?;
+        if escrow.receiver != hex::encode(&sender) {
+            return Err(format!("{} {} of escrow {}", ctx.sender, NOT_THE_RECEIVER, id));
+        }
+        if ctx.block_number > escrow.expiry_block {
+            return Err(format!("Escrow {} expired at block {}", id, escrow.expiry_block));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, transfer::parse_nonce(ctx.payload)?)?;
+        ctx.db.release_escrow(ctx.vida_id, id, &sender)
+            .map_err(|_| "Claim operation failed".to_string())?;
+        info!("Escrow {} claimed by {}", id, ctx.sender);
+        Ok(())
+    }
+}
+
+/// Built-in `reclaim` action: the sender of escrow `escrowId` takes its funds
+/// back once it has expired. Guarded by the sender's `nonce`.
+pub struct ReclaimHandler;
+
+impl TransactionHandler for ReclaimHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let (id, escrow, sender) = pending_escrow(ctx)?;
+        if escrow.sender != hex::encode(&sender) {
+            return Err(format!("{} {} of escrow {}", ctx.sender, NOT_THE_SENDER, id));
+        }
+        if ctx.block_number <= escrow.expiry_block {
+            return Err(format!("Escrow {} does not expire before block {}", id, escrow.expiry_block.saturating_add(1)));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, transfer::parse_nonce(ctx.payload)?)?;
+        ctx.db.release_escrow(ctx.vida_id, id, &sender)
+            .map_err(|_| "Reclaim operation failed".to_string())?;
+        info!("Escrow {} reclaimed by {}", id, ctx.sender);
+        Ok(())
+    }
+}
+
+/// Refunds every escrow that expired before `block_number` to its sender.
+/// Called for each checkpoint inside its write batch, so refunds are part of
+/// the block's validated state.
+pub fn refund_expired(db: &DatabaseService, vida_id: u64, block_number: u64) {
+    let expired = match db.expired_escrows(vida_id, block_number) {
+        Ok(expired) => expired,
+        Err(e) => {
+            error!("Failed to read expired escrows: {:?}", e);
+            return;
+        }
+    };
+    for id in expired {
+        let escrow = match db.get_escrow(vida_id, id) {
+            Ok(Some(escrow)) => escrow,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to read escrow {}: {:?}", id, e);
+                continue;
+            }
+        };
+        let sender = hex::decode(&escrow.sender).unwrap_or_default();
+        match db.release_escrow(vida_id, id, &sender) {
+            Ok(_) => info!("Escrow {} expired, refunded {} to 0x{}", id, escrow.amount, escrow.sender),
+            Err(e) => error!("Failed to refund escrow {}: {:?}", id, e),
+        }
+    }
+}
+
+// Reads `escrowId` and returns the pending escrow with the decoded sender address
+fn pending_escrow(ctx: &TransactionContext) -> Result<(u64, Escrow, Vec<u8>), String> {
+    let id = parse_escrow_id(ctx.payload)?;
+    let sender = decode_hex_address(ctx.sender)?;
+    let escrow = ctx.db.get_escrow(ctx.vida_id, id)
+        .map_err(|_| "Failed to read escrow".to_string())?
+        .ok_or_else(|| format!("Escrow {} is not pending", id))?;
+    Ok((id, escrow, sender))
+}
+
+// Reads the `escrowId` field, given either as a decimal string or a number
+fn parse_escrow_id(json_data: &Map<String, Value>) -> Result<u64, String> {
+    json_data.get("escrowId")
+        .and_then(|val| match val {
+            Value::String(s) => s.parse::<u64>().ok(),
+            val => val.as_u64(),
+        })
+        .ok_or_else(|| "Invalid or missing escrowId".to_string())
+}
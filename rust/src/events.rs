@@ -0,0 +1,55 @@
+use std::sync::OnceLock;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Events buffered per subscriber before the oldest are dropped
+const EVENT_BUFFER: usize = 1024;
+
+/// Notification pushed to WebSocket clients as the node makes progress.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// A checkpoint was committed up to `block_number`.
+    #[serde(rename_all = "camelCase")]
+    BlockProcessed { vida_id: u64, block_number: u64 },
+    /// The balance of `address` changed in the blocks up to `block_number`.
+    #[serde(rename_all = "camelCase")]
+    BalanceChanged { vida_id: u64, address: String, balance: String, block_number: u64 },
+    /// Peers agreed on the root hash of `block_number` and it was stored.
+    #[serde(rename_all = "camelCase")]
+    RootHashFinalized { vida_id: u64, block_number: u64, root_hash: String },
+    /// The VIDA got within `catch_up_threshold` blocks of the chain head at
+    /// `block_number`, after starting or falling behind.
+    #[serde(rename_all = "camelCase")]
+    CaughtUp { vida_id: u64, block_number: u64 },
+}
+
+impl Event {
+    /// VIDA the event belongs to.
+    pub fn vida_id(&self) -> u64 {
+        match self {
+            Event::BlockProcessed { vida_id, .. }
+            | Event::BalanceChanged { vida_id, .. }
+            | Event::RootHashFinalized { vida_id, .. }
+            | Event::CaughtUp { vida_id, .. } => *vida_id,
+        }
+    }
+}
+
+// Process-wide channel; the transaction handler publishes, API connections subscribe
+static EVENTS: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+
+fn sender() -> &'static broadcast::Sender<Event> {
+    EVENTS.get_or_init(|| broadcast::channel(EVENT_BUFFER).0)
+}
+
+/// Sends an event to every current subscriber. Events published while no
+/// one is listening are dropped.
+pub fn publish(event: Event) {
+    let _ = sender().send(event);
+}
+
+/// Returns a receiver for all events published from now on.
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    sender().subscribe()
+}
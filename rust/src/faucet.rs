@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+use num_bigint::BigUint;
+use tracing::info;
+
+use crate::authorization::{self, decode_hex_address};
+use crate::database_service::DEFAULT_TOKEN;
+use crate::registry::{TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// How much the faucet hands out: at most `cap` base units per address in
+/// each window of `window_blocks` blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaucetPolicy {
+    pub cap: BigUint,
+    pub window_blocks: u64,
+}
+
+// Policy set by `configure`; the faucet is off until then
+static POLICY: OnceLock<FaucetPolicy> = OnceLock::new();
+
+/// Turns the faucet on with the given policy. Only the first call has an effect.
+pub fn configure(policy: FaucetPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+/// Built-in `faucet` action for test deployments: mints `amount` of the
+/// default token, the whole cap if omitted, to `receiver` or else the
+/// sender. What each address received is tracked in state per window of
+/// blocks, and requests beyond the cap are rejected. The action must be
+/// listed in the VIDA's `actions` and `faucet_cap` set, the same on every node.
+pub struct FaucetHandler;
+
+impl TransactionHandler for FaucetHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let policy = POLICY.get().ok_or("Faucet is not enabled")?;
+        let receiver = match ctx.payload.get("receiver") {
+            Some(receiver) => authorization::decode_recipient(receiver.as_str().ok_or("Invalid receiver")?)?,
+            None => decode_hex_address(ctx.sender)?,
+        };
+        let amount = match ctx.payload.get("amount") {
+            Some(_) => transfer::parse_amount(ctx.payload)?,
+            None => policy.cap.clone(),
+        };
+        if amount == BigUint::from(0u32) {
+            return Err("Faucet amount must be positive".to_string());
+        }
+
+        let window = ctx.block_number / policy.window_blocks;
+        let (last_window, drawn) = ctx.db.get_faucet_usage(ctx.vida_id, &receiver)
+            .map_err(|_| "Failed to read faucet usage".to_string())?;
+        let drawn = if last_window == window { drawn } else { BigUint::from(0u32) };
+        let total = &drawn + &amount;
+        if total > policy.cap {
+            let remaining = if drawn < policy.cap { &policy.cap - &drawn } else { BigUint::from(0u32) };
+            return Err(format!(
+                "Faucet limit reached: 0x{} may get {} more until block {}",
+                hex::encode(&receiver), remaining, (window + 1) * policy.window_blocks
+            ));
+        }
+
+        ctx.db.set_faucet_usage(ctx.vida_id, &receiver, window, &total)
+            .and_then(|()| ctx.db.mint(ctx.vida_id, DEFAULT_TOKEN, &receiver, &amount))
+            .map_err(|_| "Faucet mint failed".to_string())?;
+        info!("Faucet minted {} to 0x{}", amount, hex::encode(&receiver));
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::authorization::decode_hex_address;
+use crate::database_service::FeePolicy;
+use crate::registry::{NOT_AN_ADMIN, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+// Basis points in a whole amount
+const MAX_BASIS_POINTS: u64 = 10_000;
+
+/// Built-in `setFees` action: a genesis admin replaces the transfer fee
+/// policy with a `flat` amount plus `basisPoints` of every transfer, paid to
+/// `collector`. Both default to 0, so omitting them makes transfers free.
+/// Guarded by the admin's `nonce`.
+pub struct SetFeesHandler;
+
+impl TransactionHandler for SetFeesHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let policy = parse_fee_policy(ctx.payload)?;
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let admins = ctx.db.get_admins(ctx.vida_id)
+            .map_err(|_| "Failed to read admins".to_string())?;
+        if !admins.contains(&sender) {
+            return Err(format!("{} {}", ctx.sender, NOT_AN_ADMIN));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        ctx.db.set_fee_policy(ctx.vida_id, &policy)
+            .map_err(|_| "Failed to store fee policy".to_string())?;
+        info!("Fee policy set to {} flat plus {} basis points, collected by 0x{}", policy.flat, policy.basis_points, policy.collector);
+        Ok(())
+    }
+}
+
+/// Reads a fee policy from the `flat`, `basisPoints` and `collector` fields
+/// of a payload. The collector is required unless both fees are 0.
+pub fn parse_fee_policy(json_data: &Map<String, Value>) -> Result<FeePolicy, String> {
+    let flat = match json_data.get("flat") {
+        None | Some(Value::Null) => BigUint::default(),
+        Some(Value::String(s)) => s.parse().map_err(|_| "Invalid flat fee".to_string())?,
+        Some(val) => val.as_u64().map(BigUint::from).ok_or("Invalid flat fee")?,
+    };
+    let basis_points = match json_data.get("basisPoints") {
+        None | Some(Value::Null) => 0,
+        Some(val) => val.as_u64()
+            .filter(|basis_points| *basis_points <= MAX_BASIS_POINTS)
+            .ok_or_else(|| format!("basisPoints must be between 0 and {}", MAX_BASIS_POINTS))?,
+    };
+    let collector = match json_data.get("collector").and_then(Value::as_str) {
+        Some(collector) => hex::encode(decode_hex_address(collector)?),
+        None if flat == BigUint::default() && basis_points == 0 => String::new(),
+        None => return Err("Missing collector".to_string()),
+    };
+    Ok(FeePolicy { flat: flat.to_string(), basis_points: basis_points as u32, collector })
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// When validated blocks are committed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Commit every validated checkpoint.
+    Checkpoint,
+    /// Commit once this many blocks were validated since the last commit.
+    EveryBlocks(u64),
+    /// Commit once this much time has passed since the last commit.
+    Interval(Duration),
+}
+
+impl FlushPolicy {
+    /// Reads the policy from `flush_policy` (`checkpoint`, `blocks` or
+    /// `interval`) and its `flush_every_blocks` / `flush_interval_secs` setting.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        match config.flush_policy.as_str() {
+            "checkpoint" => Ok(FlushPolicy::Checkpoint),
+            "blocks" if config.flush_every_blocks > 0 => Ok(FlushPolicy::EveryBlocks(config.flush_every_blocks)),
+            "interval" if config.flush_interval_secs > 0 => {
+                Ok(FlushPolicy::Interval(Duration::from_secs(config.flush_interval_secs)))
+            }
+            "blocks" | "interval" => Err(format!("Flush policy {} needs a non-zero setting", config.flush_policy)),
+            other => Err(format!("Unknown flush policy: {}", other)),
+        }
+    }
+}
+
+/// Decides, per VIDA, whether a validated block should be committed now or
+/// left in the open write batch. Deferred blocks are replayed from the last
+/// committed block if the node stops before the next commit.
+#[derive(Debug)]
+pub struct FlushScheduler {
+    policy: FlushPolicy,
+    // Block and time of the last commit of each VIDA
+    last_flush: HashMap<u64, (u64, Instant)>,
+}
+
+impl FlushScheduler {
+    /// Creates a scheduler for the given policy.
+    pub fn new(policy: FlushPolicy) -> Self {
+        Self { policy, last_flush: HashMap::new() }
+    }
+
+    /// Switches to another policy, keeping the time and block of each VIDA's last commit.
+    pub fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns whether the validated `block_number` should be committed.
+    /// The first block seen for a VIDA is always committed.
+    pub fn is_due(&self, vida_id: u64, block_number: u64) -> bool {
+        let (last_block, last_time) = match self.last_flush.get(&vida_id) {
+            Some(last) => *last,
+            None => return true,
+        };
+        match self.policy {
+            FlushPolicy::Checkpoint => true,
+            FlushPolicy::EveryBlocks(blocks) => block_number.saturating_sub(last_block) >= blocks,
+            FlushPolicy::Interval(interval) => last_time.elapsed() >= interval,
+        }
+    }
+
+    /// Records that a VIDA was committed up to `block_number`.
+    pub fn record_flush(&mut self, vida_id: u64, block_number: u64) {
+        self.last_flush.insert(vida_id, (block_number, Instant::now()));
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use num_bigint::BigUint;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::database_service::{DatabaseService, FeePolicy, DEFAULT_TOKEN};
+use crate::error::Error;
+use crate::http;
+
+// How long a peer may take to report its genesis hash
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Initial state of a VIDA: balance allocations plus optional total supply,
+/// admin addresses and transfer fee policy. Loaded from a JSON or TOML file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Genesis {
+    pub allocations: Vec<Allocation>,
+    #[serde(default, alias = "total_supply")]
+    pub total_supply: Option<String>,
+    #[serde(default)]
+    pub admins: Vec<String>,
+    #[serde(default)]
+    pub fees: Option<FeePolicy>,
+}
+
+/// Balance allocated to an address at genesis.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Allocation {
+    pub address: String,
+    pub balance: String,
+}
+
+impl Genesis {
+    /// Loads a genesis file, parsed as TOML when the extension is `.toml`
+    /// and as JSON otherwise, and checks it for consistency.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read genesis file {}", path), e))?;
+        let genesis: Genesis = if Path::new(path).extension().map_or(false, |ext| ext == "toml") {
+            toml::from_str(&contents).map_err(|e| Error::Config(format!("Failed to parse genesis file {}: {}", path, e)))?
+        } else {
+            serde_json::from_str(&contents).map_err(|e| Error::Config(format!("Failed to parse genesis file {}: {}", path, e)))?
+        };
+        genesis.validate()?;
+        Ok(genesis)
+    }
+
+    // Decodes all allocations and checks them against the declared total supply
+    fn validate(&self) -> Result<(), Error> {
+        let mut sum = BigUint::from(0u32);
+        for (_, balance) in self.decoded_allocations()? {
+            sum += balance;
+        }
+        if let Some(total_supply) = &self.total_supply {
+            let total_supply: BigUint = total_supply.parse()
+                .map_err(|_| Error::Config(format!("Invalid genesis total supply: {}", total_supply)))?;
+            if total_supply != sum {
+                return Err(Error::Config(format!("Genesis allocations sum to {} but total supply is {}", sum, total_supply)));
+            }
+        }
+        for admin in &self.admins {
+            decode_address(admin)?;
+        }
+        if let Some(fees) = &self.fees {
+            self.decoded_fees(fees)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the allocations as (address bytes, balance) pairs.
+    pub fn decoded_allocations(&self) -> Result<Vec<(Vec<u8>, BigUint)>, Error> {
+        self.allocations.iter()
+            .map(|allocation| -> Result<(Vec<u8>, BigUint), Error> {
+                let address = decode_address(&allocation.address)?;
+                let balance: BigUint = allocation.balance.parse()
+                    .map_err(|_| Error::Config(format!("Invalid genesis balance: {}", allocation.balance)))?;
+                Ok((address, balance))
+            })
+            .collect()
+    }
+
+    // Normalizes the fee policy's collector and checks its flat fee
+    fn decoded_fees(&self, fees: &FeePolicy) -> Result<FeePolicy, Error> {
+        fees.flat.parse::<BigUint>()
+            .map_err(|_| Error::Config(format!("Invalid genesis flat fee: {}", fees.flat)))?;
+        if fees.basis_points > 10_000 {
+            return Err(Error::Config(format!("Genesis fee basis points {} exceed 10000", fees.basis_points)));
+        }
+        Ok(FeePolicy { collector: hex::encode(decode_address(&fees.collector)?), ..fees.clone() })
+    }
+
+    /// SHA-256 over a canonical encoding of the genesis, independent of the
+    /// file format, key order and address casing.
+    pub fn hash(&self) -> Result<Vec<u8>, Error> {
+        let mut allocations = self.decoded_allocations()?;
+        allocations.sort();
+        let mut admins = self.admins.iter()
+            .map(|admin| decode_address(admin))
+            .collect::<Result<Vec<_>, _>>()?;
+        admins.sort();
+
+        let mut hasher = Sha256::new();
+        for (address, balance) in &allocations {
+            hasher.update(format!("allocation:{}:{}\n", hex::encode(address), balance));
+        }
+        if let Some(total_supply) = &self.total_supply {
+            hasher.update(format!("totalSupply:{}\n", total_supply));
+        }
+        for admin in &admins {
+            hasher.update(format!("admin:{}\n", hex::encode(admin)));
+        }
+        // Only hashed when present, so genesis files without fees keep their hash
+        if let Some(fees) = &self.fees {
+            let fees = self.decoded_fees(fees)?;
+            hasher.update(format!("fees:{}:{}:{}\n", fees.flat, fees.basis_points, fees.collector));
+        }
+        Ok(hasher.finalize().to_vec())
+    }
+
+    /// Writes the genesis state into a fresh database, or checks that an
+    /// existing database was created from the same genesis.
+    pub fn apply(&self, db: &DatabaseService, vida_id: u64) -> Result<(), Error> {
+        let hash = self.hash()?;
+        let stored_hash = db.get_genesis_hash(vida_id)
+            .map_err(|e| Error::storage("Failed to get genesis hash", e))?;
+        match stored_hash {
+            Some(stored) if stored == hash => return Ok(()),
+            Some(stored) => {
+                return Err(Error::Validation(format!(
+                    "Genesis file hash {} does not match database genesis hash {}",
+                    hex::encode(&hash), hex::encode(&stored)
+                )));
+            }
+            None => {}
+        }
+
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(|e| Error::storage("Failed to get last checked block", e))?;
+        if last_checked_block > 0 {
+            warn!("Database predates genesis tracking, skipping genesis application");
+            return Ok(());
+        }
+
+        info!("Applying genesis {} to fresh database", hex::encode(&hash));
+        let mut total_supply = BigUint::from(0u32);
+        for (address, balance) in self.decoded_allocations()? {
+            db.set_balance(vida_id, DEFAULT_TOKEN, &address, &balance)
+                .map_err(|e| Error::storage("Failed to set balance", e))?;
+            info!("Set initial balance for {}: {}", hex::encode(&address), balance);
+            total_supply += balance;
+        }
+        db.set_total_supply(vida_id, DEFAULT_TOKEN, &total_supply)
+            .map_err(|e| Error::storage("Failed to set total supply", e))?;
+        let admins = self.admins.iter()
+            .map(|admin| decode_address(admin))
+            .collect::<Result<Vec<_>, _>>()?;
+        db.set_admins(vida_id, &admins)
+            .map_err(|e| Error::storage("Failed to set admins", e))?;
+        if let Some(fees) = &self.fees {
+            db.set_fee_policy(vida_id, &self.decoded_fees(fees)?)
+                .map_err(|e| Error::storage("Failed to set fee policy", e))?;
+        }
+        db.set_genesis_hash(vida_id, &hash)
+            .map_err(|e| Error::storage("Failed to set genesis hash", e))?;
+        db.flush(vida_id)
+            .map_err(|e| Error::storage("Failed to flush database", e))?;
+        info!("Genesis setup completed");
+
+        Ok(())
+    }
+
+    /// Asks every peer for its genesis hash and fails if any reachable peer
+    /// reports a different one. Unreachable peers are skipped.
+    pub async fn verify_with_peers(&self, client: &reqwest::Client, vida_id: u64, peers: &[String]) -> Result<(), Error> {
+        let hash = self.hash()?;
+
+        for peer in peers {
+            let url = http::peer_url(peer, &format!("/genesisHash?vidaId={}", vida_id));
+            let response = match client.get(&url).timeout(PEER_TIMEOUT).send().await {
+                Ok(response) if response.status().is_success() => response,
+                _ => {
+                    warn!("Could not fetch genesis hash from peer {}", peer);
+                    continue;
+                }
+            };
+            let peer_hash = response.text().await.unwrap_or_default();
+            let peer_hash = peer_hash.trim();
+            if peer_hash.is_empty() {
+                continue;
+            }
+            if peer_hash != hex::encode(&hash) {
+                return Err(Error::Validation(format!(
+                    "Peer {} reports genesis hash {} but ours is {}; refusing to start",
+                    peer, peer_hash, hex::encode(&hash)
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Decodes a hex address with or without 0x prefix
+fn decode_address(address: &str) -> Result<Vec<u8>, Error> {
+    address::parse_address(address).map_err(|reason| Error::Config(format!("Invalid genesis address: {}", reason)))
+}
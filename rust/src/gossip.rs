@@ -0,0 +1,68 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use utoipa::ToSchema;
+
+use crate::http;
+
+// Attested blocks kept per VIDA, counted back from the newest one
+const RETAINED_BLOCKS: u64 = 1_000;
+// How long a peer may take to accept an attestation
+const BROADCAST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Root hash a node computed for a block, pushed to its peers when root
+/// gossip is enabled so they can validate without polling it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    /// Address the attesting node is listed under in its peers' configuration.
+    pub peer: String,
+    pub vida_id: u64,
+    pub block_number: u64,
+    pub root_hash: String,
+    /// Signature over the block number and root, if the node signs attestations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Roots attested by peers, per VIDA and block.
+#[derive(Debug, Default)]
+pub struct AttestationStore {
+    vidas: HashMap<u64, BTreeMap<u64, HashMap<String, Vec<u8>>>>,
+}
+
+impl AttestationStore {
+    /// Records the root a peer attested for a block, replacing any earlier
+    /// attestation of the same peer, and forgets blocks that fell out of the
+    /// retention window.
+    pub fn record(&mut self, peer: &str, vida_id: u64, block_number: u64, root: Vec<u8>) {
+        let blocks = self.vidas.entry(vida_id).or_default();
+        blocks.entry(block_number).or_default().insert(peer.to_string(), root);
+
+        let newest = blocks.keys().next_back().copied().unwrap_or(block_number);
+        let retained = blocks.split_off(&newest.saturating_sub(RETAINED_BLOCKS));
+        *blocks = retained;
+    }
+
+    /// Returns the root a peer attested for a block, if any.
+    pub fn root(&self, peer: &str, vida_id: u64, block_number: u64) -> Option<Vec<u8>> {
+        self.vidas.get(&vida_id)?.get(&block_number)?.get(peer).cloned()
+    }
+}
+
+/// Posts an attestation to every peer concurrently. Failures are only logged:
+/// a peer that misses it pulls the root from `/rootHash` instead.
+pub async fn broadcast(client: &reqwest::Client, peers: &[String], attestation: &Attestation) {
+    let posts = peers.iter().map(|peer| {
+        client.post(http::peer_url(peer, "/attestations")).json(attestation).timeout(BROADCAST_TIMEOUT).send()
+    });
+    for (peer, result) in peers.iter().zip(join_all(posts).await) {
+        match result {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => debug!("Peer {} rejected attestation with HTTP {}", peer, response.status()),
+            Err(_) => debug!("Failed to send attestation to peer {}", peer),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::authorization::decode_hex_address;
+use crate::database_service::{DatabaseService, GovernancePolicy, Proposal, ProposalStatus, DEFAULT_TOKEN};
+use crate::fees;
+use crate::registry::{HOLDS_NO_TOKENS, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+// Basis points in the whole supply
+const MAX_BASIS_POINTS: u64 = 10_000;
+
+/// Built-in `propose` action: a holder of the default token proposes setting
+/// `parameter` to `value`. Parameters are `fees` (an object like the
+/// `setFees` payload), `admins` (the addresses allowed to mint, set fees and
+/// register peers) and `governance` (`quorumBasisPoints` and
+/// `votingPeriod`). Votes are open until the voting period has passed.
+/// Guarded by the proposer's `nonce`.
+pub struct ProposeHandler;
+
+impl TransactionHandler for ProposeHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let parameter = ctx.payload.get("parameter")
+            .and_then(Value::as_str)
+            .ok_or("Missing parameter")?;
+        let value = ctx.payload.get("value").cloned().ok_or("Missing value")?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        // Rejected now rather than when the proposal is enacted
+        validate_parameter(parameter, &value)?;
+
+        let proposer = decode_hex_address(ctx.sender)?;
+        require_holder(ctx, &proposer)?;
+        let policy = ctx.db.get_governance_policy(ctx.vida_id)
+            .map_err(|_| "Failed to read governance policy".to_string())?;
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &proposer, ctx.sender, nonce)?;
+        let proposal = Proposal {
+            proposer: hex::encode(&proposer),
+            parameter: parameter.to_string(),
+            value,
+            end_block: ctx.block_number.saturating_add(policy.voting_period),
+            votes: BTreeMap::new(),
+            status: ProposalStatus::Open,
+        };
+        let id = ctx.db.add_proposal(ctx.vida_id, &proposal)
+            .map_err(|_| "Failed to store proposal".to_string())?;
+        info!("Proposal {} by {} to change {}, voting until block {}", id, ctx.sender, parameter, proposal.end_block);
+        Ok(())
+    }
+}
+
+/// Built-in `vote` action: a holder of the default token backs (`support`
+/// true) or opposes proposal `proposalId` while its vote is open. Voting
+/// again replaces the earlier vote. Guarded by the voter's `nonce`.
+pub struct VoteHandler;
+
+impl TransactionHandler for VoteHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let id = parse_proposal_id(ctx.payload)?;
+        let support = ctx.payload.get("support")
+            .and_then(Value::as_bool)
+            .ok_or("Invalid or missing support")?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+
+        let voter = decode_hex_address(ctx.sender)?;
+        let mut proposal = open_proposal(ctx, id)?;
+        if ctx.block_number > proposal.end_block {
+            return Err(format!("Voting on proposal {} closed at block {}", id, proposal.end_block));
+        }
+        require_holder(ctx, &voter)?;
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &voter, ctx.sender, nonce)?;
+        proposal.votes.insert(hex::encode(&voter), support);
+        ctx.db.set_proposal(ctx.vida_id, id, &proposal)
+            .map_err(|_| "Failed to store vote".to_string())?;
+        info!("{} voted {} proposal {}", ctx.sender, if support { "for" } else { "against" }, id);
+        Ok(())
+    }
+}
+
+/// Built-in `enact` action: once the vote on proposal `proposalId` has
+/// closed, anyone can tally it. Votes are weighted by the voters' current
+/// balances of the default token; a passing proposal's parameter change is
+/// applied, and either way the proposal is closed.
+pub struct EnactHandler;
+
+impl TransactionHandler for EnactHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let id = parse_proposal_id(ctx.payload)?;
+        let mut proposal = open_proposal(ctx, id)?;
+        if ctx.block_number <= proposal.end_block {
+            return Err(format!("Voting on proposal {} is open until block {}", id, proposal.end_block));
+        }
+
+        let passed = tally(ctx.db, ctx.vida_id, &proposal)
+            .map_err(|_| "Failed to tally votes".to_string())?;
+        if passed {
+            apply_parameter(ctx.db, ctx.vida_id, &proposal.parameter, &proposal.value)?;
+            proposal.status = ProposalStatus::Enacted;
+        } else {
+            proposal.status = ProposalStatus::Rejected;
+        }
+        ctx.db.set_proposal(ctx.vida_id, id, &proposal)
+            .map_err(|_| "Failed to store proposal".to_string())?;
+        info!("Proposal {} to change {} {:?}", id, proposal.parameter, proposal.status);
+        Ok(())
+    }
+}
+
+// Whether a proposal has more support than opposition and reaches the quorum
+fn tally(db: &DatabaseService, vida_id: u64, proposal: &Proposal) -> Result<bool, pwr_rs::merkle_tree::MerkleTreeError> {
+    let (mut support, mut opposition) = (BigUint::default(), BigUint::default());
+    for (voter, in_favour) in &proposal.votes {
+        let balance = db.get_balance(vida_id, DEFAULT_TOKEN, &hex::decode(voter).unwrap_or_default())?;
+        if *in_favour {
+            support += balance;
+        } else {
+            opposition += balance;
+        }
+    }
+    let policy = db.get_governance_policy(vida_id)?;
+    let supply = db.get_total_supply(vida_id, DEFAULT_TOKEN)?.unwrap_or_default();
+    let quorum = supply * BigUint::from(policy.quorum_basis_points) / BigUint::from(MAX_BASIS_POINTS);
+    Ok(support > opposition && support >= quorum)
+}
+
+// Checks that `value` is a valid setting for `parameter`
+fn validate_parameter(parameter: &str, value: &Value) -> Result<(), String> {
+    match parameter {
+        "fees" => fees::parse_fee_policy(value.as_object().ok_or("fees value must be an object")?).map(|_| ()),
+        "admins" => parse_admins(value).map(|_| ()),
+        "governance" => parse_governance_policy(value).map(|_| ()),
+        _ => Err(format!("Unknown parameter: {}", parameter)),
+    }
+}
+
+// Applies a passed parameter change
+fn apply_parameter(db: &DatabaseService, vida_id: u64, parameter: &str, value: &Value) -> Result<(), String> {
+    let stored = match parameter {
+        "fees" => db.set_fee_policy(vida_id, &fees::parse_fee_policy(value.as_object().ok_or("fees value must be an object")?)?),
+        "admins" => db.set_admins(vida_id, &parse_admins(value)?),
+        "governance" => db.set_governance_policy(vida_id, &parse_governance_policy(value)?),
+        _ => return Err(format!("Unknown parameter: {}", parameter)),
+    };
+    stored.map_err(|_| format!("Failed to apply {} change", parameter))
+}
+
+// Reads a list of hex admin addresses
+fn parse_admins(value: &Value) -> Result<Vec<Vec<u8>>, String> {
+    value.as_array()
+        .ok_or("admins value must be a list of addresses")?
+        .iter()
+        .map(|admin| decode_hex_address(admin.as_str().ok_or("admins value must be a list of addresses")?))
+        .collect()
+}
+
+// Reads a governance policy, keeping the current defaults for omitted fields
+fn parse_governance_policy(value: &Value) -> Result<GovernancePolicy, String> {
+    let fields: &Map<String, Value> = value.as_object().ok_or("governance value must be an object")?;
+    let defaults = GovernancePolicy::default();
+    let quorum_basis_points = match fields.get("quorumBasisPoints") {
+        None => defaults.quorum_basis_points as u64,
+        Some(val) => val.as_u64()
+            .filter(|basis_points| *basis_points <= MAX_BASIS_POINTS)
+            .ok_or_else(|| format!("quorumBasisPoints must be between 0 and {}", MAX_BASIS_POINTS))?,
+    };
+    let voting_period = match fields.get("votingPeriod") {
+        None => defaults.voting_period,
+        Some(val) => val.as_u64().filter(|period| *period > 0).ok_or("votingPeriod must be a positive number of blocks")?,
+    };
+    Ok(GovernancePolicy { quorum_basis_points: quorum_basis_points as u32, voting_period })
+}
+
+// Only holders of the default token may propose and vote
+fn require_holder(ctx: &TransactionContext, address: &[u8]) -> Result<(), String> {
+    let balance = ctx.db.get_balance(ctx.vida_id, DEFAULT_TOKEN, address)
+        .map_err(|_| "Failed to read balance".to_string())?;
+    if balance == BigUint::default() {
+        return Err(format!("{} {}", ctx.sender, HOLDS_NO_TOKENS));
+    }
+    Ok(())
+}
+
+// Returns a proposal that has not been enacted or rejected yet
+fn open_proposal(ctx: &TransactionContext, id: u64) -> Result<Proposal, String> {
+    let proposal = ctx.db.get_proposal(ctx.vida_id, id)
+        .map_err(|_| "Failed to read proposal".to_string())?
+        .ok_or_else(|| format!("Unknown proposal {}", id))?;
+    if proposal.status != ProposalStatus::Open {
+        return Err(format!("Proposal {} is already closed", id));
+    }
+    Ok(proposal)
+}
+
+// Reads the `proposalId` field, given either as a decimal string or a number
+fn parse_proposal_id(json_data: &Map<String, Value>) -> Result<u64, String> {
+    json_data.get("proposalId")
+        .and_then(|val| match val {
+            Value::String(s) => s.parse::<u64>().ok(),
+            val => val.as_u64(),
+        })
+        .ok_or_else(|| "Invalid or missing proposalId".to_string())
+}
@@ -0,0 +1,166 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use pwr_rs::merkle_tree::MerkleTreeError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use crate::address::ADDRESS_LENGTH;
+use crate::database_service::DatabaseService;
+use crate::events::{self, Event};
+use crate::state::SharedState;
+
+/// Types and service traits generated from `proto/vida.proto`.
+pub mod proto {
+    tonic::include_proto!("vida.v1");
+}
+
+use proto::vida_server::{Vida, VidaServer};
+use proto::{
+    Block, GetBalanceRequest, GetBalanceResponse, GetProofRequest, GetProofResponse,
+    GetRootHashRequest, GetRootHashResponse, StreamBlocksRequest,
+};
+
+/// Starts the gRPC server on `grpc_port` in a background task, unless the
+/// port is 0. Only built with the `grpc` feature.
+pub fn start_grpc_server(state: &SharedState) {
+    let port = state.read().unwrap().config.grpc_port;
+    if port == 0 {
+        return;
+    }
+    let service = VidaService { state: state.clone() };
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    tokio::spawn(async move {
+        info!("Starting gRPC server on port {}", port);
+        if let Err(e) = Server::builder().add_service(VidaServer::new(service)).serve(address).await {
+            error!("gRPC server stopped: {}", e);
+        }
+    });
+}
+
+/// Serves the `Vida` gRPC service from the shared state.
+pub struct VidaService {
+    state: SharedState,
+}
+
+impl VidaService {
+    // Resolves an optional VIDA id to a synced VIDA and returns it with the database
+    fn vida(&self, vida_id: Option<u64>) -> Result<(u64, DatabaseService), Status> {
+        let state = self.state.read().unwrap();
+        let vida_id = vida_id.unwrap_or(state.config.vida_id);
+        if state.config.vida(vida_id).is_none() {
+            return Err(Status::not_found(format!("VIDA {} is not synced by this node", vida_id)));
+        }
+        Ok((vida_id, state.db.clone()))
+    }
+
+    // Root hash after a block: the current root for the last checked block, the recorded one before it
+    fn root_hash(db: &DatabaseService, vida_id: u64, block_number: u64, last_checked_block: u64) -> Result<Vec<u8>, Status> {
+        let root = if block_number == last_checked_block {
+            db.get_root_hash(vida_id)
+        } else {
+            db.get_block_root_hash(vida_id, block_number)
+        }.map_err(database_status)?;
+        root.ok_or_else(|| Status::not_found(format!("No root hash recorded for block {}", block_number)))
+    }
+
+    // Signs a block root with the node key, as raw signature bytes
+    fn sign(&self, block_number: u64, root: &[u8]) -> Vec<u8> {
+        let signature = self.state.read().unwrap().node_key.sign_root(block_number, root);
+        hex::decode(signature).unwrap_or_default()
+    }
+}
+
+#[tonic::async_trait]
+impl Vida for VidaService {
+    async fn get_balance(&self, request: Request<GetBalanceRequest>) -> Result<Response<GetBalanceResponse>, Status> {
+        let request = request.into_inner();
+        let (vida_id, db) = self.vida(request.vida_id)?;
+        check_address(&request.address)?;
+
+        let (balance, block_number) = match request.block_number {
+            Some(block_number) => {
+                let balance = db.get_balance_at(vida_id, request.token_id, &request.address, block_number)
+                    .map_err(database_status)?
+                    .ok_or_else(|| Status::not_found("No balance history recorded for address"))?;
+                (balance, block_number)
+            }
+            None => {
+                let balance = db.get_balance(vida_id, request.token_id, &request.address).map_err(database_status)?;
+                (balance, db.get_last_checked_block(vida_id).map_err(database_status)?)
+            }
+        };
+        Ok(Response::new(GetBalanceResponse {
+            vida_id,
+            address: request.address,
+            token_id: request.token_id,
+            balance: balance.to_string(),
+            block_number,
+        }))
+    }
+
+    async fn get_root_hash(&self, request: Request<GetRootHashRequest>) -> Result<Response<GetRootHashResponse>, Status> {
+        let request = request.into_inner();
+        let (vida_id, db) = self.vida(request.vida_id)?;
+        let last_checked_block = db.get_last_checked_block(vida_id).map_err(database_status)?;
+        let block_number = request.block_number.unwrap_or(last_checked_block);
+        if block_number > last_checked_block {
+            return Err(Status::not_found(format!("Block {} has not been processed yet", block_number)));
+        }
+
+        let root_hash = Self::root_hash(&db, vida_id, block_number, last_checked_block)?;
+        let signature = self.sign(block_number, &root_hash);
+        Ok(Response::new(GetRootHashResponse { vida_id, block_number, root_hash, signature }))
+    }
+
+    async fn get_proof(&self, request: Request<GetProofRequest>) -> Result<Response<GetProofResponse>, Status> {
+        let request = request.into_inner();
+        let (vida_id, db) = self.vida(request.vida_id)?;
+        check_address(&request.address)?;
+
+        let block_number = db.get_last_checked_block(vida_id).map_err(database_status)?;
+        let balance = db.get_balance(vida_id, request.token_id, &request.address).map_err(database_status)?;
+        let root_hash = Self::root_hash(&db, vida_id, block_number, block_number)?;
+        let signature = self.sign(block_number, &root_hash);
+        let public_key = hex::decode(self.state.read().unwrap().node_key.public_key_hex()).unwrap_or_default();
+        Ok(Response::new(GetProofResponse {
+            vida_id,
+            block_number,
+            address: request.address,
+            token_id: request.token_id,
+            balance: balance.to_string(),
+            root_hash,
+            signature,
+            public_key,
+        }))
+    }
+
+    type StreamBlocksStream = Pin<Box<dyn Stream<Item = Result<Block, Status>> + Send>>;
+
+    async fn stream_blocks(&self, request: Request<StreamBlocksRequest>) -> Result<Response<Self::StreamBlocksStream>, Status> {
+        let (vida_id, _) = self.vida(request.into_inner().vida_id)?;
+        // Lagging clients miss the blocks dropped from the event buffer
+        let blocks = BroadcastStream::new(events::subscribe()).filter_map(move |event| match event {
+            Ok(Event::RootHashFinalized { vida_id: id, block_number, root_hash }) if id == vida_id => {
+                Some(Ok(Block { vida_id, block_number, root_hash: hex::decode(root_hash).unwrap_or_default() }))
+            }
+            _ => None,
+        });
+        Ok(Response::new(Box::pin(blocks)))
+    }
+}
+
+// Rejects addresses that are not 20 bytes, which no account can have
+fn check_address(address: &[u8]) -> Result<(), Status> {
+    if address.len() != ADDRESS_LENGTH {
+        return Err(Status::invalid_argument(format!("Address must be {} bytes", ADDRESS_LENGTH)));
+    }
+    Ok(())
+}
+
+// Database failures are internal errors for the client
+fn database_status(e: MerkleTreeError) -> Status {
+    Status::internal(format!("Database error: {:?}", e))
+}
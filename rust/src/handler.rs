@@ -1,240 +1,1320 @@
-use pwr_rs::{
-    RPC,
-    transaction::types::VidaDataTransaction,
-    rpc::types::{VidaTransactionSubscription, block_saver},
-};
-use std::sync::Arc;
-use std::time::Duration;
+use pwr_rs::merkle_tree::MerkleTreeError;
+use pwr_rs::transaction::types::VidaDataTransaction;
+use std::collections::HashMap;
+use std::process;
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use futures_util::future::{BoxFuture, FutureExt};
 use hex;
 use serde_json::{Value, Map};
-use num_bigint::BigUint;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tracing::{debug, error, info, instrument, warn};
 
-use crate::database_service::DatabaseService;
+use crate::broker;
+use crate::catch_up;
+use crate::conduit;
+use crate::database_service::{DatabaseService, FailedTransaction, Receipt, ReceiptStatus, DEFAULT_TOKEN};
+use crate::diagnostics::{self, MismatchBundle};
+use crate::error::Error;
+use crate::escrow;
+use crate::events::{self, Event};
+use crate::gossip::{self, Attestation};
+use crate::http;
+use crate::peers::{MisbehaviorReport, RequestPolicy};
+use crate::payload;
+use crate::pipeline;
+use crate::registry::{self, TransactionContext};
+use crate::resync;
+use crate::source::{BlockCallback, VidaSource};
+use crate::shutdown::ShutdownCoordinator;
+use crate::signing;
+use crate::snapshot;
+use crate::state::SharedState;
+use crate::status::CatchUpUpdate;
+use crate::vesting;
+use crate::webhooks;
 
-// Constants
-const VIDA_ID: u64 = 73_746_238;
-const RPC_URL: &str = "https://pwrrpc.pwrlabs.io/";
+/// Response header carrying the node's signature over the block number and
+/// root returned by `/rootHash`.
+pub const ROOT_SIGNATURE_HEADER: &str = "X-Root-Signature";
 
-// Global state
-#[allow(non_upper_case_globals)]
-static mut subscription: Option<VidaTransactionSubscription> = None;
+// Shared application state, set once when the subscription is started.
+// The source callbacks are plain functions, so they reach the state through here.
+static STATE: OnceLock<SharedState> = OnceLock::new();
 
-pub static mut PEERS_TO_CHECK_ROOT_HASH_WITH: Vec<String> = Vec::new();
+// Reconnects to the source type the subscription was started with, for `resume_sync`
+type Resubscribe = fn(SharedState) -> BoxFuture<'static, Result<(), String>>;
+static RESUBSCRIBE: OnceLock<Resubscribe> = OnceLock::new();
 
-// Fetches the root hash from a peer node for the specified block number
+// Held for the duration of a block checkpoint so shutdown can wait for it
+static BLOCK_PROCESSING: Mutex<()> = Mutex::const_new(());
+
+// Set once shutdown begins; new transactions and blocks are ignored afterwards
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+// Set while an operator has paused syncing; the supervisor leaves subscriptions down
+static SYNC_PAUSED: AtomicBool = AtomicBool::new(false);
+
+// Number of consecutive blocks whose root failed to reach quorum, per VIDA
+static CONSECUTIVE_MISMATCHES: OnceLock<StdMutex<HashMap<u64, u32>>> = OnceLock::new();
+
+// Attempts at finalizing a block before its changes are discarded and the
+// block is fetched again, and the delay before the first retry, doubled after
+// every failed attempt
+const FINALIZE_ATTEMPTS: u32 = 5;
+const FINALIZE_RETRY_DELAY: Duration = Duration::from_millis(200);
+// Commits taking longer than this are logged, as they hold up the next block
+const SLOW_COMMIT: Duration = Duration::from_secs(5);
+
+// Exit code of a node whose state contradicts a pinned root
+const PINNED_ROOT_EXIT_CODE: i32 = 2;
+
+// Block at which the total supply of each VIDA was last audited
+static LAST_SUPPLY_AUDIT: OnceLock<StdMutex<HashMap<u64, u64>>> = OnceLock::new();
+
+// Block of the latest automatic snapshot of each VIDA
+static LAST_SNAPSHOT: OnceLock<StdMutex<HashMap<u64, u64>>> = OnceLock::new();
+
+// Returns the database handle held by the shared state
+fn database(state: &SharedState) -> DatabaseService {
+    state.read().unwrap().db.clone()
+}
+
+// Updates the consecutive mismatch counter of a VIDA and returns its new value
+fn record_mismatch(vida_id: u64, mismatched: bool) -> u32 {
+    let mut counters = CONSECUTIVE_MISMATCHES
+        .get_or_init(|| StdMutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    let counter = counters.entry(vida_id).or_insert(0);
+    *counter = if mismatched { *counter + 1 } else { 0 };
+    *counter
+}
+
+// Fetches the root hash from a peer node for the specified block number, retrying
+// failed requests as the peer's request policy allows. If a public key is
+// configured for the peer, the root must carry its valid signature.
+#[instrument(name = "peer_check", skip(client, policy, public_key))]
 async fn fetch_peer_root_hash(
     client: &reqwest::Client,
+    policy: &RequestPolicy,
+    peer: &str,
+    vida_id: u64,
+    block_number: u64,
+    public_key: Option<&str>
+) -> (bool, Option<Vec<u8>>) {
+    let mut attempt = 0;
+    loop {
+        let result = fetch_peer_root_hash_once(client, policy, peer, vida_id, block_number, public_key).await;
+        if result.0 || attempt >= policy.retries {
+            return result;
+        }
+        let delay = policy.retry_delay(attempt);
+        debug!("Retrying peer {} for block {} in {:?}", peer, block_number, delay);
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// Makes a single root hash request to a peer
+async fn fetch_peer_root_hash_once(
+    client: &reqwest::Client,
+    policy: &RequestPolicy,
     peer: &str, 
-    block_number: u64
+    vida_id: u64,
+    block_number: u64,
+    public_key: Option<&str>
 ) -> (bool, Option<Vec<u8>>) {
-    let url = format!("http://{}/rootHash?blockNumber={}", peer, block_number);
+    let url = http::peer_url(peer, &format!("/rootHash?blockNumber={}&vidaId={}", block_number, vida_id));
     
     match client.get(&url)
         .header("Accept", "text/plain")
+        .timeout(policy.timeout)
         .send()
         .await
     {
         Ok(response) => {
             if response.status().is_success() {
+                let signature = response.headers()
+                    .get(ROOT_SIGNATURE_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
                 match response.text().await {
                     Ok(hex_string) => {
                         let trimmed = hex_string.trim();
                         if trimmed.is_empty() {
-                            println!("Peer {} returned empty root hash for block {}", peer, block_number);
+                            warn!("Peer {} returned empty root hash for block {}", peer, block_number);
                             (false, None)
                         } else {
                             match hex::decode(trimmed) {
                                 Ok(root_hash) => {
-                                    println!("Successfully fetched root hash from peer {} for block {}", peer, block_number);
-                                    (true, Some(root_hash))
+                                    let verified = match (public_key, signature.as_deref()) {
+                                        (None, _) => true,
+                                        (Some(key), Some(signature)) => signing::verify_root(key, block_number, &root_hash, signature),
+                                        (Some(_), None) => false,
+                                    };
+                                    if verified {
+                                        debug!("Successfully fetched root hash from peer {} for block {}", peer, block_number);
+                                        (true, Some(root_hash))
+                                    } else {
+                                        warn!("Missing or invalid root signature from peer {} for block {}", peer, block_number);
+                                        (false, None)
+                                    }
                                 }
                                 Err(_) => {
-                                    println!("Invalid hex response from peer {} for block {}", peer, block_number);
+                                    warn!("Invalid hex response from peer {} for block {}", peer, block_number);
                                     (false, None)
                                 }
                             }
                         }
                     }
                     Err(_) => {
-                        println!("Failed to read response from peer {} for block {}", peer, block_number);
+                        warn!("Failed to read response from peer {} for block {}", peer, block_number);
                         (false, None)
                     }
                 }
             } else {
-                println!("Peer {} returned HTTP {} for block {}", peer, response.status(), block_number);
+                warn!("Peer {} returned HTTP {} for block {}", peer, response.status(), block_number);
                 (true, None)
             }
         }
         Err(_) => {
-            println!("Failed to fetch root hash from peer {} for block {}", peer, block_number);
+            warn!("Failed to fetch root hash from peer {} for block {}", peer, block_number);
             (false, None)
         }
     }
 }
 
-// Validates the local Merkle root against peers and persists it if a quorum of peers agree
-async fn check_root_hash_validity_and_save(block_number: u64) {
-    let local_root = match DatabaseService::get_root_hash() {
-        Ok(Some(root)) => root,
-        _ => {
-            println!("No local root hash available for block {}", block_number);
-            return;
-        }
-    };
-    
-    let peers = unsafe { &PEERS_TO_CHECK_ROOT_HASH_WITH };
+// Whether a quorum of peers agreed on a block root, and the root each peer
+// asked reported, None for peers that gave none
+struct PeerVerdict {
+    agreed: bool,
+    roots: Vec<(String, Option<Vec<u8>>)>,
+}
+
+// Asks peers whether they report `local_root` for the block until a quorum of
+// responding peers does. Roots peers attested through gossip are used as is;
+// the others are pulled. In validator mode, peers that reported another root
+// for a block the quorum validated are recorded as misbehaving.
+async fn peers_agree(state: &SharedState, vida_id: u64, block_number: u64, local_root: &[u8]) -> Result<PeerVerdict, BlockError> {
+    let peers = state.read().unwrap().peers.voters();
     let mut peers_count = peers.len();
     let mut quorum = (peers_count * 2) / 3 + 1;
     let mut matches = 0;
+    let mut conflicting = Vec::new();
+    let mut roots = Vec::with_capacity(peers.len());
     
-    // Create HTTP client
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-        .unwrap();
-    
-    for peer in peers {
-        let (success, peer_root) = fetch_peer_root_hash(&client, peer, block_number).await;
+    for peer in &peers {
+        let (attested, public_key, policy, client) = {
+            let state = state.read().unwrap();
+            let policy = state.peers.request_policy(peer);
+            (
+                state.attestations.root(peer, vida_id, block_number),
+                state.config.peer_public_keys.get(peer).cloned(),
+                policy,
+                state.http.for_policy(&policy).clone(),
+            )
+        };
+        let pulled = attested.is_none();
+        let started = Instant::now();
+        let (success, peer_root) = match attested {
+            Some(root) => (true, Some(root)),
+            None => fetch_peer_root_hash(&client, &policy, peer, vida_id, block_number, public_key.as_deref()).await,
+        };
+        {
+            let peer_manager = &mut state.write().unwrap().peers;
+            if success { peer_manager.record_success(peer) } else { peer_manager.record_failure(peer) }
+            if pulled {
+                peer_manager.record_latency(peer, started.elapsed());
+            }
+            if let Some(root) = &peer_root {
+                peer_manager.record_agreement(peer, root == local_root);
+            }
+        }
+        roots.push((peer.clone(), peer_root.clone().filter(|_| success)));
         
-        if success && peer_root.is_some() {
-            if peer_root.unwrap() == local_root {
-                matches += 1;
+        match peer_root {
+            Some(peer_root) if success => {
+                if peer_root == local_root {
+                    matches += 1;
+                } else {
+                    conflicting.push((peer.clone(), peer_root));
+                }
             }
-        } else {
-            if peers_count > 0 {
-                peers_count -= 1;
-                quorum = (peers_count * 2) / 3 + 1;
+            _ => {
+                if peers_count > 0 {
+                    peers_count -= 1;
+                    quorum = (peers_count * 2) / 3 + 1;
+                }
             }
         }
         
         if matches >= quorum {
-            DatabaseService::set_block_root_hash(block_number, &local_root).unwrap();
-            println!("Root hash validated and saved for block {}", block_number);
-            return;
+            save_peer_stats(state);
+            report_misbehavior(state, vida_id, block_number, local_root, conflicting);
+            return Ok(PeerVerdict { agreed: true, roots });
+        }
+    }
+    
+    save_peer_stats(state);
+    warn!("Root hash mismatch: only {}/{} peers agreed", matches, peers.len());
+    Ok(PeerVerdict { agreed: false, roots })
+}
+
+// Writes the diagnostics bundle of a block whose root peers did not agree on,
+// unless `diagnostics_dir` is empty; failing to do so never stops the sync
+fn save_diagnostics(state: &SharedState, db: &DatabaseService, vida_id: u64, block_number: u64, local_root: &[u8], peer_roots: &[(String, Option<Vec<u8>>)]) {
+    let (dir, retention) = {
+        let config = &state.read().unwrap().config;
+        (config.diagnostics_dir.clone(), config.diagnostics_retention)
+    };
+    if dir.is_empty() {
+        return;
+    }
+    let saved = MismatchBundle::capture(db, vida_id, block_number, local_root, peer_roots)
+        .and_then(|bundle| diagnostics::save(&dir, &bundle, retention));
+    if let Err(e) = saved {
+        warn!("Failed to save root mismatch diagnostics of block {}: {}", block_number, e);
+    }
+}
+
+// Records the peers whose roots conflict with the quorum root of a block, in validator mode
+fn report_misbehavior(state: &SharedState, vida_id: u64, block_number: u64, quorum_root: &[u8], conflicting: Vec<(String, Vec<u8>)>) {
+    let (enabled, db) = {
+        let state = state.read().unwrap();
+        (state.config.validator_mode, state.db.clone())
+    };
+    if !enabled {
+        return;
+    }
+    let detected_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    for (peer, peer_root) in conflicting {
+        let report = MisbehaviorReport {
+            peer,
+            vida_id,
+            block_number,
+            peer_root: hex::encode(peer_root),
+            quorum_root: hex::encode(quorum_root),
+            detected_at,
+        };
+        match db.add_misbehavior_report(&report) {
+            Ok(true) => warn!(
+                "Peer {} reported root {} for block {} but the quorum validated {}",
+                report.peer, report.peer_root, block_number, report.quorum_root
+            ),
+            Ok(false) => {}
+            Err(e) => error!("Failed to record misbehavior of peer {}: {:?}", report.peer, e),
         }
     }
+}
+
+// Persists the peer statistics gathered by a quorum check
+fn save_peer_stats(state: &SharedState) {
+    let (db, stats) = {
+        let state = state.read().unwrap();
+        (state.db.clone(), state.peers.stats())
+    };
+    if let Err(e) = db.set_peer_stats(&stats) {
+        warn!("Failed to save peer statistics: {:?}", e);
+    }
+}
+
+// Validates the local Merkle root against peers and records it if a quorum of peers agree.
+// Returns false if the block's changes were discarded and must be reprocessed.
+async fn check_root_hash_validity_and_save(state: &SharedState, vida_id: u64, block_number: u64) -> Result<bool, BlockError> {
+    let db = database(state);
+    let local_root = match db.get_root_hash(vida_id).map_err(|e| BlockError::storage("read root hash", e))? {
+        Some(root) => root,
+        None => {
+            warn!("No local root hash available for block {}", block_number);
+            return Ok(true);
+        }
+    };
     
-    println!("Root hash mismatch: only {}/{} peers agreed", matches, peers.len());
+    // A rebuild trusts the roots this node recorded before it rather than its peers
+    let expected_root = db.get_expected_root(vida_id, block_number)
+        .map_err(|e| BlockError::storage("read expected root", e))?;
+    if let Some(expected_root) = expected_root {
+        if expected_root != local_root {
+            return Err(BlockError::PinnedRoot(format!(
+                "Rebuilt root hash {} of VIDA {} at block {} does not match {} recorded before the rebuild",
+                hex::encode(&local_root), vida_id, block_number, hex::encode(&expected_root)
+            )));
+        }
+        db.set_block_root_hash(vida_id, block_number, &local_root)
+            .map_err(|e| BlockError::storage("record block root", e))?;
+        state.write().unwrap().sync.record_validation(vida_id, block_number, true);
+        debug!("Rebuilt root hash matches the recorded root of block {}", block_number);
+        return Ok(true);
+    }
+
+    let (gossip_enabled, address) = {
+        let config = &state.read().unwrap().config;
+        (config.root_gossip, config.advertised_address())
+    };
+    if gossip_enabled {
+        let signature = state.read().unwrap().node_key.sign_root(block_number, &local_root);
+        let attestation = Attestation {
+            peer: address,
+            vida_id,
+            block_number,
+            root_hash: hex::encode(&local_root),
+            signature: Some(signature),
+        };
+        let (peers, client) = {
+            let state = state.read().unwrap();
+            (state.peers.active(), state.http.client().clone())
+        };
+        gossip::broadcast(&client, &peers, &attestation).await;
+    }
+
+    let verdict = peers_agree(state, vida_id, block_number, &local_root).await?;
+    state.write().unwrap().sync.record_validation(vida_id, block_number, verdict.agreed);
+    if verdict.agreed {
+        db.set_block_root_hash(vida_id, block_number, &local_root)
+            .map_err(|e| BlockError::storage("record block root", e))?;
+        record_mismatch(vida_id, false);
+        info!("Root hash validated and saved for block {}", block_number);
+        return Ok(true);
+    }
+    save_diagnostics(state, &db, vida_id, block_number, &local_root, &verdict.roots);
     
-    // Revert changes and reset block to reprocess the data
-    DatabaseService::revert_unsaved_changes().unwrap();
-    unsafe {
-        subscription.as_ref().unwrap().set_latest_checked_block(
-            DatabaseService::get_last_checked_block().unwrap() as u64
-        );
-    }
-}
-
-// Executes a token transfer described by the given JSON payload
-fn handle_transfer(json_data: &Map<String, Value>, sender_hex: &str) {
-    // Extract amount and receiver from JSON
-    let amount = match json_data.get("amount")
-        .and_then(|val| {
-            if let Some(s) = val.as_str() {
-                s.parse::<BigUint>().ok()
-            } else if let Some(n) = val.as_u64() {
-                Some(BigUint::from(n))
-            } else {
-                None
+    // Discard the block's changes and reset the subscription to reprocess the data
+    db.abort_block(vida_id).map_err(|e| BlockError::storage("discard block changes", e))?;
+
+    // Repeated mismatches mean the local state diverged earlier; roll back further,
+    // and if that does not help either, replace the state with a peer snapshot
+    let (rollback_after, rollback_depth, resync_after) = {
+        let config = &state.read().unwrap().config;
+        (config.rollback_after_mismatches, config.rollback_depth, config.resync_after_mismatches)
+    };
+    let mismatches = record_mismatch(vida_id, true);
+    if resync_after > 0 && mismatches >= resync_after {
+        warn!("{} consecutive root mismatches, resyncing state from peers", mismatches);
+        let (peers, client) = {
+            let state = state.read().unwrap();
+            (state.peers.active(), state.http.client().clone())
+        };
+        match resync::resync_from_peers(&client, &db, vida_id, &peers).await {
+            Ok(block_number) => {
+                record_mismatch(vida_id, false);
+                info!("State resynced from peers at block {}", block_number);
             }
-        }) {
-        Some(amt) => amt,
-        None => {
-            println!("Invalid or missing amount");
+            Err(e) => error!("State resync failed: {}", e),
+        }
+    } else if rollback_after > 0 && mismatches == rollback_after {
+        match db.get_last_checked_block(vida_id) {
+            Ok(last_checked_block) => {
+                let target = last_checked_block.saturating_sub(rollback_depth);
+                warn!("{} consecutive root mismatches, rolling back to block {}", mismatches, target);
+                if let Err(e) = db.rollback_to_block(vida_id, target) {
+                    error!("Rollback to block {} failed: {:?}", target, e);
+                }
+            }
+            Err(e) => error!("Failed to read last checked block, skipping rollback: {:?}", e),
+        }
+    }
+
+    reprocess_from_last_checked_block(vida_id, state);
+    Ok(false)
+}
+
+// Points the subscription back at the last committed block so later blocks are fetched again.
+// If that block cannot be read the subscription is left alone and stalls, which the
+// supervisor detects and answers by resubscribing.
+fn reprocess_from_last_checked_block(vida_id: u64, state: &SharedState) {
+    let last_checked_block = match database(state).get_last_checked_block(vida_id) {
+        Ok(block_number) => block_number,
+        Err(e) => {
+            error!("Failed to read last checked block of VIDA {}: {:?}", vida_id, e);
             return;
         }
     };
-    
-    let receiver_hex = match json_data.get("receiver")
-        .and_then(|val| val.as_str()) {
-        Some(r) => r,
+    if let Some(subscription) = state.read().unwrap().subscriptions.get(&vida_id) {
+        subscription.set_latest_checked_block(last_checked_block);
+    }
+}
+
+/// Whether the node is shutting down and stops applying transactions.
+pub(crate) fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+// Processes a single VIDA transaction
+#[instrument(name = "transaction", skip(txn), fields(vida_id = txn.vida_id, sender = %txn.sender))]
+pub(crate) fn process_transaction(txn: VidaDataTransaction) {
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        return;
+    }
+    let state = match STATE.get() {
+        Some(state) => state,
         None => {
-            println!("Missing receiver");
+            error!("Application state not initialized");
             return;
         }
     };
-    
-    // Decode hex addresses
-    let sender_address = if sender_hex.starts_with("0x") { &sender_hex[2..] } else { sender_hex };
-    let receiver_address = if receiver_hex.starts_with("0x") { &receiver_hex[2..] } else { receiver_hex };
+    let db = database(state);
+    let actions = state.read().unwrap().config.vida(txn.vida_id)
+        .map(|vida| vida.actions)
+        .unwrap_or_default();
 
-    let sender = hex::decode(sender_address).unwrap_or_default();
-    let receiver = hex::decode(receiver_address).unwrap_or_default();
-    
-    // Execute transfer
-    match DatabaseService::transfer(&sender, &receiver, &amount) {
-        Ok(true) => {
-            println!("Transfer succeeded: {} from {} to {}", amount, sender_hex, receiver_hex);
+    match apply_transaction(&db, &actions, txn.vida_id, txn.block_number, &txn.hash, &txn.sender, &txn.data) {
+        Ok(()) => {}
+        Err(ApplyError::Rejected(reason)) => {
+            warn!("Transaction {} rejected: {}", txn.hash, reason);
+            dead_letter(&db, &txn, &reason);
         }
+        Err(ApplyError::Storage(reason)) => error!("{}", reason),
+    }
+}
+
+/// Why `apply_transaction` did not apply a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    /// The payload is malformed or the action's handler refused it. Every
+    /// node rejects the same transactions, so this is part of the state.
+    Rejected(String),
+    /// The database failed before the transaction was recorded.
+    Storage(String),
+}
+
+/// Why finalizing a block failed. Every failure leaves the block's changes in
+/// the open write batch and its last checked block unchanged, so finalizing
+/// it again is safe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    /// The application state was not initialized; retrying does not help.
+    Uninitialized,
+    /// A database operation failed.
+    Storage(String),
+    /// No HTTP client for peer requests could be created.
+    Http(String),
+    /// The state does not reproduce the root pinned in the configuration, or
+    /// a rebuild diverged from the roots recorded before it; syncing further
+    /// would only build on the wrong history.
+    PinnedRoot(String),
+}
+
+impl BlockError {
+    fn storage(operation: &str, error: MerkleTreeError) -> Self {
+        BlockError::Storage(format!("Failed to {}: {:?}", operation, error))
+    }
+
+    /// Whether finalizing the block again may succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, BlockError::Storage(_))
+    }
+}
+
+/// Applies one VIDA transaction to the write batch of its block: skips the
+/// transaction if its hash was already applied, decodes the JSON or binary payload and
+/// runs the handler of its action if `actions` enables it, then stores the
+/// transaction's receipt. This is the whole state transition of a
+/// transaction, so nodes that apply the same transactions in the same order
+/// compute the same root for every block.
+pub fn apply_transaction(
+    db: &DatabaseService,
+    actions: &[String],
+    vida_id: u64,
+    block_number: u64,
+    hash: &str,
+    sender_hex: &str,
+    data: &[u8],
+) -> Result<(), ApplyError> {
+    // Changes stay in the write batch until the block checkpoint commits them
+    db.begin_block(vida_id, block_number).map_err(|e| {
+        ApplyError::Storage(format!("Failed to open write batch for block {}: {:?}", block_number, e))
+    })?;
+    // Resuming from the last checked block redelivers its transactions; apply each once
+    let hash_bytes = hash_bytes(hash);
+    match db.mark_transaction_processed(vida_id, block_number, &hash_bytes) {
+        Ok(true) => {}
         Ok(false) => {
-            println!("Transfer failed (insufficient funds): {} from {} to {}", amount, sender_hex, receiver_hex);
+            debug!("Skipping already processed transaction {}", hash);
+            return Ok(());
         }
-        Err(_) => {
-            println!("Transfer operation failed");
+        Err(e) => return Err(ApplyError::Storage(format!("Failed to record transaction {}: {:?}", hash, e))),
+    }
+    let index = db.start_transaction(vida_id)
+        .map_err(|e| ApplyError::Storage(format!("Failed to start transaction {}: {:?}", hash, e)))?;
+    // The chain authenticated the sender, so its letter case carries no checksum
+    let sender_hex = &sender_hex.to_ascii_lowercase();
+    let (action, result) = match payload::decode(data) {
+        Ok(obj_map) => {
+            let action = obj_map.get("action")
+                .and_then(|val| val.as_str())
+                .unwrap_or("")
+                .to_lowercase();
+            let result = dispatch_action(db, actions, vida_id, &action, &obj_map, sender_hex, block_number)
+                .map_err(|reason| (receipt_status(&reason), reason));
+            (Some(action), result)
         }
+        Err(reason) => (None, Err((ReceiptStatus::InvalidPayload, reason))),
+    };
+    let summary = Summary { sender: sender_hex, action: action.as_deref() };
+    store_receipt(db, vida_id, hash, block_number, index, summary, &result)
+        .map_err(ApplyError::Storage)?;
+    result.map_err(|(_, reason)| ApplyError::Rejected(reason))
+}
+
+// Transaction hashes are keyed by their bytes, or by their text if not hex
+fn hash_bytes(hash: &str) -> Vec<u8> {
+    hex::decode(hash.trim_start_matches("0x")).unwrap_or_else(|_| hash.as_bytes().to_vec())
+}
+
+// Who sent a transaction and which action it asked for, None if its payload
+// could not be decoded
+struct Summary<'a> {
+    sender: &'a str,
+    action: Option<&'a str>,
+}
+
+// Records the outcome of a transaction for /receipt and /block
+fn store_receipt(
+    db: &DatabaseService,
+    vida_id: u64,
+    hash: &str,
+    block_number: u64,
+    index: u32,
+    summary: Summary,
+    result: &Result<(), (ReceiptStatus, String)>,
+) -> Result<(), String> {
+    let (status, reason) = match result {
+        Ok(()) => (ReceiptStatus::Applied, None),
+        Err((status, reason)) => (*status, Some(reason.clone())),
+    };
+    let receipt = Receipt {
+        tx_hash: hash.to_string(),
+        block_number,
+        index,
+        sender: Some(format!("0x{}", summary.sender.trim_start_matches("0x"))),
+        action: summary.action.filter(|action| !action.is_empty()).map(str::to_string),
+        status,
+        reason,
+    };
+    db.put_receipt(vida_id, &hash_bytes(hash), &receipt)
+        .map_err(|e| format!("Failed to store receipt of {}: {:?}", hash, e))
+}
+
+// Classifies a handler's rejection reason by the markers handlers build
+// their reasons from; handlers report failures as text
+fn receipt_status(reason: &str) -> ReceiptStatus {
+    if reason.starts_with(registry::INSUFFICIENT_FUNDS) || reason.starts_with(registry::ALLOWANCE_EXCEEDED) {
+        ReceiptStatus::InsufficientFunds
+    } else if registry::UNAUTHORIZED.iter().any(|marker| reason.contains(marker)) {
+        ReceiptStatus::Unauthorized
+    } else if registry::INVALID_PAYLOAD.iter().any(|prefix| reason.starts_with(prefix)) {
+        ReceiptStatus::InvalidPayload
+    } else {
+        ReceiptStatus::Rejected
     }
 }
 
-// Processes a single VIDA transaction
-fn process_transaction(txn: VidaDataTransaction) {
-    let data_bytes = txn.data;
+// Keeps a transaction that could not be applied, with the reason, for later reprocessing
+fn dead_letter(db: &DatabaseService, txn: &VidaDataTransaction, reason: &str) {
+    let failed = FailedTransaction {
+        hash: txn.hash.clone(),
+        block_number: txn.block_number,
+        sender: txn.sender.clone(),
+        data: hex::encode(&txn.data),
+        reason: reason.to_string(),
+    };
+    if let Err(e) = db.add_failed_transaction(txn.vida_id, &failed) {
+        error!("Failed to store failed transaction {}: {:?}", txn.hash, e);
+    }
+}
+
+/// Evaluates the dead-lettered transactions of a VIDA again, e.g. after a
+/// handler fix; only the one with `hash` if given. Only the chain can apply
+/// a transaction, so each one runs in a simulation of the current state as
+/// part of its original block, and neither the state nor the receipts
+/// change. Transactions that still fail keep their new reason. Returns how
+/// many would now apply and how many still fail.
+pub async fn reprocess_failed(state: &SharedState, vida_id: u64, hash: Option<&str>) -> Result<(usize, usize), String> {
+    // Keeps checkpoints from committing or reverting the state being viewed
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let actions = state.read().unwrap().config.vida(vida_id)
+        .map(|vida| vida.actions)
+        .unwrap_or_default();
+    reevaluate_failed(&database(state), &actions, vida_id, hash)
+}
+
+/// Does the work of `reprocess_failed` for the given enabled actions.
+pub fn reevaluate_failed(db: &DatabaseService, actions: &[String], vida_id: u64, hash: Option<&str>) -> Result<(usize, usize), String> {
+    let failed = db.get_failed_transactions(vida_id)
+        .map_err(|e| format!("Failed to read failed transactions: {:?}", e))?;
+
+    let (mut would_apply, mut failing) = (0, 0);
+    for txn in failed.iter().filter(|txn| hash.map_or(true, |hash| txn.hash == hash)) {
+        let view = db.simulation(vida_id)
+            .map_err(|e| format!("Failed to open simulated state: {:?}", e))?;
+        view.begin_block(vida_id, txn.block_number)
+            .and_then(|()| view.start_transaction(vida_id))
+            .map(|_| ())
+            .map_err(|e| format!("Failed to start simulated transaction: {:?}", e))?;
+        let result = hex::decode(&txn.data)
+            .map_err(|_| "Corrupt transaction data".to_string())
+            .and_then(|data| payload::decode(&data))
+            .and_then(|obj_map| {
+                let action = obj_map.get("action").and_then(Value::as_str).unwrap_or("").to_lowercase();
+                dispatch_action(&view, actions, vida_id, &action, &obj_map, &txn.sender, txn.block_number)
+            });
+        match result {
+            Ok(()) => would_apply += 1,
+            Err(reason) => {
+                failing += 1;
+                db.add_failed_transaction(vida_id, &FailedTransaction { reason, ..txn.clone() })
+                    .map_err(|e| format!("Failed to update failed transaction {}: {:?}", txn.hash, e))?;
+            }
+        }
+    }
+    info!("Reprocessed failed transactions of VIDA {}: {} would apply, {} still fail", vida_id, would_apply, failing);
+    Ok((would_apply, failing))
+}
+
+// Routes an action to its registered handler if it is among the VIDA's enabled actions,
+// returning the handler's rejection reason
+fn dispatch_action(
+    db: &DatabaseService,
+    actions: &[String],
+    vida_id: u64,
+    action: &str,
+    json_data: &Map<String, Value>,
+    sender_hex: &str,
+    block_number: u64,
+) -> Result<(), String> {
+    let enabled = actions.iter().any(|enabled| enabled.eq_ignore_ascii_case(action));
+    if !enabled {
+        debug!("Ignoring action '{}' not enabled for VIDA {}", action, vida_id);
+        return Ok(());
+    }
     
-    // Parse JSON data
-    let data_str = match String::from_utf8(data_bytes) {
-        Ok(s) => s,
-        Err(_) => {
-            println!("Error decoding transaction data");
+    let handler = match registry::handler_for(action) {
+        Some(handler) => handler,
+        None => {
+            debug!("No handler registered for action '{}'", action);
+            return Ok(());
+        }
+    };
+    let ctx = TransactionContext {
+        db,
+        vida_id,
+        block_number,
+        sender: sender_hex,
+        payload: json_data,
+    };
+    registry::execute(handler.as_ref(), &ctx)
+}
+
+/// Outcome of `simulate_transaction`.
+pub struct Simulation {
+    /// Handle on the simulated state after the action ran.
+    pub db: DatabaseService,
+    /// Block the action was simulated in, the one after the last checked block.
+    pub block_number: u64,
+    /// Why the action would be rejected, if it would be.
+    pub rejection: Option<(ReceiptStatus, String)>,
+}
+
+/// Runs a payload from `sender_hex` through the handler of its action as if
+/// it were a transaction of the next block, against a `simulation` of the
+/// VIDA's state. The real state is never written, so wallets can check an
+/// action before submitting it.
+pub async fn simulate_transaction(
+    state: &SharedState,
+    vida_id: u64,
+    sender_hex: &str,
+    payload: &Map<String, Value>,
+) -> Result<Simulation, String> {
+    // Keeps checkpoints from committing or reverting the state being viewed
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let db = database(state);
+    let actions = state.read().unwrap().config.vida(vida_id)
+        .map(|vida| vida.actions)
+        .unwrap_or_default();
+
+    let view = db.simulation(vida_id)
+        .map_err(|e| format!("Failed to open simulated state: {:?}", e))?;
+    let block_number = view.get_last_checked_block(vida_id)
+        .map_err(|e| format!("Failed to read last checked block: {:?}", e))? + 1;
+    view.begin_block(vida_id, block_number)
+        .and_then(|()| view.start_transaction(vida_id))
+        .map_err(|e| format!("Failed to start simulated transaction: {:?}", e))?;
+
+    let action = payload.get("action")
+        .and_then(|val| val.as_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let rejection = dispatch_action(&view, &actions, vida_id, &action, payload, &sender_hex.to_ascii_lowercase(), block_number)
+        .err()
+        .map(|reason| (receipt_status(&reason), reason));
+    Ok(Simulation { db: view, block_number, rejection })
+}
+
+// Finalizes a block once all of its transactions were applied: validates the root,
+// then advances the last checked block and commits. Runs at most once per block;
+// a persisted marker names the block while this is in progress. Storage failures
+// are retried with backoff; once attempts run out, or on any other failure, the
+// block's changes are discarded and the block is fetched again.
+#[instrument(name = "block")]
+pub(crate) async fn on_chain_progress(vida_id: u64, block_number: u64) {
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let mut delay = FINALIZE_RETRY_DELAY;
+    for attempt in 1..=FINALIZE_ATTEMPTS {
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            return;
+        }
+        let error = match finalize_block(vida_id, block_number).await {
+            Ok(()) => return,
+            Err(error) => error,
+        };
+        if !error.is_transient() {
+            error!("Cannot finalize block {}: {:?}", block_number, error);
+            if let BlockError::PinnedRoot(_) = error {
+                stop_on_pinned_root_mismatch(vida_id);
+                return;
+            }
+            break;
+        }
+        if attempt < FINALIZE_ATTEMPTS {
+            warn!("Finalizing block {} failed (attempt {}), retrying in {:?}: {:?}", block_number, attempt, delay, error);
+            sleep(delay).await;
+            delay *= 2;
+        } else {
+            error!("Giving up on block {} after {} attempts: {:?}", block_number, attempt, error);
+        }
+    }
+    discard_block(vida_id);
+}
+
+// Drops the uncommitted changes of a block that could not be finalized, so it is
+// fetched and applied again from the last checked block
+pub(crate) fn discard_block(vida_id: u64) {
+    let Some(state) = STATE.get() else {
+        return;
+    };
+    let db = database(state);
+    if let Err(e) = db.abort_block(vida_id) {
+        error!("Failed to discard uncommitted changes of VIDA {}: {:?}", vida_id, e);
+    }
+    clear_finalizing_block(&db, vida_id);
+    reprocess_from_last_checked_block(vida_id, state);
+}
+
+// Drops the block that contradicts the pinned root and shuts the node down. The
+// shutdown waits for the block in progress, so it runs once the caller returns.
+fn stop_on_pinned_root_mismatch(vida_id: u64) {
+    let Some(state) = STATE.get() else {
+        return;
+    };
+    let db = database(state);
+    if let Err(e) = db.abort_block(vida_id) {
+        error!("Failed to discard uncommitted changes of VIDA {}: {:?}", vida_id, e);
+    }
+    clear_finalizing_block(&db, vida_id);
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = ShutdownCoordinator::new(state).shutdown().await {
+            error!("Shutdown after pinned root mismatch failed: {}", e);
+        }
+        process::exit(PINNED_ROOT_EXIT_CODE);
+    });
+}
+
+// Checks the state against the root pinned for a VIDA when its pinned block is finalized
+// for the first time. Skipping past the pinned block fails as well: the pin was never
+// verified, so the node may be syncing the wrong VIDA.
+fn verify_pinned_root(state: &SharedState, db: &DatabaseService, vida_id: u64, last_checked_block: u64, block_number: u64) -> Result<(), BlockError> {
+    let pin = state.read().unwrap().config.vida(vida_id).and_then(|vida| vida.pinned_root());
+    let Some((pinned_block, pinned_root)) = pin else {
+        return Ok(());
+    };
+    if last_checked_block >= pinned_block || block_number < pinned_block {
+        return Ok(());
+    }
+    if block_number > pinned_block {
+        return Err(BlockError::PinnedRoot(format!(
+            "VIDA {} reached block {} without a checkpoint at pinned block {}",
+            vida_id, block_number, pinned_block
+        )));
+    }
+    let local_root = db.get_root_hash(vida_id)
+        .map_err(|e| BlockError::storage("read root hash", e))?
+        .unwrap_or_default();
+    if local_root != pinned_root {
+        return Err(BlockError::PinnedRoot(format!(
+            "Root hash {} of VIDA {} at block {} does not match pinned root {}; check vida_id and start_block",
+            hex::encode(&local_root), vida_id, pinned_block, hex::encode(&pinned_root)
+        )));
+    }
+    info!("Root hash of VIDA {} matches the pinned root at block {}", vida_id, pinned_block);
+    Ok(())
+}
+
+// Commits a block on a blocking thread, as flushing a large tree can take
+// seconds that would otherwise stall the runtime's polling and API tasks
+async fn commit_blocking(db: &DatabaseService, vida_id: u64, block_number: u64) -> Result<(), MerkleTreeError> {
+    let committing = db.clone();
+    let started = Instant::now();
+    let committed = tokio::task::spawn_blocking(move || committing.commit_block(vida_id, block_number))
+        .await
+        .unwrap_or_else(|e| Err(MerkleTreeError::IllegalState(format!("Commit task failed: {}", e))));
+    if started.elapsed() > SLOW_COMMIT {
+        warn!("Commit of VIDA {} block {} took {:?}", vida_id, block_number, started.elapsed());
+    }
+    committed
+}
+
+// One attempt at finalizing a block; see `on_chain_progress`
+async fn finalize_block(vida_id: u64, block_number: u64) -> Result<(), BlockError> {
+    let state = STATE.get().ok_or(BlockError::Uninitialized)?;
+    let db = database(state);
+
+    // Resumed subscriptions redeliver the last checked block
+    let last_checked_block = db.get_last_checked_block(vida_id)
+        .map_err(|e| BlockError::storage("read last checked block", e))?;
+    if block_number <= last_checked_block {
+        debug!("Block {} already finalized", block_number);
+        return Ok(());
+    }
+    db.set_finalizing_block(vida_id, Some(block_number))
+        .map_err(|e| BlockError::storage("record finalization", e))?;
+
+    db.begin_block(vida_id, block_number)
+        .map_err(|e| BlockError::storage("open write batch", e))?;
+    escrow::refund_expired(&db, vida_id, block_number);
+    vesting::release_matured(&db, vida_id, block_number);
+    verify_pinned_root(state, &db, vida_id, last_checked_block, block_number)?;
+    if !check_root_hash_validity_and_save(state, vida_id, block_number).await? {
+        clear_finalizing_block(&db, vida_id);
+        return Ok(());
+    }
+    // Nothing after this may fail with an error that is retried: the block
+    // now counts as checked, so another attempt would skip it
+    db.set_last_checked_block(vida_id, block_number)
+        .map_err(|e| BlockError::storage("advance last checked block", e))?;
+    state.write().unwrap().sync.record_checkpoint(vida_id, block_number);
+    report_catch_up(state, vida_id, block_number);
+
+    // Validated blocks stay in the write batch until the flush policy calls for a commit
+    if !state.read().unwrap().flush_scheduler.is_due(vida_id, block_number) {
+        debug!("Deferring commit of block {}", block_number);
+        clear_finalizing_block(&db, vida_id);
+        return Ok(());
+    }
+
+    // All changes since the previous commit reach disk together or not at all
+    let first_block = db.batch_first_block(vida_id).ok().flatten().unwrap_or(block_number);
+    let committed = commit_blocking(&db, vida_id, block_number).await;
+    clear_finalizing_block(&db, vida_id);
+    if let Err(e) = committed {
+        error!("Failed to commit block {}, reprocessing: {:?}", block_number, e);
+        reprocess_from_last_checked_block(vida_id, state);
+        return Ok(());
+    }
+    info!("Checkpoint updated to block {}", block_number);
+    state.write().unwrap().flush_scheduler.record_flush(vida_id, block_number);
+    publish_checkpoint_events(&db, vida_id, block_number);
+    webhooks::notify(&db, vida_id, first_block, block_number);
+    broker::publish_committed(&db, vida_id, first_block, block_number);
+    conduit::notify(vida_id, block_number);
+    #[cfg(feature = "postgres")]
+    crate::mirror::notify(vida_id, first_block, block_number);
+    audit_supply_if_due(state, &db, vida_id, block_number);
+    snapshot_if_due(state, &db, vida_id, block_number);
+    let mut state = state.write().unwrap();
+    if state.config.peer_registry && vida_id == state.config.vida_id {
+        if let Err(e) = state.peers.merge_registered(&db, vida_id) {
+            warn!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+// Drops the finalization marker once the block was committed, deferred or aborted
+fn clear_finalizing_block(db: &DatabaseService, vida_id: u64) {
+    if let Err(e) = db.set_finalizing_block(vida_id, None) {
+        warn!("Failed to clear finalization marker of VIDA {}: {:?}", vida_id, e);
+    }
+}
+
+/// Reports blocks whose finalization was interrupted by the previous run and
+/// clears their markers. Their changes never reached disk, so they are
+/// applied again once syncing resumes from the last checked block.
+pub fn recover_interrupted_finalization(db: &DatabaseService) -> Result<(), Error> {
+    for vida_id in db.vida_ids() {
+        let block_number = match db.get_finalizing_block(vida_id) {
+            Ok(Some(block_number)) => block_number,
+            Ok(None) => continue,
+            Err(e) => return Err(Error::storage("Failed to read finalization marker", e)),
+        };
+        let last_checked_block = db.get_last_checked_block(vida_id)
+            .map_err(|e| Error::storage("Failed to read last checked block", e))?;
+        if block_number > last_checked_block {
+            warn!(
+                "Finalization of VIDA {} block {} was interrupted, replaying from block {}",
+                vida_id, block_number, last_checked_block
+            );
+        }
+        db.set_finalizing_block(vida_id, None)
+            .map_err(|e| Error::storage("Failed to clear finalization marker", e))?;
+    }
+    Ok(())
+}
+
+// Checks the total supply invariant once every `supply_audit_interval` blocks
+fn audit_supply_if_due(state: &SharedState, db: &DatabaseService, vida_id: u64, block_number: u64) {
+    let interval = state.read().unwrap().config.supply_audit_interval;
+    if interval == 0 {
+        return;
+    }
+    {
+        let mut audits = LAST_SUPPLY_AUDIT
+            .get_or_init(|| StdMutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+        let last_audit = audits.entry(vida_id).or_insert(0);
+        if block_number < last_audit.saturating_add(interval) {
             return;
         }
+        *last_audit = block_number;
+    }
+
+    match db.audit_supply(vida_id, DEFAULT_TOKEN) {
+        Ok(audit) if audit.consistent => debug!("Supply audit passed at block {}", block_number),
+        Ok(audit) => error!(
+            "Total supply invariant violated at block {}: recorded {:?}, balances sum to {}",
+            block_number, audit.recorded_supply, audit.balance_sum
+        ),
+        Err(e) => warn!("Supply audit failed: {:?}", e),
+    }
+}
+
+// Saves a snapshot of the committed state once every `snapshot_every_blocks` blocks,
+// counting from the newest snapshot already in `snapshot_dir`
+fn snapshot_if_due(state: &SharedState, db: &DatabaseService, vida_id: u64, block_number: u64) {
+    let (interval, dir, retention) = {
+        let config = &state.read().unwrap().config;
+        (config.snapshot_every_blocks, config.snapshot_dir.clone(), config.snapshot_retention)
     };
-    
-    let json_data: Value = match serde_json::from_str(&data_str) {
-        Ok(json) => json,
-        Err(_) => {
-            println!("Error parsing transaction JSON");
+    if interval == 0 {
+        return;
+    }
+    {
+        let mut snapshots = LAST_SNAPSHOT
+            .get_or_init(|| StdMutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+        let last_snapshot = snapshots.entry(vida_id)
+            .or_insert_with(|| snapshot::latest_in_dir(&dir, vida_id).unwrap_or(0));
+        if block_number < last_snapshot.saturating_add(interval) {
             return;
         }
+        *last_snapshot = block_number;
+    }
+
+    if let Err(e) = snapshot::save_to_dir(db, vida_id, &dir, retention) {
+        warn!("Automatic snapshot of VIDA {} at block {} failed: {}", vida_id, block_number, e);
+    }
+}
+
+// Logs progress now and then while a VIDA is far behind the chain head, and
+// announces when it gets within `catch_up_threshold` blocks of it
+fn report_catch_up(state: &SharedState, vida_id: u64, block_number: u64) {
+    let update = {
+        let mut state = state.write().unwrap();
+        let threshold = state.config.catch_up_threshold;
+        state.sync.update_catch_up(vida_id, block_number, threshold)
     };
-    
-    if let Some(obj_map) = json_data.as_object() {
-        let action = obj_map.get("action")
-            .and_then(|val| val.as_str())
-            .unwrap_or("");
+    match update {
+        Some(CatchUpUpdate::Progress(progress)) => {
+            let eta = progress.eta_secs.map_or("unknown".to_string(), |secs| format!("{}s", secs));
+            info!(
+                "VIDA {} catching up at block {}: {} blocks behind, {:.1} blocks/s, ETA {}",
+                vida_id, block_number, progress.remaining_blocks, progress.blocks_per_second, eta
+            );
+        }
+        Some(CatchUpUpdate::CaughtUp) => {
+            info!("VIDA {} is live at block {}", vida_id, block_number);
+            events::publish(Event::CaughtUp { vida_id, block_number });
+        }
+        None => {}
+    }
+}
+
+// Notifies WebSocket subscribers about a committed checkpoint
+pub(crate) fn publish_checkpoint_events(db: &DatabaseService, vida_id: u64, block_number: u64) {
+    events::publish(Event::BlockProcessed { vida_id, block_number });
+    if let Ok(Some(root_hash)) = db.get_block_root_hash(vida_id, block_number) {
+        events::publish(Event::RootHashFinalized { vida_id, block_number, root_hash: hex::encode(root_hash) });
+    }
+    for address in db.take_changed_balances(vida_id).unwrap_or_default() {
+        if let Ok(balance) = db.get_balance(vida_id, DEFAULT_TOKEN, &address) {
+            events::publish(Event::BalanceChanged {
+                vida_id,
+                address: format!("0x{}", hex::encode(&address)),
+                balance: balance.to_string(),
+                block_number,
+            });
+        }
+    }
+}
+
+/// Stops accepting new transactions and blocks, then waits until the block
+/// checkpoint currently in progress (if any) has completed.
+pub async fn stop_processing(state: &SharedState) {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    let subscriptions: Vec<_> = state.write().unwrap().subscriptions.drain().collect();
+    for (vida_id, subscription) in subscriptions {
+        subscription.stop();
+        info!("VIDA {} transaction subscription stopped", vida_id);
+    }
+
+    let _guard = BLOCK_PROCESSING.lock().await;
+}
+
+/// Subscribes to the transactions of every configured VIDA through the
+/// source `S` at the configured RPC URLs, each VIDA resuming from its own
+/// last checked block, and keeps the subscriptions alive afterwards. The
+/// node uses `PwrSource`; tests can drive it with a `MockSource`.
+pub async fn subscribe_and_sync<S: VidaSource>(state: SharedState) -> Result<(), Error> {
+    STATE.set(state.clone()).map_err(|_| Error::Config("Subscription already started".to_string()))?;
+    let _ = RESUBSCRIBE.set(resubscribe::<S>);
+    let urls = state.read().unwrap().config.rpc_urls();
+
+    let (source_index, source) = connect_source::<S>(&urls, 0).await.map_err(Error::Rpc)?;
+    if let Ok(latest_block) = source.latest_block().await {
+        state.write().unwrap().sync.record_chain_block(latest_block);
+    }
+
+    // Bootstrap from historical ranges first; the subscription picks up wherever this stops
+    let vidas = state.read().unwrap().config.vidas();
+    for vida in vidas {
+        if let Err(e) = catch_up::catch_up(&*source, &state, vida.id, vida.start_block).await {
+            warn!("Catch-up of VIDA {} stopped: {}", vida.id, e);
+        }
+    }
+    let (vida_ids, capacity) = {
+        let config = &state.read().unwrap().config;
+        (config.vidas().iter().map(|vida| vida.id).collect::<Vec<_>>(), config.pipeline_capacity)
+    };
+    pipeline::start(&vida_ids, capacity);
+    subscribe_all(&*source, &state).map_err(Error::Rpc)?;
+
+    tokio::spawn(supervise_subscriptions(state, source, source_index));
+    Ok(())
+}
+
+// Connects to the first reachable RPC endpoint, trying the list from `start` onwards
+async fn connect_source<S: VidaSource>(urls: &[String], start: usize) -> Result<(usize, Arc<S>), String> {
+    for offset in 0..urls.len() {
+        let index = (start + offset) % urls.len();
+        match S::connect(&urls[index]).await {
+            Ok(source) => {
+                info!("Connected to RPC {}", urls[index]);
+                return Ok((index, Arc::new(source)));
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+    Err("No RPC endpoint reachable".to_string())
+}
+
+// Subscribes every VIDA again through the first reachable endpoint
+fn resubscribe<S: VidaSource>(state: SharedState) -> BoxFuture<'static, Result<(), String>> {
+    async move {
+        let urls = state.read().unwrap().config.rpc_urls();
+        let (_, source) = connect_source::<S>(&urls, 0).await?;
+        subscribe_all(&*source, &state)
+    }.boxed()
+}
+
+// Subscribes every configured VIDA on the given source
+fn subscribe_all<S: VidaSource>(source: &S, state: &SharedState) -> Result<(), String> {
+    let vidas = state.read().unwrap().config.vidas();
+    let db = database(state);
+    for vida in vidas {
+        let vida_id = vida.id;
+        let last_block = db.get_last_checked_block(vida_id)
+            .map_err(|e| format!("Failed to get last checked block: {:?}", e))?;
+        let from_block = if last_block > 0 { last_block } else { vida.start_block };
+        info!("Starting VIDA {} transaction subscription from block {}", vida_id, from_block);
+
+        let on_block: BlockCallback = Arc::new(move |block_number| pipeline::finalize(vida_id, block_number));
+        let subscription = source.subscribe(vida_id, from_block, pipeline::ingest, on_block);
+        if let Some(previous) = state.write().unwrap().subscriptions.insert(vida_id, subscription) {
+            previous.stop();
+        }
         
-        if action.to_lowercase() == "transfer" {
-            handle_transfer(obj_map, &txn.sender);
+        info!("Successfully subscribed to VIDA {} transactions", vida_id);
+    }
+    Ok(())
+}
+
+// Stops every subscription and discards changes of checkpoints that were not committed,
+// so resubscribing from the last checked block does not apply transactions twice
+async fn unsubscribe_all(state: &SharedState) {
+    let subscriptions: Vec<_> = state.write().unwrap().subscriptions.drain().collect();
+    for (_, subscription) in subscriptions {
+        subscription.stop();
+    }
+    pipeline::discard_queued();
+
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let db = database(state);
+    for vida_id in db.vida_ids() {
+        if let Err(e) = db.abort_block(vida_id) {
+            error!("Failed to discard uncommitted changes of VIDA {}: {:?}", vida_id, e);
         }
     }
 }
 
-// Callback invoked as blocks are processed
-async fn on_chain_progress(block_number: u64) {
-    DatabaseService::set_last_checked_block(block_number).unwrap();
-    check_root_hash_validity_and_save(block_number).await;
-    println!("Checkpoint updated to block {}", block_number);
-    DatabaseService::flush().map_err(|e| format!("Failed to flush database: {:?}", e)).unwrap();
+// Periodically checks the source and subscriptions; when either is lost, resubscribes
+// through the next configured RPC endpoint with exponential backoff
+async fn supervise_subscriptions<S: VidaSource>(state: SharedState, mut source: Arc<S>, mut source_index: usize) {
+    let config = state.read().unwrap().config.clone();
+    let urls = config.rpc_urls();
+    let check_interval = Duration::from_secs(config.subscription_stall_secs.max(1));
+    let initial_delay = Duration::from_millis(config.reconnect_initial_delay_ms.max(1));
+    let max_delay = Duration::from_millis(config.reconnect_max_delay_ms).max(initial_delay);
+    let mut progress = HashMap::new();
+
+    loop {
+        sleep(check_interval).await;
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            return;
+        }
+        if SYNC_PAUSED.load(Ordering::SeqCst) || subscriptions_healthy(&*source, &state, &mut progress).await {
+            continue;
+        }
+
+        warn!("VIDA subscriptions interrupted, reconnecting");
+        unsubscribe_all(&state).await;
+        progress.clear();
+
+        let mut delay = initial_delay;
+        loop {
+            if SHUTTING_DOWN.load(Ordering::SeqCst) {
+                return;
+            }
+            if SYNC_PAUSED.load(Ordering::SeqCst) {
+                break;
+            }
+            let reconnected = match connect_source::<S>(&urls, source_index + 1).await {
+                Ok((index, client)) => subscribe_all(&*client, &state).map(|_| (index, client)),
+                Err(e) => Err(e),
+            };
+            match reconnected {
+                Ok((index, client)) => {
+                    info!("Resubscribed to VIDA transactions through {}", urls[index]);
+                    source = client;
+                    source_index = index;
+                    break;
+                }
+                Err(e) => {
+                    warn!("Reconnect failed: {}, retrying in {:?}", e, delay);
+                    sleep(delay).await;
+                    delay = (delay * 2).min(max_delay);
+                }
+            }
+        }
+    }
 }
 
-// Subscribes to VIDA transactions starting from the given block
-pub async fn subscribe_and_sync(from_block: u64) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting VIDA transaction subscription from block {}", from_block);
-    
-    // Initialize RPC client
-    let rpc = RPC::new(RPC_URL).await.map_err(|e| format!("Failed to create RPC client: {:?}", e))?;
-    let rpc = Arc::new(rpc);
-    
-    let block_saver = block_saver::from_async(on_chain_progress);
-    // Subscribe to VIDA transactions
-    unsafe {
-        subscription = Some(rpc.subscribe_to_vida_transactions(
-            VIDA_ID,
-            from_block,
-            process_transaction,
-            Some(block_saver)
-        ));
+// Returns false if the source is unreachable or a subscription made no progress since
+// the previous check while the chain moved past it
+async fn subscriptions_healthy<S: VidaSource>(source: &S, state: &SharedState, progress: &mut HashMap<u64, u64>) -> bool {
+    let latest_block = match source.latest_block().await {
+        Ok(block_number) => block_number,
+        Err(e) => {
+            warn!("RPC health check failed: {}", e);
+            return false;
+        }
+    };
+    state.write().unwrap().sync.record_chain_block(latest_block);
+
+    let state = state.read().unwrap();
+    for (vida_id, subscription) in &state.subscriptions {
+        let checked = subscription.latest_checked_block();
+        let previous = progress.insert(*vida_id, checked);
+        if previous == Some(checked) && checked < latest_block {
+            warn!("VIDA {} subscription stalled at block {} while chain is at {}", vida_id, checked, latest_block);
+            return false;
+        }
     }
-    
-    println!("Successfully subscribed to VIDA {} transactions", VIDA_ID);
+    true
+}
+
+/// Stops all subscriptions until `resume_sync` is called. Changes of the
+/// checkpoint in progress are discarded and replayed on resume.
+pub async fn pause_sync(state: &SharedState) {
+    SYNC_PAUSED.store(true, Ordering::SeqCst);
+    unsubscribe_all(state).await;
+    info!("Sync paused");
+}
 
+/// Resubscribes every VIDA from its last checked block after `pause_sync`.
+pub async fn resume_sync(state: &SharedState) -> Result<(), String> {
+    if !SYNC_PAUSED.load(Ordering::SeqCst) {
+        return Err("Sync is not paused".to_string());
+    }
+    let resubscribe = RESUBSCRIBE.get().ok_or("Subscription not started")?;
+    resubscribe(state.clone()).await?;
+    SYNC_PAUSED.store(false, Ordering::SeqCst);
+    info!("Sync resumed");
     Ok(())
 }
+
+/// Returns whether syncing is paused by an operator.
+pub fn is_sync_paused() -> bool {
+    SYNC_PAUSED.load(Ordering::SeqCst)
+}
+
+/// Flushes every VIDA without an open checkpoint to disk. VIDAs in the middle
+/// of a checkpoint are skipped, since flushing them would persist a partial block.
+/// Returns the ids of the VIDAs that were flushed.
+pub async fn flush_committed(db: &DatabaseService) -> Result<Vec<u64>, String> {
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let mut flushed = Vec::new();
+    for vida_id in db.vida_ids() {
+        if db.has_open_batch(vida_id).map_err(|e| format!("{:?}", e))? {
+            continue;
+        }
+        let flushing = db.clone();
+        tokio::task::spawn_blocking(move || flushing.flush(vida_id))
+            .await
+            .map_err(|e| format!("Flush of VIDA {} failed: {}", vida_id, e))?
+            .map_err(|e| format!("Failed to flush VIDA {}: {:?}", vida_id, e))?;
+        flushed.push(vida_id);
+    }
+    Ok(flushed)
+}
+
+/// Checks the recorded root of an already validated block against peers again.
+/// If a quorum no longer agrees, the VIDA is rolled back to the checkpoint
+/// before that block and resynced from there. Returns whether the root held.
+pub async fn revalidate_block(state: &SharedState, vida_id: u64, block_number: u64) -> Result<bool, String> {
+    let db = database(state);
+    let local_root = db.get_block_root_hash(vida_id, block_number)
+        .map_err(|e| format!("{:?}", e))?
+        .ok_or_else(|| format!("No validated root recorded for block {}", block_number))?;
+
+    let valid = peers_agree(state, vida_id, block_number, &local_root).await
+        .map_err(|e| format!("{:?}", e))?
+        .agreed;
+    state.write().unwrap().sync.record_validation(vida_id, block_number, valid);
+    if valid {
+        info!("Block {} revalidated", block_number);
+        return Ok(true);
+    }
+
+    let _guard = BLOCK_PROCESSING.lock().await;
+    let target = block_number.saturating_sub(1);
+    warn!("Block {} failed revalidation, rolling back to block {}", block_number, target);
+    db.rollback_to_block(vida_id, target).map_err(|e| format!("Rollback failed: {:?}", e))?;
+    reprocess_from_last_checked_block(vida_id, state);
+    Ok(false)
+}
@@ -0,0 +1,76 @@
+use std::collections::{BTreeSet, HashMap};
+use num_bigint::BigUint;
+
+/// Balances of every holder of each token, ordered by amount so the largest
+/// holders can be listed without scanning the tree. Built from the tree on
+/// first use and kept up to date by every balance write afterwards; cleared
+/// whenever the tree changes underneath it, e.g. on revert or state import.
+#[derive(Debug, Default)]
+pub struct HolderIndex {
+    built: bool,
+    tokens: HashMap<u64, TokenHolders>,
+}
+
+// Holders of one token, by amount and by address
+#[derive(Debug, Default)]
+struct TokenHolders {
+    by_amount: BTreeSet<(BigUint, Vec<u8>)>,
+    balances: HashMap<Vec<u8>, BigUint>,
+    total: BigUint,
+}
+
+impl HolderIndex {
+    /// Whether the index holds every balance of the tree.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Marks the index complete once every balance of the tree was added.
+    pub fn mark_built(&mut self) {
+        self.built = true;
+    }
+
+    /// Forgets all balances; the index is rebuilt on its next use.
+    pub fn clear(&mut self) {
+        self.built = false;
+        self.tokens.clear();
+    }
+
+    /// Records the new balance of an address, dropping it once it is zero.
+    pub fn update(&mut self, token_id: u64, address: &[u8], balance: &BigUint) {
+        let holders = self.tokens.entry(token_id).or_default();
+        if let Some(previous) = holders.balances.remove(address) {
+            holders.by_amount.remove(&(previous.clone(), address.to_vec()));
+            holders.total -= previous;
+        }
+        if *balance != BigUint::from(0u32) {
+            holders.by_amount.insert((balance.clone(), address.to_vec()));
+            holders.balances.insert(address.to_vec(), balance.clone());
+            holders.total += balance;
+        }
+    }
+
+    /// Returns up to `limit` holders of a token with the largest balances,
+    /// largest first; equal balances are ordered by address.
+    pub fn top(&self, token_id: u64, limit: usize) -> Vec<(Vec<u8>, BigUint)> {
+        let Some(holders) = self.tokens.get(&token_id) else {
+            return Vec::new();
+        };
+        let mut top: Vec<(Vec<u8>, BigUint)> = Vec::with_capacity(limit.min(holders.balances.len()));
+        for (balance, address) in holders.by_amount.iter().rev() {
+            if top.len() == limit {
+                break;
+            }
+            top.push((address.clone(), balance.clone()));
+        }
+        // The set orders equal balances by descending address when reversed
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top
+    }
+
+    /// Returns the number of accounts holding a token and their combined balance.
+    pub fn totals(&self, token_id: u64) -> (usize, BigUint) {
+        self.tokens.get(&token_id)
+            .map_or((0, BigUint::from(0u32)), |holders| (holders.balances.len(), holders.total.clone()))
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+use std::time::Duration;
+use reqwest::Certificate;
+
+use crate::config::Config;
+use crate::peers::RequestPolicy;
+
+// Extra certificate authority trusted for peer connections, set by `configure`
+static PEER_CA: OnceLock<Option<Certificate>> = OnceLock::new();
+
+/// Loads the certificate authority configured in `peer_ca_file`, if any, so
+/// every client built here trusts it in addition to the system roots.
+/// Only the first call has an effect.
+pub fn configure(config: &Config) -> Result<(), String> {
+    let certificate = if config.peer_ca_file.is_empty() {
+        None
+    } else {
+        let pem = fs::read(&config.peer_ca_file)
+            .map_err(|e| format!("Failed to read peer CA file {}: {}", config.peer_ca_file, e))?;
+        let certificate = Certificate::from_pem(&pem)
+            .map_err(|e| format!("Invalid peer CA file {}: {}", config.peer_ca_file, e))?;
+        Some(certificate)
+    };
+    let _ = PEER_CA.set(certificate);
+    Ok(())
+}
+
+/// Builds a one-off HTTP client with the given request timeout, for requests
+/// made outside a running node's `PeerClients`.
+pub fn client(timeout: Duration) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(Some(certificate)) = PEER_CA.get() {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    builder.build().map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+// How long an idle pooled connection to a peer is kept open
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+// Idle connections kept per peer
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+/// Pooled HTTP clients for all communication with peers, kept in the app
+/// state so connections are reused across validation rounds, gossip and
+/// state sync. Peers are reached over keep-alive connections, and over
+/// HTTP/2 where an `https://` peer offers it. reqwest only sets connect
+/// timeouts per client, so one client is kept per distinct connect timeout
+/// of the request policies; the other timeouts are set per request.
+#[derive(Clone)]
+pub struct PeerClients {
+    default: reqwest::Client,
+    by_connect_timeout: HashMap<Duration, reqwest::Client>,
+}
+
+impl PeerClients {
+    /// Builds the clients for the default request policy and the per-peer ones.
+    pub fn new(default: &RequestPolicy, per_peer: &HashMap<String, RequestPolicy>) -> Result<Self, String> {
+        let mut by_connect_timeout = HashMap::new();
+        by_connect_timeout.insert(default.connect_timeout, pooled_client(default.connect_timeout)?);
+        for policy in per_peer.values() {
+            if !by_connect_timeout.contains_key(&policy.connect_timeout) {
+                by_connect_timeout.insert(policy.connect_timeout, pooled_client(policy.connect_timeout)?);
+            }
+        }
+        let default = by_connect_timeout[&default.connect_timeout].clone();
+        Ok(PeerClients { default, by_connect_timeout })
+    }
+
+    /// Returns the client for peers under the default request policy.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.default
+    }
+
+    /// Returns the client for a peer under `policy`.
+    pub fn for_policy(&self, policy: &RequestPolicy) -> &reqwest::Client {
+        self.by_connect_timeout.get(&policy.connect_timeout).unwrap_or(&self.default)
+    }
+}
+
+// Builds a client keeping idle connections open, without a request timeout
+fn pooled_client(connect_timeout: Duration) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(POOL_IDLE_TIMEOUT)
+        .http2_adaptive_window(true);
+    if let Some(Some(certificate)) = PEER_CA.get() {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    builder.build().map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// Returns the URL of `path` on a peer. Peers are given as `host:port`, which
+/// uses plain HTTP, or with an explicit `http://` or `https://` scheme.
+pub fn peer_url(peer: &str, path: &str) -> String {
+    if peer.starts_with("http://") || peer.starts_with("https://") {
+        format!("{}{}", peer.trim_end_matches('/'), path)
+    } else {
+        format!("http://{}{}", peer, path)
+    }
+}
@@ -0,0 +1,76 @@
+//! Stateful VIDA engine for the PWR chain.
+//!
+//! Synchronizes VIDA transactions into a Merkle tree-backed database,
+//! validates the resulting root hashes against peers and serves the state
+//! over HTTP. The binary in `main.rs` parses the [`cli`] and hands off to [`node`];
+//! other projects can embed the same pieces directly:
+//!
+//! - [`database_service::DatabaseService`] for reading and writing VIDA state
+//! - [`handler`] for subscribing to VIDA transactions and checkpointing blocks,
+//!   reading them from any [`source::VidaSource`]
+//! - [`registry`] for plugging in custom actions next to the built-in ones,
+//!   which can read other VIDAs through a [`state_reader::StateReader`]
+//! - [`api::GET`] for the warp routes of the public HTTP API,
+//!   [`api::Admin`] for the token-protected peer management routes, and
+//!   [`api::Gossip`] for the root attestations pushed by peers; with the
+//!   `graphql` feature, `api::GraphQl` serves the same state as one schema
+//! - [`events`] for the block and balance notifications pushed over `/ws`
+//! - `grpc`, with the `grpc` feature, for typed gRPC access to balances and roots
+//!
+//! Fallible entry points return [`Error`], which sorts failures into storage,
+//! RPC, HTTP, configuration, validation and I/O errors.
+
+pub mod address;
+pub mod allowance;
+pub mod amount;
+pub mod api;
+pub mod authorization;
+pub mod balance_cache;
+pub mod broker;
+pub mod catch_up;
+pub mod cli;
+pub mod conduit;
+pub mod config;
+pub mod database_service;
+pub mod diagnostics;
+pub mod error;
+pub mod escrow;
+pub mod events;
+pub mod faucet;
+pub mod fees;
+pub mod flush;
+pub mod genesis;
+pub mod governance;
+pub mod gossip;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handler;
+pub mod holder_index;
+pub mod http;
+pub mod logging;
+#[cfg(feature = "postgres")]
+pub mod mirror;
+pub mod multisig;
+pub mod node;
+pub mod payload;
+pub mod peers;
+pub mod pipeline;
+pub mod registry;
+pub mod reload;
+pub mod replica;
+pub mod resync;
+pub mod shutdown;
+pub mod signing;
+pub mod snapshot;
+pub mod source;
+pub mod stall;
+pub mod state;
+pub mod state_reader;
+pub mod state_sync;
+pub mod status;
+pub mod supply;
+pub mod transfer;
+pub mod vesting;
+pub mod webhooks;
+
+pub use error::{Error, Result};
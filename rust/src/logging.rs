@@ -0,0 +1,51 @@
+use std::env;
+use std::sync::OnceLock;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::Error;
+
+// Replaces the filter of the installed subscriber; its type depends on the log format
+type FilterReloader = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
+
+static RELOAD: OnceLock<FilterReloader> = OnceLock::new();
+
+/// Installs the global tracing subscriber.
+/// `RUST_LOG` takes precedence over the configured level; `format` is
+/// either "text" (default) or "json".
+pub fn init(level: &str, format: &str) -> Result<(), Error> {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(level))
+        .map_err(|e| Error::Config(format!("Invalid log level {}: {}", level, e)))?;
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match format {
+        "json" => {
+            let builder = builder.json().with_filter_reloading();
+            let handle = builder.reload_handle();
+            let _ = RELOAD.set(Box::new(move |filter| handle.reload(filter).map_err(|e| e.to_string())));
+            builder.try_init()
+        }
+        "text" => {
+            let builder = builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            let _ = RELOAD.set(Box::new(move |filter| handle.reload(filter).map_err(|e| e.to_string())));
+            builder.try_init()
+        }
+        other => return Err(Error::Config(format!("Unknown log format: {}", other))),
+    };
+    result.map_err(|e| Error::Config(format!("Failed to initialize logging: {}", e)))?;
+
+    Ok(())
+}
+
+/// Changes the level of the installed subscriber at runtime. Does nothing
+/// while `RUST_LOG` is set, as it overrides the configured level.
+pub fn set_level(level: &str) -> Result<(), Error> {
+    let filter = EnvFilter::try_new(level)
+        .map_err(|e| Error::Config(format!("Invalid log level {}: {}", level, e)))?;
+    if env::var_os("RUST_LOG").is_some() {
+        return Ok(());
+    }
+    let reload = RELOAD.get().ok_or_else(|| Error::Config("Logging is not initialized".to_string()))?;
+    reload(filter).map_err(|e| Error::Config(format!("Failed to change log level: {}", e)))
+}
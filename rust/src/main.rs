@@ -1,98 +1,54 @@
-mod database_service;
-mod api;
-mod handler;
-
-use std::env;
-use std::time::Duration;
-use hex;
-use num_bigint::BigUint;
-use tokio::time::sleep;
-
-use crate::database_service::DatabaseService;
-use crate::api::GET;
-use crate::handler::{subscribe_and_sync, PEERS_TO_CHECK_ROOT_HASH_WITH};
-
-// Constants
-const START_BLOCK: u64 = 1;
-const PORT: u16 = 8080;
-
-// Initializes peer list from arguments or defaults
-fn initialize_peers() {
-    let args: Vec<String> = env::args().collect();
-    
-    unsafe {
-        if args.len() > 1 {
-            PEERS_TO_CHECK_ROOT_HASH_WITH = args[1..].to_vec();
-            println!("Using peers from args: {:?}", PEERS_TO_CHECK_ROOT_HASH_WITH);
-        } else {
-            PEERS_TO_CHECK_ROOT_HASH_WITH = vec![
-                "localhost:8080".to_string(),
-            ];
-            println!("Using default peers: {:?}", PEERS_TO_CHECK_ROOT_HASH_WITH);
-        }
-    }
-}
-
-// Sets up the initial account balances when starting from a fresh database
-async fn init_initial_balances() -> Result<(), Box<dyn std::error::Error>> {
-    if DatabaseService::get_last_checked_block().map_err(|e| format!("Failed to get last checked block: {:?}", e))? == 0 {
-        println!("Setting up initial balances for fresh database");
-        
-        let initial_balances = vec![
-            (hex::decode("c767ea1d613eefe0ce1610b18cb047881bafb829").unwrap(), BigUint::from(1_000_000_000_000u64)),
-            (hex::decode("3b4412f57828d1ceb0dbf0d460f7eb1f21fed8b4").unwrap(), BigUint::from(1_000_000_000_000u64)),
-            (hex::decode("9282d39ca205806473f4fde5bac48ca6dfb9d300").unwrap(), BigUint::from(1_000_000_000_000u64)),
-            (hex::decode("e68191b7913e72e6f1759531fbfaa089ff02308a").unwrap(), BigUint::from(1_000_000_000_000u64)),
-        ];
-        
-        for (address, balance) in initial_balances {
-            DatabaseService::set_balance(&address, &balance).map_err(|e| format!("Failed to set balance: {:?}", e))?;
-            println!("Set initial balance for {}: {}", hex::encode(&address), balance);
-        }
-        println!("Initial balances setup completed");
+use clap::Parser;
+use tracing::info;
+
+use pwr_stateful_vida::cli::{Cli, Command};
+use pwr_stateful_vida::config::Config;
+use pwr_stateful_vida::{logging, node, Error};
+
+// Initializes peer list from arguments or the configured defaults
+fn initialize_peers(config: &Config, args: &[String]) -> Vec<String> {
+    if !args.is_empty() {
+        let peers = args.to_vec();
+        info!("Using peers from args: {:?}", peers);
+        peers
+    } else {
+        let peers = config.peers.clone();
+        info!("Using configured peers: {:?}", peers);
+        peers
     }
-    
-    Ok(())
-}
-
-/// Start the API server in a background task
-async fn start_api_server() {
-    let routes = GET::run();
-    
-    tokio::spawn(async move {
-        println!("Starting API server on port {}", PORT);
-        warp::serve(routes)
-            .run(([0, 0, 0, 0], PORT))
-            .await;
-    });
-    
-    // Give server time to start
-    sleep(Duration::from_millis(2000)).await;
-    println!("API server started on http://0.0.0.0:{}", PORT);
 }
 
 /// Application entry point for synchronizing VIDA transactions
-/// with the local Merkle-backed database.
+/// with the local Merkle-backed database. Subcommands export, import or
+/// inspect the state instead; see `--help`.
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting PWR VIDA Transaction Synchronizer...");
-
-    initialize_peers();
-    DatabaseService::initialize().map_err(|e| format!("Database initialization failed: {:?}", e))?;
-
-    start_api_server().await;
-    init_initial_balances().await?;
-
-    let last_block = DatabaseService::get_last_checked_block().map_err(|e| format!("Failed to get last checked block: {:?}", e))?;
-    let from_block = if last_block > 0 { last_block } else { START_BLOCK };
-
-    println!("Starting synchronization from block {}", from_block);
-
-    subscribe_and_sync(from_block).await?;
-
-    // Keep the main thread alive
-    println!("Application started successfully. Press Ctrl+C to exit.");
-    tokio::signal::ctrl_c().await?;
-
-    Ok(())
+async fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+    let config = cli.load_config()?;
+    logging::init(&config.log_level, &config.log_format)?;
+
+    match cli.command.unwrap_or(Command::Sync { peers: Vec::new() }) {
+        Command::Sync { peers } => {
+            info!("Starting PWR VIDA Transaction Synchronizer...");
+            let peers = initialize_peers(&config, &peers);
+            node::run(config, peers).await
+        }
+        Command::ExportState { file, vida_id } => node::export_snapshot(&config, &file, vida_id),
+        Command::ImportState { file, vida_id } => node::import_snapshot(&config, &file, vida_id),
+        Command::VerifyState { vida_id } => node::verify_state(&config, vida_id),
+        Command::Audit { vida_id } => node::audit(&config, vida_id),
+        Command::ShowRoot { block, vida_id } => node::show_root(&config, block, vida_id),
+        Command::TrustedSync { file, block, root_hash, vida_id, peers } => {
+            let peers = initialize_peers(&config, &peers);
+            node::trusted_sync(config, peers, &file, block, &root_hash, vida_id).await
+        }
+        Command::Rebuild { vida_id } => {
+            let peers = initialize_peers(&config, &[]);
+            node::rebuild(config, peers, vida_id).await
+        }
+        Command::RebuildFromBlock { block, vida_id } => {
+            let peers = initialize_peers(&config, &[]);
+            node::rebuild_from_block(config, peers, block, vida_id).await
+        }
+    }
 }
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use num_bigint::BigUint;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use tokio_postgres::{Client, NoTls, Transaction};
+use tracing::{error, info, warn};
+
+use crate::database_service::{ChangeRecord, DatabaseService, Receipt, StateChange, DEFAULT_TOKEN};
+
+// Most blocks mirrored in one PostgreSQL transaction
+const MAX_BLOCKS_PER_TRANSACTION: u64 = 100;
+// Wait before reconnecting after PostgreSQL failed
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS vida_mirror_progress (
+        vida_id BIGINT PRIMARY KEY,
+        block_number BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS vida_balances (
+        vida_id BIGINT NOT NULL,
+        token_id BIGINT NOT NULL,
+        address TEXT NOT NULL,
+        balance NUMERIC NOT NULL,
+        block_number BIGINT NOT NULL,
+        PRIMARY KEY (vida_id, token_id, address)
+    );
+    CREATE TABLE IF NOT EXISTS vida_transfers (
+        vida_id BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        position INTEGER NOT NULL,
+        tx_index INTEGER NOT NULL,
+        tx_hash TEXT,
+        token_id BIGINT NOT NULL,
+        from_address TEXT NOT NULL,
+        to_address TEXT NOT NULL,
+        amount NUMERIC NOT NULL,
+        PRIMARY KEY (vida_id, block_number, position)
+    );
+    CREATE INDEX IF NOT EXISTS vida_transfers_from ON vida_transfers (vida_id, from_address);
+    CREATE INDEX IF NOT EXISTS vida_transfers_to ON vida_transfers (vida_id, to_address);
+    CREATE TABLE IF NOT EXISTS vida_receipts (
+        vida_id BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        tx_index INTEGER NOT NULL,
+        tx_hash TEXT NOT NULL,
+        status TEXT NOT NULL,
+        reason TEXT,
+        PRIMARY KEY (vida_id, block_number, tx_index)
+    );
+";
+
+// Blocks committed since the mirror last looked, per VIDA: the first block of
+// the earliest commit and the last block of the latest one
+static COMMITTED: OnceLock<(Mutex<BTreeMap<u64, (u64, u64)>>, Notify)> = OnceLock::new();
+
+/// Starts mirroring committed balances, transfers and receipts of every VIDA
+/// into the PostgreSQL database at `url`. Each block range is written in one
+/// transaction with the last mirrored block, kept in `vida_mirror_progress`,
+/// so the mirror resumes where it stopped after a restart or outage. A VIDA
+/// not mirrored before starts from a copy of its current balances.
+pub fn start(url: String, db: DatabaseService) {
+    // Catches up from the mirrored block of each VIDA to its last committed one
+    let committed = db.vida_ids().into_iter()
+        .map(|vida_id| (vida_id, (u64::MAX, db.get_last_checked_block(vida_id).unwrap_or(0))))
+        .collect();
+    if COMMITTED.set((Mutex::new(committed), Notify::new())).is_err() {
+        return;
+    }
+    let (pending, committed) = COMMITTED.get().expect("set above");
+
+    tokio::spawn(async move {
+        let mut client = None;
+        let mut retry = BTreeMap::new();
+        loop {
+            let mut blocks = std::mem::take(&mut *pending.lock().unwrap());
+            for (vida_id, (first_block, last_block)) in std::mem::take(&mut retry) {
+                let merged = blocks.entry(vida_id).or_insert((first_block, last_block));
+                merged.0 = merged.0.min(first_block);
+            }
+            if let Err(e) = mirror_committed(&mut client, &url, &db, &blocks).await {
+                warn!("PostgreSQL mirror failed, retrying in {:?}: {}", RETRY_DELAY, e);
+                client = None;
+                retry = blocks;
+                sleep(RETRY_DELAY).await;
+                continue;
+            }
+            committed.notified().await;
+        }
+    });
+}
+
+/// Tells the mirror that the blocks of a VIDA from `first_block` to
+/// `block_number` were committed. Blocks committed again after a rollback
+/// replace what was mirrored of them.
+pub fn notify(vida_id: u64, first_block: u64, block_number: u64) {
+    if let Some((pending, committed)) = COMMITTED.get() {
+        let mut pending = pending.lock().unwrap();
+        let blocks = pending.entry(vida_id).or_insert((first_block, block_number));
+        *blocks = (blocks.0.min(first_block), block_number);
+        committed.notify_one();
+    }
+}
+
+// Mirrors each VIDA's committed blocks, from the first committed block or the
+// block after the mirrored one, whichever is lower, connecting first if needed
+async fn mirror_committed(client: &mut Option<Client>, url: &str, db: &DatabaseService, committed: &BTreeMap<u64, (u64, u64)>) -> Result<(), String> {
+    if client.is_none() {
+        *client = Some(connect(url).await?);
+    }
+    let client = client.as_mut().expect("connected above");
+
+    for (&vida_id, &(first_block, last_block)) in committed {
+        let row = client.query_opt("SELECT block_number FROM vida_mirror_progress WHERE vida_id = $1", &[&(vida_id as i64)])
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut from_block = match row {
+            Some(row) => first_block.min(row.get::<_, i64>(0) as u64 + 1),
+            None => seed_balances(client, db, vida_id, last_block).await? + 1,
+        };
+        while from_block <= last_block {
+            let to_block = last_block.min(from_block + MAX_BLOCKS_PER_TRANSACTION - 1);
+            mirror_blocks(client, db, vida_id, from_block, to_block).await?;
+            from_block = to_block + 1;
+        }
+    }
+    Ok(())
+}
+
+async fn connect(url: &str) -> Result<Client, String> {
+    let (client, connection) = tokio_postgres::connect(url, NoTls).await.map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!("PostgreSQL connection closed: {}", e);
+        }
+    });
+    client.batch_execute(SCHEMA).await.map_err(|e| e.to_string())?;
+    info!("Mirroring VIDA state to PostgreSQL");
+    Ok(client)
+}
+
+// Copies every balance of a VIDA not mirrored before, as of `block_number`
+async fn seed_balances(client: &mut Client, db: &DatabaseService, vida_id: u64, block_number: u64) -> Result<u64, String> {
+    let transaction = client.transaction().await.map_err(|e| e.to_string())?;
+    for entry in db.balances(vida_id).map_err(|e| format!("{:?}", e))? {
+        let entry = entry.map_err(|e| format!("{:?}", e))?;
+        upsert_balance(&transaction, vida_id, entry.token_id, &hex::encode(&entry.address), &entry.balance, block_number).await?;
+    }
+    set_progress(&transaction, vida_id, block_number).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    info!("Copied the balances of VIDA {} at block {} to PostgreSQL", vida_id, block_number);
+    Ok(block_number)
+}
+
+// Writes the transfers, receipts and changed balances of a block range in one
+// transaction, replacing rows left by an earlier pass over the same blocks
+async fn mirror_blocks(client: &mut Client, db: &DatabaseService, vida_id: u64, from_block: u64, to_block: u64) -> Result<(), String> {
+    let mut changes: Vec<Vec<ChangeRecord>> = Vec::new();
+    let mut receipts: Vec<Receipt> = Vec::new();
+    for block_number in from_block..=to_block {
+        changes.push(db.get_changes(vida_id, block_number).map_err(|e| format!("{:?}", e))?);
+        let mut index = 0;
+        while let Some(receipt) = db.get_receipt_at(vida_id, block_number, index).map_err(|e| format!("{:?}", e))? {
+            receipts.push(receipt);
+            index += 1;
+        }
+    }
+
+    let transaction = client.transaction().await.map_err(|e| e.to_string())?;
+    let (vida, first, last) = (vida_id as i64, from_block as i64, to_block as i64);
+    for table in ["vida_transfers", "vida_receipts"] {
+        transaction.execute(&format!("DELETE FROM {} WHERE vida_id = $1 AND block_number BETWEEN $2 AND $3", table), &[&vida, &first, &last])
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut touched = BTreeSet::new();
+    for (position, record) in changes.iter().flat_map(|records| records.iter().enumerate()) {
+        if let StateChange::Transfer { from, to, amount, token_id } = &record.change {
+            let tx_hash = receipts.iter()
+                .find(|receipt| receipt.block_number == record.block_number && receipt.index == record.tx_index)
+                .map(|receipt| receipt.tx_hash.clone());
+            transaction.execute(
+                "INSERT INTO vida_transfers (vida_id, block_number, position, tx_index, tx_hash, token_id, from_address, to_address, amount)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::numeric)",
+                &[
+                    &(vida_id as i64), &(record.block_number as i64), &(position as i32), &(record.tx_index as i32),
+                    &tx_hash, &(token_id.unwrap_or(DEFAULT_TOKEN) as i64), from, to, amount,
+                ],
+            ).await.map_err(|e| e.to_string())?;
+        }
+        touched.extend(balance_holders(&record.change));
+    }
+
+    for receipt in &receipts {
+        let status = serde_json::to_value(receipt.status).ok()
+            .and_then(|status| status.as_str().map(str::to_string))
+            .unwrap_or_default();
+        transaction.execute(
+            "INSERT INTO vida_receipts (vida_id, block_number, tx_index, tx_hash, status, reason) VALUES ($1, $2, $3, $4, $5, $6)",
+            &[&(vida_id as i64), &(receipt.block_number as i64), &(receipt.index as i32), &receipt.tx_hash, &status, &receipt.reason],
+        ).await.map_err(|e| e.to_string())?;
+    }
+
+    for (token_id, address) in touched {
+        let decoded = hex::decode(address).map_err(|_| format!("Invalid address {} in change journal", address))?;
+        let balance = match db.get_balance_at(vida_id, token_id, &decoded, to_block) {
+            Ok(Some(balance)) => balance,
+            _ => db.get_balance(vida_id, token_id, &decoded).map_err(|e| format!("{:?}", e))?,
+        };
+        upsert_balance(&transaction, vida_id, token_id, address, &balance, to_block).await?;
+    }
+    set_progress(&transaction, vida_id, to_block).await?;
+    transaction.commit().await.map_err(|e| e.to_string())
+}
+
+// Token and hex address of every balance a change moved
+fn balance_holders(change: &StateChange) -> Vec<(u64, &str)> {
+    match change {
+        StateChange::Transfer { from, to, token_id, .. } => vec![(token_id.unwrap_or(DEFAULT_TOKEN), from.as_str()), (token_id.unwrap_or(DEFAULT_TOKEN), to.as_str())],
+        StateChange::Mint { to, token_id, .. } => vec![(token_id.unwrap_or(DEFAULT_TOKEN), to.as_str())],
+        StateChange::Burn { from, token_id, .. } => vec![(token_id.unwrap_or(DEFAULT_TOKEN), from.as_str())],
+        StateChange::Fee { from, collector, token_id, .. } => {
+            vec![(token_id.unwrap_or(DEFAULT_TOKEN), from.as_str()), (token_id.unwrap_or(DEFAULT_TOKEN), collector.as_str())]
+        }
+        _ => Vec::new(),
+    }
+}
+
+async fn upsert_balance(transaction: &Transaction<'_>, vida_id: u64, token_id: u64, address: &str, balance: &BigUint, block_number: u64) -> Result<(), String> {
+    transaction.execute(
+        "INSERT INTO vida_balances (vida_id, token_id, address, balance, block_number) VALUES ($1, $2, $3, $4::text::numeric, $5)
+         ON CONFLICT (vida_id, token_id, address) DO UPDATE SET balance = EXCLUDED.balance, block_number = EXCLUDED.block_number",
+        &[&(vida_id as i64), &(token_id as i64), &address, &balance.to_string(), &(block_number as i64)],
+    ).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn set_progress(transaction: &Transaction<'_>, vida_id: u64, block_number: u64) -> Result<(), String> {
+    transaction.execute(
+        "INSERT INTO vida_mirror_progress (vida_id, block_number) VALUES ($1, $2)
+         ON CONFLICT (vida_id) DO UPDATE SET block_number = EXCLUDED.block_number",
+        &[&(vida_id as i64), &(block_number as i64)],
+    ).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::authorization::{self, decode_hex_address};
+use crate::database_service::{Multisig, MultisigTransaction};
+use crate::registry::{INSUFFICIENT_FUNDS, NOT_AN_OWNER, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Built-in `createMultisig` action: registers a multisig account controlled
+/// by the distinct `owners` addresses, `threshold` of which must approve
+/// every spend. The account's address is derived from its sequence number
+/// and logged; funds are sent to it with ordinary transfers. Guarded by the
+/// creator's `nonce`.
+pub struct CreateMultisigHandler;
+
+impl TransactionHandler for CreateMultisigHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let owners = ctx.payload.get("owners")
+            .and_then(Value::as_array)
+            .ok_or("Missing owners")?
+            .iter()
+            .map(|owner| authorization::decode_recipient(owner.as_str().ok_or("Invalid owners: must be a list of addresses")?))
+            .collect::<Result<Vec<_>, String>>()?;
+        let threshold = ctx.payload.get("threshold")
+            .and_then(Value::as_u64)
+            .ok_or("Invalid or missing threshold")?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+
+        let distinct: BTreeSet<String> = owners.iter().map(hex::encode).collect();
+        if distinct.len() != owners.len() {
+            return Err("Invalid owners: addresses must be distinct".to_string());
+        }
+        if threshold == 0 || threshold > owners.len() as u64 {
+            return Err(format!("Invalid threshold {}: must be between 1 and {} owners", threshold, owners.len()));
+        }
+
+        let sender = decode_hex_address(ctx.sender)?;
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        let multisig = Multisig {
+            owners: owners.iter().map(hex::encode).collect(),
+            threshold: threshold as u32,
+            transaction_count: 0,
+        };
+        let address = ctx.db.create_multisig(ctx.vida_id, &multisig)
+            .map_err(|_| "Failed to create multisig".to_string())?;
+        info!("{} created multisig 0x{} with {} of {} owners required", ctx.sender, hex::encode(address), threshold, owners.len());
+        Ok(())
+    }
+}
+
+/// Built-in `submitMultisig` action: an owner of the account `multisig`
+/// proposes sending `amount` of it to `receiver`, counting as the first
+/// approval. An optional `tokenId` selects a token other than the default
+/// one. With a threshold of 1 the transfer executes at once. Guarded by the
+/// owner's `nonce`.
+pub struct SubmitMultisigHandler;
+
+impl TransactionHandler for SubmitMultisigHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+
+        let receiver = authorization::decode_recipient(receiver_hex)?;
+        let (address, multisig, owner) = owned_multisig(ctx)?;
+        if receiver == address {
+            return Err("Cannot send from a multisig to itself".to_string());
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &owner, ctx.sender, nonce)?;
+        let mut transaction = MultisigTransaction {
+            receiver: hex::encode(&receiver),
+            amount: amount.to_string(),
+            token_id,
+            approvals: BTreeSet::from([hex::encode(&owner)]),
+            executed: false,
+        };
+        if multisig.threshold <= 1 {
+            execute(ctx, &address, &mut transaction)?;
+        }
+        let id = ctx.db.add_multisig_transaction(ctx.vida_id, &address, &transaction)
+            .map_err(|_| "Failed to store multisig transaction".to_string())?;
+        info!("{} submitted transaction {} of multisig 0x{}", ctx.sender, id, hex::encode(&address));
+        Ok(())
+    }
+}
+
+/// Built-in `approveMultisig` action: an owner of the account `multisig`
+/// approves its pending transaction `transactionId`. The approval that
+/// reaches the threshold executes the transfer; if the account cannot cover
+/// it then, the approval is rejected and can be sent again later. Guarded by
+/// the owner's `nonce`.
+pub struct ApproveMultisigHandler;
+
+impl TransactionHandler for ApproveMultisigHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let id = parse_transaction_id(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+
+        let (address, multisig, owner) = owned_multisig(ctx)?;
+        let mut transaction = ctx.db.get_multisig_transaction(ctx.vida_id, &address, id)
+            .map_err(|_| "Failed to read multisig transaction".to_string())?
+            .ok_or_else(|| format!("Unknown multisig transaction {}", id))?;
+        if transaction.executed {
+            return Err(format!("Multisig transaction {} was already executed", id));
+        }
+        if !transaction.approvals.insert(hex::encode(&owner)) {
+            return Err(format!("{} already approved multisig transaction {}", ctx.sender, id));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &owner, ctx.sender, nonce)?;
+        if transaction.approvals.len() >= multisig.threshold as usize {
+            execute(ctx, &address, &mut transaction)?;
+        }
+        ctx.db.set_multisig_transaction(ctx.vida_id, &address, id, &transaction)
+            .map_err(|_| "Failed to store multisig approval".to_string())?;
+        info!("{} approved transaction {} of multisig 0x{}", ctx.sender, id, hex::encode(&address));
+        Ok(())
+    }
+}
+
+// Reads the `multisig` account and checks that the sender is one of its owners
+fn owned_multisig(ctx: &TransactionContext) -> Result<(Vec<u8>, Multisig, Vec<u8>), String> {
+    let address_hex = ctx.payload.get("multisig")
+        .and_then(Value::as_str)
+        .ok_or("Missing multisig")?;
+    let address = decode_hex_address(address_hex)?;
+    let multisig = ctx.db.get_multisig(ctx.vida_id, &address)
+        .map_err(|_| "Failed to read multisig".to_string())?
+        .ok_or_else(|| format!("Unknown multisig {}", address_hex))?;
+    let owner = decode_hex_address(ctx.sender)?;
+    if !multisig.owners.contains(&hex::encode(&owner)) {
+        return Err(format!("{} {} of multisig {}", ctx.sender, NOT_AN_OWNER, address_hex));
+    }
+    Ok((address, multisig, owner))
+}
+
+// Moves an approved transaction's amount out of the multisig account
+fn execute(ctx: &TransactionContext, address: &[u8], transaction: &mut MultisigTransaction) -> Result<(), String> {
+    let receiver = hex::decode(&transaction.receiver).map_err(|_| "Invalid receiver".to_string())?;
+    let amount: BigUint = transaction.amount.parse().map_err(|_| "Invalid or missing amount".to_string())?;
+    authorization::check_not_frozen(ctx.db, ctx.vida_id, &[address, &receiver])?;
+    match ctx.db.transfer_with_fee(ctx.vida_id, transaction.token_id, address, &receiver, &amount) {
+        Ok(true) => {
+            let note = transfer::TransferNote::default();
+            transfer::record_transfer(ctx.db, ctx.vida_id, transaction.token_id, address, &receiver, &amount, &note, ctx.block_number);
+            transaction.executed = true;
+            Ok(())
+        }
+        Ok(false) => Err(format!("{}: multisig 0x{} cannot send {}", INSUFFICIENT_FUNDS, hex::encode(address), amount)),
+        Err(_) => Err("Multisig transfer failed".to_string()),
+    }
+}
+
+// Reads the `transactionId` field, given either as a decimal string or a number
+fn parse_transaction_id(json_data: &Map<String, Value>) -> Result<u64, String> {
+    json_data.get("transactionId")
+        .and_then(|val| match val {
+            Value::String(s) => s.parse::<u64>().ok(),
+            val => val.as_u64(),
+        })
+        .ok_or_else(|| "Invalid or missing transactionId".to_string())
+}
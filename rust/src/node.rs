@@ -0,0 +1,361 @@
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::amount;
+use crate::api;
+use crate::broker;
+use crate::conduit;
+use crate::config::Config;
+use crate::database_service::{DatabaseService, DEFAULT_TOKEN};
+use crate::error::Error;
+use crate::faucet;
+use crate::genesis::Genesis;
+use crate::http;
+use crate::payload;
+use crate::reload;
+use crate::registry;
+use crate::replica;
+use crate::resync;
+use crate::handler::{self, subscribe_and_sync};
+use crate::shutdown::ShutdownCoordinator;
+use crate::signing::NodeKey;
+use crate::snapshot;
+use crate::source::{self, PwrSource};
+use crate::stall;
+use crate::state::{AppState, SharedState};
+
+/// Starts the API server in a background task, over HTTPS when a TLS
+/// certificate and key are configured.
+pub async fn start_api_server(state: &SharedState) {
+    let (port, cert_file, key_file) = {
+        let config = &state.read().unwrap().config;
+        (config.port, config.tls_cert_file.clone(), config.tls_key_file.clone())
+    };
+    let routes = api::routes(state.clone());
+    let scheme = if cert_file.is_empty() { "http" } else { "https" };
+    
+    tokio::spawn(async move {
+        info!("Starting API server on port {}", port);
+        if cert_file.is_empty() {
+            warp::serve(routes)
+                .run(([0, 0, 0, 0], port))
+                .await;
+        } else {
+            warp::serve(routes)
+                .tls()
+                .cert_path(cert_file)
+                .key_path(key_file)
+                .run(([0, 0, 0, 0], port))
+                .await;
+        }
+    });
+    
+    // Give server time to start
+    sleep(Duration::from_millis(2000)).await;
+    info!("API server started on {}://0.0.0.0:{}", scheme, port);
+}
+
+/// Runs a complete node: opens the database, serves the API, applies the
+/// genesis, subscribes to every configured VIDA and blocks until a clean
+/// shutdown has completed.
+pub async fn run(config: Config, peers: Vec<String>) -> Result<(), Error> {
+    let vida_ids: Vec<u64> = config.vidas().iter().map(|vida| vida.id).collect();
+    let db = DatabaseService::initialize(Path::new(&config.database_path), &config.database_name, &vida_ids).map_err(|e| Error::storage("Database initialization failed", e))?;
+    db.set_balance_cache_capacity(config.balance_cache_size);
+    sync(config, peers, db).await
+}
+
+// Serves the API and syncs every configured VIDA over an open database until shutdown
+async fn sync(config: Config, peers: Vec<String>, db: DatabaseService) -> Result<(), Error> {
+    let vida_ids: Vec<u64> = config.vidas().iter().map(|vida| vida.id).collect();
+    http::configure(&config).map_err(Error::Config)?;
+    payload::configure(config.payload_limits());
+    registry::configure_limits(config.execution_limits());
+    amount::configure(config.token_decimals);
+    let (min_poll, max_poll) = config.poll_intervals();
+    source::configure_polling(min_poll, max_poll);
+    if let Some(policy) = config.faucet_policy() {
+        faucet::configure(policy);
+    }
+    let node_key = NodeKey::load_or_generate(Path::new(&config.node_key_file)).map_err(Error::Config)?;
+    info!("Node public key: {}", node_key.public_key_hex());
+    handler::recover_interrupted_finalization(&db)?;
+    verify_startup_roots(&config, &db)?;
+    verify_pinned_roots(&config, &db)?;
+    let (request_policy, peer_policies) = config.request_policies();
+    let clients = http::PeerClients::new(&request_policy, &peer_policies).map_err(Error::Http)?;
+    let state = AppState::new_shared(config.clone(), peers.clone(), db.clone(), node_key, clients.clone());
+
+    start_api_server(&state).await;
+    #[cfg(feature = "grpc")]
+    crate::grpc::start_grpc_server(&state);
+    let genesis = Genesis::load(&config.genesis_file)?;
+    genesis.apply(&db, config.vida_id)?;
+    genesis.verify_with_peers(clients.client(), config.vida_id, &peers).await?;
+    #[cfg(feature = "postgres")]
+    if !config.postgres_url.is_empty() {
+        crate::mirror::start(config.postgres_url.clone(), db.clone());
+    }
+    #[cfg(not(feature = "postgres"))]
+    if !config.postgres_url.is_empty() {
+        warn!("postgres_url is set but this build lacks the postgres feature; not mirroring");
+    }
+    broker::start(&config).await.map_err(Error::Config)?;
+    conduit::start(&config, db.clone()).map_err(Error::Config)?;
+    if config.peer_registry {
+        if let Err(e) = state.write().unwrap().peers.merge_registered(&db, config.vida_id) {
+            warn!("{}", e);
+        }
+    }
+
+    info!("Starting synchronization of {} VIDA(s)", vida_ids.len());
+
+    if config.replica_of.is_empty() {
+        subscribe_and_sync::<PwrSource>(state.clone()).await?;
+        stall::watch(state.clone());
+    } else {
+        replica::follow(state.clone());
+    }
+    reload::watch_sighup(state.clone());
+
+    // Keep running until a clean shutdown completes
+    info!("Application started successfully. Press Ctrl+C to exit.");
+    ShutdownCoordinator::new(state).wait_for_signal().await?;
+
+    Ok(())
+}
+
+// Compares the tree root of each VIDA with the root recorded for its last checked block
+// before anything is served to peers, so a corrupt or partially written tree is never
+// advertised. Depending on `startup_root_mismatch`, a mismatch stops startup or rolls the
+// VIDA back checkpoint by checkpoint until a recorded root matches.
+fn verify_startup_roots(config: &Config, db: &DatabaseService) -> Result<(), Error> {
+    let rollback = config.startup_root_mismatch == "rollback";
+    for vida_id in db.vida_ids() {
+        loop {
+            let block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+            let root = db.get_root_hash(vida_id).map_err(|e| Error::storage("Failed to read root hash", e))?.unwrap_or_default();
+            let recorded = match db.get_block_root_hash(vida_id, block).map_err(|e| Error::storage("Failed to read block root hash", e))? {
+                Some(recorded) if recorded != root => recorded,
+                _ => break,
+            };
+            if !rollback || block == 0 {
+                return Err(Error::Validation(format!(
+                    "Root hash {} of VIDA {} does not match {} recorded for block {}; the database may be corrupt",
+                    hex::encode(&root), vida_id, hex::encode(&recorded), block
+                )));
+            }
+            let previous = db.previous_checkpoint(vida_id, block).map_err(|e| Error::storage("Failed to find previous checkpoint", e))?;
+            warn!("Root hash of VIDA {} does not match block {}, rolling back to block {}", vida_id, block, previous);
+            db.rollback_to_block(vida_id, previous).map_err(|e| Error::storage("Rollback failed", e))?;
+        }
+    }
+    Ok(())
+}
+
+// Checks the roots recorded for pinned blocks that were already synced, so a database
+// built from the wrong VIDA is caught even when the pin was added after the fact
+fn verify_pinned_roots(config: &Config, db: &DatabaseService) -> Result<(), Error> {
+    for vida in config.vidas() {
+        let Some((pinned_block, pinned_root)) = vida.pinned_root() else {
+            continue;
+        };
+        let block = db.get_last_checked_block(vida.id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+        if block < pinned_block {
+            continue;
+        }
+        match db.get_block_root_hash(vida.id, pinned_block).map_err(|e| Error::storage("Failed to read block root hash", e))? {
+            Some(recorded) if recorded != pinned_root => {
+                return Err(Error::Validation(format!(
+                    "Root hash {} recorded for block {} of VIDA {} does not match pinned root {}",
+                    hex::encode(&recorded), pinned_block, vida.id, hex::encode(&pinned_root)
+                )));
+            }
+            Some(_) => {}
+            None => warn!("No root hash recorded for pinned block {} of VIDA {}; the pin cannot be checked", pinned_block, vida.id),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the state of a VIDA (the primary one if `vida_id` is None) to a
+/// snapshot file and returns without syncing.
+pub fn export_snapshot(config: &Config, path: &str, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(config, vida_id)?;
+    snapshot::export_to_file(&db, vida_id, path)?;
+    Ok(())
+}
+
+/// Replaces the state of a VIDA (the primary one if `vida_id` is None) with
+/// a snapshot file, so the next run resumes syncing from its block.
+pub fn import_snapshot(config: &Config, path: &str, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(config, vida_id)?;
+    snapshot::import_from_file(&db, vida_id, path)?;
+    Ok(())
+}
+
+/// Checks that the stored state of a VIDA reproduces the root hash recorded
+/// for its last checked block and that balances add up to the total supply.
+pub fn verify_state(config: &Config, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(config, vida_id)?;
+    let block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+    let root = db.get_root_hash(vida_id).map_err(|e| Error::storage("Failed to read root hash", e))?.unwrap_or_default();
+    match db.get_block_root_hash(vida_id, block).map_err(|e| Error::storage("Failed to read block root hash", e))? {
+        Some(recorded) if recorded != root => {
+            return Err(Error::Validation(format!(
+                "Root hash {} of VIDA {} does not match {} recorded for block {}",
+                hex::encode(&root), vida_id, hex::encode(&recorded), block
+            )));
+        }
+        Some(_) => info!("Root hash {} of VIDA {} matches block {}", hex::encode(&root), vida_id, block),
+        None => warn!("No root hash recorded for block {} of VIDA {}; current root is {}", block, vida_id, hex::encode(&root)),
+    }
+
+    let audit = db.audit_supply(vida_id, DEFAULT_TOKEN).map_err(|e| Error::storage("Supply audit failed", e))?;
+    if !audit.consistent {
+        return Err(Error::Validation(format!(
+            "Balances of VIDA {} sum to {} but the recorded supply is {:?}",
+            vida_id, audit.balance_sum, audit.recorded_supply
+        )));
+    }
+    info!("Supply of VIDA {} is consistent across {} holders", vida_id, audit.holders);
+    Ok(())
+}
+
+/// Replays the journal of a VIDA from genesis and compares the recomputed
+/// balances with those the node stored, printing the first divergent block.
+pub fn audit(config: &Config, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(config, vida_id)?;
+    let audit = db.audit_ledger(vida_id).map_err(|e| Error::storage("Ledger audit failed", e))?;
+    println!("Replayed {} blocks, checked {} balances", audit.blocks_replayed, audit.balances_checked);
+    match audit.divergence {
+        Some(divergence) => {
+            println!(
+                "First divergent block: {} (root {})",
+                divergence.block_number,
+                divergence.root_hash.as_deref().unwrap_or("not recorded")
+            );
+            Err(Error::Validation(format!(
+                "Balance of {} in token {} of VIDA {} is {} but replaying the journal gives {}",
+                divergence.address, divergence.token_id, vida_id, divergence.recorded, divergence.replayed
+            )))
+        }
+        None => {
+            println!("Every balance of VIDA {} matches the journal", vida_id);
+            Ok(())
+        }
+    }
+}
+
+/// Prints the root hash of a VIDA at a block: the current root for the last
+/// checked block, the recorded checkpoint root for earlier ones.
+pub fn show_root(config: &Config, block_number: u64, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(config, vida_id)?;
+    let last_checked_block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+    let root = if block_number == last_checked_block {
+        db.get_root_hash(vida_id)
+    } else {
+        db.get_block_root_hash(vida_id, block_number)
+    }.map_err(|e| Error::storage("Failed to read root hash", e))?;
+
+    match root {
+        Some(root) => {
+            println!("{}", hex::encode(root));
+            Ok(())
+        }
+        None => Err(Error::Validation(format!("No root hash recorded for block {} of VIDA {}", block_number, vida_id))),
+    }
+}
+
+/// Rolls the state of a VIDA back to `block_number`, then runs the node so
+/// the later blocks are fetched and applied again.
+pub async fn rebuild_from_block(
+    config: Config,
+    peers: Vec<String>,
+    block_number: u64,
+    vida_id: Option<u64>,
+) -> Result<(), Error> {
+    let (db, vida_id) = open_database(&config, vida_id)?;
+    db.rollback_to_block(vida_id, block_number).map_err(|e| Error::storage("Rollback failed", e))?;
+    let block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+    info!("Rolled VIDA {} back to block {}, rebuilding from there", vida_id, block);
+    sync(config, peers, db).await
+}
+
+/// Rebuilds the state of a VIDA from genesis without trusting the current
+/// tree, e.g. after storage corruption: switches to an empty tree, then runs
+/// the node, which fetches every block again. Blocks up to the previous last
+/// checked block must reproduce the roots recorded for them; later ones are
+/// validated with peers as usual.
+pub async fn rebuild(config: Config, peers: Vec<String>, vida_id: Option<u64>) -> Result<(), Error> {
+    let (db, vida_id) = open_database(&config, vida_id)?;
+    let last_checked_block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+    let roots = db.begin_rebuild(vida_id).map_err(|e| Error::storage("Failed to start rebuild", e))?;
+    info!(
+        "Rebuilding VIDA {} from genesis, checking {} recorded roots up to block {}",
+        vida_id, roots, last_checked_block
+    );
+    sync(config, peers, db).await
+}
+
+/// Bootstraps a VIDA from a trusted checkpoint instead of replaying its
+/// history, then runs the node from there. The snapshot file must be of
+/// `block_number` and hash to `root_hash`, and a quorum of peers must report
+/// the same root for that block.
+pub async fn trusted_sync(
+    config: Config,
+    peers: Vec<String>,
+    path: &str,
+    block_number: u64,
+    root_hash: &str,
+    vida_id: Option<u64>,
+) -> Result<(), Error> {
+    let checkpoint_root = hex::decode(root_hash.trim_start_matches("0x"))
+        .map_err(|e| Error::Validation(format!("Invalid checkpoint root hash {}: {}", root_hash, e)))?;
+    let (db, vida_id) = open_database(&config, vida_id)?;
+    let last_checked_block = db.get_last_checked_block(vida_id).map_err(|e| Error::storage("Failed to read last checked block", e))?;
+    if last_checked_block >= block_number {
+        return Err(Error::Validation(format!(
+            "VIDA {} is already synced to block {}, past checkpoint block {}",
+            vida_id, last_checked_block, block_number
+        )));
+    }
+
+    let snapshot = snapshot::read_file(vida_id, path)?;
+    if snapshot.block_number != block_number {
+        return Err(Error::Validation(format!("Snapshot is of block {}, not checkpoint block {}", snapshot.block_number, block_number)));
+    }
+    if snapshot.root_hash != hex::encode(&checkpoint_root) {
+        return Err(Error::Validation(format!("Snapshot root {} does not match checkpoint root {}", snapshot.root_hash, root_hash)));
+    }
+
+    http::configure(&config).map_err(Error::Config)?;
+    let client = http::client(Duration::from_secs(10)).map_err(Error::Http)?;
+    let agreed_root = resync::agreed_block_root(&client, &peers, vida_id, block_number).await
+        .ok_or_else(|| Error::Validation(format!("No peer quorum on the root of checkpoint block {}", block_number)))?;
+    if agreed_root != checkpoint_root {
+        return Err(Error::Validation(format!(
+            "Peers agree on root {} for block {}, not on checkpoint root {}",
+            hex::encode(&agreed_root), block_number, root_hash
+        )));
+    }
+
+    // Rebuilds the tree and only activates it if it reproduces the checkpoint root
+    db.import_state(vida_id, &snapshot, &checkpoint_root).map_err(|e| Error::storage("Failed to import checkpoint", e))?;
+    info!("Imported checkpoint of VIDA {} at block {}, syncing from there", vida_id, block_number);
+    sync(config, peers, db).await
+}
+
+// Opens the database of all configured VIDAs and checks the requested one is among them
+fn open_database(config: &Config, vida_id: Option<u64>) -> Result<(DatabaseService, u64), Error> {
+    let vida_id = vida_id.unwrap_or(config.vida_id);
+    if config.vida(vida_id).is_none() {
+        return Err(Error::Config(format!("VIDA {} is not configured", vida_id)));
+    }
+    let vida_ids: Vec<u64> = config.vidas().iter().map(|vida| vida.id).collect();
+    let db = DatabaseService::open(Path::new(&config.database_path), &config.database_name, &vida_ids).map_err(|e| Error::storage("Database initialization failed", e))?;
+    db.set_balance_cache_capacity(config.balance_cache_size);
+    Ok((db, vida_id))
+}
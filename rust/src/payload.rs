@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use num_bigint::BigUint;
+use prost::Message;
+use serde_json::{Map, Value};
+
+/// First byte of a payload in the compact binary encoding, version 1: a
+/// protobuf `BinaryPayload` follows. JSON payloads never start with it.
+pub const BINARY_V1: u8 = 0x01;
+
+// Limits set by `configure`; the defaults apply until then
+static LIMITS: OnceLock<PayloadLimits> = OnceLock::new();
+
+/// Bounds on transaction payloads, checked as they are decoded so a huge or
+/// deeply nested payload is rejected before it costs time or memory. They
+/// decide which transactions apply, so every node of a VIDA must use the
+/// same limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Largest payload, in bytes.
+    pub max_bytes: usize,
+    /// Deepest nesting of objects and arrays; the payload object is level 1.
+    pub max_depth: usize,
+    /// Longest string or field name, in bytes, and longest array, in items.
+    pub max_field_length: usize,
+    /// Largest amount, in bits.
+    pub max_amount_bits: u64,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 16 * 1024,
+            max_depth: 8,
+            max_field_length: 1024,
+            max_amount_bits: 256,
+        }
+    }
+}
+
+/// Sets the limits every payload is decoded with. Only the first call has an effect.
+pub fn configure(limits: PayloadLimits) {
+    let _ = LIMITS.set(limits);
+}
+
+/// Returns the limits payloads are decoded with.
+pub fn limits() -> PayloadLimits {
+    LIMITS.get().copied().unwrap_or_default()
+}
+
+/// Compact encoding of a transaction payload, described in
+/// `proto/payload.proto`. Each field stands for the JSON field of the same
+/// name, so handlers see the same payload whichever encoding was sent.
+#[derive(Clone, PartialEq, Message)]
+pub struct BinaryPayload {
+    #[prost(string, tag = "1")]
+    pub action: String,
+    #[prost(map = "string, message", tag = "2")]
+    pub fields: HashMap<String, Field>,
+}
+
+/// One field of a `BinaryPayload`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Field {
+    #[prost(oneof = "field::Kind", tags = "1, 2, 3, 4, 5, 6")]
+    pub kind: Option<field::Kind>,
+}
+
+/// A list of fields, e.g. the owners of a multisig account.
+#[derive(Clone, PartialEq, Message)]
+pub struct FieldList {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<Field>,
+}
+
+pub mod field {
+    /// Value of a field and how it appears in the JSON payload.
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        /// A string.
+        #[prost(string, tag = "1")]
+        Text(String),
+        /// Raw address bytes, a 0x-prefixed hex string.
+        #[prost(bytes, tag = "2")]
+        Address(Vec<u8>),
+        /// A number.
+        #[prost(uint64, tag = "3")]
+        Number(u64),
+        /// A boolean.
+        #[prost(bool, tag = "4")]
+        Flag(bool),
+        /// A big-endian unsigned integer of any size, a decimal string.
+        #[prost(bytes, tag = "5")]
+        Amount(Vec<u8>),
+        /// An array.
+        #[prost(message, tag = "6")]
+        List(super::FieldList),
+    }
+}
+
+/// Decodes transaction data into its JSON object, dispatching on the first
+/// byte: `BINARY_V1` payloads are converted, anything else is read as JSON.
+/// Payloads beyond the configured `PayloadLimits` are rejected.
+pub fn decode(data: &[u8]) -> Result<Map<String, Value>, String> {
+    let limits = limits();
+    if data.len() > limits.max_bytes {
+        return Err(format!("Invalid payload: {} bytes, at most {} allowed", data.len(), limits.max_bytes));
+    }
+    let obj_map = match data.first() {
+        Some(&BINARY_V1) => decode_binary(&data[1..])?,
+        _ => {
+            check_json_depth(data, limits.max_depth)?;
+            decode_json(data)?
+        }
+    };
+    for (name, value) in &obj_map {
+        check_field(name, value, 1, &limits)?;
+    }
+    Ok(obj_map)
+}
+
+/// Checks that an amount is within the configured magnitude.
+pub fn check_amount(amount: &BigUint) -> Result<(), String> {
+    let max_bits = limits().max_amount_bits;
+    if amount.bits() > max_bits {
+        return Err(format!("Invalid amount: exceeds {} bits", max_bits));
+    }
+    Ok(())
+}
+
+// Rejects JSON nested deeper than `max_depth` before it is parsed
+fn check_json_depth(data: &[u8], max_depth: usize) -> Result<(), String> {
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    for &byte in data {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(format!("Invalid payload: nested deeper than {} levels", max_depth));
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Checks the length of a field's name and value, and the depth of its value,
+// the field being at `depth`
+fn check_field(name: &str, value: &Value, depth: usize, limits: &PayloadLimits) -> Result<(), String> {
+    if name.len() > limits.max_field_length {
+        return Err(format!("Invalid payload: field name longer than {} bytes", limits.max_field_length));
+    }
+    check_value(name, value, depth, limits)
+}
+
+fn check_value(name: &str, value: &Value, depth: usize, limits: &PayloadLimits) -> Result<(), String> {
+    match value {
+        Value::String(text) if text.len() > limits.max_field_length => {
+            Err(format!("Invalid {}: longer than {} bytes", name, limits.max_field_length))
+        }
+        Value::Array(_) | Value::Object(_) if depth >= limits.max_depth => {
+            Err(format!("Invalid payload: nested deeper than {} levels", limits.max_depth))
+        }
+        Value::Array(items) => {
+            if items.len() > limits.max_field_length {
+                return Err(format!("Invalid {}: more than {} items", name, limits.max_field_length));
+            }
+            items.iter().try_for_each(|item| check_value(name, item, depth + 1, limits))
+        }
+        Value::Object(fields) => {
+            fields.iter().try_for_each(|(field, value)| check_field(field, value, depth + 1, limits))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Encodes a payload in the compact binary encoding, version byte included.
+pub fn encode_binary(payload: &BinaryPayload) -> Vec<u8> {
+    let mut data = Vec::with_capacity(1 + payload.encoded_len());
+    data.push(BINARY_V1);
+    payload.encode(&mut data).expect("a Vec grows as needed");
+    data
+}
+
+fn decode_json(data: &[u8]) -> Result<Map<String, Value>, String> {
+    let data_str = std::str::from_utf8(data)
+        .map_err(|_| "Error decoding transaction data".to_string())?;
+    match serde_json::from_str(data_str) {
+        Ok(Value::Object(obj_map)) => Ok(obj_map),
+        Ok(_) => Err("Transaction data is not a JSON object".to_string()),
+        Err(_) => Err("Error parsing transaction JSON".to_string()),
+    }
+}
+
+fn decode_binary(data: &[u8]) -> Result<Map<String, Value>, String> {
+    let payload = BinaryPayload::decode(data)
+        .map_err(|_| "Error parsing binary transaction payload".to_string())?;
+    if payload.fields.contains_key("action") {
+        return Err("Invalid binary payload: action is given as a field".to_string());
+    }
+    let mut obj_map = Map::new();
+    obj_map.insert("action".to_string(), Value::String(payload.action));
+    for (name, field) in payload.fields {
+        obj_map.insert(name, to_json(field)?);
+    }
+    Ok(obj_map)
+}
+
+fn to_json(field: Field) -> Result<Value, String> {
+    use field::Kind;
+    Ok(match field.kind.ok_or_else(|| "Invalid binary payload: field without a value".to_string())? {
+        Kind::Text(text) => Value::String(text),
+        Kind::Address(address) => Value::String(format!("0x{}", hex::encode(address))),
+        Kind::Number(number) => Value::from(number),
+        Kind::Flag(flag) => Value::Bool(flag),
+        Kind::Amount(amount) => Value::String(BigUint::from_bytes_be(&amount).to_string()),
+        Kind::List(list) => Value::Array(list.items.into_iter().map(to_json).collect::<Result<_, _>>()?),
+    })
+}
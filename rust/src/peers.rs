@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::authorization::decode_hex_address;
+use crate::database_service::DatabaseService;
+use crate::registry::{NOT_AN_ADMIN, TransactionContext, TransactionHandler};
+
+// Weight of the newest sample in the latency moving average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Long-term statistics of a peer, persisted across restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStats {
+    pub requests: u64,
+    pub successes: u64,
+    /// Roots received from the peer, and how many matched the local root.
+    pub comparisons: u64,
+    pub agreements: u64,
+    /// Moving average of the response latency, in milliseconds.
+    pub average_latency_ms: f64,
+}
+
+impl PeerStats {
+    /// Share of requests the peer answered; 1 before any request.
+    pub fn uptime(&self) -> f64 {
+        if self.requests == 0 { 1.0 } else { self.successes as f64 / self.requests as f64 }
+    }
+
+    /// Share of the peer's roots that matched the local root; 1 before any.
+    pub fn agreement_rate(&self) -> f64 {
+        if self.comparisons == 0 { 1.0 } else { self.agreements as f64 / self.comparisons as f64 }
+    }
+
+    /// Reputation between 0 and 1: uptime weighted by agreement rate.
+    pub fn score(&self) -> f64 {
+        self.uptime() * self.agreement_rate()
+    }
+}
+
+/// A peer that reported a root for a block other than the one a quorum of
+/// peers validated, recorded in validator mode as evidence for operators
+/// deciding on social slashing. Roots are hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MisbehaviorReport {
+    pub peer: String,
+    pub vida_id: u64,
+    pub block_number: u64,
+    pub peer_root: String,
+    pub quorum_root: String,
+    /// Unix time in seconds at which the conflict was detected.
+    pub detected_at: u64,
+}
+
+/// Health and reputation of a known peer, as reported by `GET /peers` and
+/// the admin API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStatus {
+    pub address: String,
+    pub consecutive_failures: u32,
+    pub quarantined: bool,
+    pub uptime: f64,
+    pub agreement_rate: f64,
+    pub score: f64,
+    pub stats: PeerStats,
+}
+
+/// How requests to a peer are made: connect and overall timeouts, and how
+/// often a failed request is retried, with exponential backoff and jitter,
+/// before it counts as a failure of the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestPolicy {
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub retry_backoff: Duration,
+}
+
+impl RequestPolicy {
+    /// Returns the delay before retry `attempt`, counting from 0: the backoff
+    /// doubled per attempt, plus up to half of it again at random so peers
+    /// are not retried in lockstep.
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let base = self.retry_backoff.saturating_mul(1 << attempt.min(16));
+        let jitter = rand::thread_rng().gen_range(0..=base.as_millis() as u64 / 2);
+        base + Duration::from_millis(jitter)
+    }
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(3),
+            timeout: Duration::from_secs(10),
+            retries: 1,
+            retry_backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+// A known peer and its recent health
+#[derive(Debug, Clone)]
+struct Peer {
+    address: String,
+    consecutive_failures: u32,
+    quarantined_until: Option<Instant>,
+    stats: PeerStats,
+}
+
+/// Dynamic list of peers used for root hash validation and resync. Peers
+/// that fail `quarantine_after` times in a row are skipped for
+/// `quarantine_duration`, then tried again; a single further failure
+/// quarantines them again until a request succeeds. Once a peer has answered
+/// `min_samples` root comparisons, it is left out of quorum checks while
+/// its reputation score is below `min_score`.
+#[derive(Debug, Clone)]
+pub struct PeerManager {
+    peers: Vec<Peer>,
+    quarantine_after: u32,
+    quarantine_duration: Duration,
+    min_score: f64,
+    min_samples: u64,
+    // Statistics of peers not currently known, kept in case they are added back
+    retired_stats: HashMap<String, PeerStats>,
+    request_policy: RequestPolicy,
+    // Policies of the peers configured differently from `request_policy`
+    peer_policies: HashMap<String, RequestPolicy>,
+}
+
+impl PeerManager {
+    /// Creates a manager seeded with the given peers.
+    pub fn new(addresses: Vec<String>, quarantine_after: u32, quarantine_duration: Duration) -> Self {
+        let mut manager = Self {
+            peers: Vec::new(),
+            quarantine_after,
+            quarantine_duration,
+            min_score: 0.0,
+            min_samples: 0,
+            retired_stats: HashMap::new(),
+            request_policy: RequestPolicy::default(),
+            peer_policies: HashMap::new(),
+        };
+        for address in addresses {
+            manager.add(&address);
+        }
+        manager
+    }
+
+    /// Returns the peers that are not currently quarantined.
+    pub fn active(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.peers.iter()
+            .filter(|peer| peer.quarantined_until.map_or(true, |until| until <= now))
+            .map(|peer| peer.address.clone())
+            .collect()
+    }
+
+    /// Returns the active peers trusted to vote in quorum checks. Falls back to
+    /// every active peer if none has a good enough reputation.
+    pub fn voters(&self) -> Vec<String> {
+        let active = self.active();
+        let voters: Vec<String> = active.iter()
+            .filter(|address| self.peers.iter().any(|peer| {
+                peer.address == **address
+                    && (peer.stats.comparisons < self.min_samples || peer.stats.score() >= self.min_score)
+            }))
+            .cloned()
+            .collect();
+        if voters.is_empty() { active } else { voters }
+    }
+
+    /// Sets the reputation a peer needs to vote once it has `min_samples` comparisons.
+    pub fn set_reputation_threshold(&mut self, min_score: f64, min_samples: u64) {
+        self.min_score = min_score;
+        self.min_samples = min_samples;
+    }
+
+    /// Sets how many consecutive failures quarantine a peer, and for how long.
+    pub fn set_quarantine(&mut self, quarantine_after: u32, quarantine_duration: Duration) {
+        self.quarantine_after = quarantine_after;
+        self.quarantine_duration = quarantine_duration;
+    }
+
+    /// Sets how requests are made to peers, with overrides for some of them.
+    pub fn set_request_policies(&mut self, default: RequestPolicy, per_peer: HashMap<String, RequestPolicy>) {
+        self.request_policy = default;
+        self.peer_policies = per_peer;
+    }
+
+    /// Returns how requests to a peer should be made.
+    pub fn request_policy(&self, address: &str) -> RequestPolicy {
+        self.peer_policies.get(address).copied().unwrap_or(self.request_policy)
+    }
+
+    /// Returns every known peer with its health and reputation.
+    pub fn statuses(&self) -> Vec<PeerStatus> {
+        let now = Instant::now();
+        self.peers.iter()
+            .map(|peer| PeerStatus {
+                address: peer.address.clone(),
+                consecutive_failures: peer.consecutive_failures,
+                quarantined: peer.quarantined_until.map_or(false, |until| until > now),
+                uptime: peer.stats.uptime(),
+                agreement_rate: peer.stats.agreement_rate(),
+                score: peer.stats.score(),
+                stats: peer.stats.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the statistics of every peer seen, known or not, for persisting.
+    pub fn stats(&self) -> HashMap<String, PeerStats> {
+        let mut stats = self.retired_stats.clone();
+        for peer in &self.peers {
+            stats.insert(peer.address.clone(), peer.stats.clone());
+        }
+        stats
+    }
+
+    /// Restores statistics persisted by an earlier run.
+    pub fn load_stats(&mut self, stats: HashMap<String, PeerStats>) {
+        for (address, peer_stats) in stats {
+            match self.peers.iter_mut().find(|peer| peer.address == address) {
+                Some(peer) => peer.stats = peer_stats,
+                None => {
+                    self.retired_stats.insert(address, peer_stats);
+                }
+            }
+        }
+    }
+
+    /// Adds a peer; returns false if it is already known.
+    pub fn add(&mut self, address: &str) -> bool {
+        let address = address.trim();
+        if address.is_empty() || self.peers.iter().any(|peer| peer.address == address) {
+            return false;
+        }
+        self.peers.push(Peer {
+            address: address.to_string(),
+            consecutive_failures: 0,
+            quarantined_until: None,
+            stats: self.retired_stats.remove(address).unwrap_or_default(),
+        });
+        true
+    }
+
+    /// Removes a peer; returns false if it was not known.
+    pub fn remove(&mut self, address: &str) -> bool {
+        let address = address.trim();
+        match self.peers.iter().position(|peer| peer.address == address) {
+            Some(index) => {
+                let peer = self.peers.remove(index);
+                self.retired_stats.insert(peer.address, peer.stats);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds the peers registered on-chain for a VIDA that are not known yet.
+    pub fn merge_registered(&mut self, db: &DatabaseService, vida_id: u64) -> Result<(), String> {
+        let registered = db.get_registered_peers(vida_id)
+            .map_err(|e| format!("Failed to read peer registry: {:?}", e))?;
+        for address in registered {
+            if self.add(&address) {
+                info!("Added peer {} from the on-chain registry", address);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a successful request to a peer, lifting any quarantine.
+    pub fn record_success(&mut self, address: &str) {
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.address == address) {
+            peer.consecutive_failures = 0;
+            peer.quarantined_until = None;
+            peer.stats.requests += 1;
+            peer.stats.successes += 1;
+        }
+    }
+
+    /// Records how long a peer took to answer a request.
+    pub fn record_latency(&mut self, address: &str, latency: Duration) {
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.address == address) {
+            let millis = latency.as_secs_f64() * 1000.0;
+            peer.stats.average_latency_ms = if peer.stats.average_latency_ms == 0.0 {
+                millis
+            } else {
+                peer.stats.average_latency_ms * (1.0 - LATENCY_SMOOTHING) + millis * LATENCY_SMOOTHING
+            };
+        }
+    }
+
+    /// Records whether a root reported by a peer matched the local root.
+    pub fn record_agreement(&mut self, address: &str, agreed: bool) {
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.address == address) {
+            peer.stats.comparisons += 1;
+            if agreed {
+                peer.stats.agreements += 1;
+            }
+        }
+    }
+
+    /// Records a failed request to a peer, quarantining it after too many in a
+    /// row, and again on each failure until it answers a request.
+    pub fn record_failure(&mut self, address: &str) {
+        let (quarantine_after, quarantine_duration) = (self.quarantine_after, self.quarantine_duration);
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.address == address) {
+            peer.stats.requests += 1;
+            peer.consecutive_failures += 1;
+            if quarantine_after > 0 && peer.consecutive_failures >= quarantine_after {
+                warn!("Quarantining peer {} after {} consecutive failures", address, peer.consecutive_failures);
+                peer.quarantined_until = Some(Instant::now() + quarantine_duration);
+            }
+        }
+    }
+}
+
+/// Built-in `registerPeer` action: a genesis admin adds (`"enabled": true`)
+/// or removes (`"enabled": false`) a peer in the VIDA's on-chain peer registry,
+/// which nodes with `peer_registry` enabled merge into their peer list.
+pub struct RegisterPeerHandler;
+
+impl TransactionHandler for RegisterPeerHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let peer = ctx.payload.get("peer")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|peer| !peer.is_empty())
+            .ok_or("Missing peer")?;
+        let enabled = ctx.payload.get("enabled")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let admins = ctx.db.get_admins(ctx.vida_id)
+            .map_err(|_| "Failed to read admins".to_string())?;
+        if !admins.contains(&sender) {
+            return Err(format!("{} {}", ctx.sender, NOT_AN_ADMIN));
+        }
+
+        let mut peers = ctx.db.get_registered_peers(ctx.vida_id)
+            .map_err(|_| "Failed to read peer registry".to_string())?;
+        peers.retain(|known| known != peer);
+        if enabled {
+            peers.push(peer.to_string());
+        }
+        ctx.db.set_registered_peers(ctx.vida_id, &peers)
+            .map_err(|_| "Failed to update peer registry".to_string())?;
+        info!("Peer {} {} the registry", peer, if enabled { "added to" } else { "removed from" });
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use futures_util::future::{BoxFuture, FutureExt};
+use pwr_rs::transaction::types::VidaDataTransaction;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, warn};
+
+use crate::handler;
+
+// Queue of each VIDA's stage task, created by `start`
+static PIPELINES: OnceLock<Mutex<HashMap<u64, mpsc::Sender<Item>>>> = OnceLock::new();
+
+// Bumped by `discard_queued`; items queued under an older epoch are skipped
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+// Work queued for a VIDA, tagged with the epoch it was queued in
+struct Item {
+    epoch: u64,
+    stage: Stage,
+}
+
+enum Stage {
+    Apply(VidaDataTransaction),
+    // Completed through the sender once the block is finalized or skipped
+    Finalize(u64, oneshot::Sender<()>),
+}
+
+/// Starts the ordered apply and finalize stage of every VIDA that has none
+/// yet. Subscriptions feed it through [`ingest`] and [`finalize`]; its queue
+/// holds at most `capacity` items, so a subscription delivering faster than
+/// transactions are applied waits instead of running ahead, and a block is
+/// only finalized once every transaction delivered before it was applied.
+pub fn start(vida_ids: &[u64], capacity: usize) {
+    let mut pipelines = PIPELINES.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    for &vida_id in vida_ids {
+        if pipelines.contains_key(&vida_id) {
+            continue;
+        }
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        pipelines.insert(vida_id, sender);
+        tokio::spawn(run_stage(vida_id, receiver));
+    }
+}
+
+/// Queues a delivered transaction for its VIDA's stage, waiting while the
+/// queue is full. Without a started pipeline the transaction is applied
+/// right away.
+pub fn ingest(txn: VidaDataTransaction) {
+    let Some(sender) = sender_for(txn.vida_id) else {
+        handler::process_transaction(txn);
+        return;
+    };
+    let item = Item { epoch: EPOCH.load(Ordering::SeqCst), stage: Stage::Apply(txn) };
+    match sender.try_send(item) {
+        Ok(()) => {}
+        Err(TrySendError::Full(item)) => send_waiting(sender, item),
+        Err(TrySendError::Closed(_)) => warn!("Pipeline stopped, dropping delivered transaction"),
+    }
+}
+
+/// Queues the finalization of a block behind the transactions delivered
+/// before it and resolves once it has run. Without a started pipeline the
+/// block is finalized right away.
+pub fn finalize(vida_id: u64, block_number: u64) -> BoxFuture<'static, ()> {
+    async move {
+        let Some(sender) = sender_for(vida_id) else {
+            handler::on_chain_progress(vida_id, block_number).await;
+            return;
+        };
+        let (done, finalized) = oneshot::channel();
+        let item = Item { epoch: EPOCH.load(Ordering::SeqCst), stage: Stage::Finalize(block_number, done) };
+        if sender.send(item).await.is_ok() {
+            let _ = finalized.await;
+        }
+    }.boxed()
+}
+
+/// Makes every stage skip the items queued so far, e.g. once subscriptions
+/// were stopped and their uncommitted changes discarded.
+pub fn discard_queued() {
+    EPOCH.fetch_add(1, Ordering::SeqCst);
+}
+
+fn sender_for(vida_id: u64) -> Option<mpsc::Sender<Item>> {
+    PIPELINES.get()?.lock().unwrap().get(&vida_id).cloned()
+}
+
+// Blocks the delivering thread until the queue has room. Transaction callbacks
+// are synchronous, so on a current-thread runtime, where blocking would also
+// stop the stage, the send is left to a task; waiting senders are served in
+// order, so the queue keeps delivery order.
+fn send_waiting(sender: mpsc::Sender<Item>, item: Item) {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            let _ = tokio::task::block_in_place(|| handle.block_on(sender.send(item)));
+        }
+        Ok(handle) => {
+            handle.spawn(async move {
+                let _ = sender.send(item).await;
+            });
+        }
+        Err(_) => {
+            let _ = sender.blocking_send(item);
+        }
+    }
+}
+
+// Applies and finalizes a VIDA's queued items one at a time, in queue order. A
+// panic while handling an item is caught so the stage keeps serving its queue:
+// a panicking transaction is logged and dropped, and a block that panicked
+// while finalizing has its changes discarded and is fetched again.
+async fn run_stage(vida_id: u64, mut receiver: mpsc::Receiver<Item>) {
+    while let Some(item) = receiver.recv().await {
+        let current = item.epoch == EPOCH.load(Ordering::SeqCst);
+        match item.stage {
+            Stage::Apply(txn) if current => {
+                let hash = txn.hash.clone();
+                if panic::catch_unwind(AssertUnwindSafe(|| handler::process_transaction(txn))).is_err() {
+                    error!("Applying transaction {} of VIDA {} panicked", hash, vida_id);
+                }
+            }
+            Stage::Finalize(block_number, done) => {
+                if current {
+                    let finalized = AssertUnwindSafe(handler::on_chain_progress(vida_id, block_number))
+                        .catch_unwind()
+                        .await;
+                    if finalized.is_err() {
+                        error!("Finalizing block {} of VIDA {} panicked, reprocessing", block_number, vida_id);
+                        handler::discard_block(vida_id);
+                    }
+                }
+                let _ = done.send(());
+            }
+            Stage::Apply(_) => {}
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::allowance::{ApproveHandler, TransferFromHandler};
+use crate::authorization::{DelegateHandler, FreezeHandler, UnfreezeHandler};
+use crate::conduit::WithdrawHandler;
+use crate::database_service::DatabaseService;
+use crate::escrow::{ClaimHandler, EscrowHandler, ReclaimHandler};
+use crate::faucet::FaucetHandler;
+use crate::fees::SetFeesHandler;
+use crate::governance::{EnactHandler, ProposeHandler, VoteHandler};
+use crate::multisig::{ApproveMultisigHandler, CreateMultisigHandler, SubmitMultisigHandler};
+use crate::peers::RegisterPeerHandler;
+use crate::state_reader::StateReader;
+use crate::supply::{BurnHandler, MintHandler};
+use crate::transfer::TransferHandler;
+use crate::vesting::VestHandler;
+
+/// Information about the VIDA transaction being processed.
+pub struct TransactionContext<'a> {
+    /// Database the transaction is applied to.
+    pub db: &'a DatabaseService,
+    pub vida_id: u64,
+    pub block_number: u64,
+    /// Hex address of the verified transaction sender, as reported by the RPC.
+    pub sender: &'a str,
+    /// Decoded JSON payload, including the `action` field.
+    pub payload: &'a Map<String, Value>,
+}
+
+impl TransactionContext<'_> {
+    /// Opens a read-only view of another VIDA synced by this node, as of the
+    /// end of the previous block. Waits for that VIDA to get there first.
+    pub fn read_vida(&self, vida_id: u64) -> Result<StateReader, String> {
+        StateReader::open(self, vida_id)
+    }
+}
+
+/// Executes one kind of VIDA action against the database.
+pub trait TransactionHandler: Send + Sync {
+    /// Applies the transaction, or returns the reason it was rejected.
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String>;
+}
+
+// Reasons that receipts report with a status of their own are built from
+// these markers, and the handler classifies rejections by the same ones
+/// Starts the reason of a transaction rejected for lack of balance.
+pub const INSUFFICIENT_FUNDS: &str = "Insufficient funds";
+/// Starts the reason of a transfer rejected for lack of allowance.
+pub const ALLOWANCE_EXCEEDED: &str = "Allowance exceeded";
+/// Follows the sender of an admin action who is not an admin.
+pub const NOT_AN_ADMIN: &str = "is not an admin";
+/// Follows a spender without a delegation from the owner.
+pub const NOT_AUTHORIZED: &str = "is not authorized";
+/// Follows an account an admin froze.
+pub const FROZEN: &str = "is frozen";
+/// Follows the claimant of an escrow meant for someone else.
+pub const NOT_THE_RECEIVER: &str = "is not the receiver";
+/// Follows the reclaimer of an escrow someone else locked.
+pub const NOT_THE_SENDER: &str = "is not the sender";
+/// Follows a proposer or voter without governance tokens.
+pub const HOLDS_NO_TOKENS: &str = "holds no tokens";
+/// Follows a sender acting for a multisig they do not own.
+pub const NOT_AN_OWNER: &str = "is not an owner";
+/// Markers of reasons whose sender may not do what they asked.
+pub const UNAUTHORIZED: [&str; 7] = [NOT_AN_ADMIN, NOT_AUTHORIZED, FROZEN, NOT_THE_RECEIVER, NOT_THE_SENDER, HOLDS_NO_TOKENS, NOT_AN_OWNER];
+/// Starts the reasons of transactions whose payload could not be used.
+pub const INVALID_PAYLOAD: [&str; 3] = ["Invalid", "Missing", "Unknown"];
+
+/// Maps lowercase `action` strings to the handlers that execute them.
+#[derive(Clone, Default)]
+pub struct ActionRegistry {
+    handlers: HashMap<String, Arc<dyn TransactionHandler>>,
+}
+
+impl ActionRegistry {
+    /// Creates a registry with the built-in actions registered.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        registry.register("transfer", TransferHandler);
+        registry.register("delegate", DelegateHandler);
+        registry.register("approve", ApproveHandler);
+        registry.register("transferFrom", TransferFromHandler);
+        registry.register("registerPeer", RegisterPeerHandler);
+        registry.register("mint", MintHandler);
+        registry.register("burn", BurnHandler);
+        registry.register("escrow", EscrowHandler);
+        registry.register("claim", ClaimHandler);
+        registry.register("reclaim", ReclaimHandler);
+        registry.register("vest", VestHandler);
+        registry.register("setFees", SetFeesHandler);
+        registry.register("propose", ProposeHandler);
+        registry.register("vote", VoteHandler);
+        registry.register("enact", EnactHandler);
+        registry.register("freeze", FreezeHandler);
+        registry.register("unfreeze", UnfreezeHandler);
+        registry.register("createMultisig", CreateMultisigHandler);
+        registry.register("submitMultisig", SubmitMultisigHandler);
+        registry.register("approveMultisig", ApproveMultisigHandler);
+        registry.register("withdraw", WithdrawHandler);
+        registry.register("faucet", FaucetHandler);
+        registry
+    }
+
+    /// Registers a handler for an action, replacing any previous one.
+    pub fn register(&mut self, action: &str, handler: impl TransactionHandler + 'static) {
+        self.handlers.insert(action.to_lowercase(), Arc::new(handler));
+    }
+
+    /// Returns the handler for an action, if one is registered.
+    pub fn get(&self, action: &str) -> Option<Arc<dyn TransactionHandler>> {
+        self.handlers.get(&action.to_lowercase()).cloned()
+    }
+}
+
+// Process-wide registry consulted by the transaction handler
+static REGISTRY: OnceLock<RwLock<ActionRegistry>> = OnceLock::new();
+
+fn global() -> &'static RwLock<ActionRegistry> {
+    REGISTRY.get_or_init(|| RwLock::new(ActionRegistry::with_defaults()))
+}
+
+/// Registers a custom action handler for all VIDAs synced by this process.
+/// The action must also be listed in the VIDA's configured `actions`.
+pub fn register_action(action: &str, handler: impl TransactionHandler + 'static) {
+    global().write().unwrap().register(action, handler);
+}
+
+/// Looks up the handler registered for an action.
+pub fn handler_for(action: &str) -> Option<Arc<dyn TransactionHandler>> {
+    global().read().unwrap().get(action)
+}
+
+/// Bounds on a single run of an action handler. State reads and writes are
+/// enforced, as every node counts them the same; handler time differs
+/// between machines, so a slow handler is only logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    pub max_reads: u64,
+    pub max_writes: u64,
+    pub slow_handler: Duration,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self { max_reads: 10_000, max_writes: 1_000, slow_handler: Duration::from_secs(1) }
+    }
+}
+
+// Limits set by `configure_limits`
+static LIMITS: OnceLock<ExecutionLimits> = OnceLock::new();
+
+/// Sets the limits handlers run within. Only the first call has an effect.
+pub fn configure_limits(limits: ExecutionLimits) {
+    let _ = LIMITS.set(limits);
+}
+
+/// Returns the limits handlers run within.
+pub fn limits() -> ExecutionLimits {
+    LIMITS.get().copied().unwrap_or_default()
+}
+
+/// Runs a handler within the configured limits. A transaction whose handler
+/// reads or writes more state keys than allowed is rejected, and none of
+/// its writes are applied.
+pub fn execute(handler: &dyn TransactionHandler, ctx: &TransactionContext) -> Result<(), String> {
+    let limits = limits();
+    let started = Instant::now();
+    let result = ctx.db.metered(ctx.vida_id, limits.max_reads, limits.max_writes, || handler.handle(ctx));
+    let elapsed = started.elapsed();
+    if elapsed > limits.slow_handler {
+        let action = ctx.payload.get("action").and_then(Value::as_str).unwrap_or_default();
+        warn!("Handler of action '{}' in VIDA {} block {} took {:?}", action, ctx.vida_id, ctx.block_number, elapsed);
+    }
+    result
+}
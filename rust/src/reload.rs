@@ -0,0 +1,95 @@
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::error::Error;
+use crate::flush::FlushPolicy;
+use crate::http::PeerClients;
+use crate::logging;
+use crate::state::SharedState;
+
+/// Reads the configuration file again and applies it to the running node.
+/// The peers of the file, peer request policies, quarantine and reputation
+/// thresholds, the flush policy, the log level and the balance cache size
+/// are applied directly; settings read as blocks are processed, such as the rollback
+/// thresholds, take effect with the next block. Fails without changing
+/// anything if a setting that needs a restart was changed. Returns the
+/// settings applied directly that changed.
+pub fn reload(state: &SharedState) -> Result<Vec<&'static str>, Error> {
+    let current = state.read().unwrap().config.clone();
+    let reloaded = current.reload()?;
+    let flush_policy = FlushPolicy::from_config(&reloaded).map_err(Error::Config)?;
+    let request_policies = reloaded.request_policies();
+    // Built before anything is applied, so a failure leaves the node unchanged
+    let clients = if request_policies != current.request_policies() {
+        Some(PeerClients::new(&request_policies.0, &request_policies.1).map_err(Error::Http)?)
+    } else {
+        None
+    };
+
+    let mut changed = Vec::new();
+    if reloaded.log_level != current.log_level {
+        logging::set_level(&reloaded.log_level)?;
+        changed.push("log_level");
+    }
+
+    let mut state = state.write().unwrap();
+    if reloaded.peers != current.peers {
+        // Peers added through the admin API or the on-chain registry are kept
+        for peer in current.peers.iter().filter(|peer| !reloaded.peers.contains(peer)) {
+            state.peers.remove(peer);
+        }
+        for peer in &reloaded.peers {
+            state.peers.add(peer);
+        }
+        changed.push("peers");
+    }
+    if (reloaded.peer_quarantine_after, reloaded.peer_quarantine_secs) != (current.peer_quarantine_after, current.peer_quarantine_secs) {
+        state.peers.set_quarantine(reloaded.peer_quarantine_after, Duration::from_secs(reloaded.peer_quarantine_secs));
+        changed.push("peer_quarantine");
+    }
+    if let Some(clients) = clients {
+        state.http = clients;
+        let (request_policy, peer_policies) = request_policies;
+        state.peers.set_request_policies(request_policy, peer_policies);
+        changed.push("peer_requests");
+    }
+    if (reloaded.peer_min_score, reloaded.peer_min_samples) != (current.peer_min_score, current.peer_min_samples) {
+        state.peers.set_reputation_threshold(reloaded.peer_min_score, reloaded.peer_min_samples);
+        changed.push("peer_min_score");
+    }
+    if FlushPolicy::from_config(&current).ok() != Some(flush_policy) {
+        state.flush_scheduler.set_policy(flush_policy);
+        changed.push("flush_policy");
+    }
+    if reloaded.balance_cache_size != current.balance_cache_size {
+        state.db.set_balance_cache_capacity(reloaded.balance_cache_size);
+        changed.push("balance_cache_size");
+    }
+    state.config = reloaded;
+    info!("Configuration reloaded, changed: {:?}", changed);
+    Ok(changed)
+}
+
+/// Reloads the configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub fn watch_sighup(state: SharedState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Cannot listen for SIGHUP, reload the configuration through the admin API: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reload(&state) {
+                error!("Configuration reload failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn watch_sighup(_state: SharedState) {}
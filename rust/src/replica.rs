@@ -0,0 +1,111 @@
+use std::time::Duration;
+use pwr_rs::merkle_tree::MerkleTreeError;
+use tokio::time::sleep;
+use tracing::{info, instrument, warn};
+
+use crate::database_service::{DatabaseService, StateDiffEntry};
+use crate::handler;
+use crate::http;
+use crate::resync::{self, TRANSFER_TIMEOUT};
+use crate::state::SharedState;
+
+// How long the primary may take to report its last checked block
+const STATUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts following the primary node set in `replica_of`, if any, in place
+/// of processing VIDA transactions: every `replica_poll_secs` each VIDA
+/// applies the primary's state diff since its last checked block, committed
+/// only once a quorum of peers reports the resulting root. A diff that
+/// leads elsewhere is discarded and the state resynced from peers.
+pub fn follow(state: SharedState) {
+    let primary = state.read().unwrap().config.replica_of.clone();
+    if primary.is_empty() {
+        return;
+    }
+    info!("Following primary {} as a read-only replica", primary);
+
+    tokio::spawn(async move {
+        loop {
+            let (db, interval) = {
+                let state = state.read().unwrap();
+                (state.db.clone(), Duration::from_secs(state.config.replica_poll_secs))
+            };
+            for vida_id in db.vida_ids() {
+                match catch_up(&state, &db, &primary, vida_id).await {
+                    Ok(Some(block_number)) => info!("Replica of VIDA {} caught up to block {}", vida_id, block_number),
+                    Ok(None) => {}
+                    Err(e) => warn!("Replica of VIDA {} could not follow {}: {}", vida_id, primary, e),
+                }
+            }
+            sleep(interval).await;
+        }
+    });
+}
+
+// Brings a VIDA up to the primary's last checked block, returning the block
+// reached, or None if it was already there
+#[instrument(skip(state, db))]
+async fn catch_up(state: &SharedState, db: &DatabaseService, primary: &str, vida_id: u64) -> Result<Option<u64>, String> {
+    let (client, peers) = {
+        let state = state.read().unwrap();
+        (state.http.client().clone(), state.peers.voters())
+    };
+    let last_checked_block = db.get_last_checked_block(vida_id).map_err(|e| format!("{:?}", e))?;
+    let head = fetch_last_checked_block(&client, primary, vida_id).await?;
+    if head <= last_checked_block {
+        return Ok(None);
+    }
+    let diff = fetch_state_diff(&client, primary, vida_id, last_checked_block, head).await?;
+    let agreed_root = resync::agreed_block_root(&client, &peers, vida_id, head).await
+        .ok_or_else(|| format!("No peer quorum for the root of block {}", head))?;
+
+    let root = db.apply_state_diff(vida_id, head, &diff).map_err(|e| format!("{:?}", e))?;
+    if root != agreed_root {
+        db.abort_block(vida_id).map_err(|e| format!("{:?}", e))?;
+        warn!(
+            "State diff of blocks {}..{} gives root {} but peers agree on {}, resyncing state from peers",
+            last_checked_block, head, hex::encode(&root), hex::encode(&agreed_root)
+        );
+        let block_number = resync::resync_from_peers(&client, db, vida_id, &peers).await?;
+        return Ok(Some(block_number));
+    }
+
+    if let Err(e) = commit(db, vida_id, head, &root) {
+        let _ = db.abort_block(vida_id);
+        return Err(format!("Failed to commit block {}: {:?}", head, e));
+    }
+    handler::publish_checkpoint_events(db, vida_id, head);
+    Ok(Some(head))
+}
+
+// Commits the applied diff as the state after `block_number`
+fn commit(db: &DatabaseService, vida_id: u64, block_number: u64, root: &[u8]) -> Result<(), MerkleTreeError> {
+    db.set_block_root_hash(vida_id, block_number, root)?;
+    db.set_last_checked_block(vida_id, block_number)?;
+    db.commit_block(vida_id, block_number)
+}
+
+// Reads the last checked block from the primary's status
+async fn fetch_last_checked_block(client: &reqwest::Client, primary: &str, vida_id: u64) -> Result<u64, String> {
+    let url = http::peer_url(primary, &format!("/status?vidaId={}", vida_id));
+    let response = client.get(&url).timeout(STATUS_TIMEOUT).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Status request failed with HTTP {}", response.status()));
+    }
+    let status: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    status["lastCheckedBlock"].as_u64().ok_or_else(|| "Status without lastCheckedBlock".to_string())
+}
+
+// Downloads the primary's newline-delimited state diff between two blocks
+async fn fetch_state_diff(client: &reqwest::Client, primary: &str, vida_id: u64, from_block: u64, to_block: u64) -> Result<Vec<StateDiffEntry>, String> {
+    let url = http::peer_url(primary, &format!("/state-diff?vidaId={}&fromBlock={}&toBlock={}", vida_id, from_block, to_block));
+    let response = client.get(&url).timeout(TRANSFER_TIMEOUT).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("State diff request failed with HTTP {}", response.status()));
+    }
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("Invalid state diff entry: {}", e)))
+        .collect()
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+use tracing::{info, instrument, warn};
+
+use crate::database_service::{DatabaseService, StateSnapshot};
+use crate::http;
+use crate::state_sync::{StateSyncError, StateSyncer};
+
+/// How long a peer may take to send a state snapshot or chunk.
+pub(crate) const TRANSFER_TIMEOUT: Duration = Duration::from_secs(60);
+// How long a peer may take to report a block root
+const ROOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Replaces the local state of a VIDA with the state of a peer, accepted
+/// only once a quorum of peers confirms its block root. The state is
+/// downloaded in verified chunks, or as one snapshot from peers that do not
+/// serve chunks. Returns the block number the node should resume syncing from.
+#[instrument(skip(client, db, peers))]
+pub async fn resync_from_peers(client: &reqwest::Client, db: &DatabaseService, vida_id: u64, peers: &[String]) -> Result<u64, String> {
+    let syncer = StateSyncer::new(client.clone(), vida_id, peers);
+
+    for peer in peers {
+        match syncer.sync_from(db, peer).await {
+            Ok(block_number) => return Ok(block_number),
+            Err(StateSyncError::Unsupported) => {}
+            Err(StateSyncError::Failed(e)) => {
+                warn!("Chunked state sync from peer {} failed: {}", peer, e);
+                continue;
+            }
+        }
+
+        let snapshot = match fetch_snapshot(client, peer, vida_id).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Could not fetch state snapshot from peer {}: {}", peer, e);
+                continue;
+            }
+        };
+
+        let block_root = match agreed_block_root(client, peers, vida_id, snapshot.block_number).await {
+            Some(root) => root,
+            None => {
+                warn!("No peer quorum for root of block {} offered by {}", snapshot.block_number, peer);
+                continue;
+            }
+        };
+
+        match db.import_state(vida_id, &snapshot, &block_root) {
+            Ok(()) => {
+                info!("Imported state of block {} from peer {}", snapshot.block_number, peer);
+                return Ok(snapshot.block_number);
+            }
+            Err(e) => warn!("Rejected state snapshot from peer {}: {:?}", peer, e),
+        }
+    }
+
+    Err("No peer provided a verifiable state snapshot".to_string())
+}
+
+// Downloads the full state export of a VIDA from a peer
+async fn fetch_snapshot(client: &reqwest::Client, peer: &str, vida_id: u64) -> Result<StateSnapshot, String> {
+    let url = http::peer_url(peer, &format!("/state/export?vidaId={}", vida_id));
+    let response = client.get(&url).timeout(TRANSFER_TIMEOUT).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.json::<StateSnapshot>().await.map_err(|e| e.to_string())
+}
+
+// Returns the root hash a quorum of responding peers report for the block
+pub(crate) async fn agreed_block_root(client: &reqwest::Client, peers: &[String], vida_id: u64, block_number: u64) -> Option<Vec<u8>> {
+    let mut roots: Vec<(Vec<u8>, usize)> = Vec::new();
+    let mut responding = 0;
+
+    for peer in peers {
+        let url = http::peer_url(peer, &format!("/rootHash?blockNumber={}&vidaId={}", block_number, vida_id));
+        let text = match client.get(&url).timeout(ROOT_TIMEOUT).send().await {
+            Ok(response) if response.status().is_success() => response.text().await.unwrap_or_default(),
+            _ => continue,
+        };
+        let root = match hex::decode(text.trim()) {
+            Ok(root) if !root.is_empty() => root,
+            _ => continue,
+        };
+
+        responding += 1;
+        match roots.iter_mut().find(|(known, _)| *known == root) {
+            Some((_, votes)) => *votes += 1,
+            None => roots.push((root, 1)),
+        }
+    }
+
+    let quorum = (responding * 2) / 3 + 1;
+    roots.into_iter()
+        .find(|(_, votes)| *votes >= quorum)
+        .map(|(root, _)| root)
+}
@@ -0,0 +1,49 @@
+use tracing::info;
+
+use crate::error::Error;
+use crate::handler;
+use crate::state::SharedState;
+
+/// Coordinates an orderly shutdown so that no partially applied block is
+/// ever persisted to the Merkle tree.
+pub struct ShutdownCoordinator {
+    state: SharedState,
+}
+
+impl ShutdownCoordinator {
+    pub fn new(state: SharedState) -> Self {
+        Self { state }
+    }
+
+    /// Waits for Ctrl+C and then shuts the node down cleanly.
+    pub async fn wait_for_signal(&self) -> Result<(), Error> {
+        tokio::signal::ctrl_c().await
+            .map_err(|e| Error::io("Failed to listen for Ctrl+C", e))?;
+        info!("Shutdown requested, finishing in-flight block...");
+        self.shutdown().await
+    }
+
+    /// Stops the subscriptions, waits for the in-flight block, discards changes
+    /// from transactions of blocks that were not checkpointed and flushes.
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        handler::stop_processing(&self.state).await;
+
+        let db = self.state.read().unwrap().db.clone();
+
+        for vida_id in db.vida_ids() {
+            // Transactions applied after the last commit belong to blocks that were never
+            // validated or whose commit was deferred; they are replayed from lastCheckedBlock on restart.
+            db.revert_unsaved_changes(vida_id)
+                .map_err(|e| Error::storage("Failed to revert unsaved changes", e))?;
+            db.flush(vida_id)
+                .map_err(|e| Error::storage("Failed to flush database", e))?;
+
+            let last_block = db.get_last_checked_block(vida_id)
+                .map_err(|e| Error::storage("Failed to get last checked block", e))?;
+            info!("VIDA {} last fully validated block: {}", vida_id, last_block);
+        }
+        info!("Shutdown complete");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+
+/// Ed25519 keypair a node signs its root hashes with. Peers verify the
+/// signatures against the public key configured for the node in
+/// `peer_public_keys`.
+#[derive(Clone)]
+pub struct NodeKey {
+    signing_key: SigningKey,
+}
+
+impl NodeKey {
+    /// Reads the hex-encoded secret key at `path`, generating and saving a new
+    /// one if the file does not exist yet.
+    pub fn load_or_generate(path: &Path) -> Result<Self, String> {
+        if path.exists() {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read node key {}: {}", path.display(), e))?;
+            let secret: [u8; 32] = hex::decode(contents.trim())
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("Invalid node key in {}", path.display()))?;
+            return Ok(Self { signing_key: SigningKey::from_bytes(&secret) });
+        }
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        fs::write(path, hex::encode(signing_key.to_bytes()))
+            .map_err(|e| format!("Failed to write node key {}: {}", path.display(), e))?;
+        Ok(Self { signing_key })
+    }
+
+    /// Returns the hex-encoded public key peers should configure for this node.
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// Signs the root hash of a block, returning the hex-encoded signature.
+    pub fn sign_root(&self, block_number: u64, root: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(&root_message(block_number, root)).to_bytes())
+    }
+}
+
+/// Returns whether `signature_hex` is a valid signature of the block root by
+/// the holder of `public_key_hex`.
+pub fn verify_root(public_key_hex: &str, block_number: u64, root: &[u8], signature_hex: &str) -> bool {
+    let public_key = hex::decode(public_key_hex.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+    let signature = hex::decode(signature_hex.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok());
+    match (public_key, signature) {
+        (Some(public_key), Some(signature)) => {
+            public_key.verify(&root_message(block_number, root), &signature).is_ok()
+        }
+        _ => false,
+    }
+}
+
+// Signed message: the block number as 8 big-endian bytes followed by the root
+fn root_message(block_number: u64, root: &[u8]) -> Vec<u8> {
+    [&block_number.to_be_bytes()[..], root].concat()
+}
@@ -0,0 +1,161 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::database_service::{DatabaseService, StateSnapshot};
+use crate::error::Error;
+
+// Identifies snapshot files and the version of their layout
+const MAGIC: &[u8; 8] = b"PWRSNAP1";
+
+/// Writes the full state of a VIDA to a binary snapshot file. Layout, all
+/// integers big-endian: magic, vida id (u64), block number (u64), root hash
+/// (u32 length + bytes), entry count (u64), then each key and value as
+/// u32 length + bytes, in the tree's insertion order.
+pub fn export_to_file(db: &DatabaseService, vida_id: u64, path: &str) -> Result<StateSnapshot, Error> {
+    let snapshot = db.export_state(vida_id)
+        .map_err(|e| Error::storage("Failed to export state", e))?;
+    let data = encode(&snapshot)?;
+    fs::write(path, &data).map_err(|e| Error::io(format!("Failed to write snapshot {}", path), e))?;
+    info!("Exported {} entries of VIDA {} at block {} to {}", snapshot.entries.len(), vida_id, snapshot.block_number, path);
+    Ok(snapshot)
+}
+
+/// Writes the state of a VIDA to `<dir>/vida-<id>-<block>.snap`, then deletes
+/// all but the `retention` newest snapshots of the VIDA in `dir` (0 keeps
+/// them all). The file is written under a temporary name first, so a crash
+/// never leaves a truncated snapshot behind.
+pub fn save_to_dir(db: &DatabaseService, vida_id: u64, dir: &str, retention: usize) -> Result<PathBuf, Error> {
+    let snapshot = db.export_state(vida_id)
+        .map_err(|e| Error::storage("Failed to export state", e))?;
+    let data = encode(&snapshot)?;
+    fs::create_dir_all(dir).map_err(|e| Error::io(format!("Failed to create snapshot directory {}", dir), e))?;
+
+    let path = Path::new(dir).join(format!("vida-{}-{}.snap", vida_id, snapshot.block_number));
+    let partial = path.with_extension("snap.tmp");
+    fs::write(&partial, &data).map_err(|e| Error::io(format!("Failed to write snapshot {}", partial.display()), e))?;
+    fs::rename(&partial, &path).map_err(|e| Error::io(format!("Failed to move snapshot to {}", path.display()), e))?;
+    info!("Saved snapshot of VIDA {} at block {} to {}", vida_id, snapshot.block_number, path.display());
+
+    if retention > 0 {
+        for (_, old) in list_dir(dir, vida_id).into_iter().rev().skip(retention) {
+            if let Err(e) = fs::remove_file(&old) {
+                warn!("Failed to delete old snapshot {}: {}", old.display(), e);
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Returns the block of the newest snapshot of a VIDA saved in `dir`.
+pub fn latest_in_dir(dir: &str, vida_id: u64) -> Option<u64> {
+    list_dir(dir, vida_id).last().map(|(block_number, _)| *block_number)
+}
+
+// Lists the snapshots of a VIDA saved by `save_to_dir`, oldest first
+fn list_dir(dir: &str, vida_id: u64) -> Vec<(u64, PathBuf)> {
+    let prefix = format!("vida-{}-", vida_id);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let block_number = name.strip_prefix(&prefix)?.strip_suffix(".snap")?.parse().ok()?;
+            Some((block_number, entry.path()))
+        })
+        .collect();
+    snapshots.sort();
+    snapshots
+}
+
+// Serializes a snapshot to the binary layout described on `export_to_file`
+fn encode(snapshot: &StateSnapshot) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&snapshot.vida_id.to_be_bytes());
+    data.extend_from_slice(&snapshot.block_number.to_be_bytes());
+    write_chunk(&mut data, &decode_hex(&snapshot.root_hash)?);
+    data.extend_from_slice(&(snapshot.entries.len() as u64).to_be_bytes());
+    for (key, value) in &snapshot.entries {
+        write_chunk(&mut data, &decode_hex(key)?);
+        write_chunk(&mut data, &decode_hex(value)?);
+    }
+    Ok(data)
+}
+
+/// Replaces the state of a VIDA with the contents of a snapshot file. The
+/// rebuilt tree must reproduce the root hash recorded in the file.
+pub fn import_from_file(db: &DatabaseService, vida_id: u64, path: &str) -> Result<StateSnapshot, Error> {
+    let snapshot = read_file(vida_id, path)?;
+    let root_hash = decode_hex(&snapshot.root_hash)?;
+    db.import_state(vida_id, &snapshot, &root_hash)
+        .map_err(|e| Error::storage("Failed to import snapshot", e))?;
+    info!("Imported {} entries of VIDA {} at block {} from {}", snapshot.entries.len(), vida_id, snapshot.block_number, path);
+    Ok(snapshot)
+}
+
+/// Reads a snapshot file of a VIDA without importing it.
+pub fn read_file(vida_id: u64, path: &str) -> Result<StateSnapshot, Error> {
+    let data = fs::read(path).map_err(|e| Error::io(format!("Failed to read snapshot {}", path), e))?;
+    let snapshot = decode(&data).map_err(|e| Error::Validation(format!("Invalid snapshot {}: {}", path, e)))?;
+    if snapshot.vida_id != vida_id {
+        return Err(Error::Validation(format!("Snapshot belongs to VIDA {}, not {}", snapshot.vida_id, vida_id)));
+    }
+    Ok(snapshot)
+}
+
+// Parses the binary layout written by `export_to_file`
+fn decode(mut data: &[u8]) -> Result<StateSnapshot, String> {
+    if data.get(..MAGIC.len()) != Some(&MAGIC[..]) {
+        return Err("not a snapshot file".to_string());
+    }
+    data = &data[MAGIC.len()..];
+
+    let vida_id = read_u64(&mut data)?;
+    let block_number = read_u64(&mut data)?;
+    let root_hash = hex::encode(read_chunk(&mut data)?);
+    let count = read_u64(&mut data)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let key = read_chunk(&mut data)?;
+        let value = read_chunk(&mut data)?;
+        entries.push((hex::encode(key), hex::encode(value)));
+    }
+    if !data.is_empty() {
+        return Err("trailing data".to_string());
+    }
+
+    Ok(StateSnapshot { vida_id, block_number, root_hash, entries })
+}
+
+// Decodes a hex field of an exported state
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|e| Error::Validation(format!("Invalid hex in state {}: {}", value, e)))
+}
+
+// Appends a u32 length-prefixed chunk
+fn write_chunk(data: &mut Vec<u8>, chunk: &[u8]) {
+    data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    data.extend_from_slice(chunk);
+}
+
+// Reads a big-endian u64 and advances the cursor past it
+fn read_u64(data: &mut &[u8]) -> Result<u64, String> {
+    let remaining: &[u8] = *data;
+    let bytes: [u8; 8] = remaining.get(..8).and_then(|b| b.try_into().ok()).ok_or("truncated file")?;
+    *data = &remaining[8..];
+    Ok(u64::from_be_bytes(bytes))
+}
+
+// Reads a u32 length-prefixed chunk and advances the cursor past it
+fn read_chunk(data: &mut &[u8]) -> Result<Vec<u8>, String> {
+    let remaining: &[u8] = *data;
+    let len_bytes: [u8; 4] = remaining.get(..4).and_then(|b| b.try_into().ok()).ok_or("truncated file")?;
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    let chunk = remaining.get(4..4 + len).ok_or("truncated file")?.to_vec();
+    *data = &remaining[4 + len..];
+    Ok(chunk)
+}
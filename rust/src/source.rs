@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use futures_util::future::BoxFuture;
+use pwr_rs::{RPC, transaction::types::VidaDataTransaction};
+use tracing::warn;
+
+/// Called for every transaction a subscription delivers, in chain order.
+pub type TransactionCallback = fn(VidaDataTransaction);
+
+/// Called once a subscription has delivered every transaction up to a block.
+pub type BlockCallback = Arc<dyn Fn(u64) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Running delivery of a VIDA's transactions, started by `VidaSource::subscribe`.
+pub trait Subscription: Send + Sync {
+    /// Stops delivering transactions.
+    fn stop(&self);
+    /// Returns the last block whose transactions were all delivered.
+    fn latest_checked_block(&self) -> u64;
+    /// Continues delivery after `block_number`, e.g. to replay blocks after a rollback.
+    fn set_latest_checked_block(&self, block_number: u64);
+}
+
+/// Where the node reads VIDA transactions from: the PWR chain through
+/// [`PwrSource`] or an in-memory [`MockSource`] in tests.
+pub trait VidaSource: Send + Sync + Sized + 'static {
+    /// Connects to the endpoint at `url`.
+    fn connect(url: &str) -> impl Future<Output = Result<Self, String>> + Send;
+
+    /// Returns the number of the latest block of the chain.
+    fn latest_block(&self) -> impl Future<Output = Result<u64, String>> + Send;
+
+    /// Returns the transactions of a VIDA in blocks `start` to `end`, inclusive.
+    fn fetch_range(
+        &self,
+        vida_id: u64,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<VidaDataTransaction>, String>> + Send;
+
+    /// Delivers the transactions of a VIDA from `from_block` onwards to
+    /// `on_transaction` and reports delivered blocks to `on_block` until the
+    /// returned subscription is stopped.
+    fn subscribe(
+        &self,
+        vida_id: u64,
+        from_block: u64,
+        on_transaction: TransactionCallback,
+        on_block: BlockCallback,
+    ) -> Box<dyn Subscription>;
+}
+
+// Most blocks a subscription fetches in one request, as pwr-rs does
+const MAX_BLOCKS_PER_POLL: u64 = 1000;
+
+// Poll intervals set by `configure_polling`
+static POLL_INTERVALS: OnceLock<(Duration, Duration)> = OnceLock::new();
+
+/// Sets the shortest and longest pause between two polls of the chain by
+/// `PwrSource` subscriptions. Only the first call has an effect; without one
+/// they poll every 100ms, backing off to 5s.
+pub fn configure_polling(min: Duration, max: Duration) {
+    let _ = POLL_INTERVALS.set((min, max));
+}
+
+/// Paces the polling of the chain for new blocks: right away while the chain
+/// is ahead of what was delivered, after `min` once caught up, and doubling
+/// the pause up to `max` for every poll that finds nothing new.
+#[derive(Debug, Clone)]
+pub struct BlockMonitor {
+    min: Duration,
+    max: Duration,
+    interval: Duration,
+}
+
+impl BlockMonitor {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        BlockMonitor { min, max: max.max(min), interval: min }
+    }
+
+    /// Monitor with the intervals set by `configure_polling`.
+    pub fn configured() -> Self {
+        let (min, max) = POLL_INTERVALS.get().copied()
+            .unwrap_or((Duration::from_millis(100), Duration::from_secs(5)));
+        BlockMonitor::new(min, max)
+    }
+
+    /// Pause before the next poll.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Records a poll that delivered blocks up to `checked` while the chain
+    /// was at `latest`.
+    pub fn progressed(&mut self, checked: u64, latest: u64) {
+        self.interval = if checked < latest { Duration::ZERO } else { self.min };
+    }
+
+    /// Records a poll that found no new block or failed.
+    pub fn idle(&mut self) {
+        self.interval = (self.interval.max(self.min) * 2).min(self.max);
+    }
+}
+
+/// Reads VIDA transactions from a PWR RPC node.
+pub struct PwrSource {
+    rpc: Arc<RPC>,
+}
+
+impl PwrSource {
+    // Delivers the next blocks after the checked one, returning the chain's
+    // latest block, or None when there was nothing new
+    async fn poll(
+        &self,
+        vida_id: u64,
+        checked: &AtomicU64,
+        on_transaction: TransactionCallback,
+        on_block: &BlockCallback,
+    ) -> Result<Option<u64>, String> {
+        let latest_block = self.latest_block().await?;
+        let from = checked.load(Ordering::SeqCst) + 1;
+        if latest_block < from {
+            return Ok(None);
+        }
+        let end = latest_block.min(from + MAX_BLOCKS_PER_POLL - 1);
+        for txn in self.fetch_range(vida_id, from, end).await? {
+            on_transaction(txn);
+        }
+        // Stored first, so a rollback from `on_block` moving it back holds
+        checked.store(end, Ordering::SeqCst);
+        on_block(end).await;
+        Ok(Some(latest_block))
+    }
+}
+
+impl VidaSource for PwrSource {
+    async fn connect(url: &str) -> Result<Self, String> {
+        RPC::new(url).await
+            .map(|rpc| PwrSource { rpc: Arc::new(rpc) })
+            .map_err(|e| format!("Failed to create RPC client for {}: {:?}", url, e))
+    }
+
+    async fn latest_block(&self) -> Result<u64, String> {
+        self.rpc.get_latest_block_number().await.map_err(|e| format!("{:?}", e))
+    }
+
+    async fn fetch_range(&self, vida_id: u64, start: u64, end: u64) -> Result<Vec<VidaDataTransaction>, String> {
+        self.rpc.get_vida_data_transactions(start, end, vida_id).await.map_err(|e| format!("{:?}", e))
+    }
+
+    fn subscribe(
+        &self,
+        vida_id: u64,
+        from_block: u64,
+        on_transaction: TransactionCallback,
+        on_block: BlockCallback,
+    ) -> Box<dyn Subscription> {
+        let subscription = PollingSubscription {
+            stopped: Arc::new(AtomicBool::new(false)),
+            checked: Arc::new(AtomicU64::new(from_block.saturating_sub(1))),
+        };
+        let source = PwrSource { rpc: self.rpc.clone() };
+        let (stopped, checked) = (subscription.stopped.clone(), subscription.checked.clone());
+        tokio::spawn(async move {
+            let mut monitor = BlockMonitor::configured();
+            while !stopped.load(Ordering::SeqCst) {
+                match source.poll(vida_id, &checked, on_transaction, &on_block).await {
+                    Ok(Some(latest_block)) => monitor.progressed(checked.load(Ordering::SeqCst), latest_block),
+                    Ok(None) => monitor.idle(),
+                    Err(e) => {
+                        warn!("Failed to poll VIDA {} transactions: {}", vida_id, e);
+                        monitor.idle();
+                    }
+                }
+                tokio::time::sleep(monitor.interval()).await;
+            }
+        });
+        Box::new(subscription)
+    }
+}
+
+// How often mock subscriptions look for new blocks
+const MOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Mock chains by URL, so every connection to the same URL sees the same chain
+static MOCK_CHAINS: OnceLock<Mutex<HashMap<String, MockSource>>> = OnceLock::new();
+
+/// In-memory chain for tests. Every `MockSource` connected to the same URL
+/// shares one chain, which the test extends with `push` and `advance_to`.
+#[derive(Clone, Default)]
+pub struct MockSource {
+    chain: Arc<Mutex<MockChain>>,
+}
+
+#[derive(Default)]
+struct MockChain {
+    latest_block: u64,
+    transactions: Vec<VidaDataTransaction>,
+}
+
+impl MockSource {
+    /// Returns the chain behind `url`, created empty on first use.
+    pub fn chain(url: &str) -> Self {
+        let chains = MOCK_CHAINS.get_or_init(|| Mutex::new(HashMap::new()));
+        chains.lock().unwrap().entry(url.to_string()).or_default().clone()
+    }
+
+    /// Appends a transaction, advancing the chain to its block if needed.
+    /// Transactions must be pushed in chain order.
+    pub fn push(&self, txn: VidaDataTransaction) {
+        let mut chain = self.chain.lock().unwrap();
+        chain.latest_block = chain.latest_block.max(txn.block_number);
+        chain.transactions.push(txn);
+    }
+
+    /// Advances the chain to `block_number` without adding transactions.
+    pub fn advance_to(&self, block_number: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        chain.latest_block = chain.latest_block.max(block_number);
+    }
+
+    // Transactions of a VIDA in blocks `start` to `end`, in the order they were pushed
+    fn range(&self, vida_id: u64, start: u64, end: u64) -> Vec<VidaDataTransaction> {
+        self.chain.lock().unwrap().transactions.iter()
+            .filter(|txn| txn.vida_id == vida_id && (start..=end).contains(&txn.block_number))
+            .cloned()
+            .collect()
+    }
+}
+
+impl VidaSource for MockSource {
+    async fn connect(url: &str) -> Result<Self, String> {
+        Ok(MockSource::chain(url))
+    }
+
+    async fn latest_block(&self) -> Result<u64, String> {
+        Ok(self.chain.lock().unwrap().latest_block)
+    }
+
+    async fn fetch_range(&self, vida_id: u64, start: u64, end: u64) -> Result<Vec<VidaDataTransaction>, String> {
+        Ok(self.range(vida_id, start, end))
+    }
+
+    fn subscribe(
+        &self,
+        vida_id: u64,
+        from_block: u64,
+        on_transaction: TransactionCallback,
+        on_block: BlockCallback,
+    ) -> Box<dyn Subscription> {
+        let subscription = PollingSubscription {
+            stopped: Arc::new(AtomicBool::new(false)),
+            checked: Arc::new(AtomicU64::new(from_block.saturating_sub(1))),
+        };
+        let source = self.clone();
+        let (stopped, checked) = (subscription.stopped.clone(), subscription.checked.clone());
+        tokio::spawn(async move {
+            while !stopped.load(Ordering::SeqCst) {
+                let latest_block = source.chain.lock().unwrap().latest_block;
+                let from = checked.load(Ordering::SeqCst) + 1;
+                if latest_block >= from {
+                    for txn in source.range(vida_id, from, latest_block) {
+                        on_transaction(txn);
+                    }
+                    on_block(latest_block).await;
+                    checked.store(latest_block, Ordering::SeqCst);
+                }
+                tokio::time::sleep(MOCK_POLL_INTERVAL).await;
+            }
+        });
+        Box::new(subscription)
+    }
+}
+
+// Subscription polling the chain from a background task
+struct PollingSubscription {
+    stopped: Arc<AtomicBool>,
+    checked: Arc<AtomicU64>,
+}
+
+impl Subscription for PollingSubscription {
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn latest_checked_block(&self) -> u64 {
+        self.checked.load(Ordering::SeqCst)
+    }
+
+    fn set_latest_checked_block(&self, block_number: u64) {
+        self.checked.store(block_number, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::process;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::http;
+use crate::shutdown::ShutdownCoordinator;
+use crate::state::SharedState;
+
+// Longest wait between two checks of the last checked blocks
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the node does once a VIDA's sync has stalled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StallAlert {
+    /// Log an error.
+    Log,
+    /// Log an error and POST the stall as JSON to this URL.
+    Webhook(String),
+    /// Log an error, shut down cleanly and exit with this code, leaving the
+    /// restart to a process supervisor.
+    Exit(i32),
+}
+
+impl StallAlert {
+    /// Reads the alert from `stall_alert` (`log`, `webhook` or `exit`) and its
+    /// `stall_alert_url` / `stall_alert_exit_code` setting.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        match config.stall_alert.as_str() {
+            "log" => Ok(StallAlert::Log),
+            "webhook" if !config.stall_alert_url.is_empty() => Ok(StallAlert::Webhook(config.stall_alert_url.clone())),
+            "webhook" => Err("Stall alert webhook needs stall_alert_url".to_string()),
+            "exit" => Ok(StallAlert::Exit(config.stall_alert_exit_code)),
+            other => Err(format!("Unknown stall alert: {}", other)),
+        }
+    }
+}
+
+// Last progress seen of a VIDA
+struct Progress {
+    last_checked_block: u64,
+    chain_block: u64,
+    since: Instant,
+    alerted: bool,
+}
+
+/// Starts watching every VIDA for a stalled sync: its last checked block
+/// unchanged for `stall_alert_secs` while the chain head moved past it. A
+/// silently dead subscription looks like a quiet chain otherwise. Each stall
+/// raises the configured alert once; the VIDA is watched again once it
+/// makes progress.
+pub fn watch(state: SharedState) {
+    let (threshold, alert) = {
+        let config = &state.read().unwrap().config;
+        // The alert is validated when the configuration is loaded
+        (config.stall_alert_secs, StallAlert::from_config(config).unwrap_or(StallAlert::Log))
+    };
+    if threshold == 0 {
+        return;
+    }
+    let threshold = Duration::from_secs(threshold);
+    let interval = (threshold / 4).clamp(Duration::from_secs(1), MAX_CHECK_INTERVAL);
+
+    tokio::spawn(async move {
+        let mut progress: HashMap<u64, Progress> = HashMap::new();
+        loop {
+            sleep(interval).await;
+            let (db, chain_block) = {
+                let state = state.read().unwrap();
+                (state.db.clone(), state.sync.latest_chain_block())
+            };
+            let Some(chain_block) = chain_block else {
+                continue;
+            };
+
+            for vida_id in db.vida_ids() {
+                let last_checked_block = match db.get_last_checked_block(vida_id) {
+                    Ok(block_number) => block_number,
+                    Err(e) => {
+                        warn!("Stall check of VIDA {} failed: {:?}", vida_id, e);
+                        continue;
+                    }
+                };
+                let now = Instant::now();
+                let seen = progress.entry(vida_id).or_insert(Progress {
+                    last_checked_block,
+                    chain_block,
+                    since: now,
+                    alerted: false,
+                });
+                if seen.last_checked_block != last_checked_block {
+                    if seen.alerted {
+                        info!("VIDA {} sync resumed at block {}", vida_id, last_checked_block);
+                    }
+                    *seen = Progress { last_checked_block, chain_block, since: now, alerted: false };
+                    continue;
+                }
+                let stalled_for = now.duration_since(seen.since);
+                if seen.alerted || stalled_for < threshold || chain_block <= seen.chain_block.max(last_checked_block) {
+                    continue;
+                }
+                seen.alerted = true;
+                raise(&state, &alert, vida_id, last_checked_block, chain_block, stalled_for).await;
+            }
+        }
+    });
+}
+
+// Reports a stalled VIDA through the configured alert
+async fn raise(state: &SharedState, alert: &StallAlert, vida_id: u64, last_checked_block: u64, chain_block: u64, stalled_for: Duration) {
+    error!(
+        "VIDA {} sync stalled at block {} for {}s while the chain reached block {}",
+        vida_id, last_checked_block, stalled_for.as_secs(), chain_block
+    );
+    match alert {
+        StallAlert::Log => {}
+        StallAlert::Webhook(url) => {
+            let body = json!({
+                "vidaId": vida_id,
+                "lastCheckedBlock": last_checked_block,
+                "chainBlock": chain_block,
+                "stalledSecs": stalled_for.as_secs()
+            });
+            let sent = match http::client(Duration::from_secs(10)) {
+                Ok(client) => client.post(url).json(&body).send().await
+                    .map_err(|e| e.to_string())
+                    .and_then(|response| response.error_for_status().map_err(|e| e.to_string())),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                warn!("Failed to send stall alert to {}: {}", url, e);
+            }
+        }
+        StallAlert::Exit(code) => {
+            if let Err(e) = ShutdownCoordinator::new(state.clone()).shutdown().await {
+                error!("Shutdown after stall failed: {}", e);
+            }
+            process::exit(*code);
+        }
+    }
+}
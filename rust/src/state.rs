@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::database_service::DatabaseService;
+use crate::flush::{FlushPolicy, FlushScheduler};
+use crate::gossip::AttestationStore;
+use crate::http::PeerClients;
+use crate::peers::PeerManager;
+use crate::signing::NodeKey;
+use crate::source::Subscription;
+use crate::status::SyncTracker;
+
+/// State shared between `main`, the transaction handler and the API.
+pub struct AppState {
+    pub peers: PeerManager,
+    pub subscriptions: HashMap<u64, Box<dyn Subscription>>,
+    pub config: Config,
+    pub db: DatabaseService,
+    pub sync: SyncTracker,
+    pub flush_scheduler: FlushScheduler,
+    pub attestations: AttestationStore,
+    pub node_key: NodeKey,
+    pub http: PeerClients,
+}
+
+/// Thread-safe handle to the application state.
+pub type SharedState = Arc<RwLock<AppState>>;
+
+impl AppState {
+    /// Creates a new shared state handle with no active subscriptions.
+    pub fn new_shared(config: Config, peers: Vec<String>, db: DatabaseService, node_key: NodeKey, http: PeerClients) -> SharedState {
+        let mut peers = PeerManager::new(
+            peers,
+            config.peer_quarantine_after,
+            Duration::from_secs(config.peer_quarantine_secs),
+        );
+        peers.set_reputation_threshold(config.peer_min_score, config.peer_min_samples);
+        let (request_policy, peer_policies) = config.request_policies();
+        peers.set_request_policies(request_policy, peer_policies);
+        match db.get_peer_stats() {
+            Ok(stats) => peers.load_stats(stats),
+            Err(e) => warn!("Failed to load peer statistics: {:?}", e),
+        }
+        // The policy is validated when the configuration is loaded
+        let flush_policy = FlushPolicy::from_config(&config).unwrap_or(FlushPolicy::Checkpoint);
+        Arc::new(RwLock::new(AppState {
+            peers,
+            subscriptions: HashMap::new(),
+            config,
+            db,
+            sync: SyncTracker::default(),
+            flush_scheduler: FlushScheduler::new(flush_policy),
+            attestations: AttestationStore::default(),
+            node_key,
+            http,
+        }))
+    }
+}
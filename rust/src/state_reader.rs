@@ -0,0 +1,119 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use num_bigint::BigUint;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::warn;
+
+use crate::database_service::{self, DatabaseService};
+use crate::handler;
+use crate::registry::TransactionContext;
+
+// How often a reader checks whether the VIDA it reads reached its block
+const WAIT_POLL: Duration = Duration::from_millis(50);
+// Waiting longer than this is logged, as it holds up the reading VIDA
+const WAIT_WARNING: Duration = Duration::from_secs(10);
+
+/// Read-only view of another VIDA synced by this node, for handlers whose
+/// actions depend on it, e.g. paying with a token of one VIDA when a balance
+/// on another allows it.
+///
+/// The view shows the other VIDA as of the end of the block before the
+/// transaction's, once its root was checked, and never what that VIDA is
+/// applying now. Every node therefore reads the same values whatever its
+/// progress, as long as every node of the reading VIDA also syncs the one
+/// read. Writes stay with the VIDA's own state, `TransactionContext::db`.
+pub struct StateReader {
+    db: DatabaseService,
+    vida_id: u64,
+    block_number: u64,
+}
+
+impl StateReader {
+    pub(crate) fn open(ctx: &TransactionContext, vida_id: u64) -> Result<StateReader, String> {
+        if vida_id == ctx.vida_id {
+            return Err(format!("VIDA {} reads its own state through the context's database", vida_id));
+        }
+        // A simulation's view holds only its own VIDA and runs with block
+        // processing held, so it reads the live database without waiting
+        let (db, wait) = if ctx.db.vida_ids().contains(&vida_id) {
+            (ctx.db.clone(), true)
+        } else {
+            (DatabaseService::global().map_err(|e| format!("{:?}", e))?, false)
+        };
+        if !db.vida_ids().contains(&vida_id) {
+            return Err(format!("VIDA {} is not synced by this node", vida_id));
+        }
+        let block_number = ctx.block_number.saturating_sub(1);
+        let reader = StateReader { db, vida_id, block_number };
+        reader.await_block(wait)?;
+        Ok(reader)
+    }
+
+    /// VIDA this reader reads.
+    pub fn vida_id(&self) -> u64 {
+        self.vida_id
+    }
+
+    /// Block whose end state this reader shows.
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    /// Returns the balance of a token held by an address.
+    pub fn balance(&self, token_id: u64, address: &[u8]) -> Result<BigUint, String> {
+        database_service::meter_read().map_err(|e| format!("{:?}", e))?;
+        self.db.get_balance_at(self.vida_id, token_id, address, self.block_number)
+            .map_err(|e| format!("Failed to read balance in VIDA {}: {:?}", self.vida_id, e))?
+            .ok_or_else(|| format!("Balance history of VIDA {} does not reach block {}", self.vida_id, self.block_number))
+    }
+
+    /// Returns the root the VIDA's peers agreed on for a block up to this
+    /// reader's, if one was recorded.
+    pub fn block_root(&self, block_number: u64) -> Result<Option<Vec<u8>>, String> {
+        if block_number > self.block_number {
+            return Err(format!("Block {} is past the readable block {} of VIDA {}", block_number, self.block_number, self.vida_id));
+        }
+        database_service::meter_read().map_err(|e| format!("{:?}", e))?;
+        self.db.get_block_root_hash(self.vida_id, block_number)
+            .map_err(|e| format!("Failed to read root of VIDA {}: {:?}", self.vida_id, e))
+    }
+
+    // Waits until the VIDA has checked the reader's block. Giving up after a
+    // timeout would reject transactions on slow nodes only, so a lagging VIDA
+    // holds up its readers instead
+    fn await_block(&self, wait: bool) -> Result<(), String> {
+        let started = Instant::now();
+        let mut warned = false;
+        loop {
+            let checked = self.db.get_last_checked_block(self.vida_id)
+                .map_err(|e| format!("Failed to read progress of VIDA {}: {:?}", self.vida_id, e))?;
+            if checked >= self.block_number {
+                return Ok(());
+            }
+            if !wait || handler::shutting_down() || !sleep(WAIT_POLL) {
+                return Err(format!("VIDA {} has not reached block {}", self.vida_id, self.block_number));
+            }
+            if !warned && started.elapsed() > WAIT_WARNING {
+                warn!("Waiting for VIDA {} to reach block {}, now at {}", self.vida_id, self.block_number, checked);
+                warned = true;
+            }
+        }
+    }
+}
+
+// Handlers run synchronously, possibly on a runtime worker, which is handed
+// over to other tasks while this one sleeps. On a current-thread runtime
+// sleeping would also stop the VIDA being waited for, so nothing is waited
+fn sleep(duration: Duration) -> bool {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| thread::sleep(duration));
+            true
+        }
+        Ok(_) => false,
+        Err(_) => {
+            thread::sleep(duration);
+            true
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use pwr_rs::merkle_tree::MerkleTreeError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+
+use crate::database_service::{DatabaseService, StateSnapshot};
+use crate::http;
+use crate::resync;
+
+/// Most entries served in one chunk, and the chunk size `StateSyncer` asks for.
+pub const MAX_CHUNK_ENTRIES: u64 = 1_000;
+
+// Domain separation of leaf and inner node hashes in the chunk tree
+const LEAF_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+
+/// A run of consecutive entries of a VIDA's state at `block_number`, as served
+/// by `/state/chunks`. Entries are in leaf insertion order, like a full
+/// snapshot. `commitment` is the root of a SHA-256 tree over all
+/// `total_entries` entries, and `proof` holds the node hashes needed to
+/// recompute it from this chunk alone; `root_hash` is the VIDA's Merkle root
+/// at the block, which the assembled state must reproduce.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateChunk {
+    pub vida_id: u64,
+    pub block_number: u64,
+    pub root_hash: String,
+    pub commitment: String,
+    pub total_entries: u64,
+    pub start: u64,
+    pub entries: Vec<(String, String)>,
+    pub proof: Vec<String>,
+}
+
+// Binary SHA-256 tree over state entries; an odd node at the end of a level
+// is carried up unchanged
+struct ChunkTree {
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl ChunkTree {
+    fn build(leaves: Vec<[u8; 32]>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels.last().unwrap()
+                .chunks(2)
+                .map(|pair| if pair.len() == 2 { node_hash(&pair[0], &pair[1]) } else { pair[0] })
+                .collect();
+            levels.push(parents);
+        }
+        ChunkTree { levels }
+    }
+
+    fn root(&self) -> [u8; 32] {
+        self.levels.last().unwrap().first().copied().unwrap_or_default()
+    }
+
+    // Node hashes outside the leaf range [start, end) needed to recompute the
+    // root, lowest level first, left before right
+    fn range_proof(&self, start: usize, end: usize) -> Vec<[u8; 32]> {
+        let mut proof = Vec::new();
+        let (mut low, mut high) = (start, end);
+        for level in &self.levels[..self.levels.len() - 1] {
+            if low % 2 == 1 {
+                proof.push(level[low - 1]);
+                low -= 1;
+            }
+            if high % 2 == 1 && high < level.len() {
+                proof.push(level[high]);
+                high += 1;
+            }
+            low /= 2;
+            high = (high + 1) / 2;
+        }
+        proof
+    }
+}
+
+// State of a VIDA pinned for chunked download, so every chunk of a sync comes
+// from the same block even while the node keeps applying new ones
+struct PinnedState {
+    snapshot: StateSnapshot,
+    tree: ChunkTree,
+}
+
+// Latest pinned state of each VIDA
+static PINNED: OnceLock<Mutex<HashMap<u64, Arc<PinnedState>>>> = OnceLock::new();
+
+/// Serves up to `count` entries of a VIDA's state from entry `start`, with
+/// their proof. Without `block_number` the latest committed state is pinned
+/// for later requests; with it, the chunk comes from that pinned state,
+/// which stays available until a newer one is pinned.
+pub fn serve_chunk(db: &DatabaseService, vida_id: u64, block_number: Option<u64>, start: u64, count: u64) -> Result<StateChunk, MerkleTreeError> {
+    let pinned = pinned_state(db, vida_id, block_number)?;
+    let total = pinned.snapshot.entries.len() as u64;
+    if start > total {
+        return Err(MerkleTreeError::InvalidArgument(format!("Start {} is past the {} state entries", start, total)));
+    }
+    let end = (start + count.min(MAX_CHUNK_ENTRIES)).min(total);
+    let proof = if start < end { pinned.tree.range_proof(start as usize, end as usize) } else { Vec::new() };
+    Ok(StateChunk {
+        vida_id,
+        block_number: pinned.snapshot.block_number,
+        root_hash: pinned.snapshot.root_hash.clone(),
+        commitment: hex::encode(pinned.tree.root()),
+        total_entries: total,
+        start,
+        entries: pinned.snapshot.entries[start as usize..end as usize].to_vec(),
+        proof: proof.iter().map(hex::encode).collect(),
+    })
+}
+
+// Returns the pinned state of the requested block, pinning the latest one if none was asked for
+fn pinned_state(db: &DatabaseService, vida_id: u64, block_number: Option<u64>) -> Result<Arc<PinnedState>, MerkleTreeError> {
+    let last_checked_block = db.get_last_checked_block(vida_id)?;
+    let mut pinned = PINNED.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    let current = pinned.get(&vida_id).cloned();
+    match (block_number, current) {
+        (Some(block), Some(state)) if state.snapshot.block_number == block => Ok(state),
+        (Some(block), _) => Err(MerkleTreeError::InvalidArgument(format!("State of block {} is no longer served", block))),
+        (None, Some(state)) if state.snapshot.block_number == last_checked_block => Ok(state),
+        (None, _) => {
+            // Refused while a block is being applied, like a full export
+            let snapshot = db.export_state(vida_id)?;
+            let leaves = snapshot.entries.iter()
+                .map(|(key, value)| decode_entry(key, value).map(|(key, value)| leaf_hash(&key, &value)))
+                .collect::<Result<Vec<_>, String>>()
+                .map_err(MerkleTreeError::IllegalState)?;
+            let state = Arc::new(PinnedState { tree: ChunkTree::build(leaves), snapshot });
+            pinned.insert(vida_id, state.clone());
+            Ok(state)
+        }
+    }
+}
+
+/// Checks that a chunk's entries sit at its `start` in the state committed
+/// to by `commitment`.
+pub fn verify_chunk(chunk: &StateChunk, commitment: &[u8]) -> Result<(), String> {
+    let total = chunk.total_entries as usize;
+    let (start, end) = (chunk.start as usize, chunk.start as usize + chunk.entries.len());
+    if chunk.entries.is_empty() || end > total {
+        return Err(format!("Chunk at {} does not fit the {} state entries", start, total));
+    }
+    let mut nodes = chunk.entries.iter()
+        .map(|(key, value)| decode_entry(key, value).map(|(key, value)| leaf_hash(&key, &value)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut proof = chunk.proof.iter().map(|node| {
+        hex::decode(node).ok()
+            .and_then(|node| <[u8; 32]>::try_from(node).ok())
+            .ok_or_else(|| "Invalid proof node".to_string())
+    });
+
+    // Mirrors ChunkTree::range_proof, hashing the known range up one level at a time
+    let (mut low, mut high, mut width) = (start, end, total);
+    while width > 1 {
+        if low % 2 == 1 {
+            nodes.insert(0, proof.next().ok_or("Proof too short")??);
+            low -= 1;
+        }
+        if high % 2 == 1 && high < width {
+            nodes.push(proof.next().ok_or("Proof too short")??);
+            high += 1;
+        }
+        nodes = nodes.chunks(2)
+            .map(|pair| if pair.len() == 2 { node_hash(&pair[0], &pair[1]) } else { pair[0] })
+            .collect();
+        low /= 2;
+        high = (high + 1) / 2;
+        width = (width + 1) / 2;
+    }
+    if proof.next().is_some() {
+        return Err("Proof too long".to_string());
+    }
+    if nodes.first().map(|root| root.as_slice()) != Some(commitment) {
+        return Err(format!("Chunk at {} does not match the state commitment", start));
+    }
+    Ok(())
+}
+
+/// Fast sync client: downloads a VIDA's state from a peer in verified
+/// chunks and installs it as a fresh tree generation. The peer's state is
+/// only used if a quorum of peers agrees on its block root; every chunk is
+/// checked against the peer's commitment as it arrives, and the assembled
+/// tree must reproduce the agreed root before it replaces the local state.
+pub struct StateSyncer {
+    client: reqwest::Client,
+    vida_id: u64,
+    peers: Vec<String>,
+}
+
+/// Why a `StateSyncer` could not sync from a peer.
+#[derive(Debug)]
+pub enum StateSyncError {
+    /// The peer does not serve state chunks.
+    Unsupported,
+    Failed(String),
+}
+
+impl StateSyncer {
+    /// Creates a syncer for a VIDA that talks to peers with `client`,
+    /// checking block roots with `peers`.
+    pub fn new(client: reqwest::Client, vida_id: u64, peers: &[String]) -> Self {
+        StateSyncer { client, vida_id, peers: peers.to_vec() }
+    }
+
+    /// Syncs the VIDA's state from `peer` and returns the block it is at.
+    #[instrument(skip(self, db), fields(vida_id = self.vida_id))]
+    pub async fn sync_from(&self, db: &DatabaseService, peer: &str) -> Result<u64, StateSyncError> {
+        let first = self.fetch_chunk(peer, None, 0).await?;
+        let block_number = first.block_number;
+        let block_root = resync::agreed_block_root(&self.client, &self.peers, self.vida_id, block_number).await
+            .ok_or_else(|| StateSyncError::Failed(format!("No peer quorum for root of block {}", block_number)))?;
+        if hex::encode(&block_root) != first.root_hash {
+            return Err(StateSyncError::Failed(format!("Root of block {} does not match the peer quorum", block_number)));
+        }
+        let commitment = hex::decode(&first.commitment)
+            .map_err(|_| StateSyncError::Failed("Invalid state commitment".to_string()))?;
+
+        let total = first.total_entries;
+        let mut entries = Vec::with_capacity(total as usize);
+        let mut chunk = first;
+        loop {
+            if total > 0 {
+                verify_chunk(&chunk, &commitment).map_err(StateSyncError::Failed)?;
+            }
+            entries.extend(chunk.entries);
+            if entries.len() as u64 >= total {
+                break;
+            }
+            chunk = self.fetch_chunk(peer, Some(block_number), entries.len() as u64).await?;
+            if chunk.block_number != block_number || chunk.commitment != hex::encode(&commitment) || chunk.start != entries.len() as u64 {
+                return Err(StateSyncError::Failed("Peer switched state during the sync".to_string()));
+            }
+        }
+
+        let snapshot = StateSnapshot { vida_id: self.vida_id, block_number, root_hash: hex::encode(&block_root), entries };
+        db.import_state(self.vida_id, &snapshot, &block_root)
+            .map_err(|e| StateSyncError::Failed(format!("Assembled state rejected: {:?}", e)))?;
+        info!("Synced {} state entries of block {} from peer {}", total, block_number, peer);
+        Ok(block_number)
+    }
+
+    // Downloads the chunk starting at `start`
+    async fn fetch_chunk(&self, peer: &str, block_number: Option<u64>, start: u64) -> Result<StateChunk, StateSyncError> {
+        let mut path = format!("/state/chunks?vidaId={}&start={}&count={}", self.vida_id, start, MAX_CHUNK_ENTRIES);
+        if let Some(block_number) = block_number {
+            path.push_str(&format!("&blockNumber={}", block_number));
+        }
+        let response = self.client.get(http::peer_url(peer, &path)).timeout(resync::TRANSFER_TIMEOUT).send().await
+            .map_err(|e| StateSyncError::Failed(e.to_string()))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(StateSyncError::Unsupported);
+        }
+        if !response.status().is_success() {
+            return Err(StateSyncError::Failed(format!("HTTP {}", response.status())));
+        }
+        response.json::<StateChunk>().await.map_err(|e| StateSyncError::Failed(e.to_string()))
+    }
+}
+
+// Decodes a hex key/value pair of a snapshot
+fn decode_entry(key: &str, value: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let key = hex::decode(key).map_err(|_| "Invalid state entry key".to_string())?;
+    let value = hex::decode(value).map_err(|_| "Invalid state entry value".to_string())?;
+    Ok((key, value))
+}
+
+// Hashes one state entry; the key length keeps key/value boundaries unambiguous
+fn leaf_hash(key: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_TAG]);
+    hasher.update((key.len() as u32).to_be_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+// Hashes two sibling nodes into their parent
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
@@ -0,0 +1,141 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+// Checkpoints older than this no longer count towards the sync rate
+const RATE_WINDOW: Duration = Duration::from_secs(300);
+// Shortest time between two catch-up progress reports of a VIDA
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Outcome of the most recent root hash validation of a VIDA.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationResult {
+    pub block_number: u64,
+    pub valid: bool,
+    /// Unix time of the validation, in seconds.
+    pub timestamp: u64,
+}
+
+// Recent progress of a single VIDA
+#[derive(Debug, Default)]
+struct VidaProgress {
+    // Committed checkpoints within the rate window, oldest first
+    checkpoints: VecDeque<(Instant, u64)>,
+    last_validation: Option<ValidationResult>,
+    // Whether the VIDA is within the catch-up threshold of the chain head
+    live: bool,
+    last_progress_report: Option<Instant>,
+}
+
+/// How far a VIDA is from the chain head and when it should get there at
+/// the current rate.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatchUpProgress {
+    pub remaining_blocks: u64,
+    pub blocks_per_second: f64,
+    /// Estimated seconds until the head is reached, unknown until blocks
+    /// have been checkpointed for a while.
+    pub eta_secs: Option<u64>,
+}
+
+/// What a checkpoint means for a VIDA catching up with the chain head.
+#[derive(Debug, Clone, Copy)]
+pub enum CatchUpUpdate {
+    /// Still behind; reported at most every `PROGRESS_LOG_INTERVAL`.
+    Progress(CatchUpProgress),
+    /// Just got within the catch-up threshold of the head.
+    CaughtUp,
+}
+
+/// Sync progress observed by the handler, reported by `GET /status`.
+#[derive(Debug, Default)]
+pub struct SyncTracker {
+    latest_chain_block: Option<u64>,
+    vidas: HashMap<u64, VidaProgress>,
+}
+
+impl SyncTracker {
+    /// Records the chain head last reported by the RPC.
+    pub fn record_chain_block(&mut self, block_number: u64) {
+        self.latest_chain_block = Some(block_number);
+    }
+
+    /// Returns the chain head last reported by the RPC, if any.
+    pub fn latest_chain_block(&self) -> Option<u64> {
+        self.latest_chain_block
+    }
+
+    /// Records a committed checkpoint of a VIDA.
+    pub fn record_checkpoint(&mut self, vida_id: u64, block_number: u64) {
+        let now = Instant::now();
+        let checkpoints = &mut self.vidas.entry(vida_id).or_default().checkpoints;
+        checkpoints.push_back((now, block_number));
+        while checkpoints.front().map_or(false, |(at, _)| now.duration_since(*at) > RATE_WINDOW) {
+            checkpoints.pop_front();
+        }
+    }
+
+    /// Records the result of validating a block root against peers.
+    pub fn record_validation(&mut self, vida_id: u64, block_number: u64, valid: bool) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        self.vidas.entry(vida_id).or_default().last_validation = Some(ValidationResult { block_number, valid, timestamp });
+    }
+
+    /// Returns the most recent validation result of a VIDA.
+    pub fn last_validation(&self, vida_id: u64) -> Option<ValidationResult> {
+        self.vidas.get(&vida_id).and_then(|progress| progress.last_validation)
+    }
+
+    /// Returns how far a VIDA at `block_number` is from the last known chain head.
+    pub fn catch_up_progress(&self, vida_id: u64, block_number: u64) -> Option<CatchUpProgress> {
+        let remaining_blocks = self.latest_chain_block?.saturating_sub(block_number);
+        let blocks_per_second = self.blocks_per_minute(vida_id) / 60.0;
+        let eta_secs = (blocks_per_second > 0.0).then(|| (remaining_blocks as f64 / blocks_per_second).ceil() as u64);
+        Some(CatchUpProgress { remaining_blocks, blocks_per_second, eta_secs })
+    }
+
+    /// Returns whether a VIDA got within the catch-up threshold of the chain
+    /// head at its latest checkpoint.
+    pub fn is_live(&self, vida_id: u64) -> bool {
+        self.vidas.get(&vida_id).map_or(false, |progress| progress.live)
+    }
+
+    /// Tracks a VIDA's distance to the chain head after a checkpoint at
+    /// `block_number`: it is live within `threshold` blocks of the head and
+    /// catching up beyond. Returns the progress to report, if any is due.
+    pub fn update_catch_up(&mut self, vida_id: u64, block_number: u64, threshold: u64) -> Option<CatchUpUpdate> {
+        let progress = self.catch_up_progress(vida_id, block_number)?;
+        let vida = self.vidas.entry(vida_id).or_default();
+        if progress.remaining_blocks <= threshold {
+            let caught_up = !vida.live;
+            vida.live = true;
+            return caught_up.then_some(CatchUpUpdate::CaughtUp);
+        }
+        vida.live = false;
+        let now = Instant::now();
+        if vida.last_progress_report.map_or(false, |at| now.duration_since(at) < PROGRESS_LOG_INTERVAL) {
+            return None;
+        }
+        vida.last_progress_report = Some(now);
+        Some(CatchUpUpdate::Progress(progress))
+    }
+
+    /// Returns the blocks checkpointed per minute over the last few minutes.
+    pub fn blocks_per_minute(&self, vida_id: u64) -> f64 {
+        let checkpoints = match self.vidas.get(&vida_id) {
+            Some(progress) => &progress.checkpoints,
+            None => return 0.0,
+        };
+        let (first, last) = match (checkpoints.front(), checkpoints.back()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        let minutes = last.0.duration_since(first.0).as_secs_f64() / 60.0;
+        if minutes <= 0.0 {
+            return 0.0;
+        }
+        last.1.saturating_sub(first.1) as f64 / minutes
+    }
+}
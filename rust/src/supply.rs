@@ -0,0 +1,60 @@
+use serde_json::Value;
+use tracing::info;
+
+use crate::authorization::{self, decode_hex_address};
+use crate::registry::{INSUFFICIENT_FUNDS, NOT_AN_ADMIN, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Built-in `mint` action: a genesis admin creates `amount` new tokens for
+/// `receiver`, increasing the total supply. Guarded by the admin's `nonce`.
+/// An optional `tokenId` mints a token other than the default one.
+pub struct MintHandler;
+
+impl TransactionHandler for MintHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let receiver = authorization::decode_recipient(receiver_hex)?;
+        let admins = ctx.db.get_admins(ctx.vida_id)
+            .map_err(|_| "Failed to read admins".to_string())?;
+        if !admins.contains(&sender) {
+            return Err(format!("{} {}", ctx.sender, NOT_AN_ADMIN));
+        }
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        ctx.db.mint(ctx.vida_id, token_id, &receiver, &amount)
+            .map_err(|_| "Mint operation failed".to_string())?;
+        info!("Minted {} of token {} to {}", amount, token_id, receiver_hex);
+        Ok(())
+    }
+}
+
+/// Built-in `burn` action: the sender destroys `amount` of its own tokens,
+/// decreasing the total supply. Guarded by the sender's `nonce`; an optional
+/// `tokenId` burns a token other than the default one.
+pub struct BurnHandler;
+
+impl TransactionHandler for BurnHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+        let sender = decode_hex_address(ctx.sender)?;
+
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        match ctx.db.burn(ctx.vida_id, token_id, &sender, &amount) {
+            Ok(true) => {
+                info!("Burned {} of token {} from {}", amount, token_id, ctx.sender);
+                Ok(())
+            }
+            Ok(false) => Err(format!("{}: cannot burn {} from {}", INSUFFICIENT_FUNDS, amount, ctx.sender)),
+            Err(_) => Err("Burn operation failed".to_string()),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use hex;
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use tracing::{error, info};
+
+use crate::address::ZERO_ADDRESS;
+use crate::amount;
+use crate::authorization;
+use crate::payload;
+use crate::database_service::{DatabaseService, Direction, TransactionRecord, DEFAULT_TOKEN};
+use crate::registry::{INSUFFICIENT_FUNDS, TransactionContext, TransactionHandler};
+
+// Longest `memo` or `reference` accepted in a transfer payload, in bytes
+const MAX_NOTE_LENGTH: usize = 256;
+
+/// Built-in `transfer` action: moves `amount` to `receiver`, guarded by the
+/// sender's `nonce`. Funds come from the transaction sender, or from the
+/// optional `from` address if the sender is an authorized delegate of it.
+/// An optional `memo` and `reference` are kept in both parties' history, and
+/// an optional `tokenId` selects the token moved instead of the default one.
+/// Transfers from or to an account frozen by an admin are rejected; transfers
+/// to the zero address burn the amount, without a fee.
+pub struct TransferHandler;
+
+impl TransactionHandler for TransferHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        handle_transfer(ctx.db, ctx.vida_id, ctx.payload, ctx.sender, ctx.block_number)
+    }
+}
+
+// Executes a token transfer described by the given JSON payload
+fn handle_transfer(db: &DatabaseService, vida_id: u64, json_data: &Map<String, Value>, sender_hex: &str, block_number: u64) -> Result<(), String> {
+    // Extract amount and receiver from JSON
+    let amount = parse_amount(json_data)?;
+    
+    let receiver_hex = match json_data.get("receiver")
+        .and_then(|val| val.as_str()) {
+        Some(r) => r,
+        None => return Err("Missing receiver".to_string())
+    };
+
+    let nonce = parse_nonce(json_data)?;
+    let note = parse_note(json_data)?;
+    let token_id = parse_token_id(json_data)?;
+    
+    // Decode hex addresses
+    let sender = authorization::decode_hex_address(sender_hex)?;
+    let receiver = authorization::decode_hex_address(receiver_hex)?;
+
+    // The address funds move from must be the verified sender or delegate to it
+    let owner_hex = json_data.get("from")
+        .and_then(|val| val.as_str())
+        .unwrap_or(sender_hex);
+    let owner = authorization::decode_hex_address(owner_hex)?;
+    if owner == ZERO_ADDRESS {
+        return Err(format!("Invalid sender: {} is the zero address", owner_hex));
+    }
+    authorization::authorize_spend(db, vida_id, &sender, &owner)?;
+    authorization::check_not_frozen(db, vida_id, &[&owner, &receiver])?;
+
+    consume_nonce(db, vida_id, &sender, sender_hex, nonce)?;
+
+    // Transfers to the zero address burn the tokens
+    if receiver == ZERO_ADDRESS {
+        return match db.burn(vida_id, token_id, &owner, &amount) {
+            Ok(true) => {
+                info!("Transfer to the zero address burned {} of token {} from {}", amount, token_id, owner_hex);
+                Ok(())
+            }
+            Ok(false) => Err(format!("{}: cannot burn {} from {}", INSUFFICIENT_FUNDS, amount, owner_hex)),
+            Err(_) => Err("Burn operation failed".to_string()),
+        };
+    }
+    
+    // Execute transfer
+    match db.transfer_with_fee(vida_id, token_id, &owner, &receiver, &amount) {
+        Ok(true) => {
+            info!("Transfer succeeded: {} of token {} from {} to {}", amount, token_id, owner_hex, receiver_hex);
+            record_transfer(db, vida_id, token_id, &owner, &receiver, &amount, &note, block_number);
+            Ok(())
+        }
+        Ok(false) => {
+            Err(format!("{}: {} from {} to {}", INSUFFICIENT_FUNDS, amount, owner_hex, receiver_hex))
+        }
+        Err(_) => Err("Transfer operation failed".to_string()),
+    }
+}
+
+// Reads the `amount` field, given either as a decimal string or a number
+pub(crate) fn parse_amount(json_data: &Map<String, Value>) -> Result<BigUint, String> {
+    let amount = json_data.get("amount")
+        .and_then(|val| {
+            if let Some(s) = val.as_str() {
+                amount::parse(s, amount::decimals()).ok()
+            } else {
+                val.as_u64().map(BigUint::from)
+            }
+        })
+        .ok_or_else(|| "Invalid or missing amount".to_string())?;
+    payload::check_amount(&amount)?;
+    Ok(amount)
+}
+
+// Reads the `nonce` field, given either as a decimal string or a number
+pub(crate) fn parse_nonce(json_data: &Map<String, Value>) -> Result<u64, String> {
+    json_data.get("nonce")
+        .and_then(|val| {
+            if let Some(s) = val.as_str() {
+                s.parse::<u64>().ok()
+            } else {
+                val.as_u64()
+            }
+        })
+        .ok_or_else(|| "Invalid or missing nonce".to_string())
+}
+
+// Reads the optional `tokenId` field, given either as a decimal string or a number
+pub(crate) fn parse_token_id(json_data: &Map<String, Value>) -> Result<u64, String> {
+    match json_data.get("tokenId") {
+        None | Some(Value::Null) => Ok(DEFAULT_TOKEN),
+        Some(Value::String(s)) => s.parse::<u64>().map_err(|_| "Invalid tokenId".to_string()),
+        Some(val) => val.as_u64().ok_or_else(|| "Invalid tokenId".to_string()),
+    }
+}
+
+/// Optional reconciliation fields attached to a transfer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransferNote {
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+}
+
+// Reads the optional `memo` and `reference` strings, rejecting overlong ones
+pub(crate) fn parse_note(json_data: &Map<String, Value>) -> Result<TransferNote, String> {
+    let field = |name: &str| -> Result<Option<String>, String> {
+        match json_data.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(text)) if text.len() <= MAX_NOTE_LENGTH => Ok(Some(text.clone())),
+            Some(Value::String(_)) => Err(format!("{} exceeds {} bytes", name, MAX_NOTE_LENGTH)),
+            Some(_) => Err(format!("Invalid {}", name)),
+        }
+    };
+    Ok(TransferNote { memo: field("memo")?, reference: field("reference")? })
+}
+
+// Rejects stale or duplicate nonces so replayed payloads cannot move funds twice,
+// then advances the sender's nonce
+pub(crate) fn consume_nonce(db: &DatabaseService, vida_id: u64, sender: &[u8], sender_hex: &str, nonce: u64) -> Result<(), String> {
+    match db.get_nonce(vida_id, sender) {
+        Ok(expected) if nonce == expected => {}
+        Ok(expected) => {
+            return Err(format!("Nonce {} from {} does not match expected {}", nonce, sender_hex, expected));
+        }
+        Err(_) => return Err(format!("Failed to read nonce for {}", sender_hex)),
+    }
+    if db.set_nonce(vida_id, sender, nonce + 1).is_err() {
+        return Err(format!("Failed to update nonce for {}", sender_hex));
+    }
+    Ok(())
+}
+
+// Adds a completed transfer to the history of both parties
+pub(crate) fn record_transfer(db: &DatabaseService, vida_id: u64, token_id: u64, sender: &[u8], receiver: &[u8], amount: &BigUint, note: &TransferNote, block_number: u64) {
+    // History records only name tokens other than the default one
+    let token_id = Some(token_id).filter(|token| *token != DEFAULT_TOKEN);
+    let outgoing = TransactionRecord {
+        block_number,
+        counterparty: format!("0x{}", hex::encode(receiver)),
+        amount: amount.to_string(),
+        direction: Direction::Outgoing,
+        token_id,
+        memo: note.memo.clone(),
+        reference: note.reference.clone(),
+    };
+    let incoming = TransactionRecord {
+        block_number,
+        counterparty: format!("0x{}", hex::encode(sender)),
+        amount: amount.to_string(),
+        direction: Direction::Incoming,
+        token_id,
+        memo: note.memo.clone(),
+        reference: note.reference.clone(),
+    };
+    
+    if db.add_transaction_record(vida_id, sender, &outgoing).is_err()
+        || db.add_transaction_record(vida_id, receiver, &incoming).is_err()
+    {
+        error!("Failed to record transaction history for block {}", block_number);
+    }
+}
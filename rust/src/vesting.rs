@@ -0,0 +1,82 @@
+use serde_json::Value;
+use tracing::{error, info};
+
+use crate::authorization::{self, decode_hex_address};
+use crate::database_service::{DatabaseService, VestingSchedule};
+use crate::registry::{INSUFFICIENT_FUNDS, TransactionContext, TransactionHandler};
+use crate::transfer;
+
+/// Built-in `vest` action: the sender locks `amount` for `receiver`, released
+/// linearly from `startBlock` (default: the current block) to `endBlock`.
+/// With `cliffBlock`, nothing is released before that block; a cliff equal
+/// to `endBlock` releases everything at once. Matured tranches are paid out
+/// at checkpoints and listed by `/vesting`. An optional `tokenId` vests a
+/// token other than the default one.
+pub struct VestHandler;
+
+impl TransactionHandler for VestHandler {
+    fn handle(&self, ctx: &TransactionContext) -> Result<(), String> {
+        let receiver_hex = ctx.payload.get("receiver")
+            .and_then(Value::as_str)
+            .ok_or("Missing receiver")?;
+        let amount = transfer::parse_amount(ctx.payload)?;
+        let nonce = transfer::parse_nonce(ctx.payload)?;
+        let token_id = transfer::parse_token_id(ctx.payload)?;
+        let start_block = parse_block(ctx, "startBlock")?.unwrap_or(ctx.block_number);
+        let end_block = parse_block(ctx, "endBlock")?.ok_or("Invalid or missing endBlock")?;
+        let cliff_block = parse_block(ctx, "cliffBlock")?.unwrap_or(start_block);
+
+        if !(start_block <= cliff_block && cliff_block <= end_block) {
+            return Err(format!(
+                "Blocks must satisfy startBlock <= cliffBlock <= endBlock, got {}, {}, {}",
+                start_block, cliff_block, end_block
+            ));
+        }
+        if end_block <= ctx.block_number {
+            return Err(format!("End block {} is not after block {}", end_block, ctx.block_number));
+        }
+
+        let sender = decode_hex_address(ctx.sender)?;
+        let receiver = authorization::decode_recipient(receiver_hex)?;
+        transfer::consume_nonce(ctx.db, ctx.vida_id, &sender, ctx.sender, nonce)?;
+        let schedule = VestingSchedule {
+            sender: hex::encode(&sender),
+            beneficiary: hex::encode(&receiver),
+            amount: amount.to_string(),
+            released: "0".to_string(),
+            token_id,
+            start_block,
+            cliff_block,
+            end_block,
+        };
+        match ctx.db.lock_vesting(ctx.vida_id, &schedule) {
+            Ok(Some(id)) => {
+                info!("Vesting schedule {} locks {} from {} for {} until block {}", id, amount, ctx.sender, receiver_hex, end_block);
+                Ok(())
+            }
+            Ok(None) => Err(format!("{}: cannot vest {} from {}", INSUFFICIENT_FUNDS, amount, ctx.sender)),
+            Err(_) => Err("Vesting operation failed".to_string()),
+        }
+    }
+}
+
+/// Pays out the tranches matured by `block_number`. Called for each
+/// checkpoint inside its write batch, like escrow refunds.
+pub fn release_matured(db: &DatabaseService, vida_id: u64, block_number: u64) {
+    match db.release_vested(vida_id, block_number) {
+        Ok(released) => {
+            for (id, amount) in released {
+                info!("Vesting schedule {} released {} at block {}", id, amount, block_number);
+            }
+        }
+        Err(e) => error!("Failed to release vested tranches: {:?}", e),
+    }
+}
+
+// Reads an optional block number field
+fn parse_block(ctx: &TransactionContext, field: &str) -> Result<Option<u64>, String> {
+    match ctx.payload.get(field) {
+        None => Ok(None),
+        Some(val) => val.as_u64().map(Some).ok_or_else(|| format!("Invalid {}", field)),
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
+
+use crate::address;
+use crate::database_service::{ChangeRecord, DatabaseService, StateChange, DEFAULT_TOKEN};
+use crate::http;
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>` keyed by the
+/// webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+// Delivery attempts per notification, and the delay before the first retry,
+// doubled after every failed attempt
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// URL notified of the balance changes of `addresses` (lowercase hex without
+/// a 0x prefix) once the blocks changing them are committed. Registered
+/// through the admin API and kept in the node store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: u64,
+    pub url: String,
+    pub addresses: Vec<String>,
+    pub secret: String,
+}
+
+/// Registers a webhook posting to `url` the balance changes of `addresses`,
+/// signed with `secret`, and returns it with its assigned id.
+pub fn register(db: &DatabaseService, url: &str, addresses: &[String], secret: &str) -> Result<Webhook, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("Invalid webhook URL: {}", url));
+    }
+    if addresses.is_empty() {
+        return Err("A webhook must watch at least one address".to_string());
+    }
+    if secret.is_empty() {
+        return Err("A webhook needs a signing secret".to_string());
+    }
+    let addresses = addresses.iter()
+        .map(|address| address::parse_address(address).map(hex::encode))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut webhooks = db.get_webhooks().map_err(|e| format!("{:?}", e))?;
+    let webhook = Webhook {
+        id: webhooks.iter().map(|webhook| webhook.id).max().map_or(1, |id| id + 1),
+        url: url.to_string(),
+        addresses,
+        secret: secret.to_string(),
+    };
+    webhooks.push(webhook.clone());
+    db.set_webhooks(&webhooks).map_err(|e| format!("{:?}", e))?;
+    Ok(webhook)
+}
+
+/// Removes a webhook, returning false if no webhook has that id.
+pub fn remove(db: &DatabaseService, id: u64) -> Result<bool, String> {
+    let mut webhooks = db.get_webhooks().map_err(|e| format!("{:?}", e))?;
+    let count = webhooks.len();
+    webhooks.retain(|webhook| webhook.id != id);
+    if webhooks.len() == count {
+        return Ok(false);
+    }
+    db.set_webhooks(&webhooks).map_err(|e| format!("{:?}", e))?;
+    Ok(true)
+}
+
+/// Posts the balance changes committed in blocks `first_block` to
+/// `block_number` to every webhook watching an address they touch. Each
+/// webhook gets one notification listing its changed addresses with their
+/// current default token balance and the changes with their block, position
+/// and transaction hash. Deliveries run in the background and are retried
+/// with backoff until they succeed or run out of attempts.
+pub fn notify(db: &DatabaseService, vida_id: u64, first_block: u64, block_number: u64) {
+    let webhooks = match db.get_webhooks() {
+        Ok(webhooks) if !webhooks.is_empty() => webhooks,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to read webhooks: {:?}", e);
+            return;
+        }
+    };
+
+    let mut changes = Vec::new();
+    for block in first_block..=block_number {
+        match db.get_changes(vida_id, block) {
+            Ok(records) => changes.extend(records),
+            Err(e) => warn!("Failed to read changes of block {} for webhooks: {:?}", block, e),
+        }
+    }
+    if changes.is_empty() {
+        return;
+    }
+
+    for webhook in webhooks {
+        let mut by_address: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+        for record in &changes {
+            for address in touched_addresses(&record.change) {
+                if webhook.addresses.iter().any(|watched| watched == address) {
+                    by_address.entry(address).or_default().push(change_entry(db, vida_id, record));
+                }
+            }
+        }
+        if by_address.is_empty() {
+            continue;
+        }
+
+        let addresses: Vec<Value> = by_address.into_iter()
+            .map(|(address, changes)| {
+                let balance = hex::decode(address).ok()
+                    .and_then(|address| db.get_balance(vida_id, DEFAULT_TOKEN, &address).ok())
+                    .unwrap_or_default();
+                json!({
+                    "address": format!("0x{}", address),
+                    "balance": balance.to_string(),
+                    "changes": changes
+                })
+            })
+            .collect();
+        let body = json!({
+            "webhookId": webhook.id,
+            "vidaId": vida_id,
+            "blockNumber": block_number,
+            "addresses": addresses
+        }).to_string();
+        tokio::spawn(deliver(webhook, body));
+    }
+}
+
+/// Returns the hex HMAC-SHA256 of `body` keyed by `secret`, as sent in
+/// `SIGNATURE_HEADER` without the `sha256=` prefix.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Addresses whose balance a change moved
+fn touched_addresses(change: &StateChange) -> Vec<&str> {
+    match change {
+        StateChange::Transfer { from, to, .. } => vec![from.as_str(), to.as_str()],
+        StateChange::Mint { to, .. } => vec![to.as_str()],
+        StateChange::Burn { from, .. } => vec![from.as_str()],
+        StateChange::Fee { from, collector, .. } => vec![from.as_str(), collector.as_str()],
+        _ => Vec::new(),
+    }
+}
+
+// Renders a change with the hash of the transaction that applied it, if known
+fn change_entry(db: &DatabaseService, vida_id: u64, record: &ChangeRecord) -> Value {
+    let mut entry = serde_json::to_value(record).unwrap_or_default();
+    if let Ok(Some(receipt)) = db.get_receipt_at(vida_id, record.block_number, record.tx_index) {
+        entry["txHash"] = json!(receipt.tx_hash);
+    }
+    entry
+}
+
+// Posts a signed notification, retrying failed attempts with exponential backoff
+async fn deliver(webhook: Webhook, body: String) {
+    let client = match http::client(Duration::from_secs(10)) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let signature = format!("sha256={}", sign(&webhook.secret, body.as_bytes()));
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let response = client.post(&webhook.url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                debug!("Delivered notification to webhook {}", webhook.id);
+                return;
+            }
+            Ok(response) => warn!("Webhook {} answered HTTP {} (attempt {})", webhook.id, response.status(), attempt),
+            Err(e) => warn!("Webhook {} unreachable (attempt {}): {}", webhook.id, attempt, e),
+        }
+        if attempt < MAX_ATTEMPTS {
+            sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    error!("Giving up on notification to webhook {} after {} attempts", webhook.id, MAX_ATTEMPTS);
+}
@@ -0,0 +1,56 @@
+//! Checks the paging that the list endpoints of the HTTP API share.
+
+use std::collections::HashMap;
+
+use pwr_stateful_vida::api::{ListQuery, Order};
+
+fn query(params: &[(&str, &str)], default_order: Order) -> ListQuery {
+    let params: HashMap<String, String> = params.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    ListQuery::parse(&params, default_order).unwrap()
+}
+
+// Receipts keyed by block and index within it
+fn receipts(blocks: u64) -> Vec<(u64, u32)> {
+    (1..=blocks).flat_map(|block| (0..2).map(move |index| (block, index))).collect()
+}
+
+fn next_page(items: Vec<(u64, u32)>, params: &[(&str, &str)], cursor: Option<&str>) -> (Vec<(u64, u32)>, Option<String>) {
+    let mut params: Vec<(&str, &str)> = params.to_vec();
+    if let Some(cursor) = cursor {
+        params.push(("cursor", cursor));
+    }
+    let page = query(&params, Order::Asc).page(items, |item| *item, |item| Some(item.0)).unwrap();
+    (page.items, page.next_cursor)
+}
+
+#[test]
+fn cursors_page_through_a_list_that_grows() {
+    let params = [("limit", "3")];
+    let (first, cursor) = next_page(receipts(3), &params, None);
+    assert_eq!(first, vec![(1, 0), (1, 1), (2, 0)]);
+
+    // Items added after the first page was read do not shift the next one
+    let (second, cursor) = next_page(receipts(4), &params, cursor.as_deref());
+    assert_eq!(second, vec![(2, 1), (3, 0), (3, 1)]);
+    let (third, cursor) = next_page(receipts(4), &params, cursor.as_deref());
+    assert_eq!(third, vec![(4, 0), (4, 1)]);
+    assert_eq!(cursor, None, "a short page is the last one");
+}
+
+#[test]
+fn lists_filter_by_block_range_and_sort_either_way() {
+    let range = [("fromBlock", "2"), ("toBlock", "3"), ("order", "desc"), ("limit", "3")];
+    let (first, cursor) = next_page(receipts(5), &range, None);
+    assert_eq!(first, vec![(3, 1), (3, 0), (2, 1)]);
+    let (second, _) = next_page(receipts(5), &range, cursor.as_deref());
+    assert_eq!(second, vec![(2, 0)]);
+
+    let descending = query(&[], Order::Desc).page(vec![1u64, 3, 2], |item| *item, |_| None).unwrap();
+    assert_eq!(descending.items, vec![3, 2, 1]);
+
+    let invalid: [&[(&str, &str)]; 4] = [&[("limit", "0")], &[("order", "up")], &[("fromBlock", "3"), ("toBlock", "2")], &[("cursor", "zz")]];
+    for params in invalid {
+        let params: HashMap<String, String> = params.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        assert!(ListQuery::parse(&params, Order::Asc).is_err(), "{:?} accepted", params);
+    }
+}
@@ -0,0 +1,234 @@
+//! Runs two in-process nodes, each with its own temporary database, over the
+//! same synthetic transaction stream and checks that they compute the same
+//! root hash after every block. A difference means a state transition depends
+//! on something other than the transactions and their order (HashMap
+//! iteration, floats, caches, the clock) and would fork real deployments.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use pwr_stateful_vida::database_service::DatabaseService;
+use pwr_stateful_vida::genesis::Genesis;
+use pwr_stateful_vida::handler::{apply_transaction, ApplyError};
+use serde_json::{json, Value};
+
+const VIDA_ID: u64 = 7;
+const BLOCKS: u64 = 60;
+const ACCOUNTS: u64 = 8;
+const INITIAL_BALANCE: u64 = 1_000_000;
+
+// Transaction as delivered by the RPC
+#[derive(Clone)]
+struct Transaction {
+    hash: String,
+    sender: String,
+    data: Vec<u8>,
+}
+
+// Stands in for the PWR RPC the nodes subscribe to
+trait TransactionSource {
+    fn latest_block(&self) -> u64;
+    fn transactions(&self, block_number: u64) -> Vec<Transaction>;
+}
+
+// Serves a pre-generated stream of transactions per block
+struct MockRpc {
+    blocks: BTreeMap<u64, Vec<Transaction>>,
+}
+
+impl TransactionSource for MockRpc {
+    fn latest_block(&self) -> u64 {
+        self.blocks.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn transactions(&self, block_number: u64) -> Vec<Transaction> {
+        self.blocks.get(&block_number).cloned().unwrap_or_default()
+    }
+}
+
+// xorshift64, so the stream is the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+fn address(account: u64) -> String {
+    format!("0x{:040x}", account + 1)
+}
+
+// Account 0 is the genesis admin, the only one allowed to mint
+fn genesis() -> Genesis {
+    let allocations: Vec<Value> = (0..ACCOUNTS)
+        .map(|account| json!({ "address": address(account), "balance": INITIAL_BALANCE.to_string() }))
+        .collect();
+    serde_json::from_value(json!({ "allocations": allocations, "admins": [address(0)] })).unwrap()
+}
+
+// Builds a stream mixing every built-in action with transactions that must be
+// rejected: malformed payloads, stale nonces and unknown actions. Amounts stay
+// far below the balances so valid transactions never run out of funds.
+fn synthetic_stream(seed: u64) -> MockRpc {
+    let mut rng = Rng(seed);
+    let mut nonces = vec![0u64; ACCOUNTS as usize];
+    let mut allowances: BTreeMap<(u64, u64), u64> = BTreeMap::new();
+    let mut blocks = BTreeMap::new();
+    let mut counter = 0u64;
+
+    for block_number in 1..=BLOCKS {
+        let mut transactions = Vec::new();
+        for _ in 0..rng.below(8) {
+            let sender = rng.below(ACCOUNTS);
+            let other = (sender + 1 + rng.below(ACCOUNTS - 1)) % ACCOUNTS;
+            let amount = 1 + rng.below(100);
+            let nonce = nonces[sender as usize];
+
+            counter += 1;
+            let hash = format!("0x{:064x}", counter);
+            if counter % 17 == 0 {
+                transactions.push(Transaction { hash, sender: address(sender), data: b"{not json".to_vec() });
+                continue;
+            }
+
+            // Valid transactions consume what they spend, so later ones stay valid
+            let (sender, payload) = match rng.below(10) {
+                0..=2 => {
+                    nonces[sender as usize] += 1;
+                    (sender, json!({ "action": "transfer", "receiver": address(other), "amount": amount.to_string(), "nonce": nonce, "memo": format!("payment {}", counter) }))
+                }
+                3 => {
+                    nonces[0] += 1;
+                    (0, json!({ "action": "mint", "receiver": address(other), "amount": amount, "nonce": nonces[0] - 1, "tokenId": rng.below(3) }))
+                }
+                4 => {
+                    nonces[sender as usize] += 1;
+                    (sender, json!({ "action": "burn", "amount": amount, "nonce": nonce }))
+                }
+                5 => {
+                    nonces[sender as usize] += 1;
+                    allowances.insert((sender, other), amount * 10);
+                    (sender, json!({ "action": "approve", "spender": address(other), "amount": amount * 10, "nonce": nonce }))
+                }
+                6 => match allowances.iter_mut().find(|((_, spender), allowance)| *spender == sender && **allowance > 0) {
+                    Some((&(owner, _), allowance)) => {
+                        let spent = amount.min(*allowance);
+                        *allowance -= spent;
+                        nonces[sender as usize] += 1;
+                        (sender, json!({ "action": "transferFrom", "from": address(owner), "receiver": address(other), "amount": spent, "nonce": nonce }))
+                    }
+                    // Nothing approved: rejected before the nonce is consumed
+                    None => (sender, json!({ "action": "transferFrom", "from": address(other), "receiver": address(other), "amount": amount, "nonce": nonce })),
+                },
+                7 => (sender, json!({ "action": "delegate", "spender": address(other), "enabled": rng.below(2) == 0 })),
+                8 => (sender, json!({ "action": "transfer", "receiver": address(other), "amount": amount, "nonce": nonce + 1 + rng.below(3) })),
+                _ => (sender, json!({ "action": "unknown", "nonce": nonce })),
+            };
+
+            transactions.push(Transaction {
+                hash,
+                sender: address(sender),
+                data: serde_json::to_vec(&payload).unwrap(),
+            });
+        }
+        blocks.insert(block_number, transactions);
+    }
+    MockRpc { blocks }
+}
+
+// A node reduced to its state: a database in its own temporary directory
+struct Node {
+    db: DatabaseService,
+    dir: PathBuf,
+    actions: Vec<String>,
+}
+
+impl Node {
+    fn start(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("pwr-determinism-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        let db = DatabaseService::open(&dir, "state", &[VIDA_ID]).unwrap();
+        genesis().apply(&db, VIDA_ID).unwrap();
+        let actions = ["transfer", "mint", "burn", "approve", "transferFrom", "delegate"]
+            .iter()
+            .map(|action| action.to_string())
+            .collect();
+        Node { db, dir, actions }
+    }
+
+    // Applies every block of the source and returns the root after each one,
+    // along with how many transactions were applied and rejected
+    fn sync(&self, rpc: &dyn TransactionSource, redeliver: bool) -> (Vec<Vec<u8>>, usize, usize) {
+        let (mut roots, mut applied, mut rejected) = (Vec::new(), 0, 0);
+        for block_number in 1..=rpc.latest_block() {
+            let mut transactions = rpc.transactions(block_number);
+            if redeliver {
+                // A resumed subscription delivers the block again; each transaction must apply once
+                transactions.extend(rpc.transactions(block_number));
+            }
+            for txn in transactions {
+                match apply_transaction(&self.db, &self.actions, VIDA_ID, block_number, &txn.hash, &txn.sender, &txn.data) {
+                    Ok(()) => applied += 1,
+                    Err(ApplyError::Rejected(_)) => rejected += 1,
+                    Err(ApplyError::Storage(reason)) => panic!("block {}: {}", block_number, reason),
+                }
+            }
+            if self.db.has_open_batch(VIDA_ID).unwrap() {
+                self.db.commit_block(VIDA_ID, block_number).unwrap();
+            }
+            roots.push(self.db.get_root_hash(VIDA_ID).unwrap().unwrap_or_default());
+        }
+        (roots, applied, rejected)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn nodes_agree_on_every_block_root() {
+    let rpc = synthetic_stream(0x9e37_79b9_7f4a_7c15);
+    let first = Node::start("first");
+    let second = Node::start("second");
+    // Cached balances must never change what a node computes
+    second.db.set_balance_cache_capacity(0);
+
+    let (first_roots, applied, rejected) = first.sync(&rpc, false);
+    let (second_roots, _, _) = second.sync(&rpc, true);
+
+    assert!(applied > 0 && rejected > 0, "stream should both apply and reject transactions");
+    assert_eq!(first_roots.len(), second_roots.len());
+    for (index, (first_root, second_root)) in first_roots.iter().zip(&second_roots).enumerate() {
+        assert!(!first_root.is_empty(), "block {} has no root", index + 1);
+        assert_eq!(
+            hex::encode(first_root),
+            hex::encode(second_root),
+            "nodes diverged at block {}",
+            index + 1
+        );
+    }
+}
+
+#[test]
+fn different_streams_produce_different_roots() {
+    let first = Node::start("seed-a");
+    let second = Node::start("seed-b");
+
+    let (first_roots, _, _) = first.sync(&synthetic_stream(1), false);
+    let (second_roots, _, _) = second.sync(&synthetic_stream(2), false);
+
+    assert_ne!(first_roots.last(), second_roots.last());
+}
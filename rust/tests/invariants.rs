@@ -0,0 +1,190 @@
+//! Drives `DatabaseService` with random sequences of transfers, mints, burns,
+//! commits and reverts, including ones that must fail, and checks after every
+//! step that balances match a simple model, that no operation creates or
+//! destroys tokens beyond what it mints or burns, and that a revert restores
+//! the root of the last committed block exactly.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use pwr_stateful_vida::database_service::{DatabaseService, DEFAULT_TOKEN};
+
+const VIDA_ID: u64 = 7;
+const ACCOUNTS: u8 = 5;
+const INITIAL_BALANCE: u64 = 1_000;
+
+// Each database gets its own directory, as proptest runs many cases per test
+static DATABASES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+enum Op {
+    Transfer { from: u8, to: u8, amount: u64 },
+    Mint { to: u8, amount: u64 },
+    Burn { from: u8, amount: u64 },
+    // Always rejected: the zero address cannot receive transfers
+    TransferToZero { from: u8, amount: u64 },
+    Commit,
+    Revert,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    // Amounts go past the balances so insufficient funds come up often
+    let account = 0..ACCOUNTS;
+    let amount = 0..INITIAL_BALANCE * 2;
+    prop_oneof![
+        4 => (account.clone(), account.clone(), amount.clone()).prop_map(|(from, to, amount)| Op::Transfer { from, to, amount }),
+        1 => (account.clone(), amount.clone()).prop_map(|(to, amount)| Op::Mint { to, amount }),
+        2 => (account.clone(), amount.clone()).prop_map(|(from, amount)| Op::Burn { from, amount }),
+        1 => (account, amount).prop_map(|(from, amount)| Op::TransferToZero { from, amount }),
+        1 => Just(Op::Commit),
+        1 => Just(Op::Revert),
+    ]
+}
+
+fn address(account: u8) -> Vec<u8> {
+    let mut address = vec![0u8; 20];
+    address[19] = account + 1;
+    address
+}
+
+// A database in its own temporary directory, every account funded in block 1
+struct Node {
+    db: DatabaseService,
+    dir: PathBuf,
+}
+
+impl Node {
+    fn start() -> Self {
+        let id = DATABASES.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("pwr-invariants-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&dir);
+        let db = DatabaseService::open(&dir, "state", &[VIDA_ID]).unwrap();
+        db.begin_block(VIDA_ID, 1).unwrap();
+        for account in 0..ACCOUNTS {
+            db.mint(VIDA_ID, DEFAULT_TOKEN, &address(account), &BigUint::from(INITIAL_BALANCE)).unwrap();
+        }
+        db.commit_block(VIDA_ID, 1).unwrap();
+        Node { db, dir }
+    }
+
+    fn balance(&self, account: u8) -> u64 {
+        let balance = self.db.get_balance(VIDA_ID, DEFAULT_TOKEN, &address(account)).unwrap();
+        u64::try_from(balance).unwrap()
+    }
+
+    fn root(&self) -> Vec<u8> {
+        self.db.get_root_hash(VIDA_ID).unwrap().unwrap_or_default()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Expected balances and supply, with the state of the last committed block
+#[derive(Clone, PartialEq)]
+struct Model {
+    balances: BTreeMap<u8, u64>,
+    supply: u64,
+}
+
+// Applies the operations to a fresh node, checking the invariants after each
+// one, and returns the root the node ends with
+fn run(ops: &[Op]) -> Result<Vec<u8>, TestCaseError> {
+    let node = Node::start();
+    let mut model = Model {
+        balances: (0..ACCOUNTS).map(|account| (account, INITIAL_BALANCE)).collect(),
+        supply: INITIAL_BALANCE * u64::from(ACCOUNTS),
+    };
+    let mut committed = (model.clone(), node.root());
+    let mut block_number = 2;
+
+    for op in ops {
+        if !matches!(op, Op::Commit | Op::Revert) {
+            node.db.begin_block(VIDA_ID, block_number).unwrap();
+        }
+        match *op {
+            Op::Transfer { from, to, amount } => {
+                let moved = node.db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(from), &address(to), &BigUint::from(amount)).unwrap();
+                prop_assert_eq!(moved, amount <= model.balances[&from], "transfer of {} from {}", amount, from);
+                if moved {
+                    *model.balances.get_mut(&from).unwrap() -= amount;
+                    *model.balances.get_mut(&to).unwrap() += amount;
+                }
+            }
+            Op::Mint { to, amount } => {
+                node.db.mint(VIDA_ID, DEFAULT_TOKEN, &address(to), &BigUint::from(amount)).unwrap();
+                *model.balances.get_mut(&to).unwrap() += amount;
+                model.supply += amount;
+            }
+            Op::Burn { from, amount } => {
+                let burned = node.db.burn(VIDA_ID, DEFAULT_TOKEN, &address(from), &BigUint::from(amount)).unwrap();
+                prop_assert_eq!(burned, amount <= model.balances[&from], "burn of {} from {}", amount, from);
+                if burned {
+                    *model.balances.get_mut(&from).unwrap() -= amount;
+                    model.supply -= amount;
+                }
+            }
+            Op::TransferToZero { from, amount } => {
+                let root = node.root();
+                let result = node.db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(from), &[0u8; 20], &BigUint::from(amount));
+                prop_assert!(result.is_err(), "transfer to the zero address was accepted");
+                prop_assert_eq!(node.root(), root, "rejected transfer changed the state");
+            }
+            Op::Commit => {
+                if node.db.has_open_batch(VIDA_ID).unwrap() {
+                    node.db.commit_block(VIDA_ID, block_number).unwrap();
+                    block_number += 1;
+                }
+                committed = (model.clone(), node.root());
+            }
+            Op::Revert => {
+                node.db.abort_block(VIDA_ID).unwrap();
+                model = committed.0.clone();
+                prop_assert_eq!(hex::encode(node.root()), hex::encode(&committed.1), "revert did not restore the committed root");
+            }
+        }
+
+        for (&account, &balance) in &model.balances {
+            prop_assert_eq!(node.balance(account), balance, "balance of account {} after {:?}", account, op);
+        }
+        let audit = node.db.audit_supply(VIDA_ID, DEFAULT_TOKEN).unwrap();
+        prop_assert!(audit.consistent, "balances sum to {} but supply is {:?} after {:?}", audit.balance_sum, audit.recorded_supply, op);
+        prop_assert_eq!(audit.balance_sum, model.supply.to_string());
+    }
+
+    Ok(node.root())
+}
+
+#[test]
+fn self_transfers_keep_the_supply() {
+    run(&[
+        Op::Transfer { from: 1, to: 1, amount: 300 },
+        Op::Commit,
+        Op::Transfer { from: 2, to: 2, amount: INITIAL_BALANCE },
+    ])
+    .unwrap();
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn state_invariants_hold(ops in prop::collection::vec(op(), 1..40)) {
+        run(&ops)?;
+    }
+
+    #[test]
+    fn same_operations_give_same_root(ops in prop::collection::vec(op(), 1..40)) {
+        let first = run(&ops)?;
+        let second = run(&ops)?;
+        prop_assert_eq!(hex::encode(first), hex::encode(second));
+    }
+}
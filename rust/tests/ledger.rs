@@ -0,0 +1,384 @@
+//! Exercises single `DatabaseService` features on a fresh database each:
+//! rollbacks, write batches, metered handlers and the records actions keep.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use num_bigint::BigUint;
+use pwr_stateful_vida::database_service::{DatabaseService, FailedTransaction, FeePolicy, ReceiptStatus, DEFAULT_TOKEN};
+use pwr_stateful_vida::escrow;
+use pwr_stateful_vida::handler::{apply_transaction, reevaluate_failed, ApplyError};
+use serde_json::{json, Value};
+
+const VIDA_ID: u64 = 7;
+
+static DATABASES: AtomicU64 = AtomicU64::new(0);
+
+fn address(account: u8) -> Vec<u8> {
+    let mut address = vec![0u8; 20];
+    address[19] = account;
+    address
+}
+
+fn hex_address(account: u8) -> String {
+    format!("0x{}", hex::encode(address(account)))
+}
+
+// A database in its own temporary directory
+struct Node {
+    db: DatabaseService,
+    dir: PathBuf,
+}
+
+impl Node {
+    fn start() -> Self {
+        let id = DATABASES.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("pwr-ledger-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&dir);
+        let db = DatabaseService::open(&dir, "state", &[VIDA_ID]).unwrap();
+        Node { db, dir }
+    }
+
+    // Runs `f` as the changes of a block, commits it and records its root
+    // as the handler does once peers agree
+    fn block(&self, block_number: u64, f: impl FnOnce(&DatabaseService)) -> Vec<u8> {
+        self.db.begin_block(VIDA_ID, block_number).unwrap();
+        f(&self.db);
+        self.db.commit_block(VIDA_ID, block_number).unwrap();
+        let root = self.root();
+        self.db.set_block_root_hash(VIDA_ID, block_number, &root).unwrap();
+        root
+    }
+
+    // Applies a transaction of `sender` as part of `block_number`
+    fn apply(&self, actions: &[&str], block_number: u64, hash: &str, sender: u8, payload: Value) -> Result<(), ApplyError> {
+        let actions: Vec<String> = actions.iter().map(|action| action.to_string()).collect();
+        let data = serde_json::to_vec(&payload).unwrap();
+        apply_transaction(&self.db, &actions, VIDA_ID, block_number, hash, &hex_address(sender), &data)
+    }
+
+    fn balance(&self, account: u8) -> BigUint {
+        self.db.get_balance(VIDA_ID, DEFAULT_TOKEN, &address(account)).unwrap()
+    }
+
+    fn root(&self) -> Vec<u8> {
+        self.db.get_root_hash(VIDA_ID).unwrap().unwrap_or_default()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn mint(account: u8, amount: u32) -> impl FnOnce(&DatabaseService) {
+    move |db| db.mint(VIDA_ID, DEFAULT_TOKEN, &address(account), &BigUint::from(amount)).unwrap()
+}
+
+#[test]
+fn rollback_over_created_keys_restores_the_recorded_root() {
+    let node = Node::start();
+    let first_root = node.block(1, mint(1, 500));
+    // Block 2 creates the balance of account 2, which the tree cannot drop again
+    let second_root = node.block(2, |db| {
+        assert!(db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(2), &BigUint::from(200u32)).unwrap());
+    });
+
+    node.db.rollback_to_block(VIDA_ID, 1).unwrap();
+    assert_eq!(hex::encode(node.root()), hex::encode(&first_root));
+    assert_eq!(node.balance(1), BigUint::from(500u32));
+    assert_eq!(node.balance(2), BigUint::from(0u32));
+
+    // The rebuilt state goes on to the same roots as before
+    let replayed_root = node.block(2, |db| {
+        assert!(db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(2), &BigUint::from(200u32)).unwrap());
+    });
+    assert_eq!(hex::encode(replayed_root), hex::encode(second_root));
+}
+
+#[test]
+fn rollback_restores_changed_values() {
+    let node = Node::start();
+    node.block(1, mint(1, 500));
+    node.block(2, mint(2, 100));
+    let third_root = node.block(3, mint(2, 50));
+    node.block(4, |db| {
+        assert!(db.burn(VIDA_ID, DEFAULT_TOKEN, &address(2), &BigUint::from(150u32)).unwrap());
+    });
+
+    node.db.rollback_to_block(VIDA_ID, 3).unwrap();
+    assert_eq!(hex::encode(node.root()), hex::encode(third_root));
+    assert_eq!(node.balance(2), BigUint::from(150u32));
+}
+
+#[test]
+fn reprocessing_failed_transactions_changes_no_state() {
+    let node = Node::start();
+    node.block(1, mint(1, 500));
+    let payload = json!({ "action": "transfer", "receiver": hex_address(2), "amount": "800", "nonce": 0 });
+    let failed = FailedTransaction {
+        hash: "0x01".to_string(),
+        block_number: 2,
+        sender: hex_address(1),
+        data: hex::encode(serde_json::to_vec(&payload).unwrap()),
+        reason: "Handler crashed".to_string(),
+    };
+    node.db.add_failed_transaction(VIDA_ID, &failed).unwrap();
+    let actions = vec!["transfer".to_string()];
+
+    assert_eq!(reevaluate_failed(&node.db, &actions, VIDA_ID, None).unwrap(), (0, 1));
+    let queue = node.db.get_failed_transactions(VIDA_ID).unwrap();
+    assert!(queue[0].reason.starts_with("Insufficient funds"), "{}", queue[0].reason);
+
+    node.block(3, mint(1, 500));
+    let root = node.root();
+    assert_eq!(reevaluate_failed(&node.db, &actions, VIDA_ID, None).unwrap(), (1, 0));
+    assert_eq!(hex::encode(node.root()), hex::encode(root));
+    assert_eq!(node.balance(2), BigUint::from(0u32));
+    assert!(node.db.get_receipt(VIDA_ID, &[1]).unwrap().is_none());
+    assert_eq!(node.db.get_failed_transactions(VIDA_ID).unwrap().len(), 1);
+}
+
+#[test]
+fn transfer_from_spends_the_allowance_of_its_token_and_the_fee() {
+    const TOKEN: u64 = 5;
+    let node = Node::start();
+    node.block(1, |db| {
+        db.mint(VIDA_ID, TOKEN, &address(1), &BigUint::from(1_000u32)).unwrap();
+        let policy = FeePolicy { flat: "10".to_string(), basis_points: 0, collector: hex_address(9) };
+        db.set_fee_policy(VIDA_ID, &policy).unwrap();
+    });
+    let actions = ["approve", "transferFrom"];
+    let approve = json!({ "action": "approve", "spender": hex_address(2), "amount": "300", "tokenId": TOKEN, "nonce": 0 });
+    node.apply(&actions, 2, "0x01", 1, approve).unwrap();
+    assert_eq!(node.db.get_allowance(VIDA_ID, TOKEN, &address(1), &address(2)).unwrap(), BigUint::from(300u32));
+    assert_eq!(node.db.get_allowance(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(2)).unwrap(), BigUint::from(0u32));
+
+    let spend = |amount: &str, nonce: u64| {
+        json!({ "action": "transferFrom", "from": hex_address(1), "receiver": hex_address(3), "amount": amount, "tokenId": TOKEN, "nonce": nonce })
+    };
+    node.apply(&actions, 2, "0x02", 2, spend("290", 0)).unwrap();
+    let balance = |account| node.db.get_balance(VIDA_ID, TOKEN, &address(account)).unwrap();
+    assert_eq!(balance(1), BigUint::from(700u32));
+    assert_eq!(balance(3), BigUint::from(290u32));
+    assert_eq!(balance(9), BigUint::from(10u32));
+    assert_eq!(node.db.get_allowance(VIDA_ID, TOKEN, &address(1), &address(2)).unwrap(), BigUint::from(0u32));
+
+    // Nothing is left for another transfer and its fee
+    assert!(matches!(node.apply(&actions, 2, "0x03", 2, spend("1", 1)), Err(ApplyError::Rejected(_))));
+}
+
+#[test]
+fn fee_policies_with_malformed_flat_fees_are_refused() {
+    let node = Node::start();
+    let policy = FeePolicy { flat: "ten".to_string(), basis_points: 0, collector: hex_address(9) };
+    assert!(node.db.set_fee_policy(VIDA_ID, &policy).is_err());
+    assert!(node.db.get_fee_policy(VIDA_ID).unwrap().is_none());
+    assert!(policy.fee(&BigUint::from(100u32)).is_err());
+}
+
+#[test]
+fn receipts_classify_rejections_by_their_reason() {
+    let node = Node::start();
+    node.block(1, mint(1, 500));
+    let actions = ["transfer", "mint", "claim"];
+    let rejected = [
+        ("0x01", json!({ "action": "transfer", "receiver": hex_address(2), "amount": "800", "nonce": 0 }), ReceiptStatus::InsufficientFunds),
+        ("0x02", json!({ "action": "mint", "receiver": hex_address(1), "amount": "1", "nonce": 0 }), ReceiptStatus::Unauthorized),
+        ("0x03", json!({ "action": "transfer", "receiver": "0xzz", "amount": "1", "nonce": 0 }), ReceiptStatus::InvalidPayload),
+    ];
+    for (hash, payload, status) in rejected {
+        assert!(matches!(node.apply(&actions, 2, hash, 1, payload), Err(ApplyError::Rejected(_))));
+        let receipt = node.db.get_receipt(VIDA_ID, &hex::decode(&hash[2..]).unwrap()).unwrap().unwrap();
+        assert_eq!(receipt.status, status, "{:?}", receipt.reason);
+    }
+}
+
+#[test]
+fn transactions_over_the_write_limit_leave_the_root_unchanged() {
+    let node = Node::start();
+    let root = node.block(1, mint(1, 500));
+    let transfers = |db: &DatabaseService| -> Result<(), String> {
+        for account in 2..=4 {
+            db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(account), &BigUint::from(100u32))
+                .map_err(|e| format!("{:?}", e))?;
+        }
+        Ok(())
+    };
+
+    let rejected = node.block(2, |db| {
+        let result = db.metered(VIDA_ID, 1_000, 2, || transfers(db));
+        assert!(result.unwrap_err().contains("limit of 2 state writes"));
+    });
+    assert_eq!(hex::encode(rejected), hex::encode(&root));
+    assert_eq!(node.balance(1), BigUint::from(500u32));
+    assert_eq!(node.balance(2), BigUint::from(0u32));
+
+    // Within the limits the writes reach the tree as they would unmetered
+    let unmetered = Node::start();
+    unmetered.block(1, mint(1, 500));
+    unmetered.block(2, |_| {});
+    let expected = unmetered.block(3, |db| transfers(db).unwrap());
+    let applied = node.block(3, |db| db.metered(VIDA_ID, 1_000, 1_000, || transfers(db)).unwrap());
+    assert_eq!(hex::encode(applied), hex::encode(expected));
+    assert_eq!(node.balance(4), BigUint::from(100u32));
+}
+
+#[test]
+fn successive_rollbacks_walk_the_undo_journal_back() {
+    let node = Node::start();
+    let first_root = node.block(1, mint(1, 500));
+    node.block(2, |db| {
+        assert!(db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(2), &BigUint::from(200u32)).unwrap());
+    });
+    let third_root = node.block(3, mint(2, 50));
+    node.block(4, |db| {
+        assert!(db.burn(VIDA_ID, DEFAULT_TOKEN, &address(1), &BigUint::from(300u32)).unwrap());
+    });
+
+    node.db.rollback_to_block(VIDA_ID, 3).unwrap();
+    assert_eq!(hex::encode(node.root()), hex::encode(third_root));
+    assert_eq!(node.db.get_last_checked_block(VIDA_ID).unwrap(), 3);
+    assert_eq!(node.balance(1), BigUint::from(300u32));
+
+    // Uncommitted changes go with the rollback
+    node.db.begin_block(VIDA_ID, 4).unwrap();
+    node.db.mint(VIDA_ID, DEFAULT_TOKEN, &address(3), &BigUint::from(10u32)).unwrap();
+    node.db.rollback_to_block(VIDA_ID, 1).unwrap();
+    assert_eq!(hex::encode(node.root()), hex::encode(first_root));
+    assert_eq!(node.db.get_last_checked_block(VIDA_ID).unwrap(), 1);
+    assert_eq!(node.balance(2), BigUint::from(0u32));
+    assert_eq!(node.balance(3), BigUint::from(0u32));
+
+    // The undo records of the undone blocks are gone
+    assert!(node.db.state_diff(VIDA_ID, 1, 2).is_err());
+}
+
+#[test]
+fn aborted_blocks_leave_no_trace() {
+    let node = Node::start();
+    let root = node.block(1, mint(1, 500));
+
+    node.db.begin_block(VIDA_ID, 2).unwrap();
+    assert!(node.db.transfer(VIDA_ID, DEFAULT_TOKEN, &address(1), &address(2), &BigUint::from(200u32)).unwrap());
+    node.db.begin_block(VIDA_ID, 3).unwrap();
+    node.db.mint(VIDA_ID, DEFAULT_TOKEN, &address(3), &BigUint::from(50u32)).unwrap();
+    assert_eq!(node.db.batch_first_block(VIDA_ID).unwrap(), Some(2));
+    node.db.abort_block(VIDA_ID).unwrap();
+
+    assert!(!node.db.has_open_batch(VIDA_ID).unwrap());
+    assert_eq!(hex::encode(node.root()), hex::encode(&root));
+    assert_eq!(node.balance(1), BigUint::from(500u32));
+    assert_eq!(node.balance(2), BigUint::from(0u32));
+
+    // Keys the aborted batch created are indexed anew, in the same order as
+    // on a node that never saw it
+    let replayed = node.block(2, mint(3, 50));
+    let fresh = Node::start();
+    fresh.block(1, mint(1, 500));
+    assert_eq!(hex::encode(replayed), hex::encode(fresh.block(2, mint(3, 50))));
+}
+
+#[test]
+fn redelivered_transactions_apply_once() {
+    let node = Node::start();
+    node.block(1, mint(1, 500));
+    let actions = ["transfer"];
+    let transfer = |nonce: u64| json!({ "action": "transfer", "receiver": hex_address(2), "amount": "100", "nonce": nonce });
+
+    node.apply(&actions, 2, "0x01", 1, transfer(0)).unwrap();
+    node.apply(&actions, 2, "0x01", 1, transfer(0)).unwrap();
+    assert_eq!(node.balance(2), BigUint::from(100u32));
+
+    // An aborted batch forgets the hashes it recorded, so the block applies again
+    node.db.abort_block(VIDA_ID).unwrap();
+    node.apply(&actions, 2, "0x01", 1, transfer(0)).unwrap();
+    node.apply(&actions, 2, "0x02", 1, transfer(1)).unwrap();
+    node.db.commit_block(VIDA_ID, 2).unwrap();
+    assert_eq!(node.balance(2), BigUint::from(200u32));
+    assert!(!node.db.mark_transaction_processed(VIDA_ID, 2, &[2]).unwrap());
+}
+
+#[test]
+fn tokens_keep_separate_balances_under_their_own_keys() {
+    const TOKEN: u64 = 5;
+    let node = Node::start();
+    node.db.begin_block(VIDA_ID, 1).unwrap();
+    node.db.mint(VIDA_ID, DEFAULT_TOKEN, &address(1), &BigUint::from(500u32)).unwrap();
+    node.db.mint(VIDA_ID, TOKEN, &address(1), &BigUint::from(70u32)).unwrap();
+    // Default token balances keep the key they had before token ids
+    let keys: Vec<String> = node.db.uncommitted_diff(VIDA_ID).unwrap().into_iter().map(|entry| entry.key).collect();
+    assert!(keys.contains(&format!("01{}", hex::encode(address(1)))), "{:?}", keys);
+    assert!(keys.contains(&format!("01{:016x}{}", TOKEN, hex::encode(address(1)))), "{:?}", keys);
+    node.db.commit_block(VIDA_ID, 1).unwrap();
+
+    let transfer = json!({ "action": "transfer", "receiver": hex_address(2), "amount": "30", "tokenId": TOKEN, "nonce": 0 });
+    node.apply(&["transfer"], 2, "0x01", 1, transfer).unwrap();
+    let balance = |token_id, account| node.db.get_balance(VIDA_ID, token_id, &address(account)).unwrap();
+    assert_eq!(balance(TOKEN, 1), BigUint::from(40u32));
+    assert_eq!(balance(TOKEN, 2), BigUint::from(30u32));
+    assert_eq!(balance(DEFAULT_TOKEN, 1), BigUint::from(500u32));
+    assert_eq!(balance(DEFAULT_TOKEN, 2), BigUint::from(0u32));
+    assert_eq!(node.db.get_total_supply(VIDA_ID, TOKEN).unwrap(), Some(BigUint::from(70u32)));
+    assert_eq!(node.db.get_total_supply(VIDA_ID, DEFAULT_TOKEN).unwrap(), Some(BigUint::from(500u32)));
+}
+
+#[test]
+fn escrows_go_to_the_receiver_or_back_to_the_sender_after_expiry() {
+    let node = Node::start();
+    node.block(1, mint(1, 500));
+    let actions = ["escrow", "claim", "reclaim"];
+    let lock = |nonce: u64, expiry_block: u64| {
+        json!({ "action": "escrow", "receiver": hex_address(2), "amount": "100", "expiryBlock": expiry_block, "nonce": nonce })
+    };
+    node.apply(&actions, 2, "0x01", 1, lock(0, 5)).unwrap();
+    node.apply(&actions, 2, "0x02", 1, lock(1, 6)).unwrap();
+    assert_eq!(node.balance(1), BigUint::from(300u32));
+
+    let settle = |action: &str, id: u64, nonce: u64| json!({ "action": action, "escrowId": id, "nonce": nonce });
+    // Only the receiver claims, and only the sender reclaims once it expired
+    assert!(matches!(node.apply(&actions, 3, "0x03", 1, settle("claim", 0, 2)), Err(ApplyError::Rejected(_))));
+    assert!(matches!(node.apply(&actions, 3, "0x04", 1, settle("reclaim", 1, 2)), Err(ApplyError::Rejected(_))));
+    node.apply(&actions, 3, "0x05", 2, settle("claim", 0, 0)).unwrap();
+    assert_eq!(node.balance(2), BigUint::from(100u32));
+    assert!(node.db.get_escrow(VIDA_ID, 0).unwrap().is_none());
+    node.db.commit_block(VIDA_ID, 3).unwrap();
+
+    // Expired escrows are refunded with the first checkpoint after them
+    node.block(7, |db| escrow::refund_expired(db, VIDA_ID, 7));
+    assert!(node.db.get_escrow(VIDA_ID, 1).unwrap().is_none());
+    assert_eq!(node.balance(1), BigUint::from(400u32));
+    assert_eq!(node.balance(2), BigUint::from(100u32));
+}
+
+#[test]
+fn multisig_spends_execute_once_the_threshold_approves() {
+    let node = Node::start();
+    let actions = ["createMultisig", "submitMultisig", "approveMultisig"];
+    let create = json!({ "action": "createMultisig", "owners": [hex_address(1), hex_address(2), hex_address(3)], "threshold": 2, "nonce": 0 });
+    node.apply(&actions, 1, "0x01", 1, create).unwrap();
+    let multisig = DatabaseService::multisig_address(0);
+    node.db.mint(VIDA_ID, DEFAULT_TOKEN, &multisig, &BigUint::from(500u32)).unwrap();
+    node.db.commit_block(VIDA_ID, 1).unwrap();
+
+    let multisig_hex = format!("0x{}", hex::encode(multisig));
+    let submit = json!({ "action": "submitMultisig", "multisig": multisig_hex, "receiver": hex_address(4), "amount": "100", "nonce": 1 });
+    node.apply(&actions, 2, "0x02", 1, submit).unwrap();
+    assert_eq!(node.balance(4), BigUint::from(0u32));
+
+    let approve = |nonce: u64| json!({ "action": "approveMultisig", "multisig": multisig_hex, "transactionId": 0, "nonce": nonce });
+    // Neither outsiders nor the submitter count towards the threshold
+    assert!(matches!(node.apply(&actions, 2, "0x03", 4, approve(0)), Err(ApplyError::Rejected(_))));
+    assert!(matches!(node.apply(&actions, 2, "0x04", 1, approve(2)), Err(ApplyError::Rejected(_))));
+    assert_eq!(node.balance(4), BigUint::from(0u32));
+
+    node.apply(&actions, 2, "0x05", 2, approve(0)).unwrap();
+    assert_eq!(node.balance(4), BigUint::from(100u32));
+    assert_eq!(node.db.get_balance(VIDA_ID, DEFAULT_TOKEN, &multisig).unwrap(), BigUint::from(400u32));
+    assert!(node.db.get_multisig_transaction(VIDA_ID, &multisig, 0).unwrap().unwrap().executed);
+    assert!(matches!(node.apply(&actions, 2, "0x06", 3, approve(0)), Err(ApplyError::Rejected(_))));
+    assert_eq!(node.balance(4), BigUint::from(100u32));
+}
@@ -0,0 +1,124 @@
+//! Checks how transaction payloads and the addresses and amounts in them are
+//! read, independent of any database.
+
+use std::collections::HashMap;
+
+use num_bigint::BigUint;
+
+use pwr_stateful_vida::{address, amount};
+use pwr_stateful_vida::payload::{self, field::Kind, BinaryPayload, Field, FieldList};
+use serde_json::json;
+
+// Example address of EIP-55
+const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+#[test]
+fn addresses_are_checked_against_their_eip55_checksum() {
+    let bytes = address::parse_address(CHECKSUMMED).unwrap();
+    assert_eq!(address::to_checksum(&bytes), CHECKSUMMED);
+    // Single-case addresses carry no checksum
+    assert_eq!(address::parse_address(&CHECKSUMMED.to_lowercase()).unwrap(), bytes);
+    assert_eq!(address::parse_address(&CHECKSUMMED[2..].to_uppercase()).unwrap(), bytes);
+
+    let mistyped = CHECKSUMMED.replacen('a', "A", 1);
+    assert!(address::parse_address(&mistyped).unwrap_err().contains("checksum"));
+    assert!(address::parse_address(&CHECKSUMMED[..40]).is_err());
+    assert!(address::parse_address("0xzz").is_err());
+    assert!(address::parse_address("").is_err());
+
+    let zero = format!("0x{}", "0".repeat(40));
+    assert_eq!(address::parse_address(&zero).unwrap(), address::ZERO_ADDRESS);
+    assert!(address::parse_recipient(&zero).is_err());
+}
+
+fn field(kind: Kind) -> Field {
+    Field { kind: Some(kind) }
+}
+
+#[test]
+fn binary_payloads_decode_to_the_json_they_stand_for() {
+    let receiver = address::parse_address(CHECKSUMMED).unwrap();
+    let fields = HashMap::from([
+        ("receiver".to_string(), field(Kind::Address(receiver))),
+        ("amount".to_string(), field(Kind::Amount(vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]))),
+        ("nonce".to_string(), field(Kind::Number(3))),
+        ("note".to_string(), field(Kind::Text("rent".to_string()))),
+        ("owners".to_string(), field(Kind::List(FieldList { items: vec![field(Kind::Flag(true))] }))),
+    ]);
+    let data = payload::encode_binary(&BinaryPayload { action: "transfer".to_string(), fields });
+    assert_eq!(data[0], payload::BINARY_V1);
+
+    let expected = json!({
+        "action": "transfer",
+        "receiver": CHECKSUMMED.to_lowercase(),
+        "amount": "18446744073709551616",
+        "nonce": 3,
+        "note": "rent",
+        "owners": [true],
+    });
+    assert_eq!(serde_json::Value::Object(payload::decode(&data).unwrap()), expected);
+    // The same payload sent as JSON reads the same
+    assert_eq!(serde_json::Value::Object(payload::decode(&serde_json::to_vec(&expected).unwrap()).unwrap()), expected);
+
+    let action_field = HashMap::from([("action".to_string(), field(Kind::Text("mint".to_string())))]);
+    let smuggled = payload::encode_binary(&BinaryPayload { action: "transfer".to_string(), fields: action_field });
+    assert!(payload::decode(&smuggled).is_err());
+    let empty = HashMap::from([("nonce".to_string(), Field { kind: None })]);
+    assert!(payload::decode(&payload::encode_binary(&BinaryPayload { action: "transfer".to_string(), fields: empty })).is_err());
+    assert!(payload::decode(&[payload::BINARY_V1, 0xff, 0xff]).is_err());
+}
+
+// A payload whose `nested` field holds `levels` arrays, one inside the other
+fn nested(levels: usize) -> Vec<u8> {
+    format!(r#"{{"action":"transfer","nested":{}1{}}}"#, "[".repeat(levels), "]".repeat(levels)).into_bytes()
+}
+
+#[test]
+fn payloads_beyond_the_limits_are_rejected() {
+    let limits = payload::limits();
+    // The payload object is the first level
+    assert!(payload::decode(&nested(limits.max_depth - 1)).is_ok());
+    assert!(payload::decode(&nested(limits.max_depth)).is_err());
+    // Brackets inside strings do not nest
+    let bracketed = json!({ "action": "transfer", "note": "[".repeat(limits.max_depth + 1) });
+    assert!(payload::decode(&serde_json::to_vec(&bracketed).unwrap()).is_ok());
+
+    let long = |length: usize| json!({ "action": "transfer", "note": "x".repeat(length) });
+    assert!(payload::decode(&serde_json::to_vec(&long(limits.max_field_length)).unwrap()).is_ok());
+    assert!(payload::decode(&serde_json::to_vec(&long(limits.max_field_length + 1)).unwrap()).is_err());
+    let items = json!({ "action": "createMultisig", "owners": vec![0; limits.max_field_length + 1] });
+    assert!(payload::decode(&serde_json::to_vec(&items).unwrap()).is_err());
+
+    let mut huge = b"{\"action\":\"transfer\",\"note\":\"".to_vec();
+    huge.resize(limits.max_bytes, b' ');
+    huge.extend_from_slice(b"\"}");
+    assert!(payload::decode(&huge).unwrap_err().contains("at most"));
+
+    let largest = (BigUint::from(1u32) << limits.max_amount_bits) - 1u32;
+    assert!(payload::check_amount(&largest).is_ok());
+    assert!(payload::check_amount(&(largest + 1u32)).is_err());
+}
+
+#[test]
+fn amounts_parse_and_format_in_whole_tokens() {
+    let parse = |text: &str| amount::parse(text, 6);
+    assert_eq!(parse("1.5").unwrap(), BigUint::from(1_500_000u32));
+    assert_eq!(parse("0.000001").unwrap(), BigUint::from(1u32));
+    // Amounts without a decimal point stay in base units
+    assert_eq!(parse("15").unwrap(), BigUint::from(15u32));
+    for invalid in ["0.0000001", "1.", ".5", "-1", "1.5.0", "1e6", ""] {
+        assert!(parse(invalid).is_err(), "{} accepted", invalid);
+    }
+    assert!(amount::parse("1.5", 0).is_err());
+
+    let format = |base_units: u32| amount::format(&BigUint::from(base_units), 6);
+    assert_eq!(format(1_500_000), "1.5");
+    assert_eq!(format(1), "0.000001");
+    assert_eq!(format(2_000_000), "2");
+    assert_eq!(format(0), "0");
+    assert_eq!(amount::format(&BigUint::from(15u32), 0), "15");
+
+    // Formatted amounts parse back exactly, however large
+    let large: BigUint = "123456789012345678901234567890123".parse().unwrap();
+    assert_eq!(amount::parse(&amount::format(&large, amount::MAX_DECIMALS), amount::MAX_DECIMALS).unwrap(), large);
+}
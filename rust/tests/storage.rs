@@ -0,0 +1,118 @@
+//! Checks where `DatabaseService` keeps its files on disk and how it opens
+//! stores written by earlier builds.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use num_bigint::BigUint;
+use pwr_rs::merkle_tree::{MerkleTree, MerkleTreeError};
+use pwr_stateful_vida::database_service::{DatabaseService, DEFAULT_TOKEN, SCHEMA_VERSION};
+
+const VIDA_ID: u64 = 7;
+// Where pwr-rs opens every tree, relative to the working directory
+const TREE_ROOT: &str = "merkleTree";
+
+// A directory removed again when the test ends
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(path: PathBuf) -> Self {
+        let _ = fs::remove_dir_all(&path);
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn address(account: u8) -> Vec<u8> {
+    let mut address = vec![0u8; 20];
+    address[19] = account;
+    address
+}
+
+// The state key of a default token balance in the namespaced layout
+fn balance_key(account: u8) -> Vec<u8> {
+    [&[0x01][..], &address(account)].concat()
+}
+
+// Writes a store as an earlier build left it, with the given state keys
+// indexed in order and the given meta entries, and returns it with its root.
+// It is created below `TREE_ROOT` so that its trees can be named directly
+fn write_store(name: &str, state: &[(Vec<u8>, u64)], meta: &[(&[u8], Vec<u8>)]) -> (TempDir, Vec<u8>) {
+    let dir_name = format!("pwr-storage-{}-{}", process::id(), name);
+    let dir = TempDir::new(Path::new(TREE_ROOT).join(&dir_name));
+    let open = |suffix: &str| MerkleTree::new(format!("{}/state{}", dir_name, suffix)).unwrap();
+
+    let (tree, journal, meta_tree) = (open(""), open("Journal"), open("Meta"));
+    for (index, (key, balance)) in state.iter().enumerate() {
+        tree.add_or_update_data(key, &BigUint::from(*balance).to_bytes_be()).unwrap();
+        journal.add_or_update_data(&[&b"key_"[..], &(index as u64).to_be_bytes()].concat(), key).unwrap();
+    }
+    journal.add_or_update_data(b"keyCount", &(state.len() as u64).to_be_bytes()).unwrap();
+    for (key, value) in meta {
+        meta_tree.add_or_update_data(key, value).unwrap();
+    }
+    let root = tree.get_root_hash().unwrap().unwrap();
+    for tree in [tree, journal, meta_tree] {
+        tree.close().unwrap();
+    }
+    (dir, root)
+}
+
+// RocksDB writes a CURRENT file into every database it creates
+fn is_tree(path: &Path) -> bool {
+    path.join("CURRENT").is_file()
+}
+
+#[test]
+fn instances_keep_their_trees_in_their_own_directories() {
+    let first = TempDir::new(std::env::temp_dir().join(format!("pwr-storage-{}-first", process::id())));
+    let second = TempDir::new(std::env::temp_dir().join(format!("pwr-storage-{}-second", process::id())));
+    let _first_db = DatabaseService::open(&first.0, "state", &[VIDA_ID]).unwrap();
+    let _second_db = DatabaseService::open(&second.0, "state", &[VIDA_ID]).unwrap();
+
+    for dir in [&first.0, &second.0] {
+        for tree in ["state", "stateJournal", "stateMeta", "stateNode"] {
+            assert!(is_tree(&dir.join(tree)), "{} missing from {}", tree, dir.display());
+        }
+        // Nothing may land below the working directory instead
+        let stray = Path::new(TREE_ROOT).join(dir.strip_prefix("/").unwrap_or(dir));
+        assert!(!stray.exists(), "trees written to {}", stray.display());
+    }
+}
+
+#[test]
+fn legacy_stores_keep_their_balances_and_migrate_to_one_root() {
+    // Balances were kept under the bare address before keys were namespaced
+    let state: Vec<(Vec<u8>, u64)> = (1..=5).map(|account| (address(account), 1_000 * account as u64)).collect();
+    let (first, legacy_root) = write_store("legacy-first", &state, &[]);
+    let (second, _) = write_store("legacy-second", &state, &[]);
+
+    let mut roots = Vec::new();
+    for dir in [&first.0, &second.0] {
+        let db = DatabaseService::open(dir, "state", &[VIDA_ID]).unwrap();
+        for (key, balance) in &state {
+            assert_eq!(db.get_balance(VIDA_ID, DEFAULT_TOKEN, key).unwrap(), BigUint::from(*balance));
+        }
+        roots.push(db.get_root_hash(VIDA_ID).unwrap().unwrap());
+    }
+    assert_ne!(roots[0], legacy_root, "state was not rewritten");
+    assert_eq!(hex::encode(&roots[0]), hex::encode(&roots[1]), "migrated stores differ");
+}
+
+#[test]
+fn stores_of_newer_schema_versions_are_refused() {
+    let version = (SCHEMA_VERSION + 1).to_be_bytes().to_vec();
+    let (dir, _) = write_store("newer", &[(balance_key(1), 1_000)], &[(b"schemaVersion", version)]);
+
+    match DatabaseService::open(&dir.0, "state", &[VIDA_ID]) {
+        Err(MerkleTreeError::IllegalState(reason)) => assert!(reason.contains("newer"), "{}", reason),
+        Err(e) => panic!("unexpected error: {:?}", e),
+        Ok(_) => panic!("store of schema version {} was opened", SCHEMA_VERSION + 1),
+    }
+}